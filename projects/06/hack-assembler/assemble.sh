@@ -3,6 +3,8 @@ set -euo pipefail
 
 IMAGE_NAME="hack-assembler:latest"
 SCRIPT_DIR="$(cd "$(dirname "${BASH_SOURCE[0]}")" && pwd)"
+# Path dependencies on projects 08, 10 and 11 need the whole repository
+REPO_ROOT="$(cd "$SCRIPT_DIR/../../.." && pwd)"
 
 # Colors for output
 RED='\033[0;31m'
@@ -61,7 +63,7 @@ EOF
 build_image() {
     check_podman
     echo -e "${YELLOW}Building Hack Assembler container...${NC}"
-    podman build -t "$IMAGE_NAME" -f "$SCRIPT_DIR/Containerfile" "$SCRIPT_DIR"
+    podman build -t "$IMAGE_NAME" -f "$SCRIPT_DIR/Containerfile" "$REPO_ROOT"
     echo -e "${GREEN}✓ Build complete${NC}"
 }
 
@@ -70,9 +72,9 @@ run_tests() {
     echo -e "${YELLOW}Running tests...${NC}"
     cd "$SCRIPT_DIR"
     podman run --rm -it \
-        -v "$SCRIPT_DIR:/build:ro" \
+        -v "$REPO_ROOT:/build:ro" \
         docker.io/rust:alpine \
-        sh -c "cd /build && cargo test --release"
+        sh -c "cd /build/projects/06/hack-assembler && cargo test --release"
     echo -e "${GREEN}✓ All tests passed${NC}"
 }
 
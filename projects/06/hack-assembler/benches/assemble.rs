@@ -0,0 +1,74 @@
+//! Assembler benchmarks.
+//!
+//! Run with `cargo bench`; the allocations of one assembly of a large
+//! generated program are printed first (see [`alloc_counter`]). Symbol names
+//! borrow from the source, so the symbol table allocates per distinct symbol
+//! at most, not per `@symbol` line.
+
+use alloc_counter::CountingAllocator;
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use hack_assembler::assemble;
+use std::hint::black_box;
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+const BLOCKS: usize = 2_000;
+
+/// Translator-style output: labelled blocks that jump to each other and
+/// touch a handful of variables, about 25 lines per block.
+fn big_program_source() -> String {
+    let mut source = String::from("// generated\n@256\nD=A\n@SP\nM=D\n");
+    for i in 0..BLOCKS {
+        let next = (i + 1) % BLOCKS;
+        let var = i % 50;
+        source.push_str(&format!(
+            "(Block.f{i})
+    @SP
+    AM=M-1
+    D=M
+    @var{var}
+    M=D
+    @Block.f{i}$IF_TRUE
+    D;JNE
+    @LCL
+    A=M
+    D=M
+    @THAT
+    M=D+1
+    @Block.f{next}
+    0;JMP
+(Block.f{i}$IF_TRUE)
+    @var{var}
+    D=M
+    @R13
+    M=D
+    @Block.f{i}$END
+    0;JMP
+(Block.f{i}$END)
+    @Block.f{i}
+    0;JMP
+"
+        ));
+    }
+    source
+}
+
+fn bench_assemble(c: &mut Criterion) {
+    let source = big_program_source();
+    let lines = source.lines().count();
+
+    let (output, allocations) = alloc_counter::count(|| assemble(&source).unwrap());
+    black_box(output);
+    println!("assemble/{lines}_lines: {allocations} allocations per program");
+
+    let mut group = c.benchmark_group("assemble");
+    group.throughput(Throughput::Elements(lines as u64));
+    group.bench_function(format!("{lines}_lines"), |b| {
+        b.iter(|| assemble(black_box(&source)).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_assemble);
+criterion_main!(benches);
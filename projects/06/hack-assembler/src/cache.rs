@@ -0,0 +1,217 @@
+//! Re-assembly cache keyed by a hash of the source.
+//!
+//! [`assemble_cached`] looks up the output for a source in an [`AsmCache`]
+//! and only assembles on a miss, storing the result. The key covers the
+//! source text, the [`AssembleOptions`] and the assembler version, so a
+//! stale entry is never reused after either changes.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::error::Result;
+use crate::{AssembleOptions, assemble_with_options};
+
+/// Storage for assembled output, keyed by [`cache_key`].
+pub trait AsmCache {
+    /// The stored output for `key`, or `None` if there is no usable entry.
+    fn get(&mut self, key: u64) -> Option<String>;
+
+    /// Store the output for `key`.
+    fn put(&mut self, key: u64, output: &str) -> io::Result<()>;
+}
+
+/// 64-bit FNV-1a hash.
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// The cache key for assembling `source` with `options`.
+pub fn cache_key(source: &str, options: &AssembleOptions) -> u64 {
+    let flags = [u8::from(options.anonymous_labels), u8::from(options.strict)];
+    let hash = fnv1a(0xcbf2_9ce4_8422_2325, env!("CARGO_PKG_VERSION").as_bytes());
+    fnv1a(fnv1a(hash, &flags), source.as_bytes())
+}
+
+/// Assemble `source` with default options, reusing cached output.
+pub fn assemble_cached(source: &str, cache: &mut dyn AsmCache) -> Result<String> {
+    assemble_cached_with_options(source, &AssembleOptions::default(), cache)
+}
+
+/// [`assemble_cached`] with the given options.
+///
+/// A source that fails to assemble is not cached. Failing to store output
+/// is not an error: the output is returned anyway.
+pub fn assemble_cached_with_options(
+    source: &str,
+    options: &AssembleOptions,
+    cache: &mut dyn AsmCache,
+) -> Result<String> {
+    let key = cache_key(source, options);
+    if let Some(output) = cache.get(key) {
+        return Ok(output);
+    }
+    let output = assemble_with_options(source, options)?;
+    let _ = cache.put(key, &output);
+    Ok(output)
+}
+
+/// An in-memory cache counting its hits and misses.
+#[derive(Debug, Default)]
+pub struct MemoryCache {
+    entries: HashMap<u64, String>,
+    /// Lookups that found an entry.
+    pub hits: usize,
+    /// Lookups that found none.
+    pub misses: usize,
+}
+
+impl MemoryCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl AsmCache for MemoryCache {
+    fn get(&mut self, key: u64) -> Option<String> {
+        let output = self.entries.get(&key).cloned();
+        match output {
+            Some(_) => self.hits += 1,
+            None => self.misses += 1,
+        }
+        output
+    }
+
+    fn put(&mut self, key: u64, output: &str) -> io::Result<()> {
+        self.entries.insert(key, output.to_string());
+        Ok(())
+    }
+}
+
+/// A cache storing one file per entry in a directory.
+///
+/// Each entry is `<key>.hack` holding the output length on its first line,
+/// then the output. An entry that cannot be read or whose length does not
+/// match (e.g. truncated by an interrupted write) counts as a miss and is
+/// overwritten by the next store.
+#[derive(Debug)]
+pub struct DirCache {
+    dir: PathBuf,
+    /// Lookups that found a usable entry.
+    pub hits: usize,
+    /// Lookups that found none.
+    pub misses: usize,
+}
+
+impl DirCache {
+    /// A cache in `dir`, created on the first store.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    fn entry_path(&self, key: u64) -> PathBuf {
+        self.dir.join(format!("{key:016x}.hack"))
+    }
+
+    fn read_entry(&self, key: u64) -> Option<String> {
+        let entry = fs::read_to_string(self.entry_path(key)).ok()?;
+        let (len, output) = entry.split_once('\n')?;
+        (len.parse() == Ok(output.len())).then(|| output.to_string())
+    }
+}
+
+impl AsmCache for DirCache {
+    fn get(&mut self, key: u64) -> Option<String> {
+        let output = self.read_entry(key);
+        match output {
+            Some(_) => self.hits += 1,
+            None => self.misses += 1,
+        }
+        output
+    }
+
+    fn put(&mut self, key: u64, output: &str) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        // Write aside and rename, so readers never see a partial entry
+        let path = self.entry_path(key);
+        let partial = path.with_extension(format!("tmp{}", std::process::id()));
+        fs::write(&partial, format!("{}\n{}", output.len(), output))?;
+        fs::rename(&partial, &path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADD: &str = "@2\nD=A\n@3\nD=D+A\n@0\nM=D\n";
+
+    #[test]
+    fn test_memory_cache_hits_identical_source() {
+        let mut cache = MemoryCache::new();
+        let first = assemble_cached(ADD, &mut cache).unwrap();
+        let second = assemble_cached(ADD, &mut cache).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(first, crate::assemble(ADD).unwrap());
+        assert_eq!((cache.hits, cache.misses), (1, 1));
+    }
+
+    #[test]
+    fn test_changed_source_or_options_miss() {
+        let mut cache = MemoryCache::new();
+        assemble_cached(ADD, &mut cache).unwrap();
+        let changed = assemble_cached(&ADD.replace("@3", "@4"), &mut cache).unwrap();
+        assert_eq!(changed, crate::assemble(&ADD.replace("@3", "@4")).unwrap());
+
+        let lenient = AssembleOptions {
+            strict: false,
+            ..AssembleOptions::default()
+        };
+        assemble_cached_with_options(ADD, &lenient, &mut cache).unwrap();
+        assert_eq!((cache.hits, cache.misses), (0, 3));
+    }
+
+    #[test]
+    fn test_errors_not_cached() {
+        let mut cache = MemoryCache::new();
+        assert!(assemble_cached("D=Q\n", &mut cache).is_err());
+        assert!(assemble_cached("D=Q\n", &mut cache).is_err());
+        assert_eq!((cache.hits, cache.misses), (0, 2));
+    }
+
+    #[test]
+    fn test_dir_cache_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cache = DirCache::new(dir.path().join("cache"));
+        let first = assemble_cached(ADD, &mut cache).unwrap();
+
+        // A fresh cache on the same directory sees the entry
+        let mut cache = DirCache::new(dir.path().join("cache"));
+        assert_eq!(assemble_cached(ADD, &mut cache).unwrap(), first);
+        assert_eq!((cache.hits, cache.misses), (1, 0));
+    }
+
+    #[test]
+    fn test_dir_cache_recovers_from_truncated_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cache = DirCache::new(dir.path());
+        let expected = assemble_cached(ADD, &mut cache).unwrap();
+
+        let path = cache.entry_path(cache_key(ADD, &AssembleOptions::default()));
+        let entry = fs::read_to_string(&path).unwrap();
+        fs::write(&path, &entry[..entry.len() - 20]).unwrap();
+        assert_eq!(assemble_cached(ADD, &mut cache).unwrap(), expected);
+        assert_eq!((cache.hits, cache.misses), (0, 2));
+
+        // The entry was rewritten
+        assert_eq!(assemble_cached(ADD, &mut cache).unwrap(), expected);
+        assert_eq!(cache.hits, 1);
+    }
+}
@@ -0,0 +1,178 @@
+//! Input, output and exit-code conventions of the command-line assembler.
+//!
+//! Kept in the library, away from argument parsing, so build-system
+//! behaviour can be tested. An input or output of `-` is a standard stream,
+//! which makes the assembler usable as a filter:
+//!
+//! ```
+//! use hack_assembler::cli::{Stream, output_for};
+//!
+//! // hack-assembler - < prog.asm > prog.hack
+//! let input = Stream::from_arg("-");
+//! assert_eq!(output_for(&input, None), Stream::Standard);
+//!
+//! // hack-assembler prog.asm -o -
+//! let input = Stream::from_arg("prog.asm");
+//! assert_eq!(output_for(&input, Some(&Stream::from_arg("-"))), Stream::Standard);
+//! ```
+
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+
+/// A file, or a standard stream (`-`): stdin as an input, stdout as an
+/// output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stream {
+    Standard,
+    File(PathBuf),
+}
+
+impl Stream {
+    /// Parse a command-line argument: `-` is the standard stream.
+    pub fn from_arg(arg: &str) -> Self {
+        if arg == "-" {
+            Stream::Standard
+        } else {
+            Stream::File(PathBuf::from(arg))
+        }
+    }
+
+    /// Read all of this input: stdin, or the file.
+    pub fn read_to_string(&self) -> io::Result<String> {
+        match self {
+            Stream::Standard => {
+                let mut text = String::new();
+                io::stdin().read_to_string(&mut text)?;
+                Ok(text)
+            }
+            Stream::File(path) => fs::read_to_string(path),
+        }
+    }
+
+    /// Write `text` to this output: stdout, or the file (replacing it).
+    pub fn write(&self, text: &str) -> io::Result<()> {
+        match self {
+            Stream::Standard => {
+                let mut stdout = io::stdout().lock();
+                stdout.write_all(text.as_bytes())?;
+                stdout.flush()
+            }
+            Stream::File(path) => fs::write(path, text),
+        }
+    }
+}
+
+impl fmt::Display for Stream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stream::Standard => f.write_str("-"),
+            Stream::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// Where the output for `input` goes: `output` (the `-o` argument) if
+/// given, stdout for stdin, and otherwise the input path with a `.hack`
+/// extension.
+pub fn output_for(input: &Stream, output: Option<&Stream>) -> Stream {
+    match (output, input) {
+        (Some(output), _) => output.clone(),
+        (None, Stream::Standard) => Stream::Standard,
+        (None, Stream::File(path)) => Stream::File(path.with_extension("hack")),
+    }
+}
+
+/// How a run ended, ordered by severity; [`code`](Self::code) is the exit
+/// code.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Outcome {
+    /// Every input assembled (and matched its reference). Exit code 0.
+    #[default]
+    Success,
+    /// An input failed to assemble or mismatched its reference. Exit code 1.
+    AssemblyError,
+    /// Bad arguments, or an input or output that could not be read or
+    /// written. Exit code 2.
+    UsageOrIoError,
+}
+
+impl Outcome {
+    /// The process exit code.
+    pub fn code(self) -> i32 {
+        match self {
+            Outcome::Success => 0,
+            Outcome::AssemblyError => 1,
+            Outcome::UsageOrIoError => 2,
+        }
+    }
+
+    /// The more severe of two outcomes, for a run over several inputs.
+    pub fn worst(self, other: Outcome) -> Outcome {
+        self.max(other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_arg() {
+        assert_eq!(Stream::from_arg("-"), Stream::Standard);
+        assert_eq!(
+            Stream::from_arg("Add.asm"),
+            Stream::File(PathBuf::from("Add.asm"))
+        );
+        // Only a lone dash is a stream
+        assert_eq!(Stream::from_arg("-x"), Stream::File(PathBuf::from("-x")));
+        assert_eq!(Stream::from_arg("./-").to_string(), "./-");
+    }
+
+    #[test]
+    fn test_output_defaults_to_hack_extension() {
+        let input = Stream::from_arg("dir/Max.asm");
+        assert_eq!(output_for(&input, None), Stream::from_arg("dir/Max.hack"));
+        let input = Stream::from_arg("Prog");
+        assert_eq!(output_for(&input, None), Stream::from_arg("Prog.hack"));
+        assert_eq!(output_for(&Stream::Standard, None), Stream::Standard);
+    }
+
+    #[test]
+    fn test_output_flag_overrides_default() {
+        let explicit = Stream::from_arg("build/out.hack");
+        assert_eq!(
+            output_for(&Stream::from_arg("Max.asm"), Some(&explicit)),
+            explicit
+        );
+        assert_eq!(output_for(&Stream::Standard, Some(&explicit)), explicit);
+        assert_eq!(
+            output_for(&Stream::from_arg("Max.asm"), Some(&Stream::Standard)),
+            Stream::Standard
+        );
+    }
+
+    #[test]
+    fn test_file_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = Stream::File(dir.path().join("Add.hack"));
+        output.write("0000000000000010").unwrap();
+        assert_eq!(output.read_to_string().unwrap(), "0000000000000010");
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        assert_eq!(Outcome::default().code(), 0);
+        assert_eq!(Outcome::AssemblyError.code(), 1);
+        assert_eq!(Outcome::UsageOrIoError.code(), 2);
+        assert_eq!(
+            Outcome::Success.worst(Outcome::AssemblyError),
+            Outcome::AssemblyError
+        );
+        assert_eq!(
+            Outcome::UsageOrIoError.worst(Outcome::AssemblyError),
+            Outcome::UsageOrIoError
+        );
+    }
+}
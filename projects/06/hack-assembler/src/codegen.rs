@@ -1,10 +1,10 @@
-use crate::parser::ResolvedInstruction;
+use crate::instruction::{Comp, Dest, Jump, ResolvedInstruction, encode};
 
 /// Zero-cost extension point for different output formats
 /// Now uses a buffer-based approach for zero allocations
 pub trait Backend {
     fn encode_a(&self, value: u16, buf: &mut String);
-    fn encode_c(&self, dest: u8, comp: u8, jump: u8, buf: &mut String);
+    fn encode_c(&self, dest: Dest, comp: Comp, jump: Jump, buf: &mut String);
 }
 
 /// Hack binary format (15-bit addresses, 16-bit instructions)
@@ -12,20 +12,24 @@ pub struct HackBinary;
 
 impl Backend for HackBinary {
     fn encode_a(&self, value: u16, buf: &mut String) {
-        let value = value & 0x7FFF; // 15-bit address
-        // Manual bit manipulation - cannot fail, zero allocations, no unwrap
-        for i in (0..16).rev() {
-            buf.push(if value & (1 << i) != 0 { '1' } else { '0' });
-        }
+        push_bits(encode(ResolvedInstruction::A(value)), buf);
     }
 
-    fn encode_c(&self, dest: u8, comp: u8, jump: u8, buf: &mut String) {
-        let word =
-            0b1110_0000_0000_0000 | ((comp as u16) << 6) | ((dest as u16) << 3) | (jump as u16);
-        // Manual bit manipulation - cannot fail, zero allocations, no unwrap
-        for i in (0..16).rev() {
-            buf.push(if word & (1 << i) != 0 { '1' } else { '0' });
-        }
+    fn encode_c(&self, dest: Dest, comp: Comp, jump: Jump, buf: &mut String) {
+        push_bits(encode(ResolvedInstruction::C { dest, comp, jump }), buf);
+    }
+}
+
+/// Encode an instruction as its 16-bit Hack machine word
+pub fn hack_word(inst: &ResolvedInstruction) -> u16 {
+    encode(*inst)
+}
+
+/// Append `word` as 16 binary digits.
+fn push_bits(word: u16, buf: &mut String) {
+    // Manual bit manipulation - cannot fail, zero allocations, no unwrap
+    for i in (0..16).rev() {
+        buf.push(if word & (1 << i) != 0 { '1' } else { '0' });
     }
 }
 
@@ -42,8 +46,8 @@ impl<B: Backend> CodeGen<B> {
     /// Encode instruction to buffer (zero-allocation design)
     pub fn encode(&self, inst: &ResolvedInstruction, buf: &mut String) {
         match inst {
-            ResolvedInstruction::AValue(value) => self.backend.encode_a(*value, buf),
-            ResolvedInstruction::CInstruction { dest, comp, jump } => {
+            ResolvedInstruction::A(value) => self.backend.encode_a(*value, buf),
+            ResolvedInstruction::C { dest, comp, jump } => {
                 self.backend.encode_c(*dest, *comp, *jump, buf)
             }
         }
@@ -68,15 +72,15 @@ mod tests {
         let codegen = HackCodeGen::hack();
         let mut buf = String::new();
 
-        codegen.encode(&ResolvedInstruction::AValue(0), &mut buf);
+        codegen.encode(&ResolvedInstruction::A(0), &mut buf);
         assert_eq!(buf, "0000000000000000");
 
         buf.clear();
-        codegen.encode(&ResolvedInstruction::AValue(17), &mut buf);
+        codegen.encode(&ResolvedInstruction::A(17), &mut buf);
         assert_eq!(buf, "0000000000010001");
 
         buf.clear();
-        codegen.encode(&ResolvedInstruction::AValue(32767), &mut buf);
+        codegen.encode(&ResolvedInstruction::A(32767), &mut buf);
         assert_eq!(buf, "0111111111111111");
     }
 
@@ -86,32 +90,51 @@ mod tests {
         let mut buf = String::new();
 
         // D=M
-        let inst = ResolvedInstruction::CInstruction {
-            dest: 0b010,     // D
-            comp: 0b1110000, // M
-            jump: 0b000,     // no jump
+        let inst = ResolvedInstruction::C {
+            dest: Dest::D,
+            comp: Comp::M,
+            jump: Jump::Null,
         };
         codegen.encode(&inst, &mut buf);
         assert_eq!(buf, "1111110000010000");
 
         buf.clear();
         // D;JGT
-        let inst = ResolvedInstruction::CInstruction {
-            dest: 0b000,
-            comp: 0b0001100, // D
-            jump: 0b001,     // JGT
+        let inst = ResolvedInstruction::C {
+            dest: Dest::Null,
+            comp: Comp::D,
+            jump: Jump::JGT,
         };
         codegen.encode(&inst, &mut buf);
         assert_eq!(buf, "1110001100000001");
 
         buf.clear();
         // MD=D+1;JMP
-        let inst = ResolvedInstruction::CInstruction {
-            dest: 0b011,     // MD
-            comp: 0b0011111, // D+1
-            jump: 0b111,     // JMP
+        let inst = ResolvedInstruction::C {
+            dest: Dest::MD,
+            comp: Comp::DPlusOne,
+            jump: Jump::JMP,
         };
         codegen.encode(&inst, &mut buf);
         assert_eq!(buf, "1110011111011111");
+        assert_eq!(hack_word(&inst), 0b1110011111011111);
+    }
+
+    #[test]
+    fn test_hack_word_matches_text() {
+        let codegen = HackCodeGen::hack();
+        for inst in [
+            ResolvedInstruction::A(0xFFFF),
+            ResolvedInstruction::A(256),
+            ResolvedInstruction::C {
+                dest: Dest::D,
+                comp: Comp::M,
+                jump: Jump::Null,
+            },
+        ] {
+            let mut buf = String::new();
+            codegen.encode(&inst, &mut buf);
+            assert_eq!(format!("{:016b}", hack_word(&inst)), buf);
+        }
     }
 }
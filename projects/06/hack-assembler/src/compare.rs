@@ -0,0 +1,150 @@
+//! Line-by-line comparison of assembler output against a reference `.hack` file.
+
+use std::fmt;
+
+use crate::error::Result;
+use crate::{AssembleOptions, assemble_with_source_map_and_options};
+
+/// Placeholder shown when one side has no instruction at a given line.
+const MISSING: &str = "<missing>";
+
+/// A single differing line between our output and the reference.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    /// 1-based line in the `.hack` output (ROM address + 1).
+    pub line: usize,
+    /// Our encoding, or `None` if we emitted fewer instructions.
+    pub ours: Option<String>,
+    /// Reference encoding, or `None` if the reference is shorter.
+    pub reference: Option<String>,
+    /// 1-based `.asm` line that produced our instruction.
+    pub source_line: Option<usize>,
+    /// Trimmed text of that `.asm` line.
+    pub source: Option<String>,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}: ours={} ref={}",
+            self.line,
+            self.ours.as_deref().unwrap_or(MISSING),
+            self.reference.as_deref().unwrap_or(MISSING)
+        )?;
+        if let (Some(line), Some(source)) = (self.source_line, &self.source) {
+            write!(f, " (source: line {}: {})", line, source)?;
+        }
+        Ok(())
+    }
+}
+
+/// Result of comparing assembled output with a reference file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Comparison {
+    /// All differing lines, in output order.
+    pub mismatches: Vec<Mismatch>,
+    /// Number of instructions we emitted.
+    pub ours_len: usize,
+    /// Number of instructions in the reference.
+    pub reference_len: usize,
+}
+
+impl Comparison {
+    /// Whether the output matches the reference exactly.
+    pub fn is_match(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+/// Assemble `source` and compare the result with `reference` line by line.
+///
+/// Blank lines and surrounding whitespace in the reference are ignored.
+pub fn compare_with_reference(source: &str, reference: &str) -> Result<Comparison> {
+    compare_with_options(source, reference, &AssembleOptions::default())
+}
+
+/// [`compare_with_reference`], assembling `source` with the given options.
+pub fn compare_with_options(
+    source: &str,
+    reference: &str,
+    options: &AssembleOptions,
+) -> Result<Comparison> {
+    let (output, source_map) = assemble_with_source_map_and_options(source, options)?;
+    let source_lines: Vec<&str> = source.lines().collect();
+
+    let ours: Vec<&str> = output.lines().collect();
+    let theirs: Vec<&str> = reference
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect();
+
+    let mut mismatches = Vec::new();
+    for i in 0..ours.len().max(theirs.len()) {
+        let (a, b) = (ours.get(i).copied(), theirs.get(i).copied());
+        if a == b {
+            continue;
+        }
+        let source_line = source_map.get(i).copied();
+        mismatches.push(Mismatch {
+            line: i + 1,
+            ours: a.map(str::to_string),
+            reference: b.map(str::to_string),
+            source_line,
+            source: source_line
+                .and_then(|n| source_lines.get(n - 1))
+                .map(|text| text.trim().to_string()),
+        });
+    }
+
+    Ok(Comparison {
+        mismatches,
+        ours_len: ours.len(),
+        reference_len: theirs.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "@2\nD=A\n// comment\n@3\nD=D+A\n";
+
+    #[test]
+    fn test_identical_output() {
+        let reference = crate::assemble(SOURCE).unwrap();
+        let comparison = compare_with_reference(SOURCE, &reference).unwrap();
+        assert!(comparison.is_match());
+        assert_eq!(comparison.ours_len, 4);
+        assert_eq!(comparison.reference_len, 4);
+    }
+
+    #[test]
+    fn test_single_bit_difference() {
+        let reference = "0000000000000010\n1110110000010000\n0000000000000111\n1110000010010000\n";
+        let comparison = compare_with_reference(SOURCE, reference).unwrap();
+        assert_eq!(comparison.mismatches.len(), 1);
+
+        let mismatch = &comparison.mismatches[0];
+        assert_eq!(mismatch.line, 3);
+        assert_eq!(mismatch.source_line, Some(4));
+        assert_eq!(
+            mismatch.to_string(),
+            "line 3: ours=0000000000000011 ref=0000000000000111 (source: line 4: @3)"
+        );
+    }
+
+    #[test]
+    fn test_length_difference() {
+        let reference = "0000000000000010\n1110110000010000\n";
+        let comparison = compare_with_reference(SOURCE, reference).unwrap();
+        assert_eq!(comparison.mismatches.len(), 2);
+        assert_eq!(comparison.mismatches[0].reference, None);
+        assert!(
+            comparison.mismatches[0]
+                .to_string()
+                .contains("ref=<missing>")
+        );
+    }
+}
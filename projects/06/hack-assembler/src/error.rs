@@ -1,5 +1,7 @@
 use thiserror::Error;
 
+use crate::parser::Direction;
+
 #[derive(Error, Debug)]
 pub enum AsmError {
     #[error("line {line}: invalid A-instruction value: {value}")]
@@ -17,11 +19,75 @@ pub enum AsmError {
     #[error("line {line}: invalid comp field: {comp}")]
     InvalidComp { line: usize, comp: String },
 
+    #[error("line {line}: missing computation in C-instruction: {text}")]
+    MissingComp { line: usize, text: String },
+
+    #[error("line {line}: missing destination before '=': {text}")]
+    MissingDest { line: usize, text: String },
+
+    #[error("line {line}: missing jump after ';': {text}")]
+    MissingJump { line: usize, text: String },
+
     #[error("line {line}: invalid jump field: {jump}")]
     InvalidJump { line: usize, jump: String },
 
+    #[error("line {line}, column {column}: unexpected text after instruction: {text}")]
+    TrailingText {
+        line: usize,
+        column: usize,
+        text: String,
+    },
+
+    #[error("line {line}: unknown directive: {directive}")]
+    UnknownDirective { line: usize, directive: String },
+
+    #[error("line {line}: {} has no {} anonymous label", .direction.syntax(), .direction.as_str())]
+    DanglingAnonymousLabel { line: usize, direction: Direction },
+
+    #[error("invalid instruction word: {word:016b}")]
+    InvalidWord { word: u16 },
+
+    #[cfg(feature = "vm")]
+    #[error("VM translation error: {0}")]
+    Translate(#[from] vm_translator::VMError),
+
     #[error("I/O error: {0}")]
     Io(#[from] std::io::Error),
 }
 
 pub type Result<T> = std::result::Result<T, AsmError>;
+
+/// A failed stage of [`build_rom_from_jack_dir`](crate::toolchain::build_rom_from_jack_dir).
+#[cfg(feature = "jack")]
+#[derive(Error, Debug)]
+pub enum PipelineError {
+    #[error("reading {path}: {source}")]
+    Read {
+        path: String,
+        source: std::io::Error,
+    },
+
+    #[error("no .jack files found in {path}")]
+    NoJackFiles { path: String },
+
+    #[error("compiling {file}: {}", join_errors(.errors))]
+    Compile {
+        file: String,
+        errors: Vec<jack_compiler::CompileError>,
+    },
+
+    #[error("translating: {0}")]
+    Translate(#[from] vm_translator::VMError),
+
+    #[error("assembling: {0}")]
+    Assemble(#[from] AsmError),
+}
+
+#[cfg(feature = "jack")]
+fn join_errors(errors: &[jack_compiler::CompileError]) -> String {
+    errors
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
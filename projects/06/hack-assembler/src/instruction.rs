@@ -0,0 +1,284 @@
+//! Resolved Hack instructions and their 16-bit encoding.
+//!
+//! This module holds the only copy of the dest/comp/jump tables: the parser
+//! reads mnemonics through them and the code generator encodes through
+//! [`encode`]. Emulators can use [`decode`] to turn ROM words back into
+//! instructions:
+//!
+//! ```
+//! use hack_assembler::instruction::{Comp, Dest, Jump, ResolvedInstruction, decode, encode};
+//!
+//! let inst = ResolvedInstruction::C { dest: Dest::D, comp: Comp::DPlusM, jump: Jump::Null };
+//! assert_eq!(encode(inst), 0b1111_0000_1001_0000);
+//! assert_eq!(decode(0b1111_0000_1001_0000).unwrap(), inst);
+//! assert_eq!(inst.to_string(), "D=D+M");
+//! ```
+
+use std::fmt;
+
+use crate::error::{AsmError, Result};
+
+/// Define a C-instruction field: an enum with its bit pattern and mnemonics.
+///
+/// The first mnemonic of a variant is canonical; the rest are accepted
+/// aliases (operand orders).
+macro_rules! field {
+    (
+        $(#[$meta:meta])*
+        $name:ident {
+            $($variant:ident = $bits:literal, $mnemonic:literal $(| $alias:literal)*;)*
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+        pub enum $name {
+            $($variant,)*
+        }
+
+        impl $name {
+            /// Every value, in table order.
+            pub const ALL: &[$name] = &[$($name::$variant,)*];
+
+            /// The field's bits in a C-instruction word.
+            pub const fn bits(self) -> u8 {
+                match self {
+                    $($name::$variant => $bits,)*
+                }
+            }
+
+            /// The value with the given bits, if any.
+            pub const fn from_bits(bits: u8) -> Option<Self> {
+                match bits {
+                    $($bits => Some($name::$variant),)*
+                    _ => None,
+                }
+            }
+
+            /// The canonical assembly mnemonic (empty for a missing field).
+            pub const fn mnemonic(self) -> &'static str {
+                match self {
+                    $($name::$variant => $mnemonic,)*
+                }
+            }
+
+            /// Parse a mnemonic, accepting operand-order aliases.
+            pub fn from_mnemonic(s: &str) -> Option<Self> {
+                match s {
+                    $($mnemonic $(| $alias)* => Some($name::$variant),)*
+                    _ => None,
+                }
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(self.mnemonic())
+            }
+        }
+    };
+}
+
+field! {
+    /// Destination registers (3 bits: A D M).
+    Dest {
+        Null = 0b000, "";
+        M = 0b001, "M";
+        D = 0b010, "D";
+        MD = 0b011, "MD" | "DM";
+        A = 0b100, "A";
+        AM = 0b101, "AM" | "MA";
+        AD = 0b110, "AD" | "DA";
+        AMD = 0b111, "AMD" | "ADM" | "MAD" | "MDA" | "DAM" | "DMA";
+    }
+}
+
+field! {
+    /// ALU computation (7 bits: the `a` bit selects M instead of A).
+    Comp {
+        Zero = 0b0101010, "0";
+        One = 0b0111111, "1";
+        MinusOne = 0b0111010, "-1";
+        D = 0b0001100, "D";
+        NotD = 0b0001101, "!D";
+        NegD = 0b0001111, "-D";
+        DPlusOne = 0b0011111, "D+1" | "1+D";
+        DMinusOne = 0b0001110, "D-1";
+        A = 0b0110000, "A";
+        NotA = 0b0110001, "!A";
+        NegA = 0b0110011, "-A";
+        APlusOne = 0b0110111, "A+1" | "1+A";
+        AMinusOne = 0b0110010, "A-1";
+        DPlusA = 0b0000010, "D+A" | "A+D";
+        DMinusA = 0b0010011, "D-A";
+        AMinusD = 0b0000111, "A-D";
+        DAndA = 0b0000000, "D&A" | "A&D";
+        DOrA = 0b0010101, "D|A" | "A|D";
+        M = 0b1110000, "M";
+        NotM = 0b1110001, "!M";
+        NegM = 0b1110011, "-M";
+        MPlusOne = 0b1110111, "M+1" | "1+M";
+        MMinusOne = 0b1110010, "M-1";
+        DPlusM = 0b1000010, "D+M" | "M+D";
+        DMinusM = 0b1010011, "D-M";
+        MMinusD = 0b1000111, "M-D";
+        DAndM = 0b1000000, "D&M" | "M&D";
+        DOrM = 0b1010101, "D|M" | "M|D";
+    }
+}
+
+field! {
+    /// Jump condition (3 bits: lt eq gt).
+    Jump {
+        Null = 0b000, "";
+        JGT = 0b001, "JGT";
+        JEQ = 0b010, "JEQ";
+        JGE = 0b011, "JGE";
+        JLT = 0b100, "JLT";
+        JNE = 0b101, "JNE";
+        JLE = 0b110, "JLE";
+        JMP = 0b111, "JMP";
+    }
+}
+
+/// Resolved instruction with all symbols converted to addresses.
+///
+/// This type makes it impossible to have unresolved symbols at codegen time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ResolvedInstruction {
+    /// `@value` (15 bits; higher bits are dropped when encoding)
+    A(u16),
+    /// `dest=comp;jump`
+    C { dest: Dest, comp: Comp, jump: Jump },
+}
+
+impl fmt::Display for ResolvedInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ResolvedInstruction::A(value) => write!(f, "@{value}"),
+            ResolvedInstruction::C { dest, comp, jump } => {
+                if dest != Dest::Null {
+                    write!(f, "{dest}=")?;
+                }
+                write!(f, "{comp}")?;
+                if jump != Jump::Null {
+                    write!(f, ";{jump}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Prefix bits of a C-instruction word.
+const C_PREFIX: u16 = 0b111 << 13;
+
+/// Encode an instruction as its 16-bit Hack machine word.
+pub fn encode(inst: ResolvedInstruction) -> u16 {
+    match inst {
+        ResolvedInstruction::A(value) => value & 0x7FFF, // 15-bit address
+        ResolvedInstruction::C { dest, comp, jump } => {
+            C_PREFIX
+                | (u16::from(comp.bits()) << 6)
+                | (u16::from(dest.bits()) << 3)
+                | u16::from(jump.bits())
+        }
+    }
+}
+
+/// Decode a 16-bit Hack machine word.
+///
+/// Fails with [`AsmError::InvalidWord`] for a C-instruction whose two unused
+/// bits are not set or whose comp bits name no computation.
+pub fn decode(word: u16) -> Result<ResolvedInstruction> {
+    if word & 0x8000 == 0 {
+        return Ok(ResolvedInstruction::A(word));
+    }
+    if word & C_PREFIX != C_PREFIX {
+        return Err(AsmError::InvalidWord { word });
+    }
+    let comp = Comp::from_bits(((word >> 6) & 0x7F) as u8).ok_or(AsmError::InvalidWord { word })?;
+    let dest =
+        Dest::from_bits(((word >> 3) & 0b111) as u8).ok_or(AsmError::InvalidWord { word })?;
+    let jump = Jump::from_bits((word & 0b111) as u8).ok_or(AsmError::InvalidWord { word })?;
+    Ok(ResolvedInstruction::C { dest, comp, jump })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_every_c_instruction() {
+        let mut words = std::collections::HashSet::new();
+        for &dest in Dest::ALL {
+            for &comp in Comp::ALL {
+                for &jump in Jump::ALL {
+                    let inst = ResolvedInstruction::C { dest, comp, jump };
+                    let word = encode(inst);
+                    assert_eq!(decode(word).unwrap(), inst, "{word:016b}");
+                    words.insert(word);
+                }
+            }
+        }
+        assert_eq!(words.len(), 8 * 28 * 8);
+    }
+
+    #[test]
+    fn test_round_trip_every_a_instruction() {
+        for value in 0..=0x7FFF {
+            assert_eq!(
+                decode(encode(ResolvedInstruction::A(value))).unwrap(),
+                ResolvedInstruction::A(value)
+            );
+        }
+        assert_eq!(encode(ResolvedInstruction::A(0xFFFF)), 0x7FFF);
+    }
+
+    #[test]
+    fn test_mnemonics_round_trip() {
+        for &comp in Comp::ALL {
+            assert_eq!(Comp::from_mnemonic(comp.mnemonic()), Some(comp));
+        }
+        for &dest in Dest::ALL {
+            assert_eq!(Dest::from_mnemonic(dest.mnemonic()), Some(dest));
+        }
+        for &jump in Jump::ALL {
+            assert_eq!(Jump::from_mnemonic(jump.mnemonic()), Some(jump));
+        }
+        assert_eq!(Dest::from_mnemonic("DMA"), Some(Dest::AMD));
+        assert_eq!(Comp::from_mnemonic("M+D"), Some(Comp::DPlusM));
+        assert_eq!(Comp::from_mnemonic("D+D"), None);
+    }
+
+    #[test]
+    fn test_decode_rejects_invalid_words() {
+        // Unused bits clear
+        assert!(matches!(
+            decode(0b1000_1100_0001_0000),
+            Err(AsmError::InvalidWord {
+                word: 0b1000_1100_0001_0000
+            })
+        ));
+        // Comp bits 0b0000001 name no computation
+        assert!(decode(0b1110_0000_0100_0000).is_err());
+    }
+
+    #[test]
+    fn test_display() {
+        let inst = ResolvedInstruction::C {
+            dest: Dest::AMD,
+            comp: Comp::MMinusOne,
+            jump: Jump::JNE,
+        };
+        assert_eq!(inst.to_string(), "AMD=M-1;JNE");
+        let inst = ResolvedInstruction::C {
+            dest: Dest::Null,
+            comp: Comp::Zero,
+            jump: Jump::JMP,
+        };
+        assert_eq!(inst.to_string(), "0;JMP");
+        assert_eq!(ResolvedInstruction::A(21).to_string(), "@21");
+    }
+}
@@ -1,39 +1,118 @@
+pub mod cache;
+pub mod cli;
 pub mod codegen;
+pub mod compare;
 pub mod error;
+pub mod instruction;
 pub mod parser;
+pub mod stage;
 pub mod symbols;
+pub mod toolchain;
 
 use codegen::HackCodeGen;
 use error::{AsmError, Result};
-use parser::{Instruction, Line, parse_line};
+use parser::{Direction, Instruction, Line, parse_line_borrowed};
 use symbols::SymbolTable;
 
+pub use cache::{AsmCache, DirCache, MemoryCache, assemble_cached};
+pub use instruction::{ResolvedInstruction, decode, encode};
+
+/// Assembler options. Every syntax extension is off by default.
+#[derive(Debug, Clone, Copy)]
+pub struct AssembleOptions {
+    /// Accept anonymous labels: `(:)` defines one, `@:+` / `@:-` reference
+    /// the next / previous one relative to the current instruction.
+    pub anonymous_labels: bool,
+    /// Reject text after an instruction or label ([`AsmError::TrailingText`])
+    /// and lines starting with `.` ([`AsmError::UnknownDirective`]). On by
+    /// default; when off, `@foo bar` loads a symbol named `foo bar`.
+    pub strict: bool,
+}
+
+impl Default for AssembleOptions {
+    fn default() -> Self {
+        Self {
+            anonymous_labels: false,
+            strict: true,
+        }
+    }
+}
+
 /// Assemble Hack assembly source to binary
 pub fn assemble(source: &str) -> Result<String> {
-    let lines: Vec<&str> = source.lines().collect();
+    assemble_with_options(source, &AssembleOptions::default())
+}
+
+/// Assemble Hack assembly source to binary with the given options
+pub fn assemble_with_options(source: &str, options: &AssembleOptions) -> Result<String> {
+    assemble_with_source_map_and_options(source, options).map(|(output, _)| output)
+}
+
+/// Assemble Hack assembly source to binary, also returning the 1-based
+/// source line of each emitted instruction (indexed by ROM address).
+pub fn assemble_with_source_map(source: &str) -> Result<(String, Vec<usize>)> {
+    assemble_with_source_map_and_options(source, &AssembleOptions::default())
+}
+
+/// [`assemble_with_source_map`] with the given options
+pub fn assemble_with_source_map_and_options(
+    source: &str,
+    options: &AssembleOptions,
+) -> Result<(String, Vec<usize>)> {
+    let (instructions, source_map) = resolve_program(source, options)?;
+
+    // Pre-allocate output (16 digits and a newline per instruction)
+    let mut output = String::with_capacity(instructions.len() * 17);
+    let codegen = HackCodeGen::hack();
+    for inst in &instructions {
+        // Zero-allocation encoding: write directly to output buffer
+        codegen.encode(inst, &mut output);
+        output.push('\n');
+    }
 
-    // Pre-allocate output (estimate ~16 chars per line)
-    let mut output = String::with_capacity(lines.len() * 17);
+    Ok((output.trim_end().to_string(), source_map))
+}
+
+/// Parse Hack assembly and resolve every symbol, without encoding.
+///
+/// Returns the instructions in ROM order; [`encode`] turns each into its
+/// machine word.
+pub fn parse_program(source: &str) -> Result<Vec<ResolvedInstruction>> {
+    resolve_program(source, &AssembleOptions::default()).map(|(instructions, _)| instructions)
+}
+
+/// Parse `source` and resolve every symbol.
+///
+/// Returns the instructions in ROM order and the 1-based source line of each.
+pub(crate) fn resolve_program(
+    source: &str,
+    options: &AssembleOptions,
+) -> Result<(Vec<ResolvedInstruction>, Vec<usize>)> {
+    let lines: Vec<&str> = source.lines().collect();
 
     // Pass 1: Parse and build symbol table
     let mut symbol_table = SymbolTable::new();
     let mut parsed_lines = Vec::with_capacity(lines.len());
+    let mut source_map = Vec::with_capacity(lines.len());
+    let mut anonymous_labels = Vec::new(); // ROM addresses, ascending
     let mut rom_address = 0u16;
 
     for (line_num, line) in lines.iter().enumerate() {
-        let parsed = parse_line(line, line_num + 1)?;
+        let parsed = parse_line_borrowed(line, line_num + 1, options)?;
 
-        match &parsed {
+        match parsed {
             Line::Label(label) => {
-                symbol_table
-                    .add_label(label.clone(), rom_address)
-                    .map_err(|dup| AsmError::DuplicateLabel {
+                symbol_table.add_label(label, rom_address).map_err(|dup| {
+                    AsmError::DuplicateLabel {
                         line: line_num + 1,
                         label: dup,
-                    })?;
+                    }
+                })?;
             }
+            Line::AnonymousLabel => anonymous_labels.push(rom_address),
             Line::Instruction(_) => {
                 rom_address += 1;
+                source_map.push(line_num + 1);
             }
             Line::Empty => {}
         }
@@ -41,31 +120,50 @@ pub fn assemble(source: &str) -> Result<String> {
         parsed_lines.push(parsed);
     }
 
-    // Pass 2: Resolve symbols and generate code
-    let codegen = HackCodeGen::hack();
-
-    for parsed in parsed_lines.iter() {
+    // Pass 2: Resolve symbols to addresses
+    let mut instructions = Vec::with_capacity(source_map.len());
+    for (rom, parsed) in parsed_lines
+        .iter()
+        .filter(|p| matches!(p, Line::Instruction(_)))
+        .enumerate()
+    {
         match parsed {
             Line::Instruction(inst) => {
-                // Resolve symbols to addresses
                 let resolved = match inst {
                     Instruction::ASymbol(symbol) => {
-                        let addr = symbol_table.get_or_allocate(symbol);
+                        let addr = symbol_table.get_or_allocate(*symbol);
+                        inst.clone().resolve(addr)
+                    }
+                    Instruction::AAnonymous(direction) => {
+                        let addr = resolve_anonymous(&anonymous_labels, rom as u16, *direction)
+                            .ok_or(AsmError::DanglingAnonymousLabel {
+                                line: source_map[rom],
+                                direction: *direction,
+                            })?;
                         inst.clone().resolve(addr)
                     }
                     Instruction::AValue(v) => inst.clone().resolve(*v),
                     Instruction::CInstruction { .. } => inst.clone().resolve(0), // addr unused for C-instructions
                 };
-
-                // Zero-allocation encoding: write directly to output buffer
-                codegen.encode(&resolved, &mut output);
-                output.push('\n');
+                instructions.push(resolved);
             }
-            Line::Label(_) | Line::Empty => {}
+            Line::Label(_) | Line::AnonymousLabel | Line::Empty => {}
         }
     }
 
-    Ok(output.trim_end().to_string())
+    Ok((instructions, source_map))
+}
+
+/// Find the anonymous label nearest to the instruction at `rom`.
+///
+/// A label defined before the instruction has an address `<= rom`; one
+/// defined after it has an address `> rom`.
+fn resolve_anonymous(labels: &[u16], rom: u16, direction: Direction) -> Option<u16> {
+    let after = labels.partition_point(|&addr| addr <= rom);
+    match direction {
+        Direction::Forward => labels.get(after).copied(),
+        Direction::Backward => after.checked_sub(1).map(|i| labels[i]),
+    }
 }
 
 #[cfg(test)]
@@ -184,6 +282,94 @@ mod tests {
         assert_eq!(lines.len(), 2); // Only 2 instructions
     }
 
+    #[test]
+    fn test_source_map() {
+        let source = "// header\n@2\n(LOOP)\nD=A\n\n@LOOP\n";
+        let (output, source_map) = assemble_with_source_map(source).unwrap();
+        assert_eq!(output.lines().count(), 3);
+        assert_eq!(source_map, vec![2, 4, 6]);
+    }
+
+    const ANONYMOUS: AssembleOptions = AssembleOptions {
+        anonymous_labels: true,
+        strict: true,
+    };
+
+    #[test]
+    fn test_anonymous_labels_nested_loops() {
+        let anonymous = r#"
+            @i
+            M=0
+        (OUTER)
+            @j
+            M=0
+        (:)
+            @j
+            M=M+1
+            D=M
+            @2
+            D=D-A
+            @:-
+            D;JLT
+            @i
+            M=M+1
+            D=M
+            @3
+            D=D-A
+            @:+
+            D;JGE
+            @OUTER
+            0;JMP
+        (:)
+            @:-
+            0;JMP
+        "#;
+        let named = anonymous
+            .replacen("(:)", "(INNER)", 1)
+            .replacen("@:-", "@INNER", 1)
+            .replacen("@:+", "@DONE", 1)
+            .replacen("(:)", "(DONE)", 1)
+            .replacen("@:-", "@DONE", 1);
+
+        let output = assemble_with_options(anonymous, &ANONYMOUS).unwrap();
+        assert_eq!(output, assemble(&named).unwrap());
+
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[9], "0000000000000100"); // @:- -> inner loop at 4
+        assert_eq!(lines[16], "0000000000010100"); // @:+ -> done at 20
+        assert_eq!(lines[20], "0000000000010100"); // @:- on the label's own address
+    }
+
+    #[test]
+    fn test_dangling_anonymous_references() {
+        for (source, line, direction) in [
+            ("@:-\n0;JMP\n(:)\n", 1, Direction::Backward),
+            ("(:)\n@0\n@:+\n0;JMP\n", 3, Direction::Forward),
+            ("@:+\n", 1, Direction::Forward),
+        ] {
+            match assemble_with_options(source, &ANONYMOUS).unwrap_err() {
+                AsmError::DanglingAnonymousLabel {
+                    line: l,
+                    direction: d,
+                } => {
+                    assert_eq!((l, d), (line, direction), "{source:?}");
+                }
+                other => panic!("unexpected error: {other}"),
+            }
+        }
+
+        let err = assemble_with_options("@:+\n", &ANONYMOUS).unwrap_err();
+        assert_eq!(err.to_string(), "line 1: @:+ has no next anonymous label");
+    }
+
+    #[test]
+    fn test_anonymous_labels_off_by_default() {
+        // `(:)` is an ordinary label and `@:+` an ordinary variable
+        let output = assemble("(:)\n@:+\n@:\n").unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines, vec!["0000000000010000", "0000000000000000"]);
+    }
+
     #[test]
     fn test_duplicate_label_error() {
         let source = r#"
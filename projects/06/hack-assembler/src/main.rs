@@ -1,10 +1,23 @@
 use std::env;
 use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
 use std::process;
-use std::time::Instant;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
-use hack_assembler::assemble;
+use hack_assembler::cache::assemble_cached_with_options;
+use hack_assembler::cli::{Outcome, Stream, output_for};
+use hack_assembler::compare::compare_with_options;
+use hack_assembler::{AssembleOptions, DirCache, assemble_with_options};
+#[cfg(feature = "mmap")]
+use vm_translator::with_source;
+
+/// Default number of mismatches printed by `--compare`.
+const DEFAULT_MAX_MISMATCHES: usize = 10;
+
+/// Polling interval for `--watch`.
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
 
 fn print_usage() {
     eprintln!("Hack Assembler v{}", env!("CARGO_PKG_VERSION"));
@@ -12,93 +25,323 @@ fn print_usage() {
     eprintln!("USAGE:");
     eprintln!("    hack-assembler <file.asm> [options]");
     eprintln!("    hack-assembler <file1.asm> <file2.asm> ... [options]");
+    eprintln!("    hack-assembler - < prog.asm > prog.hack");
     eprintln!();
     eprintln!("OPTIONS:");
-    eprintln!("    -v, --verbose    Show detailed output");
-    eprintln!("    -h, --help       Show this help message");
+    eprintln!("    -o, --output <PATH>      Write the output to PATH (- for stdout)");
+    eprintln!("    -v, --verbose            Show detailed output");
+    eprintln!("    --compare <ref.hack>     Diff output against a reference .hack file");
+    eprintln!(
+        "    --max-mismatches <N>     Mismatches to print with --compare (default {})",
+        DEFAULT_MAX_MISMATCHES
+    );
+    eprintln!("    --watch                  Re-assemble whenever an input file changes");
+    eprintln!(
+        "    --anonymous-labels       Accept (:) labels referenced as @:+ (next) / @:- (previous)"
+    );
+    eprintln!("    --no-strict              Ignore text after instructions (old behaviour)");
+    eprintln!("    --cache-dir <DIR>        Reuse stored output for unchanged inputs");
+    eprintln!("    -h, --help               Show this help message");
     eprintln!();
     eprintln!("EXAMPLES:");
     eprintln!("    hack-assembler Add.asm");
     eprintln!("    hack-assembler prog1.asm prog2.asm -v");
+    eprintln!("    hack-assembler Max.asm --compare Max.cmp.hack --watch");
+    eprintln!("    hack-assembler *.asm --cache-dir .hackcache");
+    eprintln!();
+    eprintln!("An input of - reads stdin and writes stdout unless -o is given.");
+    eprintln!("Exit codes: 0 success, 1 assembly error or mismatch, 2 usage or I/O error.");
 }
 
-fn assemble_file(input_path: &Path, verbose: bool) -> Result<(), Box<dyn std::error::Error>> {
-    let start = Instant::now();
+/// Command-line options.
+struct Options {
+    files: Vec<Stream>,
+    output: Option<Stream>,
+    verbose: bool,
+    compare: Option<PathBuf>,
+    max_mismatches: usize,
+    watch: bool,
+    cache_dir: Option<PathBuf>,
+    assemble: AssembleOptions,
+    /// Some output goes to stdout, so status lines must not.
+    stdout_output: bool,
+}
 
-    // Read source
-    let source = fs::read_to_string(input_path)?;
+fn assemble_file(input: &Stream, options: &Options) -> Outcome {
+    let verbose = options.verbose;
+    let start = Instant::now();
 
     if verbose {
-        eprintln!("Assembling: {}", input_path.display());
+        eprintln!("Assembling: {}", input);
     }
 
-    // Assemble
-    let output = assemble(&source)?;
+    // Read and assemble, or reuse the cached output
+    let mut cache = options.cache_dir.as_ref().map(DirCache::new);
+    let assembled = with_input(input, |source| {
+        let output = match &mut cache {
+            Some(cache) => assemble_cached_with_options(source, &options.assemble, cache),
+            None => assemble_with_options(source, &options.assemble),
+        };
+        output.map(|output| (output, source.lines().count()))
+    });
+    let (output, lines) = match assembled {
+        Ok(Ok(assembled)) => assembled,
+        Ok(Err(e)) => {
+            eprintln!("Error processing {}: {}", input, e);
+            return Outcome::AssemblyError;
+        }
+        Err(e) => {
+            eprintln!("Error reading {}: {}", input, e);
+            return Outcome::UsageOrIoError;
+        }
+    };
+    let cached = cache.is_some_and(|cache| cache.hits > 0);
 
     // Write output
-    let output_path = input_path.with_extension("hack");
-    fs::write(&output_path, output)?;
+    let output_path = output_for(input, options.output.as_ref());
+    if let Err(e) = output_path.write(&output) {
+        eprintln!("Error writing {}: {}", output_path, e);
+        return Outcome::UsageOrIoError;
+    }
 
     let elapsed = start.elapsed();
 
     if verbose {
-        let lines = source.lines().count();
         eprintln!(
-            "  ✓ {} lines assembled in {:.2}ms",
+            "  ✓ {} lines {} in {:.2}ms",
             lines,
+            if cached {
+                "reused from cache"
+            } else {
+                "assembled"
+            },
             elapsed.as_secs_f64() * 1000.0
         );
-        eprintln!("  Output: {}", output_path.display());
+        eprintln!("  Output: {}", output_path);
+    } else if options.stdout_output {
+        // Stdout carries the output itself when piping
+        if output_path != Stream::Standard {
+            eprintln!("{} -> {}", input, output_path);
+        }
     } else {
-        println!("{} -> {}", input_path.display(), output_path.display());
+        println!("{} -> {}", input, output_path);
     }
 
-    Ok(())
+    Outcome::Success
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
+/// [`with_source`] for a file, or `f` on all of stdin.
+fn with_input<T>(input: &Stream, f: impl FnOnce(&str) -> T) -> io::Result<T> {
+    match input {
+        Stream::File(path) => with_source(path, f),
+        Stream::Standard => Ok(f(&input.read_to_string()?)),
+    }
+}
 
-    if args.len() < 2 {
-        print_usage();
-        process::exit(1);
+/// Call `f` with the text of the file at `path`, read into a `String`. With
+/// the `mmap` feature this is [`vm_translator::with_source`], which maps the
+/// file instead.
+#[cfg(not(feature = "mmap"))]
+fn with_source<T>(path: &Path, f: impl FnOnce(&str) -> T) -> io::Result<T> {
+    Ok(f(&fs::read_to_string(path)?))
+}
+
+/// Compare the assembled file against a reference; a mismatch is an
+/// [`Outcome::AssemblyError`].
+fn compare_file(input_path: &Path, reference_path: &Path, options: &Options) -> Outcome {
+    let max_mismatches = options.max_mismatches;
+    let read = fs::read_to_string(input_path)
+        .and_then(|source| Ok((source, fs::read_to_string(reference_path)?)));
+    let (source, reference) = match read {
+        Ok(texts) => texts,
+        Err(e) => {
+            eprintln!("Error comparing {}: {}", input_path.display(), e);
+            return Outcome::UsageOrIoError;
+        }
+    };
+    let comparison = match compare_with_options(&source, &reference, &options.assemble) {
+        Ok(comparison) => comparison,
+        Err(e) => {
+            eprintln!("Error comparing {}: {}", input_path.display(), e);
+            return Outcome::AssemblyError;
+        }
+    };
+
+    if comparison.is_match() {
+        println!(
+            "{}: matches {} ({} instructions)",
+            input_path.display(),
+            reference_path.display(),
+            comparison.ours_len
+        );
+        return Outcome::Success;
     }
 
-    let mut files = Vec::new();
-    let mut verbose = false;
+    eprintln!(
+        "{}: {} mismatch(es) against {} ({} vs {} instructions)",
+        input_path.display(),
+        comparison.mismatches.len(),
+        reference_path.display(),
+        comparison.ours_len,
+        comparison.reference_len
+    );
+    for mismatch in comparison.mismatches.iter().take(max_mismatches) {
+        eprintln!("  {}", mismatch);
+    }
+    if comparison.mismatches.len() > max_mismatches {
+        eprintln!(
+            "  ... {} more",
+            comparison.mismatches.len() - max_mismatches
+        );
+    }
+    Outcome::AssemblyError
+}
+
+/// Assemble (and optionally compare) every input file, returning the most
+/// severe outcome.
+fn run(options: &Options) -> Outcome {
+    let mut outcome = Outcome::Success;
 
-    for arg in &args[1..] {
+    for file in &options.files {
+        let assembled = assemble_file(file, options);
+        outcome = outcome.worst(assembled);
+        if assembled != Outcome::Success {
+            continue;
+        }
+        // Parsing rejects `--compare` with stdin
+        if let (Some(reference), Stream::File(path)) = (&options.compare, file) {
+            outcome = outcome.worst(compare_file(path, reference, options));
+        }
+    }
+
+    outcome
+}
+
+/// Latest modification time across the input files.
+fn latest_mtime(files: &[Stream]) -> Option<SystemTime> {
+    files
+        .iter()
+        .filter_map(|f| match f {
+            Stream::File(path) => fs::metadata(path).and_then(|m| m.modified()).ok(),
+            Stream::Standard => None,
+        })
+        .max()
+}
+
+/// Re-run whenever an input file's mtime changes. Never returns.
+fn watch(options: &Options) -> ! {
+    let mut last = latest_mtime(&options.files);
+    eprintln!("Watching {} file(s) for changes...", options.files.len());
+
+    loop {
+        thread::sleep(WATCH_INTERVAL);
+        let current = latest_mtime(&options.files);
+        if current != last {
+            last = current;
+            run(options);
+        }
+    }
+}
+
+fn parse_args(args: &[String]) -> Options {
+    let mut options = Options {
+        files: Vec::new(),
+        output: None,
+        verbose: false,
+        compare: None,
+        max_mismatches: DEFAULT_MAX_MISMATCHES,
+        watch: false,
+        cache_dir: None,
+        assemble: AssembleOptions::default(),
+        stdout_output: false,
+    };
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
         match arg.as_str() {
-            "-v" | "--verbose" => verbose = true,
+            "-v" | "--verbose" => options.verbose = true,
+            "--watch" => options.watch = true,
+            "--anonymous-labels" => options.assemble.anonymous_labels = true,
+            "--no-strict" => options.assemble.strict = false,
+            "-o" | "--output" => match iter.next() {
+                Some(path) => options.output = Some(Stream::from_arg(path)),
+                None => usage_error("--output requires a path (- for stdout)"),
+            },
+            "--compare" => match iter.next() {
+                Some(path) => options.compare = Some(PathBuf::from(path)),
+                None => usage_error("--compare requires a reference file"),
+            },
+            "--cache-dir" => match iter.next() {
+                Some(dir) => options.cache_dir = Some(PathBuf::from(dir)),
+                None => usage_error("--cache-dir requires a directory"),
+            },
+            "--max-mismatches" => match iter.next().and_then(|n| n.parse().ok()) {
+                Some(n) => options.max_mismatches = n,
+                None => usage_error("--max-mismatches requires a number"),
+            },
             "-h" | "--help" => {
                 print_usage();
                 process::exit(0);
             }
-            _ if arg.starts_with('-') => {
-                eprintln!("Error: Unknown option: {}", arg);
-                print_usage();
-                process::exit(1);
-            }
-            _ => files.push(PathBuf::from(arg)),
+            "-" => options.files.push(Stream::Standard),
+            _ if arg.starts_with('-') => usage_error(&format!("Unknown option: {}", arg)),
+            _ => options.files.push(Stream::from_arg(arg)),
         }
     }
 
-    if files.is_empty() {
-        eprintln!("Error: No input files specified");
-        print_usage();
-        process::exit(1);
+    if options.files.is_empty() {
+        usage_error("No input files specified");
+    }
+    if options.compare.is_some() && options.files.len() > 1 {
+        usage_error("--compare accepts a single input file");
     }
+    if options.output.is_some() && options.files.len() > 1 {
+        usage_error("--output accepts a single input file");
+    }
+    let stdin = options.files.contains(&Stream::Standard);
+    if options
+        .files
+        .iter()
+        .filter(|f| **f == Stream::Standard)
+        .count()
+        > 1
+    {
+        usage_error("stdin (-) can only be read once");
+    }
+    if stdin && (options.compare.is_some() || options.watch) {
+        usage_error("--compare and --watch need an input file, not stdin");
+    }
+    if options.output == Some(Stream::Standard) && options.compare.is_some() {
+        usage_error("--compare reports on stdout; write the output to a file");
+    }
+    options.stdout_output = options
+        .files
+        .iter()
+        .any(|f| output_for(f, options.output.as_ref()) == Stream::Standard);
 
-    let mut errors = 0;
+    options
+}
 
-    for file in files {
-        if let Err(e) = assemble_file(&file, verbose) {
-            eprintln!("Error processing {}: {}", file.display(), e);
-            errors += 1;
-        }
+fn usage_error(message: &str) -> ! {
+    eprintln!("Error: {}", message);
+    print_usage();
+    process::exit(Outcome::UsageOrIoError.code());
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    if args.len() < 2 {
+        print_usage();
+        process::exit(Outcome::UsageOrIoError.code());
     }
 
-    if errors > 0 {
-        process::exit(1);
+    let options = parse_args(&args[1..]);
+    let outcome = run(&options);
+
+    if options.watch {
+        watch(&options);
     }
+
+    process::exit(outcome.code());
 }
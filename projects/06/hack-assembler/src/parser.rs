@@ -1,47 +1,170 @@
+use std::fmt;
+
+use crate::AssembleOptions;
 use crate::error::{AsmError, Result};
+use crate::instruction::{Comp, Dest, Jump, ResolvedInstruction};
+
+/// Direction of an anonymous label reference.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// `@:+` - the next anonymous label
+    Forward,
+    /// `@:-` - the previous anonymous label
+    Backward,
+}
 
-#[derive(Debug, Clone, PartialEq)]
-pub enum Instruction {
-    AValue(u16),
-    ASymbol(String),
-    CInstruction { dest: u8, comp: u8, jump: u8 },
+impl Direction {
+    /// `"next"` or `"previous"`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Direction::Forward => "next",
+            Direction::Backward => "previous",
+        }
+    }
+
+    /// The reference syntax, `@:+` or `@:-`
+    pub fn syntax(self) -> &'static str {
+        match self {
+            Direction::Forward => "@:+",
+            Direction::Backward => "@:-",
+        }
+    }
 }
 
-/// Resolved instruction with all symbols converted to addresses
-/// This type makes it impossible to have unresolved symbols at codegen time
+/// A parsed instruction. Symbol names are `S`: owned by default, or
+/// `&str` borrowed from the source line (see [`parse_line_borrowed`]).
 #[derive(Debug, Clone, PartialEq)]
-pub enum ResolvedInstruction {
+pub enum Instruction<S = String> {
     AValue(u16),
-    CInstruction { dest: u8, comp: u8, jump: u8 },
+    ASymbol(S),
+    /// Reference to the nearest anonymous label (`@:+` / `@:-`)
+    AAnonymous(Direction),
+    CInstruction {
+        dest: Dest,
+        comp: Comp,
+        jump: Jump,
+    },
 }
 
-impl Instruction {
+impl<S> Instruction<S> {
     /// Resolve an instruction by converting symbols to addresses
     pub fn resolve(self, addr: u16) -> ResolvedInstruction {
         match self {
-            Instruction::AValue(v) => ResolvedInstruction::AValue(v),
-            Instruction::ASymbol(_) => ResolvedInstruction::AValue(addr),
+            Instruction::AValue(v) => ResolvedInstruction::A(v),
+            Instruction::ASymbol(_) | Instruction::AAnonymous(_) => ResolvedInstruction::A(addr),
             Instruction::CInstruction { dest, comp, jump } => {
-                ResolvedInstruction::CInstruction { dest, comp, jump }
+                ResolvedInstruction::C { dest, comp, jump }
             }
         }
     }
 }
 
+/// Assembly text that parses back to the same instruction: `@17`, `@LOOP`,
+/// `@:+`, or `dest=comp;jump` without empty parts (as [`ResolvedInstruction`]).
+impl<S: fmt::Display> fmt::Display for Instruction<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::AValue(value) => write!(f, "@{value}"),
+            Instruction::ASymbol(symbol) => write!(f, "@{symbol}"),
+            Instruction::AAnonymous(direction) => f.write_str(direction.syntax()),
+            &Instruction::CInstruction { dest, comp, jump } => {
+                ResolvedInstruction::C { dest, comp, jump }.fmt(f)
+            }
+        }
+    }
+}
+
+impl Instruction<&str> {
+    /// Copy the symbol name out of the source.
+    pub fn into_owned(self) -> Instruction {
+        match self {
+            Instruction::AValue(v) => Instruction::AValue(v),
+            Instruction::ASymbol(s) => Instruction::ASymbol(s.to_string()),
+            Instruction::AAnonymous(d) => Instruction::AAnonymous(d),
+            Instruction::CInstruction { dest, comp, jump } => {
+                Instruction::CInstruction { dest, comp, jump }
+            }
+        }
+    }
+}
+
+/// A parsed source line. Names are `S`, as in [`Instruction`].
 #[derive(Debug, Clone, PartialEq)]
-pub enum Line {
-    Instruction(Instruction),
-    Label(String),
+pub enum Line<S = String> {
+    Instruction(Instruction<S>),
+    Label(S),
+    /// Anonymous label `(:)`
+    AnonymousLabel,
     Empty,
 }
 
+/// Assembly text for the line, without comments; empty for [`Line::Empty`].
+impl<S: fmt::Display> fmt::Display for Line<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Line::Instruction(inst) => inst.fmt(f),
+            Line::Label(label) => write!(f, "({label})"),
+            Line::AnonymousLabel => f.write_str(ANONYMOUS_LABEL),
+            Line::Empty => Ok(()),
+        }
+    }
+}
+
+impl Line<&str> {
+    /// Copy the label or symbol name out of the source.
+    pub fn into_owned(self) -> Line {
+        match self {
+            Line::Instruction(inst) => Line::Instruction(inst.into_owned()),
+            Line::Label(label) => Line::Label(label.to_string()),
+            Line::AnonymousLabel => Line::AnonymousLabel,
+            Line::Empty => Line::Empty,
+        }
+    }
+}
+
+/// Anonymous label definition syntax.
+const ANONYMOUS_LABEL: &str = "(:)";
+
 /// Strip comments and whitespace
 fn clean_line(line: &str) -> &str {
     line.split("//").next().unwrap_or("").trim()
 }
 
+/// Split off the instruction at the start of `clean` (the cleaned form of
+/// `line`), rejecting directives and any text after the instruction.
+fn strict_instruction<'a>(line: &str, clean: &'a str, line_num: usize) -> Result<&'a str> {
+    if clean.starts_with('.') {
+        let directive = clean.split_whitespace().next().unwrap_or(clean);
+        return Err(AsmError::UnknownDirective {
+            line: line_num,
+            directive: directive.to_string(),
+        });
+    }
+
+    // A label ends at its `)`, anything else at the first whitespace
+    let end = if clean.starts_with('(') {
+        clean.find(')').map(|close| close + 1)
+    } else {
+        clean.find(char::is_whitespace)
+    };
+    let Some(end) = end else {
+        return Ok(clean);
+    };
+    let junk = clean[end..].trim_start();
+    if junk.is_empty() {
+        return Ok(&clean[..end]);
+    }
+
+    let offset = line.len() - line.trim_start().len() + (clean.len() - junk.len());
+    Err(AsmError::TrailingText {
+        line: line_num,
+        column: line[..offset].chars().count() + 1,
+        text: junk.to_string(),
+    })
+}
+
 /// Parse A-instruction (@value or @symbol)
-fn parse_a_instruction(line: &str, line_num: usize) -> Result<Instruction> {
+fn parse_a_instruction(line: &str, line_num: usize) -> Result<Instruction<&str>> {
     let value_str = &line[1..]; // Skip '@'
 
     if value_str.is_empty() {
@@ -62,12 +185,12 @@ fn parse_a_instruction(line: &str, line_num: usize) -> Result<Instruction> {
         Ok(Instruction::AValue(value))
     } else {
         // Symbol
-        Ok(Instruction::ASymbol(value_str.to_string()))
+        Ok(Instruction::ASymbol(value_str))
     }
 }
 
 /// Parse C-instruction (dest=comp;jump)
-fn parse_c_instruction(line: &str, line_num: usize) -> Result<Instruction> {
+fn parse_c_instruction(line: &str, line_num: usize) -> Result<Instruction<&str>> {
     let (dest_str, rest) = if let Some(eq_pos) = line.find('=') {
         (&line[..eq_pos], &line[eq_pos + 1..])
     } else {
@@ -80,17 +203,38 @@ fn parse_c_instruction(line: &str, line_num: usize) -> Result<Instruction> {
         (rest, "")
     };
 
-    let dest = parse_dest(dest_str).ok_or_else(|| AsmError::InvalidDest {
+    // `D=` and `;JGT` are not `0`, nor is `D;` a jump-free `D`: an empty
+    // field is only valid when its separator is absent too
+    if line.contains('=') && dest_str.is_empty() {
+        return Err(AsmError::MissingDest {
+            line: line_num,
+            text: line.to_string(),
+        });
+    }
+    if comp_str.is_empty() {
+        return Err(AsmError::MissingComp {
+            line: line_num,
+            text: line.to_string(),
+        });
+    }
+    if rest.contains(';') && jump_str.is_empty() {
+        return Err(AsmError::MissingJump {
+            line: line_num,
+            text: line.to_string(),
+        });
+    }
+
+    let dest = Dest::from_mnemonic(dest_str).ok_or_else(|| AsmError::InvalidDest {
         line: line_num,
         dest: dest_str.to_string(),
     })?;
 
-    let comp = parse_comp(comp_str).ok_or_else(|| AsmError::InvalidComp {
+    let comp = Comp::from_mnemonic(comp_str).ok_or_else(|| AsmError::InvalidComp {
         line: line_num,
         comp: comp_str.to_string(),
     })?;
 
-    let jump = parse_jump(jump_str).ok_or_else(|| AsmError::InvalidJump {
+    let jump = Jump::from_mnemonic(jump_str).ok_or_else(|| AsmError::InvalidJump {
         line: line_num,
         jump: jump_str.to_string(),
     })?;
@@ -98,92 +242,56 @@ fn parse_c_instruction(line: &str, line_num: usize) -> Result<Instruction> {
     Ok(Instruction::CInstruction { dest, comp, jump })
 }
 
-/// Parse dest field (3 bits: A D M)
-fn parse_dest(s: &str) -> Option<u8> {
-    match s {
-        "" => Some(0b000),
-        "M" => Some(0b001),
-        "D" => Some(0b010),
-        "MD" | "DM" => Some(0b011),
-        "A" => Some(0b100),
-        "AM" | "MA" => Some(0b101),
-        "AD" | "DA" => Some(0b110),
-        "AMD" | "ADM" | "MAD" | "MDA" | "DAM" | "DMA" => Some(0b111),
-        _ => None,
-    }
-}
-
-/// Parse comp field (7 bits: a + 6 c-bits)
-/// The 'a' bit determines if M (a=1) or A (a=0) is used
-fn parse_comp(s: &str) -> Option<u8> {
-    match s {
-        // === Constants (a=0) ===
-        "0" => Some(0b0101010),
-        "1" => Some(0b0111111),
-        "-1" => Some(0b0111010),
-
-        // === D-register operations (a=0) ===
-        "D" => Some(0b0001100),
-        "!D" => Some(0b0001101),
-        "-D" => Some(0b0001111),
-        "D+1" | "1+D" => Some(0b0011111),
-        "D-1" => Some(0b0001110),
-
-        // === A-register operations (a=0) ===
-        "A" => Some(0b0110000),
-        "!A" => Some(0b0110001),
-        "-A" => Some(0b0110011),
-        "A+1" | "1+A" => Some(0b0110111),
-        "A-1" => Some(0b0110010),
-
-        // === ALU operations with A-register (a=0) ===
-        "D+A" | "A+D" => Some(0b0000010),
-        "D-A" => Some(0b0010011),
-        "A-D" => Some(0b0000111),
-        "D&A" | "A&D" => Some(0b0000000),
-        "D|A" | "A|D" => Some(0b0010101),
-
-        // === M-register operations (a=1) ===
-        "M" => Some(0b1110000),
-        "!M" => Some(0b1110001),
-        "-M" => Some(0b1110011),
-        "M+1" | "1+M" => Some(0b1110111),
-        "M-1" => Some(0b1110010),
-
-        // === ALU operations with M-register (a=1) ===
-        "D+M" | "M+D" => Some(0b1000010),
-        "D-M" => Some(0b1010011),
-        "M-D" => Some(0b1000111),
-        "D&M" | "M&D" => Some(0b1000000),
-        "D|M" | "M|D" => Some(0b1010101),
-
-        _ => None,
-    }
+/// Parse single line
+pub fn parse_line(line: &str, line_num: usize) -> Result<Line> {
+    parse_line_with_options(line, line_num, &AssembleOptions::default())
 }
 
-/// Parse jump field (3 bits)
-fn parse_jump(s: &str) -> Option<u8> {
-    match s {
-        "" => Some(0b000),
-        "JGT" => Some(0b001),
-        "JEQ" => Some(0b010),
-        "JGE" => Some(0b011),
-        "JLT" => Some(0b100),
-        "JNE" => Some(0b101),
-        "JLE" => Some(0b110),
-        "JMP" => Some(0b111),
-        _ => None,
-    }
+/// Parse single line, recognizing the syntax extensions enabled in `options`
+pub fn parse_line_with_options(
+    line: &str,
+    line_num: usize,
+    options: &AssembleOptions,
+) -> Result<Line> {
+    parse_line_borrowed(line, line_num, options).map(Line::into_owned)
 }
 
-/// Parse single line
-pub fn parse_line(line: &str, line_num: usize) -> Result<Line> {
+/// [`parse_line_with_options`] without copying: label and symbol names
+/// borrow from `line`.
+pub fn parse_line_borrowed<'a>(
+    line: &'a str,
+    line_num: usize,
+    options: &AssembleOptions,
+) -> Result<Line<&'a str>> {
     let clean = clean_line(line);
 
     if clean.is_empty() {
         return Ok(Line::Empty);
     }
 
+    let clean = if options.strict {
+        strict_instruction(line, clean, line_num)?
+    } else {
+        clean
+    };
+
+    if options.anonymous_labels {
+        match clean {
+            ANONYMOUS_LABEL => return Ok(Line::AnonymousLabel),
+            "@:+" => {
+                return Ok(Line::Instruction(Instruction::AAnonymous(
+                    Direction::Forward,
+                )));
+            }
+            "@:-" => {
+                return Ok(Line::Instruction(Instruction::AAnonymous(
+                    Direction::Backward,
+                )));
+            }
+            _ => {}
+        }
+    }
+
     // Label
     if clean.starts_with('(') {
         if !clean.ends_with(')') {
@@ -192,8 +300,7 @@ pub fn parse_line(line: &str, line_num: usize) -> Result<Line> {
                 text: line.to_string(),
             });
         }
-        let label = clean[1..clean.len() - 1].to_string();
-        return Ok(Line::Label(label));
+        return Ok(Line::Label(&clean[1..clean.len() - 1]));
     }
 
     // A-instruction
@@ -237,14 +344,41 @@ mod tests {
         assert_eq!(line, Line::Label("LOOP".to_string()));
     }
 
+    #[test]
+    fn test_parse_anonymous_labels() {
+        let options = AssembleOptions {
+            anonymous_labels: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            parse_line_with_options("(:)  // loop", 1, &options).unwrap(),
+            Line::AnonymousLabel
+        );
+        assert_eq!(
+            parse_line_with_options("@:+", 1, &options).unwrap(),
+            Line::Instruction(Instruction::AAnonymous(Direction::Forward))
+        );
+        assert_eq!(
+            parse_line_with_options("@:-", 1, &options).unwrap(),
+            Line::Instruction(Instruction::AAnonymous(Direction::Backward))
+        );
+
+        // Off by default: the same text is an ordinary label and symbol
+        assert_eq!(parse_line("(:)", 1).unwrap(), Line::Label(":".to_string()));
+        assert_eq!(
+            parse_line("@:+", 1).unwrap(),
+            Line::Instruction(Instruction::ASymbol(":+".to_string()))
+        );
+    }
+
     #[test]
     fn test_parse_c_instruction() {
         let inst = parse_line("D=M+1", 1).unwrap();
         match inst {
             Line::Instruction(Instruction::CInstruction { dest, comp, jump }) => {
-                assert_eq!(dest, 0b010); // D
-                assert_eq!(comp, 0b1110111); // M+1
-                assert_eq!(jump, 0b000); // no jump
+                assert_eq!(dest, Dest::D);
+                assert_eq!(comp, Comp::MPlusOne);
+                assert_eq!(jump, Jump::Null);
             }
             _ => panic!("Expected C-instruction"),
         }
@@ -255,11 +389,156 @@ mod tests {
         let inst = parse_line("D;JGT", 1).unwrap();
         match inst {
             Line::Instruction(Instruction::CInstruction { dest, comp, jump }) => {
-                assert_eq!(dest, 0b000);
-                assert_eq!(comp, 0b0001100); // D
-                assert_eq!(jump, 0b001); // JGT
+                assert_eq!(dest, Dest::Null);
+                assert_eq!(comp, Comp::D);
+                assert_eq!(jump, Jump::JGT);
             }
             _ => panic!("Expected C-instruction"),
         }
     }
+
+    #[test]
+    fn test_trailing_text_is_rejected() {
+        let err = parse_line("  @foo bar // comment", 3).unwrap_err();
+        assert!(matches!(
+            &err,
+            AsmError::TrailingText { line: 3, column: 8, text } if text == "bar"
+        ));
+        assert_eq!(
+            err.to_string(),
+            "line 3, column 8: unexpected text after instruction: bar"
+        );
+
+        assert!(matches!(
+            parse_line("D=M+1 ;JGT", 1),
+            Err(AsmError::TrailingText { column: 7, .. })
+        ));
+        assert!(matches!(
+            parse_line("0;JMP\tx y", 1),
+            Err(AsmError::TrailingText { column: 7, text, .. }) if text == "x y"
+        ));
+        assert!(matches!(
+            parse_line("(LOOP) @1", 1),
+            Err(AsmError::TrailingText { column: 8, .. })
+        ));
+
+        // Without strict mode the old parse stands
+        let lenient = AssembleOptions {
+            strict: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            parse_line_with_options("@foo bar", 1, &lenient).unwrap(),
+            Line::Instruction(Instruction::ASymbol("foo bar".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_unknown_directive() {
+        let err = parse_line(".org 100", 2).unwrap_err();
+        assert!(matches!(
+            &err,
+            AsmError::UnknownDirective { line: 2, directive } if directive == ".org"
+        ));
+        assert_eq!(err.to_string(), "line 2: unknown directive: .org");
+    }
+
+    #[test]
+    fn test_comment_abutting_instruction() {
+        assert_eq!(
+            parse_line("@2//x", 1).unwrap(),
+            Line::Instruction(Instruction::AValue(2))
+        );
+        assert_eq!(
+            parse_line("(END)// done", 1).unwrap(),
+            Line::Label("END".to_string())
+        );
+        assert!(matches!(
+            parse_line("D=A//x", 1).unwrap(),
+            Line::Instruction(Instruction::CInstruction { dest: Dest::D, .. })
+        ));
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        let options = AssembleOptions {
+            anonymous_labels: true,
+            ..AssembleOptions::default()
+        };
+        for source in [
+            "@0",
+            "@32767",
+            "@LOOP",
+            "@R13",
+            "@:+",
+            "@:-",
+            "(END)",
+            "(:)",
+            "D=M+1;JGT",
+            "M=D",
+            "0;JMP",
+            "AMD=!A",
+            "D;JNE",
+            "",
+        ] {
+            let line = parse_line_with_options(source, 1, &options).unwrap();
+            assert_eq!(line.to_string(), source);
+            assert_eq!(
+                parse_line_with_options(&line.to_string(), 1, &options).unwrap(),
+                line
+            );
+        }
+
+        // Aliases and spacing render canonically
+        let line = parse_line("DM=1+D;JMP // comment", 1).unwrap();
+        assert_eq!(line.to_string(), "MD=D+1;JMP");
+    }
+
+    #[test]
+    fn test_display_round_trip_every_c_instruction() {
+        for &dest in Dest::ALL {
+            for &comp in Comp::ALL {
+                for &jump in Jump::ALL {
+                    let inst: Instruction = Instruction::CInstruction { dest, comp, jump };
+                    let text = inst.to_string();
+                    assert_eq!(
+                        parse_line(&text, 1).unwrap(),
+                        Line::Instruction(inst),
+                        "{text}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_missing_fields() {
+        for source in [";JGT", "M=", "D=;JMP", "="] {
+            let err = parse_line(source, 4).unwrap_err();
+            let expected = if source == "=" {
+                "line 4: missing destination before '=': ="
+            } else {
+                &format!("line 4: missing computation in C-instruction: {source}")
+            };
+            assert_eq!(err.to_string(), expected);
+        }
+        assert!(matches!(
+            parse_line(";JGT", 1),
+            Err(AsmError::MissingComp { line: 1, .. })
+        ));
+        assert!(matches!(
+            parse_line("=D", 2),
+            Err(AsmError::MissingDest { line: 2, .. })
+        ));
+        assert_eq!(
+            parse_line("D;", 3).unwrap_err().to_string(),
+            "line 3: missing jump after ';': D;"
+        );
+        // An invalid computation is still reported as such
+        assert!(matches!(
+            parse_line("M=Q", 1),
+            Err(AsmError::InvalidComp { .. })
+        ));
+        assert!(parse_line("0;JMP", 1).is_ok());
+    }
 }
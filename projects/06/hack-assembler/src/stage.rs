@@ -0,0 +1,320 @@
+//! One diagnostic interface for every toolchain stage.
+//!
+//! Each stage has its own error type: [`AsmError`] here, `VMError` in the
+//! project 08 translator (`vm` feature), and `JackError` / `CompileError` in
+//! the project 10 analyzer and project 11 compiler (`jack` feature).
+//! [`StageError`] is implemented for all of them, and for
+//! [`PipelineError`](crate::error::PipelineError), so a tool driving the
+//! whole pipeline can render any failure the same way:
+//!
+//! ```
+//! use hack_assembler::assemble;
+//! use hack_assembler::stage::StageError;
+//!
+//! let err = assemble("(A)\n(A)\n").unwrap_err();
+//! assert_eq!(err.render(), "assemble: line 2: duplicate label: A");
+//! ```
+
+use std::fmt;
+
+use crate::error::AsmError;
+
+/// Where in its input a stage error occurred.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Location {
+    /// A 1-based line (assembly, VM code).
+    Line(usize),
+    /// A 1-based line and column (Jack source).
+    Span { line: usize, column: usize },
+}
+
+impl Location {
+    /// The 1-based line.
+    pub fn line(&self) -> usize {
+        match *self {
+            Location::Line(line) | Location::Span { line, .. } => line,
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Location::Line(line) => write!(f, "line {line}"),
+            Location::Span { line, column } => write!(f, "line {line}, column {column}"),
+        }
+    }
+}
+
+/// An error from one stage of the toolchain.
+pub trait StageError: std::error::Error {
+    /// The stage that failed: `"assemble"`, `"translate"`, `"analyze"` or
+    /// `"compile"`.
+    fn stage(&self) -> &'static str;
+
+    /// Where the error occurred, if it refers to a place in the input.
+    fn span_or_line(&self) -> Option<Location>;
+
+    /// The error message without its location.
+    fn message(&self) -> String;
+
+    /// `stage: location: message`, leaving out the location when unknown.
+    fn render(&self) -> String {
+        match self.span_or_line() {
+            Some(location) => format!("{}: {}: {}", self.stage(), location, self.message()),
+            None => format!("{}: {}", self.stage(), self.message()),
+        }
+    }
+}
+
+impl StageError for AsmError {
+    fn stage(&self) -> &'static str {
+        match self {
+            #[cfg(feature = "vm")]
+            AsmError::Translate(err) => err.stage(),
+            _ => "assemble",
+        }
+    }
+
+    fn span_or_line(&self) -> Option<Location> {
+        match self {
+            AsmError::InvalidAValue { line, .. }
+            | AsmError::DuplicateLabel { line, .. }
+            | AsmError::InvalidSyntax { line, .. }
+            | AsmError::InvalidDest { line, .. }
+            | AsmError::InvalidComp { line, .. }
+            | AsmError::MissingComp { line, .. }
+            | AsmError::MissingDest { line, .. }
+            | AsmError::MissingJump { line, .. }
+            | AsmError::InvalidJump { line, .. }
+            | AsmError::UnknownDirective { line, .. }
+            | AsmError::DanglingAnonymousLabel { line, .. } => Some(Location::Line(*line)),
+            AsmError::TrailingText { line, column, .. } => Some(Location::Span {
+                line: *line,
+                column: *column,
+            }),
+            #[cfg(feature = "vm")]
+            AsmError::Translate(err) => err.span_or_line(),
+            AsmError::InvalidWord { .. } | AsmError::Io(_) => None,
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            #[cfg(feature = "vm")]
+            AsmError::Translate(err) => err.message(),
+            AsmError::Io(err) => format!("I/O error: {err}"),
+            _ => strip_location(self.to_string(), self.span_or_line(), |location| {
+                format!("{location}: ")
+            }),
+        }
+    }
+}
+
+#[cfg(feature = "vm")]
+impl StageError for vm_translator::VMError {
+    fn stage(&self) -> &'static str {
+        "translate"
+    }
+
+    fn span_or_line(&self) -> Option<Location> {
+        self.location().map(|(_, line)| Location::Line(line))
+    }
+
+    fn message(&self) -> String {
+        let file = self.location().map_or("", |(file, _)| file);
+        strip_location(self.to_string(), self.span_or_line(), |location| {
+            format!("{file}:{}: ", location.line())
+        })
+    }
+}
+
+#[cfg(feature = "jack")]
+impl StageError for jack_analyzer::error::JackError {
+    fn stage(&self) -> &'static str {
+        "analyze"
+    }
+
+    fn span_or_line(&self) -> Option<Location> {
+        use jack_analyzer::error::JackError;
+
+        match self {
+            JackError::Include { line, .. } => Some(Location::Line(*line)),
+            _ => self.span().map(|span| Location::Span {
+                line: span.line,
+                column: span.column,
+            }),
+        }
+    }
+
+    fn message(&self) -> String {
+        use jack_analyzer::error::JackError;
+
+        match self {
+            JackError::Lexical { message, .. }
+            | JackError::Syntax { message, .. }
+            | JackError::Include { message, .. } => message.clone(),
+            JackError::Io { .. } => self.to_string(),
+        }
+    }
+}
+
+#[cfg(feature = "jack")]
+impl StageError for jack_compiler::CompileError {
+    fn stage(&self) -> &'static str {
+        match self {
+            jack_compiler::CompileError::Parse(err) => err.stage(),
+            _ => "compile",
+        }
+    }
+
+    fn span_or_line(&self) -> Option<Location> {
+        match self {
+            jack_compiler::CompileError::Parse(err) => err.span_or_line(),
+            _ => self.span().map(|span| Location::Span {
+                line: span.line,
+                column: span.column,
+            }),
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            jack_compiler::CompileError::Parse(err) => err.message(),
+            // Semantic errors mention their span mid-sentence ("... at 3:5")
+            _ => match self.span() {
+                Some(span) => self.to_string().replacen(&format!(" at {span}"), "", 1),
+                None => self.to_string(),
+            },
+        }
+    }
+}
+
+#[cfg(feature = "jack")]
+impl StageError for crate::error::PipelineError {
+    fn stage(&self) -> &'static str {
+        use crate::error::PipelineError;
+
+        match self {
+            PipelineError::NoJackFiles { .. } => "compile",
+            // Reading the `.vm` files linked in before translation
+            PipelineError::Read { .. } => "translate",
+            PipelineError::Compile { errors, .. } => {
+                errors.first().map_or("compile", |e| e.stage())
+            }
+            PipelineError::Translate(err) => err.stage(),
+            PipelineError::Assemble(err) => err.stage(),
+        }
+    }
+
+    fn span_or_line(&self) -> Option<Location> {
+        use crate::error::PipelineError;
+
+        match self {
+            PipelineError::NoJackFiles { .. } | PipelineError::Read { .. } => None,
+            PipelineError::Compile { errors, .. } => errors.first()?.span_or_line(),
+            PipelineError::Translate(err) => err.span_or_line(),
+            PipelineError::Assemble(err) => err.span_or_line(),
+        }
+    }
+
+    fn message(&self) -> String {
+        use crate::error::PipelineError;
+
+        match self {
+            PipelineError::NoJackFiles { .. } | PipelineError::Read { .. } => self.to_string(),
+            PipelineError::Compile { errors, .. } => errors
+                .first()
+                .map_or_else(|| self.to_string(), |e| e.message()),
+            PipelineError::Translate(err) => err.message(),
+            PipelineError::Assemble(err) => err.message(),
+        }
+    }
+}
+
+/// Remove the `prefix(location)` an error's `Display` starts with.
+fn strip_location(
+    message: String,
+    location: Option<Location>,
+    prefix: impl Fn(Location) -> String,
+) -> String {
+    match location {
+        Some(location) => match message.strip_prefix(&prefix(location)) {
+            Some(rest) => rest.to_string(),
+            None => message,
+        },
+        None => message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_asm_error() {
+        let err = crate::assemble("@1\nD=Q\n").unwrap_err();
+        assert_eq!(err.stage(), "assemble");
+        assert_eq!(err.span_or_line(), Some(Location::Line(2)));
+        assert_eq!(err.message(), "invalid comp field: Q");
+        assert_eq!(err.render(), "assemble: line 2: invalid comp field: Q");
+
+        let err = crate::assemble("@1 @2\n").unwrap_err();
+        assert_eq!(
+            err.span_or_line(),
+            Some(Location::Span { line: 1, column: 4 })
+        );
+        assert_eq!(err.message(), "unexpected text after instruction: @2");
+    }
+
+    #[cfg(feature = "vm")]
+    #[test]
+    fn test_vm_error() {
+        let err = AsmError::from(vm_translator::VMError::PopToConstant {
+            line: 4,
+            column: 5,
+            file: "Main.vm".to_string(),
+        });
+        assert_eq!(err.stage(), "translate");
+        assert_eq!(err.span_or_line(), Some(Location::Line(4)));
+        assert_eq!(err.message(), "cannot pop to constant segment");
+    }
+
+    #[cfg(feature = "jack")]
+    #[test]
+    fn test_jack_errors() {
+        use crate::error::PipelineError;
+
+        let compile = |source: &str| {
+            let errors = jack_compiler::compile_source(source, "Main").errors;
+            PipelineError::Compile {
+                file: "Main.jack".to_string(),
+                errors,
+            }
+        };
+
+        let err = compile("class Main { function void main() { let x = 1; return; } }");
+        assert_eq!(err.stage(), "compile");
+        assert_eq!(
+            err.span_or_line(),
+            Some(Location::Span {
+                line: 1,
+                column: 37
+            })
+        );
+        assert_eq!(err.message(), "Undefined variable 'x'");
+
+        let err = compile("class Main { function void main() { return 1 +; } }");
+        assert_eq!(err.stage(), "analyze");
+        assert!(matches!(
+            err.span_or_line(),
+            Some(Location::Span { line: 1, .. })
+        ));
+        assert!(!err.message().contains("Syntax error at"));
+
+        let err = PipelineError::NoJackFiles {
+            path: "src".to_string(),
+        };
+        assert_eq!(err.render(), "compile: no .jack files found in src");
+    }
+}
@@ -1,5 +1,14 @@
+//! Symbol table for labels, variables and the predefined symbols.
+//!
+//! Names are `Cow<'a, str>` so symbols parsed with
+//! [`parse_line_borrowed`](crate::parser::parse_line_borrowed) stay borrowed
+//! from the source: the table allocates no strings of its own. All symbols,
+//! predefined ones included, live in one `FxHashMap`, so resolving an
+//! `@symbol` is a single probe with a cheap hash.
+
 use phf::phf_map;
-use std::collections::HashMap;
+use rustc_hash::FxHashMap;
+use std::borrow::Cow;
 
 /// Predefined symbols (compile-time perfect hash map)
 pub static PREDEFINED: phf::Map<&'static str, u16> = phf_map! {
@@ -11,56 +20,74 @@ pub static PREDEFINED: phf::Map<&'static str, u16> = phf_map! {
     "SCREEN" => 16384, "KBD" => 24576,
 };
 
-pub struct SymbolTable {
-    symbols: HashMap<String, u16>,
+pub struct SymbolTable<'a> {
+    /// Predefined symbols, labels and variables
+    symbols: FxHashMap<Cow<'a, str>, u16>,
+    /// Labels named like a predefined symbol. They are accepted (and must
+    /// be unique), but references still resolve to the predefined address.
+    shadowed_labels: Vec<Cow<'a, str>>,
     next_var_address: u16,
 }
 
-impl Default for SymbolTable {
+impl Default for SymbolTable<'_> {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl SymbolTable {
+impl<'a> SymbolTable<'a> {
     pub fn new() -> Self {
+        let mut symbols = FxHashMap::with_capacity_and_hasher(64, Default::default());
+        symbols.extend(
+            PREDEFINED
+                .entries()
+                .map(|(&name, &addr)| (Cow::Borrowed(name), addr)),
+        );
         Self {
-            symbols: HashMap::with_capacity(64),
+            symbols,
+            shadowed_labels: Vec::new(),
             next_var_address: 16,
         }
     }
 
-    pub fn add_label(&mut self, label: String, address: u16) -> Result<(), String> {
+    /// Define a label; fails with the label's name if it is already defined.
+    pub fn add_label(
+        &mut self,
+        label: impl Into<Cow<'a, str>>,
+        address: u16,
+    ) -> Result<(), String> {
+        let label = label.into();
+        if PREDEFINED.contains_key(&label) {
+            if self.shadowed_labels.contains(&label) {
+                return Err(label.into_owned());
+            }
+            self.shadowed_labels.push(label);
+            return Ok(());
+        }
         if self.symbols.contains_key(&label) {
-            return Err(label);
+            return Err(label.into_owned());
         }
         self.symbols.insert(label, address);
         Ok(())
     }
 
-    pub fn get_or_allocate(&mut self, symbol: &str) -> u16 {
-        // Check predefined symbols first
-        if let Some(&addr) = PREDEFINED.get(symbol) {
-            return addr;
-        }
-
-        // Check user-defined symbols
-        if let Some(&addr) = self.symbols.get(symbol) {
+    /// The address of `symbol`, allocating the next variable address if it
+    /// is not defined yet.
+    pub fn get_or_allocate(&mut self, symbol: impl Into<Cow<'a, str>>) -> u16 {
+        let symbol = symbol.into();
+        if let Some(&addr) = self.symbols.get(&symbol) {
             return addr;
         }
 
         // Allocate new variable
         let addr = self.next_var_address;
-        self.symbols.insert(symbol.to_string(), addr);
+        self.symbols.insert(symbol, addr);
         self.next_var_address += 1;
         addr
     }
 
     pub fn get(&self, symbol: &str) -> Option<u16> {
-        PREDEFINED
-            .get(symbol)
-            .copied()
-            .or_else(|| self.symbols.get(symbol).copied())
+        self.symbols.get(symbol).copied()
     }
 }
 
@@ -85,6 +112,15 @@ mod tests {
         assert!(table.add_label("LOOP".to_string(), 20).is_err());
     }
 
+    #[test]
+    fn test_label_named_like_predefined_symbol() {
+        let mut table = SymbolTable::new();
+        assert!(table.add_label("SP", 10).is_ok());
+        assert_eq!(table.get("SP"), Some(0));
+        assert_eq!(table.add_label("SP", 20), Err("SP".to_string()));
+        assert_eq!(table.get_or_allocate("SP"), 0);
+    }
+
     #[test]
     fn test_variable_allocation() {
         let mut table = SymbolTable::new();
@@ -0,0 +1,114 @@
+//! Build ROM images in memory.
+//!
+//! Instead of `.hack` text these functions return the decoded 16-bit
+//! instruction words, ready to be loaded into an emulator's ROM. With the
+//! `vm` feature a directory of `.vm` files can be translated (by the project
+//! 08 translator) and assembled in one call; with the `jack` feature a
+//! directory of `.jack` files is compiled (by the project 11 compiler) first.
+//! Intermediate VM and assembly code stays in memory.
+
+use crate::error::Result;
+use crate::instruction::encode;
+use crate::{AssembleOptions, resolve_program};
+
+/// Assemble Hack assembly source to ROM words, one per instruction
+pub fn build_rom_from_asm(asm: &str) -> Result<Vec<u16>> {
+    let (instructions, _) = resolve_program(asm, &AssembleOptions::default())?;
+    Ok(instructions.into_iter().map(encode).collect())
+}
+
+/// Translate a directory of `.vm` files and assemble the result to ROM words.
+///
+/// Translation follows `vm_translator::translate_directory`: bootstrap code
+/// is emitted when the directory contains `Sys.vm`.
+#[cfg(feature = "vm")]
+pub fn build_rom_from_vm_dir(dir: &std::path::Path) -> Result<Vec<u16>> {
+    let asm = vm_translator::translate_directory(dir)?;
+    build_rom_from_asm(&asm)
+}
+
+/// Compile a directory of `.jack` files and build the program's ROM.
+///
+/// Every `.jack` file is compiled in memory. `.vm` files in the directory are
+/// linked in too unless a `.jack` file of the same name replaces them, so the
+/// Jack OS (or any precompiled class) can sit next to the sources, as for
+/// the course's VM emulator. The program always starts with bootstrap code
+/// calling `Sys.init`. Errors name the stage, and where known the file, that
+/// failed.
+#[cfg(feature = "jack")]
+pub fn build_rom_from_jack_dir(
+    dir: &std::path::Path,
+) -> std::result::Result<Vec<u16>, crate::error::PipelineError> {
+    use crate::error::PipelineError;
+
+    let mut classes = jack_compiler::compile_directory(dir);
+    if classes.is_empty() {
+        return Err(PipelineError::NoJackFiles {
+            path: dir.display().to_string(),
+        });
+    }
+    classes.sort_by(|a, b| a.filename.cmp(&b.filename));
+    if let Some(failed) = classes.iter_mut().find(|class| !class.is_ok()) {
+        return Err(PipelineError::Compile {
+            file: format!("{}.jack", failed.filename),
+            errors: std::mem::take(&mut failed.errors),
+        });
+    }
+
+    let read_error = |path: &std::path::Path, source| PipelineError::Read {
+        path: path.display().to_string(),
+        source,
+    };
+    let mut vm_files: Vec<_> = std::fs::read_dir(dir)
+        .map_err(|e| read_error(dir, e))?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "vm"))
+        .collect();
+    vm_files.sort();
+    let mut linked = Vec::new();
+    for path in vm_files {
+        let Some(stem) = path.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        if classes.iter().all(|class| class.filename != stem) {
+            let source = std::fs::read_to_string(&path).map_err(|e| read_error(&path, e))?;
+            linked.push((stem.to_string(), source));
+        }
+    }
+
+    let sources: Vec<(&str, &str)> = classes
+        .iter()
+        .map(|class| (class.filename.as_str(), class.vm_code.as_str()))
+        .chain(
+            linked
+                .iter()
+                .map(|(name, source)| (name.as_str(), source.as_str())),
+        )
+        .collect();
+    let translation =
+        vm_translator::translate_program(&sources, &vm_translator::TranslateOptions::default())?;
+    Ok(build_rom_from_asm(&translation.asm)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rom_words_match_text_output() {
+        let source = "@i\nM=1\n(LOOP)\n@LOOP\nD;JGT\n";
+        let text = crate::assemble(source).unwrap();
+        let words: Vec<String> = build_rom_from_asm(source)
+            .unwrap()
+            .iter()
+            .map(|word| format!("{word:016b}"))
+            .collect();
+        assert_eq!(words.join("\n"), text);
+    }
+
+    #[test]
+    fn test_rom_errors_propagate() {
+        assert!(build_rom_from_asm("(A)\n(A)\n").is_err());
+    }
+}
@@ -1,4 +1,4 @@
-use hack_assembler::assemble;
+use hack_assembler::{assemble, decode, encode, parse_program};
 use std::fs;
 
 fn test_file(name: &str) {
@@ -19,6 +19,20 @@ fn test_file(name: &str) {
         "Output mismatch for {}",
         name
     );
+
+    // The instruction stream encodes to, and decodes from, the same words
+    let instructions = parse_program(&source).unwrap();
+    let words: Vec<u16> = expected
+        .lines()
+        .map(|line| u16::from_str_radix(line.trim(), 2).unwrap())
+        .collect();
+    assert_eq!(
+        instructions.iter().copied().map(encode).collect::<Vec<_>>(),
+        words
+    );
+    for (inst, word) in instructions.iter().zip(&words) {
+        assert_eq!(decode(*word).unwrap(), *inst, "{}: {:016b}", name, word);
+    }
 }
 
 #[test]
@@ -40,3 +54,127 @@ fn test_rect() {
 fn test_pong() {
     test_file("Pong");
 }
+
+#[test]
+fn test_compare_reports_single_bit_flip() {
+    use hack_assembler::compare::compare_with_reference;
+
+    let source = fs::read_to_string("tests/Max.asm").unwrap();
+    let expected = fs::read_to_string("tests/Max.hack").unwrap();
+
+    // Flip the lowest bit of the fifth instruction (@ITSR0)
+    let mut lines: Vec<String> = expected.lines().map(str::to_string).collect();
+    let flipped = if lines[4].ends_with('0') { '1' } else { '0' };
+    lines[4].pop();
+    lines[4].push(flipped);
+    let reference = lines.join("\n");
+
+    let comparison = compare_with_reference(&source, &reference).unwrap();
+    assert_eq!(comparison.mismatches.len(), 1);
+
+    let mismatch = &comparison.mismatches[0];
+    assert_eq!(mismatch.line, 5);
+    assert_eq!(mismatch.source.as_deref(), Some("@ITSR0"));
+    let source_line = mismatch.source_line.unwrap();
+    assert_eq!(
+        source.lines().nth(source_line - 1).unwrap().trim(),
+        "@ITSR0"
+    );
+}
+
+#[test]
+fn test_rom_from_simple_add_asm() {
+    let source = fs::read_to_string("../../07/SimpleAdd/SimpleAdd.asm").unwrap();
+    let rom = hack_assembler::toolchain::build_rom_from_asm(&source).unwrap();
+    assert_eq!(
+        rom[..6],
+        [
+            7,                  // @7
+            0b1110110000010000, // D=A
+            0,                  // @SP
+            0b1111110000100000, // A=M
+            0b1110001100001000, // M=D
+            0,                  // @SP
+        ]
+    );
+}
+
+#[cfg(feature = "vm")]
+#[test]
+fn test_rom_from_vm_dir_starts_with_bootstrap() {
+    let dir = std::path::Path::new("../../08/FibonacciElement");
+    let rom = hack_assembler::toolchain::build_rom_from_vm_dir(dir).unwrap();
+    assert_eq!(rom[0], 256); // @256
+    assert_eq!(rom[1], 0b1110110000010000); // D=A
+}
+
+#[cfg(feature = "jack")]
+mod jack_pipeline {
+    use hack_assembler::error::PipelineError;
+    use hack_assembler::toolchain::{build_rom_from_asm, build_rom_from_jack_dir};
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn test_rom_from_jack_dir_matches_manual_pipeline() {
+        let dir = Path::new("../../11/Seven");
+        let rom = build_rom_from_jack_dir(dir).unwrap();
+
+        // Main.jack replaces the Main.vm next to it
+        let source = fs::read_to_string(dir.join("Main.jack")).unwrap();
+        let vm = jack_compiler::compile_source(&source, "Main").vm_code;
+        let asm = vm_translator::translate_program(
+            &[("Main", vm.as_str())],
+            &vm_translator::TranslateOptions::default(),
+        )
+        .unwrap()
+        .asm;
+        assert_eq!(rom, build_rom_from_asm(&asm).unwrap());
+        assert_eq!(rom[0], 256); // bootstrap: @256
+    }
+
+    #[test]
+    fn test_rom_from_jack_dir_links_vm_files() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::copy("../../11/Seven/Main.jack", dir.path().join("Main.jack")).unwrap();
+        let without_os = build_rom_from_jack_dir(dir.path()).unwrap();
+
+        for entry in fs::read_dir("../../12/compiled_os_vm").unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|ext| ext == "vm") {
+                fs::copy(&path, dir.path().join(path.file_name().unwrap())).unwrap();
+            }
+        }
+        let with_os = build_rom_from_jack_dir(dir.path()).unwrap();
+        assert!(with_os.len() > without_os.len());
+    }
+
+    #[test]
+    fn test_rom_from_jack_dir_reports_failing_stage() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(matches!(
+            build_rom_from_jack_dir(dir.path()),
+            Err(PipelineError::NoJackFiles { .. })
+        ));
+
+        fs::write(
+            dir.path().join("Main.jack"),
+            "class Main { function void main() { let x = 1; return; } }",
+        )
+        .unwrap();
+        let err = build_rom_from_jack_dir(dir.path()).unwrap_err();
+        assert!(matches!(&err, PipelineError::Compile { file, .. } if file == "Main.jack"));
+        assert!(
+            err.to_string().starts_with("compiling Main.jack: "),
+            "{err}"
+        );
+
+        fs::write(
+            dir.path().join("Main.jack"),
+            "class Main { function void main() { do Foo.bar(); return; } }",
+        )
+        .unwrap();
+        let err = build_rom_from_jack_dir(dir.path()).unwrap_err();
+        assert!(matches!(err, PipelineError::Translate(_)), "{err}");
+    }
+}
@@ -0,0 +1,117 @@
+//! Peak memory of translating a large `.vm` file, memory-mapped versus read.
+//!
+//! Run with `cargo bench --features mmap --bench mmap` (Linux: reads
+//! `/proc/self/status`). Translates a generated ~18 MB file once through
+//! [`translate_file`] (which maps it) and once by reading it into a `String`
+//! first (what `translate_file` does without the feature), each in a fresh
+//! child process so neither inherits the other's heap, and prints the peak
+//! resident set size (`VmHWM`) of both.
+//!
+//! Resident mapped pages count towards RSS just like the heap copy they
+//! replace, so both runs peak at about the same RSS. What mapping saves is
+//! the anonymous copy: the mapped pages are file-backed page cache, which
+//! the kernel can drop under memory pressure instead of swapping.
+
+use std::env;
+use std::fs;
+use std::hint::black_box;
+use std::path::Path;
+use std::process::Command;
+use std::time::Instant;
+use vm_translator::{translate, translate_file};
+
+/// Set in a child process to the route it measures: `mapped` or `read`.
+const ROUTE_VAR: &str = "VM_MMAP_BENCH_ROUTE";
+/// Set in a child process to the generated input file.
+const INPUT_VAR: &str = "VM_MMAP_BENCH_INPUT";
+
+/// Functions in the generated program.
+const FUNCTIONS: usize = 2_000;
+
+/// A generated program: many functions of straight-line code behind long
+/// comments, like the output of a table-generating compiler.
+fn large_program() -> String {
+    let mut source = String::new();
+    for f in 0..FUNCTIONS {
+        source.push_str(&format!("function Table.f{f} 2\n"));
+        for i in 0..100 {
+            source.push_str(&format!(
+                "// table entry {i} of function {f}, generated; do not edit by hand\n"
+            ));
+            source.push_str(&format!("push constant {i}\npop local {}\n", i % 2));
+        }
+        source.push_str("push local 0\nreturn\n");
+    }
+    source
+}
+
+/// A size field of `/proc/self/status` (`VmRSS`, `VmHWM`, ...), in bytes.
+fn status_bytes(field: &str) -> Option<usize> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let value = status
+        .lines()
+        .find_map(|line| line.strip_prefix(field)?.strip_prefix(':'))?;
+    let kb: usize = value.trim().strip_suffix("kB")?.trim().parse().ok()?;
+    Some(kb * 1024)
+}
+
+fn mb(bytes: usize) -> String {
+    format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+/// In a child process: translate `path` by `route`, then print the
+/// process's peak RSS in bytes (empty if unavailable).
+fn measure(route: &str, path: &Path) {
+    match route {
+        "mapped" => {
+            black_box(translate_file(path).unwrap());
+        }
+        _ => {
+            let source = fs::read_to_string(path).unwrap();
+            black_box(translate(&source, "Table").unwrap());
+        }
+    }
+    print!(
+        "{}",
+        status_bytes("VmHWM").map_or(String::new(), |b| b.to_string())
+    );
+}
+
+/// Run this benchmark again as a child measuring `route` on `path`.
+fn run_child(route: &str, path: &Path) -> Option<usize> {
+    let output = Command::new(env::current_exe().unwrap())
+        .env(ROUTE_VAR, route)
+        .env(INPUT_VAR, path)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{route} run failed");
+    String::from_utf8(output.stdout).ok()?.trim().parse().ok()
+}
+
+fn main() {
+    if let (Ok(route), Some(path)) = (env::var(ROUTE_VAR), env::var_os(INPUT_VAR)) {
+        measure(&route, Path::new(&path));
+        return;
+    }
+
+    let dir = env::temp_dir().join(format!("vm-translator-mmap-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("Table.vm");
+    fs::write(&path, large_program()).unwrap();
+    let size = fs::metadata(&path).unwrap().len() as usize;
+    println!("input: {}", mb(size));
+
+    let mut peaks = Vec::new();
+    for route in ["mapped", "read"] {
+        let start = Instant::now();
+        let peak = run_child(route, &path);
+        let shown = peak.map_or("unavailable".to_string(), mb);
+        println!("{route:<7} peak RSS {shown} in {:.0?}", start.elapsed());
+        peaks.push(peak);
+    }
+    if let [Some(mapped), Some(read)] = peaks[..] {
+        println!("difference: {}", mb(read.saturating_sub(mapped)));
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,70 @@
+//! Translation throughput benchmarks.
+//!
+//! Run with `cargo bench`; criterion prints the measured times and the
+//! change against the previous run.
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use std::hint::black_box;
+use std::path::Path;
+use vm_translator::{translate, translate_directory};
+
+const COMMANDS: usize = 10_000;
+
+/// Arithmetic-heavy program: pushes, pops and all nine arithmetic commands.
+fn arithmetic_program() -> String {
+    const BODY: [&str; 10] = [
+        "push constant 7",
+        "push local 2",
+        "add",
+        "push argument 1",
+        "lt",
+        "push static 3",
+        "sub",
+        "not",
+        "pop this 4",
+        "push that 5",
+    ];
+    let mut source = String::from("function Bench.arith 4\n");
+    for line in BODY.iter().cycle().take(COMMANDS) {
+        source.push_str(line);
+        source.push('\n');
+    }
+    source
+}
+
+/// Call/return-heavy program: many small functions calling each other.
+fn call_program() -> String {
+    let mut source = String::new();
+    let functions = COMMANDS / 5;
+    for i in 0..functions {
+        source.push_str(&format!(
+            "function Bench.f{i} 1\npush argument 0\ncall Bench.f{} 1\npop local 0\nreturn\n",
+            (i + 1) % functions
+        ));
+    }
+    source
+}
+
+fn bench_translate(c: &mut Criterion) {
+    let mut group = c.benchmark_group("translate");
+    for (name, source) in [
+        ("arithmetic", arithmetic_program()),
+        ("call_return", call_program()),
+    ] {
+        group.throughput(Throughput::Elements(source.lines().count() as u64));
+        group.bench_with_input(BenchmarkId::new(name, COMMANDS), &source, |b, source| {
+            b.iter(|| translate(black_box(source), "Bench").unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn bench_directory(c: &mut Criterion) {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../FunctionCalls/FibonacciElement");
+    c.bench_function("translate_directory/FibonacciElement", |b| {
+        b.iter(|| translate_directory(black_box(&dir)).unwrap())
+    });
+}
+
+criterion_group!(benches, bench_translate, bench_directory);
+criterion_main!(benches);
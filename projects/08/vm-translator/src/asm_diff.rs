@@ -0,0 +1,193 @@
+//! Semantic comparison of two Hack assembly texts.
+//!
+//! Assembly from two VM translators rarely matches line for line even when
+//! it does the same thing: comments, blank lines and label names differ.
+//! [`compare`] normalizes both texts before comparing them instruction by
+//! instruction:
+//!
+//! - comments, blank lines and whitespace inside instructions are dropped;
+//! - every label defined with `(NAME)` is renamed `L0`, `L1`, ... in order
+//!   of definition, in its definition and in every `@NAME`;
+//! - predefined symbols (`SP`, `R13`, `SCREEN`, ...) become their addresses,
+//!   so `@R13` and `@13` are the same instruction.
+//!
+//! Other symbols (static variables such as `@Main.0`) are compared as
+//! written.
+
+use std::collections::HashMap;
+
+use crate::SourceMapEntry;
+
+/// One instruction or label definition of normalized assembly.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmInstruction {
+    /// The instruction after normalization.
+    pub normalized: String,
+    /// The instruction as written, without its comment.
+    pub original: String,
+    /// 1-based line in the assembly text.
+    pub line: usize,
+}
+
+/// An instruction at which two normalized texts differ.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmDifference {
+    /// Position in both normalized sequences (0-based).
+    pub index: usize,
+    /// Our instruction, or `None` past the end of our assembly.
+    pub ours: Option<AsmInstruction>,
+    /// The reference instruction, or `None` past the end of the reference.
+    pub reference: Option<AsmInstruction>,
+    /// VM source line our instruction was generated from, if known.
+    pub vm_line: Option<usize>,
+}
+
+/// The address of a predefined Hack symbol.
+fn predefined_symbol(name: &str) -> Option<u16> {
+    let address = match name {
+        "SP" => 0,
+        "LCL" => 1,
+        "ARG" => 2,
+        "THIS" => 3,
+        "THAT" => 4,
+        "SCREEN" => 16384,
+        "KBD" => 24576,
+        _ => {
+            let register: u16 = name.strip_prefix('R')?.parse().ok()?;
+            // `R05` is a variable, not R5
+            return (register < 16 && name.len() == register.to_string().len() + 1)
+                .then_some(register);
+        }
+    };
+    Some(address)
+}
+
+/// Normalize assembly text into its instructions and label definitions.
+pub fn normalize(asm: &str) -> Vec<AsmInstruction> {
+    let lines: Vec<(usize, &str, String)> = asm
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.split("//").next().unwrap_or_default().trim()))
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(line, original)| (line, original, original.split_whitespace().collect()))
+        .collect();
+
+    let mut labels = HashMap::new();
+    for (_, _, compact) in &lines {
+        if let Some(name) = label_definition(compact) {
+            let next = labels.len();
+            labels.entry(name.to_string()).or_insert(next);
+        }
+    }
+
+    lines
+        .into_iter()
+        .map(|(line, original, compact)| {
+            let normalized = if let Some(name) = label_definition(&compact) {
+                format!("(L{})", labels[name])
+            } else if let Some(symbol) = compact.strip_prefix('@') {
+                match (labels.get(symbol), predefined_symbol(symbol)) {
+                    (Some(index), _) => format!("@L{index}"),
+                    (None, Some(address)) => format!("@{address}"),
+                    (None, None) => compact,
+                }
+            } else {
+                compact
+            };
+            AsmInstruction {
+                normalized,
+                original: original.to_string(),
+                line,
+            }
+        })
+        .collect()
+}
+
+/// The name defined by a `(NAME)` line.
+fn label_definition(line: &str) -> Option<&str> {
+    line.strip_prefix('(')?.strip_suffix(')')
+}
+
+/// Compare our assembly with a reference, returning at most `limit`
+/// differences in order.
+///
+/// Instructions are compared by position after [`normalize`], so an extra
+/// or missing instruction makes everything after it differ. `source_map`
+/// (from [`translate_with_sourcemap`](crate::translate_with_sourcemap), or
+/// empty) gives each difference the VM line of our instruction. An empty
+/// result means the texts are equivalent.
+pub fn compare(
+    ours: &str,
+    reference: &str,
+    source_map: &[SourceMapEntry],
+    limit: usize,
+) -> Vec<AsmDifference> {
+    let ours = normalize(ours);
+    let reference = normalize(reference);
+    (0..ours.len().max(reference.len()))
+        .filter(|&i| {
+            ours.get(i).map(|ins| &ins.normalized) != reference.get(i).map(|ins| &ins.normalized)
+        })
+        .take(limit)
+        .map(|index| {
+            let ours = ours.get(index).cloned();
+            let vm_line = ours.as_ref().and_then(|ins| {
+                source_map
+                    .iter()
+                    .find(|entry| entry.asm_lines.contains(&ins.line))
+                    .map(|entry| entry.vm_line)
+            });
+            AsmDifference {
+                index,
+                ours,
+                reference: reference.get(index).cloned(),
+                vm_line,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_equivalent_with_different_labels() {
+        let ours = "// push constant 1\n@1\nD=A\n(Main.f$LOOP)\n@Main.f$LOOP\nD;JNE\n\
+                    @Main.f$ret.0\n0;JMP\n(Main.f$ret.0)\n@R13\nM=D\n@Main.0\n";
+        let reference = "@1\nD = A   // one\n\n(LABEL_7)\n@LABEL_7\nD;JNE\n@RET_3\n0;JMP\n\
+                         (RET_3)\n@13\nM=D\n@Main.0\n";
+        assert_eq!(compare(ours, reference, &[], 10), vec![]);
+
+        let normalized: Vec<String> = normalize(ours).into_iter().map(|i| i.normalized).collect();
+        assert_eq!(normalized[2..6], ["(L0)", "@L0", "D;JNE", "@L1"]);
+        assert_eq!(normalized[8], "@13");
+    }
+
+    #[test]
+    fn test_different_instructions() {
+        let ours = "@SP\nAM=M-1\nD=M+1\n@END\nD;JNE\n(END)\n";
+        let reference = "@SP\nAM=M-1\nD=M\n@END\nD;JEQ\n(END)\n@R05\n";
+        let diffs = compare(ours, reference, &[], 10);
+        assert_eq!(diffs.len(), 3);
+        assert_eq!(diffs[0].index, 2);
+        assert_eq!(diffs[0].ours.as_ref().unwrap().original, "D=M+1");
+        assert_eq!(diffs[0].reference.as_ref().unwrap().original, "D=M");
+        assert_eq!(diffs[1].ours.as_ref().unwrap().line, 5);
+        // `@R05` is a variable, and ours has ended
+        assert_eq!(diffs[2].ours, None);
+        assert_eq!(diffs[2].reference.as_ref().unwrap().normalized, "@R05");
+
+        assert_eq!(compare(ours, reference, &[], 1).len(), 1);
+    }
+
+    #[test]
+    fn test_vm_line_from_source_map() {
+        let source = "push constant 7\nneg\n";
+        let (ours, map) = crate::translate_with_sourcemap(source, "Main").unwrap();
+        let reference = ours.replace("M=-M", "M=!M");
+        let diffs = compare(&ours, &reference, &map, 10);
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].vm_line, Some(2));
+    }
+}
@@ -2,10 +2,33 @@
 //!
 //! Generates optimized assembly with zero-allocation hot paths.
 
-use crate::memory::{SegmentAccess, pointer_symbol, segment_access, temp_address};
-use crate::parser::{ArithmeticOp, Segment, VMCommand};
+use std::collections::HashMap;
+
+use crate::error::{OperandError, Result, VMError};
+use crate::memory::{
+    DEFAULT_SCRATCH_BASE, SCRATCH_REGISTER_COUNT, SegmentAccess, TEMP_BASE, TEMP_SIZE,
+    is_scratch_register, is_valid_scratch_base, is_valid_temp_base, pointer_symbol, segment_access,
+};
+use crate::parser::{ArithmeticOp, Segment, VMCommand, validate_index, validate_pop};
+
+/// How `label`, `goto` and `if-goto` outside any function are named.
+///
+/// The VM spec only defines labels inside functions (`Function$label`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LabelScope {
+    /// Scope to the file: `File$label` (default).
+    #[default]
+    File,
+    /// Reject them; see [`crate::VMError::LabelOutsideFunction`]. Emitted
+    /// as in `File` mode if the generator is driven directly.
+    Strict,
+    /// Use the bare label, as the reference translator does for programs
+    /// without functions (BasicLoop, FibonacciSeries).
+    Global,
+}
 
 /// Code generator for Hack assembly.
+#[derive(Clone)]
 pub struct CodeGenerator {
     /// Counter for unique comparison labels
     label_counter: usize,
@@ -15,27 +38,198 @@ pub struct CodeGenerator {
     static_filename: String,
     /// Current function name for label scoping
     current_function: String,
+    /// First scratch register (R13 by default)
+    scratch_base: u8,
+    /// First temp segment register (R5 by default)
+    temp_base: u16,
+    /// Naming of labels outside functions
+    label_scope: LabelScope,
+    /// Declared function name -> (file, line) of its `function` command
+    functions: HashMap<String, (String, usize)>,
+}
+
+#[cfg(feature = "fs")]
+/// State of a [`CodeGenerator`] before a file, from
+/// [`CodeGenerator::checkpoint`].
+#[derive(Debug)]
+pub(crate) struct CodegenCheckpoint {
+    label_counter: usize,
+    call_counter: usize,
+    static_filename: String,
+    current_function: String,
+}
+
+/// Configuration for a [`CodeGenerator`], validated by
+/// [`build`](Self::build).
+///
+/// ```
+/// use vm_translator::LabelScope;
+/// use vm_translator::codegen::CodeGenerator;
+///
+/// let codegen = CodeGenerator::builder()
+///     .scratch_base(11)
+///     .temp_base(20)
+///     .label_scope(LabelScope::Global)
+///     .build()
+///     .unwrap();
+/// assert_eq!(codegen.temp_address(7).unwrap(), 27);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CodeGeneratorBuilder {
+    scratch_base: u8,
+    temp_base: u16,
+    label_scope: LabelScope,
+}
+
+impl CodeGeneratorBuilder {
+    /// A builder with the defaults of [`CodeGenerator::new`].
+    pub fn new() -> Self {
+        Self {
+            scratch_base: DEFAULT_SCRATCH_BASE,
+            temp_base: TEMP_BASE,
+            label_scope: LabelScope::File,
+        }
+    }
+
+    /// First of the two scratch registers used by `pop`, `call` and
+    /// `return` (default: 13, so R13 and R14). Must be 5 to 14.
+    pub fn scratch_base(mut self, base: u8) -> Self {
+        self.scratch_base = base;
+        self
+    }
+
+    /// First register of the temp segment (default: 5, so R5 to R12). The
+    /// eight slots must fit between THAT (RAM[4]) and the screen.
+    pub fn temp_base(mut self, base: u16) -> Self {
+        self.temp_base = base;
+        self
+    }
+
+    /// Naming of labels outside functions (default: [`LabelScope::File`]).
+    pub fn label_scope(mut self, scope: LabelScope) -> Self {
+        self.label_scope = scope;
+        self
+    }
+
+    /// Create the generator.
+    ///
+    /// Fails with [`VMError::InvalidScratchBase`] or
+    /// [`VMError::InvalidTempBase`] for a base out of range, and with
+    /// [`VMError::TempScratchOverlap`] if a temp slot is a scratch register.
+    pub fn build(self) -> Result<CodeGenerator> {
+        let scratch_base = self.scratch_base;
+        if let Some(index) = (0..TEMP_SIZE)
+            .find(|i| is_scratch_register(self.temp_base.saturating_add(*i), scratch_base))
+        {
+            return Err(VMError::TempScratchOverlap {
+                index,
+                register: self.temp_base + index,
+                scratch_base,
+            });
+        }
+        self.build_overlapping()
+    }
+
+    /// [`build`](Self::build) without the overlap check, for the translator,
+    /// which instead rejects each `push`/`pop temp` of a scratch register.
+    pub(crate) fn build_overlapping(self) -> Result<CodeGenerator> {
+        if !is_valid_scratch_base(self.scratch_base) {
+            return Err(VMError::InvalidScratchBase {
+                base: self.scratch_base,
+            });
+        }
+        let mut codegen = CodeGenerator::new();
+        codegen.set_scratch_base(self.scratch_base);
+        codegen.set_temp_base(self.temp_base)?;
+        codegen.set_label_scope(self.label_scope);
+        Ok(codegen)
+    }
+}
+
+impl Default for CodeGeneratorBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl CodeGenerator {
-    /// Create a new code generator.
+    /// Create a new code generator with the default configuration: scratch
+    /// registers R13–R14, temp segment R5–R12, file-scoped labels.
     pub fn new() -> Self {
         Self {
             label_counter: 0,
             call_counter: 0,
             static_filename: String::new(),
             current_function: String::new(),
+            scratch_base: DEFAULT_SCRATCH_BASE,
+            temp_base: TEMP_BASE,
+            label_scope: LabelScope::File,
+            functions: HashMap::new(),
         }
     }
 
+    /// Configure a code generator; see [`CodeGeneratorBuilder`].
+    pub fn builder() -> CodeGeneratorBuilder {
+        CodeGeneratorBuilder::new()
+    }
+
+    /// Relocate the scratch registers to start at `R{base}`.
+    ///
+    /// The caller is responsible for validating the base;
+    /// [`CodeGeneratorBuilder::build`] does.
+    pub fn set_scratch_base(&mut self, base: u8) {
+        self.scratch_base = base;
+    }
+
+    /// Get the first scratch register number.
+    pub fn scratch_base(&self) -> u8 {
+        self.scratch_base
+    }
+
+    /// Relocate the temp segment to `R{base}`–`R{base + 7}`.
+    ///
+    /// Fails with [`VMError::InvalidTempBase`] unless the eight slots fit
+    /// between THAT (RAM[4]) and the screen (RAM[16384]).
+    pub fn set_temp_base(&mut self, base: u16) -> Result<()> {
+        if !is_valid_temp_base(base) {
+            return Err(VMError::InvalidTempBase { base });
+        }
+        self.temp_base = base;
+        Ok(())
+    }
+
+    /// Get the first temp segment register number.
+    pub fn temp_base(&self) -> u16 {
+        self.temp_base
+    }
+
+    /// RAM address of `temp index` with the configured base.
+    ///
+    /// Fails with [`OperandError::TempIndex`] for an index outside the
+    /// 8-slot window, which the parser would have rejected.
+    pub fn temp_address(&self, index: u16) -> Result<u16> {
+        if index >= TEMP_SIZE {
+            return Err(OperandError::TempIndex(index).into());
+        }
+        Ok(self.temp_base + index)
+    }
+
+    /// Set how labels outside functions are named.
+    pub fn set_label_scope(&mut self, scope: LabelScope) {
+        self.label_scope = scope;
+    }
+
     /// Set the current filename for static variable naming.
     pub fn set_filename(&mut self, filename: &str) {
-        self.static_filename = filename.to_string();
+        self.static_filename.clear();
+        self.static_filename.push_str(filename);
     }
 
     /// Set the current function for label scoping.
     pub fn set_function(&mut self, name: &str) {
-        self.current_function = name.to_string();
+        // Runs for every `function` command: reuse the buffer
+        self.current_function.clear();
+        self.current_function.push_str(name);
     }
 
     /// Get the current function name.
@@ -43,12 +237,58 @@ impl CodeGenerator {
         &self.current_function
     }
 
+    /// Record that `function name` is declared at `file:line`.
+    ///
+    /// A second declaration would emit a second `(name)` label, which the
+    /// assembler only reports against the `.asm`; this fails with
+    /// [`VMError::DuplicateFunction`] naming both declarations instead.
+    pub fn declare_function(&mut self, name: &str, file: &str, line: usize) -> Result<()> {
+        if let Some(first) = self.functions.get(name) {
+            return Err(VMError::DuplicateFunction {
+                name: name.to_string(),
+                first: first.clone(),
+                second: (file.to_string(), line),
+            });
+        }
+        self.functions
+            .insert(name.to_string(), (file.to_string(), line));
+        Ok(())
+    }
+
+    #[cfg(feature = "fs")]
+    /// The per-file state, for [`rollback`](Self::rollback).
+    pub(crate) fn checkpoint(&self) -> CodegenCheckpoint {
+        CodegenCheckpoint {
+            label_counter: self.label_counter,
+            call_counter: self.call_counter,
+            static_filename: self.static_filename.clone(),
+            current_function: self.current_function.clone(),
+        }
+    }
+
+    #[cfg(feature = "fs")]
+    /// Abandon the translation of `file`: restore `checkpoint`, taken
+    /// before it, and forget the functions it declared.
+    pub(crate) fn rollback(&mut self, checkpoint: CodegenCheckpoint, file: &str) {
+        self.label_counter = checkpoint.label_counter;
+        self.call_counter = checkpoint.call_counter;
+        self.static_filename = checkpoint.static_filename;
+        self.current_function = checkpoint.current_function;
+        self.functions
+            .retain(|_, (declared_in, _)| declared_in != file);
+    }
+
     /// Translate a VM command to Hack assembly.
-    pub fn translate(&mut self, cmd: &VMCommand, buf: &mut String) {
+    ///
+    /// Fails, leaving `buf` unchanged, only for a `push`/`pop` operand the
+    /// parser would have rejected (see [`crate::parser::validate_index`]).
+    pub fn translate(&mut self, cmd: &VMCommand, buf: &mut String) -> Result<()> {
         match cmd {
             VMCommand::Arithmetic(op) => self.translate_arithmetic(*op, buf),
-            VMCommand::Push { segment, index } => self.translate_push(*segment, *index, buf),
-            VMCommand::Pop { segment, index } => self.translate_pop(*segment, *index, buf),
+            VMCommand::Push { segment, index } => {
+                return self.translate_push(*segment, *index, buf);
+            }
+            VMCommand::Pop { segment, index } => return self.translate_pop(*segment, *index, buf),
             VMCommand::Label { name } => self.translate_label(name, buf),
             VMCommand::Goto { label } => self.translate_goto(label, buf),
             VMCommand::IfGoto { label } => self.translate_if_goto(label, buf),
@@ -58,6 +298,7 @@ impl CodeGenerator {
             VMCommand::Call { name, num_args } => self.translate_call(name, *num_args, buf),
             VMCommand::Return => self.translate_return(buf),
         }
+        Ok(())
     }
 
     // =========================================================================
@@ -124,7 +365,8 @@ impl CodeGenerator {
     // Memory Access Commands
     // =========================================================================
 
-    fn translate_push(&self, segment: Segment, index: u16, buf: &mut String) {
+    fn translate_push(&self, segment: Segment, index: u16, buf: &mut String) -> Result<()> {
+        validate_index(segment, index)?;
         match segment_access(segment) {
             SegmentAccess::Constant => {
                 // @index, D=A, push D
@@ -143,7 +385,7 @@ impl CodeGenerator {
             SegmentAccess::Direct => {
                 if segment == Segment::Temp {
                     buf.push('@');
-                    write_u16(temp_address(index), buf);
+                    write_u16(self.temp_address(index)?, buf);
                     buf.push_str("\nD=M\n@SP\nA=M\nM=D\n@SP\nM=M+1\n");
                 } else {
                     // Pointer
@@ -160,27 +402,29 @@ impl CodeGenerator {
                 buf.push_str("\nD=M\n@SP\nA=M\nM=D\n@SP\nM=M+1\n");
             }
         }
+        Ok(())
     }
 
-    fn translate_pop(&self, segment: Segment, index: u16, buf: &mut String) {
+    fn translate_pop(&self, segment: Segment, index: u16, buf: &mut String) -> Result<()> {
+        validate_pop(segment, index)?;
         match segment_access(segment) {
-            SegmentAccess::Constant => {
-                // Parser validates this - dead code path
-                // Debug builds catch if invariant is violated
-                debug_assert!(false, "pop to constant should be caught by parser");
-            }
+            SegmentAccess::Constant => unreachable!("rejected by validate_pop"),
             SegmentAccess::Indirect(base) => {
                 // Calculate address, store in R13, pop into address
                 buf.push('@');
                 write_u16(index, buf);
                 buf.push_str("\nD=A\n@");
                 buf.push_str(base);
-                buf.push_str("\nD=D+M\n@R13\nM=D\n@SP\nAM=M-1\nD=M\n@R13\nA=M\nM=D\n");
+                buf.push_str("\nD=D+M\n");
+                self.write_scratch(0, buf);
+                buf.push_str("M=D\n@SP\nAM=M-1\nD=M\n");
+                self.write_scratch(0, buf);
+                buf.push_str("A=M\nM=D\n");
             }
             SegmentAccess::Direct => {
                 if segment == Segment::Temp {
                     buf.push_str("@SP\nAM=M-1\nD=M\n@");
-                    write_u16(temp_address(index), buf);
+                    write_u16(self.temp_address(index)?, buf);
                     buf.push_str("\nM=D\n");
                 } else {
                     // Pointer
@@ -197,6 +441,7 @@ impl CodeGenerator {
                 buf.push_str("\nM=D\n");
             }
         }
+        Ok(())
     }
 
     // =========================================================================
@@ -221,13 +466,24 @@ impl CodeGenerator {
         buf.push_str("\nD;JNE\n");
     }
 
+    /// Translate `not` immediately followed by `if-goto label` as one jump.
+    ///
+    /// `not x` is non-zero unless `x` is -1 (bitwise, so for every value,
+    /// not only the 0/-1 booleans comparisons produce), so this jumps when
+    /// `x + 1` is non-zero and never writes `!x` back to the stack.
+    pub fn translate_not_if_goto(&self, label: &str, buf: &mut String) {
+        buf.push_str("@SP\nAM=M-1\nD=M+1\n@");
+        self.write_scoped_label(label, buf);
+        buf.push_str("\nD;JNE\n");
+    }
+
     /// Write a function-scoped label without allocation.
     #[inline]
     fn write_scoped_label(&self, label: &str, buf: &mut String) {
         if !self.current_function.is_empty() {
             buf.push_str(&self.current_function);
             buf.push('$');
-        } else if !self.static_filename.is_empty() {
+        } else if self.label_scope != LabelScope::Global && !self.static_filename.is_empty() {
             buf.push_str(&self.static_filename);
             buf.push('$');
         }
@@ -307,11 +563,15 @@ impl CodeGenerator {
     }
 
     fn translate_return(&self, buf: &mut String) {
-        // frame = LCL (store in R13)
-        buf.push_str("@LCL\nD=M\n@R13\nM=D\n");
+        // frame = LCL (store in scratch 0)
+        buf.push_str("@LCL\nD=M\n");
+        self.write_scratch(0, buf);
+        buf.push_str("M=D\n");
 
-        // retAddr = *(frame - 5) (store in R14)
-        buf.push_str("@5\nA=D-A\nD=M\n@R14\nM=D\n");
+        // retAddr = *(frame - 5) (store in scratch 1)
+        buf.push_str("@5\nA=D-A\nD=M\n");
+        self.write_scratch(1, buf);
+        buf.push_str("M=D\n");
 
         // *ARG = pop()
         buf.push_str("@SP\nAM=M-1\nD=M\n@ARG\nA=M\nM=D\n");
@@ -320,19 +580,33 @@ impl CodeGenerator {
         buf.push_str("@ARG\nD=M+1\n@SP\nM=D\n");
 
         // THAT = *(frame - 1)
-        buf.push_str("@R13\nAM=M-1\nD=M\n@THAT\nM=D\n");
+        self.write_scratch(0, buf);
+        buf.push_str("AM=M-1\nD=M\n@THAT\nM=D\n");
 
         // THIS = *(frame - 2)
-        buf.push_str("@R13\nAM=M-1\nD=M\n@THIS\nM=D\n");
+        self.write_scratch(0, buf);
+        buf.push_str("AM=M-1\nD=M\n@THIS\nM=D\n");
 
         // ARG = *(frame - 3)
-        buf.push_str("@R13\nAM=M-1\nD=M\n@ARG\nM=D\n");
+        self.write_scratch(0, buf);
+        buf.push_str("AM=M-1\nD=M\n@ARG\nM=D\n");
 
         // LCL = *(frame - 4)
-        buf.push_str("@R13\nAM=M-1\nD=M\n@LCL\nM=D\n");
+        self.write_scratch(0, buf);
+        buf.push_str("AM=M-1\nD=M\n@LCL\nM=D\n");
 
         // goto retAddr
-        buf.push_str("@R14\nA=M\n0;JMP\n");
+        self.write_scratch(1, buf);
+        buf.push_str("A=M\n0;JMP\n");
+    }
+
+    /// Write `@R{base + slot}` for a scratch register without allocation.
+    #[inline]
+    fn write_scratch(&self, slot: u8, buf: &mut String) {
+        debug_assert!(slot < SCRATCH_REGISTER_COUNT, "scratch slot out of range");
+        buf.push_str("@R");
+        write_u16(u16::from(self.scratch_base + slot), buf);
+        buf.push('\n');
     }
 }
 
@@ -370,6 +644,31 @@ fn write_u16(n: u16, buf: &mut String) {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_label_scope_outside_function() {
+        let goto = VMCommand::Goto {
+            label: "LOOP".to_string(),
+        };
+        for (scope, expected) in [
+            (LabelScope::File, "@Main$LOOP\n"),
+            (LabelScope::Strict, "@Main$LOOP\n"),
+            (LabelScope::Global, "@LOOP\n"),
+        ] {
+            let mut cgen = CodeGenerator::new();
+            cgen.set_filename("Main");
+            cgen.set_label_scope(scope);
+            let mut buf = String::new();
+            cgen.translate(&goto, &mut buf).unwrap();
+            assert!(buf.starts_with(expected), "{scope:?}: {buf}");
+
+            // Inside a function the scope never matters
+            cgen.set_function("Main.f");
+            buf.clear();
+            cgen.translate(&goto, &mut buf).unwrap();
+            assert!(buf.starts_with("@Main.f$LOOP\n"), "{scope:?}: {buf}");
+        }
+    }
+
     #[test]
     fn test_translate_add() {
         let cgen = CodeGenerator::new();
@@ -383,7 +682,7 @@ mod tests {
     fn test_translate_push_constant() {
         let cgen = CodeGenerator::new();
         let mut buf = String::new();
-        cgen.translate_push(Segment::Constant, 7, &mut buf);
+        cgen.translate_push(Segment::Constant, 7, &mut buf).unwrap();
         assert!(buf.contains("@7"));
         assert!(buf.contains("D=A"));
         assert!(buf.contains("M=M+1"));
@@ -393,7 +692,7 @@ mod tests {
     fn test_translate_push_local() {
         let cgen = CodeGenerator::new();
         let mut buf = String::new();
-        cgen.translate_push(Segment::Local, 2, &mut buf);
+        cgen.translate_push(Segment::Local, 2, &mut buf).unwrap();
         assert!(buf.contains("@2"));
         assert!(buf.contains("@LCL"));
         assert!(buf.contains("A=D+M"));
@@ -403,7 +702,7 @@ mod tests {
     fn test_translate_pop_local() {
         let cgen = CodeGenerator::new();
         let mut buf = String::new();
-        cgen.translate_pop(Segment::Local, 3, &mut buf);
+        cgen.translate_pop(Segment::Local, 3, &mut buf).unwrap();
         assert!(buf.contains("@3"));
         assert!(buf.contains("@LCL"));
         assert!(buf.contains("@R13"));
@@ -438,6 +737,15 @@ mod tests {
         assert!(buf.contains("D;JNE"));
     }
 
+    #[test]
+    fn test_translate_not_if_goto() {
+        let mut cgen = CodeGenerator::new();
+        cgen.set_function("Foo.bar");
+        let mut buf = String::new();
+        cgen.translate_not_if_goto("END", &mut buf);
+        assert_eq!(buf, "@SP\nAM=M-1\nD=M+1\n@Foo.bar$END\nD;JNE\n");
+    }
+
     #[test]
     fn test_translate_function() {
         let mut cgen = CodeGenerator::new();
@@ -470,6 +778,145 @@ mod tests {
         assert!(buf.contains("A=M\n0;JMP"));
     }
 
+    #[test]
+    fn test_relocated_scratch_registers() {
+        let mut cgen = CodeGenerator::new();
+        cgen.set_scratch_base(5);
+        let mut buf = String::new();
+        cgen.translate_pop(Segment::Local, 1, &mut buf).unwrap();
+        cgen.translate_return(&mut buf);
+        assert!(buf.contains("@R5\n"));
+        assert!(buf.contains("@R6\nA=M\n0;JMP"));
+        assert!(!buf.contains("@R13"));
+        assert!(!buf.contains("@R14"));
+    }
+
+    #[test]
+    fn test_relocated_temp_segment() {
+        let mut cgen = CodeGenerator::new();
+        cgen.set_temp_base(20).unwrap();
+        let mut buf = String::new();
+        cgen.translate_pop(Segment::Temp, 0, &mut buf).unwrap();
+        assert_eq!(buf, "@SP\nAM=M-1\nD=M\n@20\nM=D\n");
+
+        buf.clear();
+        cgen.translate_push(Segment::Temp, 7, &mut buf).unwrap();
+        assert!(buf.starts_with("@27\nD=M\n"));
+
+        for base in [4, 16377] {
+            assert!(matches!(
+                cgen.set_temp_base(base),
+                Err(VMError::InvalidTempBase { base: b }) if b == base
+            ));
+        }
+        assert_eq!(cgen.temp_base(), 20);
+        cgen.set_temp_base(16376).unwrap();
+    }
+
+    #[test]
+    fn test_builder() {
+        let mut buf = String::new();
+        let cgen = CodeGenerator::builder().build().unwrap();
+        cgen.translate_pop(Segment::Temp, 0, &mut buf).unwrap();
+        let mut expected = String::new();
+        CodeGenerator::new()
+            .translate_pop(Segment::Temp, 0, &mut expected)
+            .unwrap();
+        assert_eq!(buf, expected);
+
+        let cgen = CodeGenerator::builder()
+            .scratch_base(5)
+            .temp_base(20)
+            .build()
+            .unwrap();
+        assert_eq!((cgen.scratch_base(), cgen.temp_base()), (5, 20));
+        buf.clear();
+        cgen.translate_pop(Segment::Local, 1, &mut buf).unwrap();
+        assert!(buf.contains("@R5\n"));
+
+        assert!(matches!(
+            CodeGenerator::builder().scratch_base(15).build(),
+            Err(VMError::InvalidScratchBase { base: 15 })
+        ));
+        assert!(matches!(
+            CodeGenerator::builder().temp_base(4).build(),
+            Err(VMError::InvalidTempBase { base: 4 })
+        ));
+    }
+
+    #[test]
+    fn test_builder_rejects_temp_scratch_overlap() {
+        // Default temp segment R5-R12
+        assert!(matches!(
+            CodeGenerator::builder().scratch_base(11).build(),
+            Err(VMError::TempScratchOverlap {
+                index: 6,
+                register: 11,
+                scratch_base: 11
+            })
+        ));
+        // Relocated temp segment over the default scratch registers
+        assert!(matches!(
+            CodeGenerator::builder().temp_base(14).build(),
+            Err(VMError::TempScratchOverlap {
+                index: 0,
+                register: 14,
+                scratch_base: 13
+            })
+        ));
+        assert!(matches!(
+            CodeGenerator::builder().temp_base(6).build(),
+            Err(VMError::TempScratchOverlap { index: 7, .. })
+        ));
+        // Clear of each other
+        CodeGenerator::builder()
+            .scratch_base(11)
+            .temp_base(16)
+            .build()
+            .unwrap();
+        CodeGenerator::builder().temp_base(15).build().unwrap();
+    }
+
+    #[test]
+    fn test_invalid_operands_are_errors() {
+        let mut cgen = CodeGenerator::new();
+        cgen.set_temp_base(20).unwrap();
+        assert!(matches!(
+            cgen.temp_address(8),
+            Err(VMError::InvalidOperand(OperandError::TempIndex(8)))
+        ));
+        let mut buf = String::new();
+        for (cmd, expected) in [
+            (
+                VMCommand::Pop {
+                    segment: Segment::Temp,
+                    index: 8,
+                },
+                OperandError::TempIndex(8),
+            ),
+            (
+                VMCommand::Push {
+                    segment: Segment::Pointer,
+                    index: 2,
+                },
+                OperandError::PointerIndex(2),
+            ),
+            (
+                VMCommand::Pop {
+                    segment: Segment::Constant,
+                    index: 0,
+                },
+                OperandError::PopToConstant,
+            ),
+        ] {
+            assert!(matches!(
+                cgen.translate(&cmd, &mut buf),
+                Err(VMError::InvalidOperand(e)) if e == expected
+            ));
+        }
+        assert_eq!(buf, "");
+    }
+
     #[test]
     fn test_write_u16() {
         let mut buf = String::new();
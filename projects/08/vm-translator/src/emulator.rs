@@ -0,0 +1,601 @@
+//! Direct execution of VM programs.
+//!
+//! [`Emulator`] runs parsed VM commands on a model of the Hack RAM instead of
+//! going through assembly. It follows the memory layout and calling
+//! convention of the translated code: the stack starts at 256, a frame holds
+//! the return address and the caller's LCL, ARG, THIS and THAT, and static
+//! variables are allocated from RAM[16] in order of first use, as the
+//! assembler allocates the translated `File.i` symbols.
+//!
+//! The OS is not built in: calls into OS classes need the OS `.vm` files to
+//! be part of the program. A run halts when it falls off the end of the
+//! program (or returns from the bootstrap call), executes a `goto` to itself
+//! (the `label END` / `goto END` idiom), or calls `Sys.halt`.
+
+use std::collections::HashMap;
+#[cfg(feature = "fs")]
+use std::fs;
+#[cfg(feature = "fs")]
+use std::path::Path;
+
+use crate::error::{Result, VMError};
+#[cfg(feature = "fs")]
+use crate::files::directory_files;
+use crate::memory::TEMP_BASE;
+use crate::parser::{ArithmeticOp, ParseOptions, Segment, VMCommand, parse_line_with_options};
+
+/// Number of RAM words (the Hack address space).
+pub const RAM_SIZE: usize = 32768;
+
+/// Initial stack pointer, as set by bootstrap code.
+pub const STACK_BASE: i16 = 256;
+
+/// First RAM address of static variables.
+const STATIC_BASE: u16 = 16;
+
+const SP: usize = 0;
+const LCL: usize = 1;
+const ARG: usize = 2;
+const THIS: usize = 3;
+const THAT: usize = 4;
+
+/// Where a `push` reads from or a `pop` writes to.
+#[derive(Debug, Clone, Copy)]
+enum Location {
+    Constant(i16),
+    /// A fixed RAM address (static, temp, pointer).
+    Direct(u16),
+    /// `RAM[RAM[pointer] + index]` (local, argument, this, that).
+    Indirect {
+        pointer: usize,
+        index: u16,
+    },
+}
+
+/// A command with its jump and call targets resolved.
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    Arithmetic(ArithmeticOp),
+    Push(Location),
+    Pop(Location),
+    Goto(usize),
+    IfGoto(usize),
+    Function {
+        num_locals: u16,
+    },
+    /// `target` is `None` for an undefined function, an error once reached.
+    Call {
+        name: usize,
+        target: Option<usize>,
+        num_args: u16,
+    },
+    /// `call Sys.halt`.
+    Halt,
+    Return,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Instruction {
+    op: Op,
+    file: usize,
+    line: usize,
+}
+
+/// A VM program loaded for execution, with its RAM.
+#[derive(Debug, Clone)]
+pub struct Emulator {
+    program: Vec<Instruction>,
+    files: Vec<String>,
+    /// Names of called functions, indexed by `Op::Call::name`.
+    callees: Vec<String>,
+    ram: Vec<i16>,
+    pc: usize,
+    halted: bool,
+}
+
+impl Emulator {
+    /// Load a `.vm` file or a directory of them.
+    ///
+    /// As in translation, a directory is run from bootstrap code (SP = 256,
+    /// `call Sys.init 0`) when it contains `Sys.vm`. Otherwise execution
+    /// starts at the first command with SP = 256.
+    #[cfg(feature = "fs")]
+    pub fn from_path(path: &Path) -> Result<Self> {
+        Self::from_path_with_options(path, &ParseOptions::default())
+    }
+
+    /// [`from_path`](Self::from_path), parsing with `options`.
+    #[cfg(feature = "fs")]
+    pub fn from_path_with_options(path: &Path, options: &ParseOptions) -> Result<Self> {
+        let (files, bootstrap) = if path.is_dir() {
+            let files = directory_files(path)?;
+            let bootstrap = files
+                .first()
+                .is_some_and(|f| f.file_stem() == Some("Sys".as_ref()));
+            (files, bootstrap)
+        } else {
+            (vec![path.to_path_buf()], false)
+        };
+
+        let mut sources = Vec::with_capacity(files.len());
+        for file in &files {
+            let name = file
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or("Unknown");
+            let source = fs::read_to_string(file).map_err(|e| VMError::FileRead {
+                path: file.display().to_string(),
+                source: e,
+            })?;
+            sources.push((name, source));
+        }
+        let sources: Vec<(&str, &str)> = sources.iter().map(|(n, s)| (*n, s.as_str())).collect();
+        Self::with_options(&sources, bootstrap, options)
+    }
+
+    /// Load a program from `(name, source)` pairs, optionally bootstrapped.
+    ///
+    /// Labels and functions are resolved up front: a jump to an undefined
+    /// label is an error here, a call to an undefined function only when
+    /// it is executed.
+    pub fn new(sources: &[(&str, &str)], bootstrap: bool) -> Result<Self> {
+        Self::with_options(sources, bootstrap, &ParseOptions::default())
+    }
+
+    /// [`new`](Self::new), parsing with `options`.
+    pub fn with_options(
+        sources: &[(&str, &str)],
+        bootstrap: bool,
+        options: &ParseOptions,
+    ) -> Result<Self> {
+        let mut loader = Loader::default();
+        for (file, (name, source)) in sources.iter().enumerate() {
+            loader.files.push(name.to_string());
+            let mut scope = name.to_string();
+            for (i, line) in source.lines().enumerate() {
+                if let Some(cmd) = parse_line_with_options(line, i + 1, name, options)? {
+                    loader.add(cmd, file, i + 1, &mut scope);
+                }
+            }
+        }
+        let program = loader.resolve()?;
+
+        let mut emulator = Self {
+            program,
+            files: loader.files,
+            callees: loader.callees,
+            ram: vec![0; RAM_SIZE],
+            pc: 0,
+            halted: false,
+        };
+        emulator.ram[SP] = STACK_BASE;
+        if bootstrap {
+            let target = loader.functions.get("Sys.init").copied().ok_or_else(|| {
+                VMError::UndefinedFunction {
+                    line: 0,
+                    file: "<bootstrap>".to_string(),
+                    name: "Sys.init".to_string(),
+                }
+            })?;
+            // Returning from Sys.init ends the run
+            let end = emulator.program.len();
+            emulator.call(target, 0, end, 0, 0)?;
+        }
+        Ok(emulator)
+    }
+
+    /// Run until the program halts or `max_steps` commands have executed.
+    ///
+    /// Returns the number of commands executed.
+    pub fn run(&mut self, max_steps: u64) -> Result<u64> {
+        let mut steps = 0;
+        while !self.halted {
+            if steps == max_steps {
+                return Err(VMError::StepLimitExceeded { steps });
+            }
+            self.step()?;
+            steps += 1;
+        }
+        Ok(steps)
+    }
+
+    /// Execute a single command (halting if the program has ended).
+    pub fn step(&mut self) -> Result<()> {
+        let Some(&Instruction { op, file, line }) = self.program.get(self.pc) else {
+            self.halted = true;
+            return Ok(());
+        };
+        let next = self.pc + 1;
+        self.pc = next;
+
+        match op {
+            Op::Arithmetic(op) => self.arithmetic(op, file, line)?,
+            Op::Push(location) => {
+                let value = self.load(location, file, line)?;
+                self.push(value, file, line)?;
+            }
+            Op::Pop(location) => {
+                let value = self.pop(file, line)?;
+                let address = self.address(location, file, line)?;
+                self.ram[address] = value;
+            }
+            Op::Goto(target) => {
+                // A jump to itself loops forever: treat it as halting
+                self.halted = target == next - 1;
+                self.pc = target;
+            }
+            Op::IfGoto(target) => {
+                if self.pop(file, line)? != 0 {
+                    self.pc = target;
+                }
+            }
+            Op::Function { num_locals } => {
+                for _ in 0..num_locals {
+                    self.push(0, file, line)?;
+                }
+            }
+            Op::Call {
+                name,
+                target,
+                num_args,
+            } => {
+                let target = target.ok_or_else(|| VMError::UndefinedFunction {
+                    line,
+                    file: self.files[file].clone(),
+                    name: self.callees[name].clone(),
+                })?;
+                self.call(target, num_args, next, file, line)?;
+            }
+            Op::Halt => self.halted = true,
+            Op::Return => self.ret(file, line)?,
+        }
+        Ok(())
+    }
+
+    /// The RAM contents.
+    pub fn ram(&self) -> &[i16] {
+        &self.ram
+    }
+
+    /// Mutable RAM, e.g. to set up segment pointers before a run.
+    pub fn ram_mut(&mut self) -> &mut [i16] {
+        &mut self.ram
+    }
+
+    /// The stack pointer, RAM[0].
+    pub fn sp(&self) -> i16 {
+        self.ram[SP]
+    }
+
+    /// Whether the program has halted.
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    fn arithmetic(&mut self, op: ArithmeticOp, file: usize, line: usize) -> Result<()> {
+        let y = self.pop(file, line)?;
+        let value = match op {
+            ArithmeticOp::Neg => y.wrapping_neg(),
+            ArithmeticOp::Not => !y,
+            _ => {
+                let x = self.pop(file, line)?;
+                match op {
+                    ArithmeticOp::Add => x.wrapping_add(y),
+                    ArithmeticOp::Sub => x.wrapping_sub(y),
+                    ArithmeticOp::Eq => -i16::from(x == y),
+                    ArithmeticOp::Lt => -i16::from(x < y),
+                    ArithmeticOp::Gt => -i16::from(x > y),
+                    ArithmeticOp::And => x & y,
+                    ArithmeticOp::Or => x | y,
+                    ArithmeticOp::Neg | ArithmeticOp::Not => unreachable!(),
+                }
+            }
+        };
+        self.push(value, file, line)
+    }
+
+    /// Push a frame and jump to `target`.
+    fn call(
+        &mut self,
+        target: usize,
+        num_args: u16,
+        return_address: usize,
+        file: usize,
+        line: usize,
+    ) -> Result<()> {
+        self.push(return_address as i16, file, line)?;
+        for pointer in [LCL, ARG, THIS, THAT] {
+            self.push(self.ram[pointer], file, line)?;
+        }
+        let sp = self.ram[SP];
+        self.ram[ARG] = sp.wrapping_sub(5).wrapping_sub(num_args as i16);
+        self.ram[LCL] = sp;
+        self.pc = target;
+        Ok(())
+    }
+
+    /// Return to the caller's frame.
+    fn ret(&mut self, file: usize, line: usize) -> Result<()> {
+        let frame = i32::from(self.ram[LCL]);
+        let return_address = self.read(frame - 5, file, line)? as u16 as usize;
+        let value = self.pop(file, line)?;
+        let arg = self.checked(i32::from(self.ram[ARG]), file, line)?;
+        self.ram[arg] = value;
+        self.ram[SP] = self.ram[ARG].wrapping_add(1);
+        for (offset, pointer) in [(1, THAT), (2, THIS), (3, ARG), (4, LCL)] {
+            self.ram[pointer] = self.read(frame - offset, file, line)?;
+        }
+        self.pc = return_address;
+        self.halted = return_address >= self.program.len();
+        Ok(())
+    }
+
+    fn push(&mut self, value: i16, file: usize, line: usize) -> Result<()> {
+        let sp = self.checked(i32::from(self.ram[SP]), file, line)?;
+        self.ram[sp] = value;
+        self.ram[SP] = self.ram[SP].wrapping_add(1);
+        Ok(())
+    }
+
+    fn pop(&mut self, file: usize, line: usize) -> Result<i16> {
+        self.ram[SP] = self.ram[SP].wrapping_sub(1);
+        self.read(i32::from(self.ram[SP]), file, line)
+    }
+
+    fn load(&self, location: Location, file: usize, line: usize) -> Result<i16> {
+        match location {
+            Location::Constant(value) => Ok(value),
+            _ => Ok(self.ram[self.address(location, file, line)?]),
+        }
+    }
+
+    fn address(&self, location: Location, file: usize, line: usize) -> Result<usize> {
+        match location {
+            Location::Direct(address) => Ok(usize::from(address)),
+            // `pop constant` is rejected by the parser
+            Location::Constant(_) => unreachable!("constant has no address"),
+            Location::Indirect { pointer, index } => {
+                self.checked(i32::from(self.ram[pointer]) + i32::from(index), file, line)
+            }
+        }
+    }
+
+    fn read(&self, address: i32, file: usize, line: usize) -> Result<i16> {
+        Ok(self.ram[self.checked(address, file, line)?])
+    }
+
+    /// Check that `address` is inside RAM.
+    fn checked(&self, address: i32, file: usize, line: usize) -> Result<usize> {
+        usize::try_from(address)
+            .ok()
+            .filter(|&a| a < RAM_SIZE)
+            .ok_or_else(|| VMError::MemoryOutOfRange {
+                line,
+                file: self.files.get(file).cloned().unwrap_or_default(),
+                address,
+            })
+    }
+}
+
+/// A command before jump targets are resolved.
+struct Pending {
+    cmd: VMCommand,
+    file: usize,
+    line: usize,
+    /// Label scope: the enclosing function, or the file outside functions.
+    scope: String,
+}
+
+/// First pass over a program: positions of labels, functions and statics.
+#[derive(Default)]
+struct Loader {
+    files: Vec<String>,
+    pending: Vec<Pending>,
+    labels: HashMap<(String, String), usize>,
+    functions: HashMap<String, usize>,
+    /// `(file, index)` -> RAM address, in order of first use.
+    statics: HashMap<(usize, u16), u16>,
+    callees: Vec<String>,
+}
+
+impl Loader {
+    fn add(&mut self, cmd: VMCommand, file: usize, line: usize, scope: &mut String) {
+        match cmd {
+            VMCommand::Label { name } => {
+                self.labels
+                    .insert((scope.clone(), name), self.pending.len());
+                return;
+            }
+            VMCommand::Function { ref name, .. } => {
+                scope.clone_from(name);
+                self.functions.insert(name.clone(), self.pending.len());
+            }
+            VMCommand::Push {
+                segment: Segment::Static,
+                index,
+            }
+            | VMCommand::Pop {
+                segment: Segment::Static,
+                index,
+            } => {
+                let next = STATIC_BASE + self.statics.len() as u16;
+                self.statics.entry((file, index)).or_insert(next);
+            }
+            _ => {}
+        }
+        self.pending.push(Pending {
+            cmd,
+            file,
+            line,
+            scope: scope.clone(),
+        });
+    }
+
+    fn resolve(&mut self) -> Result<Vec<Instruction>> {
+        let pending = std::mem::take(&mut self.pending);
+        pending
+            .into_iter()
+            .map(|p| {
+                let op = match p.cmd {
+                    VMCommand::Arithmetic(op) => Op::Arithmetic(op),
+                    VMCommand::Push { segment, index } => {
+                        Op::Push(self.location(segment, index, p.file))
+                    }
+                    VMCommand::Pop { segment, index } => {
+                        Op::Pop(self.location(segment, index, p.file))
+                    }
+                    VMCommand::Goto { label } => {
+                        Op::Goto(self.label(&p.scope, label, p.file, p.line)?)
+                    }
+                    VMCommand::IfGoto { label } => {
+                        Op::IfGoto(self.label(&p.scope, label, p.file, p.line)?)
+                    }
+                    VMCommand::Function { num_locals, .. } => Op::Function { num_locals },
+                    VMCommand::Call { name, .. } if name == "Sys.halt" => Op::Halt,
+                    VMCommand::Call { name, num_args } => {
+                        let target = self.functions.get(&name).copied();
+                        self.callees.push(name);
+                        Op::Call {
+                            name: self.callees.len() - 1,
+                            target,
+                            num_args,
+                        }
+                    }
+                    VMCommand::Return => Op::Return,
+                    VMCommand::Label { .. } => unreachable!("labels are not instructions"),
+                };
+                Ok(Instruction {
+                    op,
+                    file: p.file,
+                    line: p.line,
+                })
+            })
+            .collect()
+    }
+
+    fn location(&self, segment: Segment, index: u16, file: usize) -> Location {
+        match segment {
+            Segment::Constant => Location::Constant(index as i16),
+            Segment::Local => Location::Indirect {
+                pointer: LCL,
+                index,
+            },
+            Segment::Argument => Location::Indirect {
+                pointer: ARG,
+                index,
+            },
+            Segment::This => Location::Indirect {
+                pointer: THIS,
+                index,
+            },
+            Segment::That => Location::Indirect {
+                pointer: THAT,
+                index,
+            },
+            Segment::Pointer => Location::Direct(THIS as u16 + index),
+            Segment::Temp => Location::Direct(TEMP_BASE + index),
+            Segment::Static => Location::Direct(self.statics[&(file, index)]),
+        }
+    }
+
+    fn label(&self, scope: &str, label: String, file: usize, line: usize) -> Result<usize> {
+        let key = (scope.to_string(), label);
+        self.labels
+            .get(&key)
+            .copied()
+            .ok_or_else(|| VMError::UndefinedLabel {
+                line,
+                file: self.files[file].clone(),
+                label: key.1,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(source: &str) -> Emulator {
+        let mut emulator = Emulator::new(&[("Test", source)], false).unwrap();
+        emulator.run(10_000).unwrap();
+        emulator
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let emulator = run("push constant 7\npush constant 8\nadd\n\
+                            push constant 3\npush constant 5\nlt\n\
+                            push constant 1\nneg\nnot");
+        assert_eq!(emulator.sp(), 259);
+        assert_eq!(emulator.ram()[256..259], [15, -1, 0]);
+    }
+
+    #[test]
+    fn test_segments_and_statics() {
+        let emulator = run("push constant 3000\npop pointer 0\n\
+                            push constant 9\npop this 2\n\
+                            push constant 4\npop static 3\n\
+                            push constant 5\npop static 1\n\
+                            push this 2\npush static 3\nadd\npop temp 6");
+        assert_eq!(emulator.ram()[3002], 9);
+        // Allocated from RAM[16] in order of first use
+        assert_eq!(emulator.ram()[16..18], [4, 5]);
+        assert_eq!(emulator.ram()[11], 13);
+        assert_eq!(emulator.sp(), 256);
+    }
+
+    #[test]
+    fn test_call_and_return() {
+        let source = "push constant 5\ncall Test.double 1\nlabel END\ngoto END\n\
+                      function Test.double 1\npush argument 0\npop local 0\n\
+                      push local 0\npush local 0\nadd\nreturn";
+        let emulator = run(source);
+        assert_eq!(emulator.sp(), 257);
+        assert_eq!(emulator.ram()[256], 10);
+    }
+
+    #[test]
+    fn test_halts_on_goto_self() {
+        let mut emulator =
+            Emulator::new(&[("Test", "push constant 1\nlabel END\ngoto END")], false).unwrap();
+        assert_eq!(emulator.run(100).unwrap(), 2);
+        assert!(emulator.is_halted());
+    }
+
+    #[test]
+    fn test_step_limit() {
+        let source = "label LOOP\npush constant 1\nif-goto LOOP";
+        let mut emulator = Emulator::new(&[("Test", source)], false).unwrap();
+        let err = emulator.run(50).unwrap_err();
+        assert_eq!(err.to_string(), "step limit exceeded after 50 steps");
+    }
+
+    #[test]
+    fn test_labels_scoped_by_function() {
+        let source = "function A.f 0\nlabel L\ngoto L\nfunction B.g 0\ngoto L";
+        let err = Emulator::new(&[("Test", source)], false).unwrap_err();
+        assert!(matches!(err, VMError::UndefinedLabel { line: 5, ref label, .. } if label == "L"));
+    }
+
+    #[test]
+    fn test_runtime_errors() {
+        let mut emulator = Emulator::new(&[("Test", "call Math.multiply 2")], false).unwrap();
+        let err = emulator.run(10).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Test:1: call to undefined function Math.multiply"
+        );
+
+        let source = "push constant 32767\npop pointer 1\npush constant 1\npop that 1";
+        let mut emulator = Emulator::new(&[("Test", source)], false).unwrap();
+        let err = emulator.run(10).unwrap_err();
+        assert!(matches!(
+            err,
+            VMError::MemoryOutOfRange {
+                line: 4,
+                address: 32768,
+                ..
+            }
+        ));
+    }
+}
@@ -4,6 +4,23 @@
 
 use thiserror::Error;
 
+/// A `push`/`pop` operand the VM does not allow, found without a source
+/// line; see [`crate::parser::validate_index`].
+///
+/// The parser reports the same problems as [`VMError::PopToConstant`],
+/// [`VMError::InvalidPointerIndex`] and [`VMError::InvalidTempIndex`].
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperandError {
+    #[error("cannot pop to constant segment")]
+    PopToConstant,
+
+    #[error("invalid pointer index {0} (must be 0 or 1)")]
+    PointerIndex(u16),
+
+    #[error("invalid temp index {0} (must be 0-7)")]
+    TempIndex(u16),
+}
+
 /// VM translation error with full context.
 #[derive(Error, Debug)]
 pub enum VMError {
@@ -11,6 +28,7 @@ pub enum VMError {
     #[error("{file}:{line}: invalid command: {command}")]
     InvalidCommand {
         line: usize,
+        column: usize,
         file: String,
         command: String,
     },
@@ -18,10 +36,24 @@ pub enum VMError {
     #[error("{file}:{line}: invalid segment: {segment}")]
     InvalidSegment {
         line: usize,
+        column: usize,
         file: String,
         segment: String,
     },
 
+    #[error(
+        "{file}:{line}: invalid {kind}: {word} (did you mean '{expected}'? {kind}s are lowercase)"
+    )]
+    WrongCase {
+        line: usize,
+        column: usize,
+        file: String,
+        /// `"command"` or `"segment"`.
+        kind: &'static str,
+        word: String,
+        expected: &'static str,
+    },
+
     #[error("{file}:{line}: index {index} out of range for segment {segment}")]
     IndexOutOfRange {
         line: usize,
@@ -31,11 +63,16 @@ pub enum VMError {
     },
 
     #[error("{file}:{line}: cannot pop to constant segment")]
-    PopToConstant { line: usize, file: String },
+    PopToConstant {
+        line: usize,
+        column: usize,
+        file: String,
+    },
 
     #[error("{file}:{line}: invalid pointer index {index} (must be 0 or 1)")]
     InvalidPointerIndex {
         line: usize,
+        column: usize,
         file: String,
         index: u16,
     },
@@ -43,13 +80,19 @@ pub enum VMError {
     #[error("{file}:{line}: invalid temp index {index} (must be 0-7)")]
     InvalidTempIndex {
         line: usize,
+        column: usize,
         file: String,
         index: u16,
     },
 
+    /// An operand checked outside the parser, so with no location.
+    #[error(transparent)]
+    InvalidOperand(#[from] OperandError),
+
     #[error("{file}:{line}: missing argument for {command}")]
     MissingArgument {
         line: usize,
+        column: usize,
         file: String,
         command: String,
     },
@@ -57,6 +100,7 @@ pub enum VMError {
     #[error("{file}:{line}: invalid number: {value}")]
     InvalidNumber {
         line: usize,
+        column: usize,
         file: String,
         value: String,
     },
@@ -65,18 +109,103 @@ pub enum VMError {
     #[error("{file}:{line}: invalid label name: {name}")]
     InvalidLabelName {
         line: usize,
+        column: usize,
         file: String,
         name: String,
     },
 
+    #[error("{file}:{line}: label {label} used outside a function")]
+    LabelOutsideFunction {
+        line: usize,
+        file: String,
+        label: String,
+    },
+
     // Function errors
     #[error("{file}:{line}: invalid function name: {name}")]
     InvalidFunctionName {
         line: usize,
+        column: usize,
         file: String,
         name: String,
     },
 
+    #[error(
+        "{}:{}: function {name} already declared at {}:{}",
+        second.0, second.1, first.0, first.1
+    )]
+    DuplicateFunction {
+        name: String,
+        /// File and line of the first declaration.
+        first: (String, usize),
+        /// File and line of the offending declaration.
+        second: (String, usize),
+    },
+
+    #[error("{file}:{line}: call to undefined function {name}")]
+    UndefinedFunction {
+        line: usize,
+        file: String,
+        name: String,
+    },
+
+    // Register contract errors
+    #[error("invalid scratch base R{base} (must be R5-R14)")]
+    InvalidScratchBase { base: u8 },
+
+    #[error("invalid temp base R{base} (temp 0-7 must fit in R5-R16383)")]
+    InvalidTempBase { base: u16 },
+
+    #[error(
+        "temp {index} (R{register}) collides with scratch registers starting at R{scratch_base}"
+    )]
+    TempScratchOverlap {
+        index: u16,
+        register: u16,
+        scratch_base: u8,
+    },
+
+    #[error("{file}:{line}: temp {index} (R{register}) collides with a scratch register")]
+    ScratchRegisterCollision {
+        line: usize,
+        file: String,
+        index: u16,
+        register: u16,
+    },
+
+    // Manifest errors
+    #[error("{file}:{line}: file not found: {path}")]
+    ManifestFileNotFound {
+        line: usize,
+        file: String,
+        path: String,
+    },
+
+    #[error("{file}:{line}: unknown manifest directive: !{directive}")]
+    InvalidManifestDirective {
+        line: usize,
+        file: String,
+        directive: String,
+    },
+
+    // Emulator errors
+    #[error("{file}:{line}: undefined label: {label}")]
+    UndefinedLabel {
+        line: usize,
+        file: String,
+        label: String,
+    },
+
+    #[error("{file}:{line}: memory access out of range: {address}")]
+    MemoryOutOfRange {
+        line: usize,
+        file: String,
+        address: i32,
+    },
+
+    #[error("step limit exceeded after {steps} steps")]
+    StepLimitExceeded { steps: u64 },
+
     // I/O errors
     #[error("failed to read file {path}: {source}")]
     FileRead {
@@ -97,6 +226,195 @@ pub enum VMError {
 
     #[error("path is not a file or directory: {path}")]
     InvalidPath { path: String },
+
+    #[error("{first} and {second} are both named {stem}; their statics would collide")]
+    DuplicateFileStem {
+        stem: String,
+        first: String,
+        second: String,
+    },
+}
+
+impl VMError {
+    /// Source file and 1-based line the error refers to, if any.
+    pub fn location(&self) -> Option<(&str, usize)> {
+        match self {
+            Self::InvalidCommand { file, line, .. }
+            | Self::InvalidSegment { file, line, .. }
+            | Self::WrongCase { file, line, .. }
+            | Self::IndexOutOfRange { file, line, .. }
+            | Self::PopToConstant { file, line, .. }
+            | Self::InvalidPointerIndex { file, line, .. }
+            | Self::InvalidTempIndex { file, line, .. }
+            | Self::MissingArgument { file, line, .. }
+            | Self::InvalidNumber { file, line, .. }
+            | Self::InvalidLabelName { file, line, .. }
+            | Self::LabelOutsideFunction { file, line, .. }
+            | Self::InvalidFunctionName { file, line, .. }
+            | Self::UndefinedFunction { file, line, .. }
+            | Self::ScratchRegisterCollision { file, line, .. }
+            | Self::ManifestFileNotFound { file, line, .. }
+            | Self::InvalidManifestDirective { file, line, .. }
+            | Self::UndefinedLabel { file, line, .. }
+            | Self::MemoryOutOfRange { file, line, .. } => Some((file, *line)),
+            Self::DuplicateFunction {
+                second: (file, line),
+                ..
+            } => Some((file, *line)),
+            Self::InvalidScratchBase { .. }
+            | Self::InvalidTempBase { .. }
+            | Self::TempScratchOverlap { .. }
+            | Self::StepLimitExceeded { .. }
+            | Self::FileRead { .. }
+            | Self::FileWrite { .. }
+            | Self::NoVmFiles { .. }
+            | Self::InvalidPath { .. }
+            | Self::InvalidOperand(_)
+            | Self::DuplicateFileStem { .. } => None,
+        }
+    }
+
+    /// 1-based column of the offending word, for errors found by the parser.
+    pub fn column(&self) -> Option<usize> {
+        match self {
+            Self::InvalidCommand { column, .. }
+            | Self::InvalidSegment { column, .. }
+            | Self::WrongCase { column, .. }
+            | Self::PopToConstant { column, .. }
+            | Self::InvalidPointerIndex { column, .. }
+            | Self::InvalidTempIndex { column, .. }
+            | Self::MissingArgument { column, .. }
+            | Self::InvalidNumber { column, .. }
+            | Self::InvalidLabelName { column, .. }
+            | Self::InvalidFunctionName { column, .. } => Some(*column),
+            _ => None,
+        }
+    }
+
+    /// The error followed by an excerpt of `source` (the file it refers to):
+    /// the offending line between its neighbours, with carets under the bad
+    /// word, or under the whole command when the column is unknown.
+    ///
+    /// With `color`, ANSI escapes highlight the message, the error line and
+    /// the carets. Errors without a line (or past the end of `source`) are
+    /// formatted as the message alone.
+    pub fn render_with_source(&self, source: &str, color: bool) -> String {
+        let paint = |code: &str, text: &str| {
+            if color {
+                format!("\x1b[{code}m{text}\x1b[0m")
+            } else {
+                text.to_string()
+            }
+        };
+        let mut out = format!("{} {}\n", paint("1;31", "error:"), self);
+
+        let lines: Vec<&str> = source.lines().collect();
+        let Some((_, line)) = self.location() else {
+            return out;
+        };
+        let Some(text) = line.checked_sub(1).and_then(|i| lines.get(i)) else {
+            return out;
+        };
+
+        let first = line.saturating_sub(1).max(1);
+        let last = (line + 1).min(lines.len());
+        let width = last.to_string().len();
+        let gutter = |number: &str| paint("1;34", &format!("{number:>width$} |"));
+
+        out.push_str(&gutter(""));
+        out.push('\n');
+        for number in first..=last {
+            out.push_str(&gutter(&number.to_string()));
+            let excerpt = lines[number - 1];
+            if !excerpt.is_empty() {
+                out.push(' ');
+                if number == line {
+                    out.push_str(&paint("1", excerpt));
+                } else {
+                    out.push_str(excerpt);
+                }
+            }
+            out.push('\n');
+            if number == line {
+                let (start, len) = underline(text, self.column());
+                out.push_str(&gutter(""));
+                out.push(' ');
+                out.push_str(&" ".repeat(start - 1));
+                out.push_str(&paint("1;31", &"^".repeat(len)));
+                out.push('\n');
+            }
+        }
+        out
+    }
+}
+
+/// 1-based start column and length of the text to underline on `line`:
+/// the word at `column`, or the command (without its comment) otherwise.
+fn underline(line: &str, column: Option<usize>) -> (usize, usize) {
+    let code = line.split("//").next().unwrap_or("");
+    let start = match column {
+        Some(column) => column,
+        None => code.chars().take_while(|c| c.is_whitespace()).count() + 1,
+    };
+    let rest: String = code.chars().skip(start - 1).collect();
+    let len = match column {
+        Some(_) => rest.chars().take_while(|c| !c.is_whitespace()).count(),
+        None => rest.trim_end().chars().count(),
+    };
+    (start, len.max(1))
+}
+
+/// Non-fatal diagnostic produced while translating.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum VMWarning {
+    #[error(
+        "{file}:{line}: {name} called with {num_args} argument(s), but with {expected} elsewhere"
+    )]
+    InconsistentArity {
+        line: usize,
+        file: String,
+        name: String,
+        num_args: u16,
+        expected: u16,
+    },
+
+    #[error("{file}:{line}: {function} consumes {needed} value(s) with {depth} on its stack")]
+    StackUnderflow {
+        line: usize,
+        file: String,
+        function: String,
+        needed: u16,
+        depth: u16,
+    },
+
+    #[error("{file}:{line}: {function} returns with {depth} value(s) on its stack instead of 1")]
+    UnbalancedReturn {
+        line: usize,
+        file: String,
+        function: String,
+        depth: u16,
+    },
+
+    #[error("{file}:{line}: {command} outside any function")]
+    OutsideFunction {
+        line: usize,
+        file: String,
+        /// The command's keyword, e.g. `return` or `push`.
+        command: &'static str,
+    },
+}
+
+impl VMWarning {
+    /// Name of the function the warning is about; empty for code outside
+    /// any function.
+    pub fn function_name(&self) -> &str {
+        match self {
+            Self::InconsistentArity { name, .. }
+            | Self::StackUnderflow { function: name, .. }
+            | Self::UnbalancedReturn { function: name, .. } => name,
+            Self::OutsideFunction { .. } => "",
+        }
+    }
 }
 
 /// Result type alias for VM operations.
@@ -110,16 +428,20 @@ mod tests {
     fn test_error_display() {
         let err = VMError::InvalidCommand {
             line: 42,
+            column: 1,
             file: "Test.vm".to_string(),
             command: "foo".to_string(),
         };
         assert_eq!(format!("{}", err), "Test.vm:42: invalid command: foo");
+        assert_eq!(err.location(), Some(("Test.vm", 42)));
+        assert_eq!(VMError::StepLimitExceeded { steps: 1 }.location(), None);
     }
 
     #[test]
     fn test_pop_constant_error() {
         let err = VMError::PopToConstant {
             line: 10,
+            column: 5,
             file: "Main.vm".to_string(),
         };
         assert!(format!("{}", err).contains("cannot pop to constant"));
@@ -136,4 +458,54 @@ mod tests {
         assert!(format!("{}", err).contains("99"));
         assert!(format!("{}", err).contains("temp"));
     }
+
+    const SOURCE: &str = "function Main.main 0\npush constant 1\npush locol 0 // x\nadd\n";
+
+    #[test]
+    fn test_render_with_source() {
+        let err = crate::translate(SOURCE, "Main").unwrap_err();
+        assert_eq!(
+            err.render_with_source(SOURCE, false),
+            "error: Main:3: invalid segment: locol\n  |\n2 | push constant 1\n3 | push locol 0 // x\n  |      ^^^^^\n4 | add\n"
+        );
+    }
+
+    #[test]
+    fn test_render_with_source_edges() {
+        // First line: no line above; missing argument points past the end
+        let err = crate::translate("push constant\nadd\n", "Main").unwrap_err();
+        assert_eq!(
+            err.render_with_source("push constant\nadd\n", false),
+            "error: Main:1: missing argument for push\n  |\n1 | push constant\n  |               ^\n2 | add\n"
+        );
+
+        // No column: the whole command is underlined
+        let err = VMError::UndefinedFunction {
+            line: 4,
+            file: "Main".to_string(),
+            name: "Foo.bar".to_string(),
+        };
+        let source = "\n\n\n  call Foo.bar 0 // here";
+        assert!(
+            err.render_with_source(source, false)
+                .ends_with("4 |   call Foo.bar 0 // here\n  |   ^^^^^^^^^^^^^^\n")
+        );
+
+        // No location: message only
+        let err = VMError::StepLimitExceeded { steps: 3 };
+        assert_eq!(
+            err.render_with_source(SOURCE, false),
+            "error: step limit exceeded after 3 steps\n"
+        );
+    }
+
+    #[test]
+    fn test_render_with_source_color() {
+        let err = crate::translate(SOURCE, "Main").unwrap_err();
+        let colored = err.render_with_source(SOURCE, true);
+        assert!(colored.starts_with("\x1b[1;31merror:\x1b[0m "));
+        assert!(colored.contains("\x1b[1mpush locol 0 // x\x1b[0m"));
+        assert!(colored.contains("\x1b[1;31m^^^^^\x1b[0m"));
+        assert!(!err.render_with_source(SOURCE, false).contains('\x1b'));
+    }
 }
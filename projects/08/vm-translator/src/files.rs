@@ -0,0 +1,485 @@
+//! File-system entry points: single files, directories and manifests.
+//!
+//! Compiled only with the `fs` feature (on by default). Without it the crate
+//! is the string-in/string-out core ([`translate`](crate::translate),
+//! [`translate_program`](crate::translate_program)), e.g. for WASM targets.
+//!
+//! With the `mmap` feature, `.vm` files are memory-mapped and translated
+//! from the mapping instead of being copied into a `String` first, which
+//! saves a heap copy of each multi-megabyte generated file. A file that
+//! cannot be mapped is read as usual.
+
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::bootstrap::generate_bootstrap;
+use crate::codegen::CodegenCheckpoint;
+use crate::error::{Result, VMError, VMWarning};
+use crate::link::LinkChecker;
+use crate::parser::{VMCommand, parse_line_with_options};
+use crate::stack_check::{StackChecker, StackCheckpoint};
+use crate::{Normalization, TranslateOptions, Translation, Translator};
+
+impl Translator {
+    /// The state before a file, for [`rollback`](Self::rollback): lengths
+    /// of what files append to, not copies of it.
+    fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            codegen: self.codegen.checkpoint(),
+            links: self.links.as_ref().map(LinkChecker::checkpoint),
+            stack: self.stack.as_ref().map(StackChecker::checkpoint),
+            warnings: self.warnings.len(),
+            normalizations: self.normalizations.len(),
+            source_map: self.source_map.as_ref().map(Vec::len),
+            in_function: self.in_function,
+        }
+    }
+
+    /// Abandon the translation of `filename`, restoring `checkpoint`.
+    fn rollback(&mut self, checkpoint: Checkpoint, filename: &str) {
+        self.codegen.rollback(checkpoint.codegen, filename);
+        if let (Some(links), Some(calls)) = (&mut self.links, checkpoint.links) {
+            links.rollback(calls);
+        }
+        if let (Some(stack), Some(state)) = (&mut self.stack, checkpoint.stack) {
+            stack.rollback(state);
+        }
+        self.warnings.truncate(checkpoint.warnings);
+        self.normalizations.truncate(checkpoint.normalizations);
+        if let (Some(source_map), Some(len)) = (&mut self.source_map, checkpoint.source_map) {
+            source_map.truncate(len);
+        }
+        self.in_function = checkpoint.in_function;
+    }
+
+    /// Translate a .vm file, named after its stem.
+    fn file(&mut self, path: &Path) -> Result<String> {
+        let filename = file_name(path);
+
+        let mut output = String::new();
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        self.file_marker(&name, &mut output);
+        let asm = with_source(path, |source| self.source(source, filename)).map_err(|e| {
+            VMError::FileRead {
+                path: path.display().to_string(),
+                source: e,
+            }
+        })??;
+        output.push_str(&asm);
+        Ok(output)
+    }
+
+    /// Record the functions a skipped file declares for the link check, so
+    /// calls into it are not reported as undefined. Lines that do not parse
+    /// are ignored.
+    fn declare_functions(&mut self, path: &Path) {
+        let Some(links) = &mut self.links else {
+            return;
+        };
+        let filename = file_name(path);
+        let options = self.parse_options;
+        let _ = with_source(path, |source| {
+            for (i, line) in source.lines().enumerate() {
+                if let Ok(Some(cmd @ VMCommand::Function { .. })) =
+                    parse_line_with_options(line, i + 1, filename, &options)
+                {
+                    links.record(&cmd, filename, i + 1);
+                }
+            }
+        });
+    }
+}
+
+/// State of a [`Translator`] before a file, from [`Translator::checkpoint`].
+struct Checkpoint {
+    codegen: CodegenCheckpoint,
+    links: Option<usize>,
+    stack: Option<StackCheckpoint>,
+    warnings: usize,
+    normalizations: usize,
+    source_map: Option<usize>,
+    in_function: bool,
+}
+
+/// The name a .vm file's statics and errors go by: its stem.
+fn file_name(path: &Path) -> &str {
+    path.file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("Unknown")
+}
+
+/// Check that no two files go by the same [`file_name`]. Static `i` of a
+/// file is the symbol `Name.i`, so such files would share their statics.
+fn check_unique_names(vm_files: &[PathBuf]) -> Result<()> {
+    let mut seen: HashMap<&str, &PathBuf> = HashMap::new();
+    for path in vm_files {
+        match seen.entry(file_name(path)) {
+            Entry::Occupied(first) => {
+                return Err(VMError::DuplicateFileStem {
+                    stem: first.key().to_string(),
+                    first: first.get().display().to_string(),
+                    second: path.display().to_string(),
+                });
+            }
+            Entry::Vacant(slot) => {
+                slot.insert(path);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Call `f` with the text of the file at `path`: memory-mapped with the
+/// `mmap` feature, read into a `String` otherwise or if mapping fails.
+///
+/// Fails like [`fs::read_to_string`] if the file cannot be read or is not
+/// UTF-8. Public so the assembler CLI reads its inputs the same way.
+pub fn with_source<T>(path: &Path, f: impl FnOnce(&str) -> T) -> io::Result<T> {
+    #[cfg(feature = "mmap")]
+    if let Some(map) = map_file(path) {
+        let source =
+            std::str::from_utf8(&map).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        return Ok(f(source));
+    }
+    Ok(f(&fs::read_to_string(path)?))
+}
+
+/// Memory-map the file at `path`, if possible.
+#[cfg(feature = "mmap")]
+fn map_file(path: &Path) -> Option<memmap2::Mmap> {
+    let file = fs::File::open(path).ok()?;
+    // SAFETY: the mapping is only read, and only for the duration of the
+    // `with_source` call. Like the rest of the toolchain, this assumes the
+    // input is not rewritten while it is being processed.
+    unsafe { memmap2::Mmap::map(&file) }.ok()
+}
+
+/// Translate a single .vm file to Hack assembly.
+pub fn translate_file(path: &Path) -> Result<String> {
+    translate_file_with_options(path, &TranslateOptions::default())
+}
+
+/// Translate a single .vm file with the given options.
+///
+/// Stack-check warnings are discarded; use [`translate_file_report`] to
+/// receive them.
+pub fn translate_file_with_options(path: &Path, options: &TranslateOptions) -> Result<String> {
+    translate_file_report(path, options).map(|t| t.asm)
+}
+
+/// Translate a single .vm file, returning stack-check warnings.
+pub fn translate_file_report(path: &Path, options: &TranslateOptions) -> Result<Translation> {
+    let mut translator = options.translator(false)?;
+    let asm = translator.file(path)?;
+    translator.finish(asm)
+}
+
+/// Translate independent single-file programs in parallel.
+///
+/// Each file is translated on its own, as by [`translate_file`] (no
+/// bootstrap, no link check), so one failing file does not affect the
+/// others. Results are in the order of `paths`.
+pub fn translate_files(paths: &[PathBuf]) -> Vec<(PathBuf, Result<String>)> {
+    translate_files_with_options(paths, &TranslateOptions::default())
+}
+
+/// Translate independent single-file programs in parallel with the given
+/// options.
+pub fn translate_files_with_options(
+    paths: &[PathBuf],
+    options: &TranslateOptions,
+) -> Vec<(PathBuf, Result<String>)> {
+    paths
+        .par_iter()
+        .map(|path| (path.clone(), translate_file_with_options(path, options)))
+        .collect()
+}
+
+/// Translate all .vm files in a directory to a single .asm file.
+///
+/// - Generates bootstrap code if Sys.vm exists
+/// - Processes Sys.vm first, then other files alphabetically
+/// - Returns the combined assembly output
+pub fn translate_directory(dir_path: &Path) -> Result<String> {
+    translate_directory_with_options(dir_path, &TranslateOptions::default())
+}
+
+/// Translate all .vm files in a directory with the given options.
+///
+/// Warnings are discarded; use [`translate_directory_report`] to receive them.
+pub fn translate_directory_with_options(
+    dir_path: &Path,
+    options: &TranslateOptions,
+) -> Result<String> {
+    translate_directory_report(dir_path, options).map(|t| t.asm)
+}
+
+/// Translate all .vm files in a directory, returning link- and stack-check
+/// warnings.
+pub fn translate_directory_report(
+    dir_path: &Path,
+    options: &TranslateOptions,
+) -> Result<Translation> {
+    let mut translator = options.translator(true)?;
+
+    let vm_files = directory_files(dir_path)?;
+    let has_sys = vm_files[0].file_name() == Some(std::ffi::OsStr::new("Sys.vm"));
+
+    // Estimate output size
+    let total_lines: usize = vm_files
+        .iter()
+        .map(|f| with_source(f, |s| s.lines().count()).unwrap_or(0))
+        .sum();
+    let mut output = String::with_capacity(total_lines * 50 + 512);
+
+    // Generate bootstrap if Sys.vm exists
+    if has_sys {
+        output.push_str(&generate_bootstrap());
+    }
+
+    for vm_file in vm_files {
+        let asm = translator.file(&vm_file)?;
+        output.push_str(&asm);
+    }
+
+    translator.finish(output)
+}
+
+/// Result of [`translate_directory_collecting`]: the files that translated,
+/// plus an error for each one that did not.
+#[derive(Debug)]
+pub struct DirectoryOutcome {
+    /// Bootstrap code (if emitted) and the assembly of every file that
+    /// translated cleanly, in translation order.
+    pub asm: String,
+    /// `(file name, error)` for each skipped file, in translation order,
+    /// followed by a link-check error, if any.
+    pub errors: Vec<(String, VMError)>,
+    /// Link- and stack-check warnings (none if the link check failed).
+    pub warnings: Vec<VMWarning>,
+    /// Files with unusual whitespace among those that translated.
+    pub normalizations: Vec<Normalization>,
+    /// Whether bootstrap code was emitted (the directory has a `Sys.vm`).
+    pub bootstrap: bool,
+}
+
+impl DirectoryOutcome {
+    /// Whether every file translated and the link check passed, i.e.
+    /// [`asm`](Self::asm) is what [`translate_directory`] would return.
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// Translate all .vm files in a directory, skipping the ones that fail.
+///
+/// Unlike [`translate_directory`], an error in one file does not stop the
+/// others: the file is left out of the output entirely, so a partially
+/// translated function cannot corrupt it, and the error is collected. The
+/// functions it declares still count for the link check, so calls into it
+/// are not reported against the callers. Fails only if the directory cannot
+/// be listed or the options are invalid.
+pub fn translate_directory_collecting(dir_path: &Path) -> Result<DirectoryOutcome> {
+    translate_directory_collecting_with_options(dir_path, &TranslateOptions::default())
+}
+
+/// Translate all .vm files in a directory with the given options, skipping
+/// the ones that fail.
+pub fn translate_directory_collecting_with_options(
+    dir_path: &Path,
+    options: &TranslateOptions,
+) -> Result<DirectoryOutcome> {
+    let mut translator = options.translator(true)?;
+
+    let vm_files = directory_files(dir_path)?;
+    let bootstrap = vm_files[0].file_name() == Some(std::ffi::OsStr::new("Sys.vm"));
+    let mut output = if bootstrap {
+        generate_bootstrap()
+    } else {
+        String::new()
+    };
+    let mut errors = Vec::new();
+
+    for vm_file in &vm_files {
+        // A failed file must not leave its calls or code behind
+        let checkpoint = translator.checkpoint();
+        match translator.file(vm_file) {
+            Ok(asm) => output.push_str(&asm),
+            Err(err) => {
+                translator.rollback(checkpoint, file_name(vm_file));
+                translator.declare_functions(vm_file);
+                let name = vm_file.file_name().unwrap_or_default();
+                errors.push((name.to_string_lossy().into_owned(), err));
+            }
+        }
+    }
+
+    let normalizations = std::mem::take(&mut translator.normalizations);
+    let warnings = match translator.finish(String::new()) {
+        Ok(translation) => translation.warnings,
+        Err(err) => {
+            let name = err
+                .location()
+                .map_or_else(String::new, |(file, _)| format!("{file}.vm"));
+            errors.push((name, err));
+            Vec::new()
+        }
+    };
+
+    Ok(DirectoryOutcome {
+        asm: output,
+        errors,
+        warnings,
+        normalizations,
+        bootstrap,
+    })
+}
+
+/// The `.vm` files of a directory in translation order: `Sys.vm` first if
+/// present, then the rest alphabetically.
+pub(crate) fn directory_files(dir_path: &Path) -> Result<Vec<PathBuf>> {
+    let mut vm_files: Vec<_> = fs::read_dir(dir_path)
+        .map_err(|e| VMError::FileRead {
+            path: dir_path.display().to_string(),
+            source: e,
+        })?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "vm"))
+        .collect();
+
+    if vm_files.is_empty() {
+        return Err(VMError::NoVmFiles {
+            path: dir_path.display().to_string(),
+        });
+    }
+
+    // Sort files alphabetically, with Sys.vm first
+    vm_files.sort_by_key(|f| {
+        (
+            f.file_name() != Some(std::ffi::OsStr::new("Sys.vm")),
+            f.clone(),
+        )
+    });
+    check_unique_names(&vm_files)?;
+    Ok(vm_files)
+}
+
+/// Manifest directive that forces bootstrap code.
+const BOOTSTRAP_DIRECTIVE: &str = "bootstrap";
+
+/// Translate the .vm files listed in a manifest, in the listed order.
+///
+/// Entries may come from different directories, but no two may share a
+/// file name ([`VMError::DuplicateFileStem`]), since statics are named
+/// after the file.
+pub fn translate_manifest(manifest_path: &Path) -> Result<String> {
+    translate_manifest_with_options(manifest_path, &TranslateOptions::default())
+}
+
+/// Translate the .vm files listed in a manifest with the given options.
+///
+/// Warnings are discarded; use [`translate_manifest_report`] to receive them.
+pub fn translate_manifest_with_options(
+    manifest_path: &Path,
+    options: &TranslateOptions,
+) -> Result<String> {
+    translate_manifest_report(manifest_path, options).map(|t| t.asm)
+}
+
+/// Translate the .vm files listed in a manifest, returning link- and
+/// stack-check warnings.
+pub fn translate_manifest_report(
+    manifest_path: &Path,
+    options: &TranslateOptions,
+) -> Result<Translation> {
+    let manifest = fs::read_to_string(manifest_path).map_err(|e| VMError::FileRead {
+        path: manifest_path.display().to_string(),
+        source: e,
+    })?;
+    let base_dir = manifest_path.parent().unwrap_or(Path::new(""));
+
+    let mut vm_files = Vec::new();
+    let mut bootstrap = false;
+
+    for (line_num, line) in manifest.lines().enumerate() {
+        let entry = line.trim();
+        if entry.is_empty() || entry.starts_with('#') {
+            continue;
+        }
+
+        if let Some(directive) = entry.strip_prefix('!') {
+            if directive != BOOTSTRAP_DIRECTIVE {
+                return Err(VMError::InvalidManifestDirective {
+                    line: line_num + 1,
+                    file: manifest_path.display().to_string(),
+                    directive: directive.to_string(),
+                });
+            }
+            bootstrap = true;
+            continue;
+        }
+
+        let vm_file = base_dir.join(entry);
+        if !vm_file.is_file() {
+            return Err(VMError::ManifestFileNotFound {
+                line: line_num + 1,
+                file: manifest_path.display().to_string(),
+                path: entry.to_string(),
+            });
+        }
+        bootstrap |= vm_file.file_stem() == Some(std::ffi::OsStr::new("Sys"));
+        vm_files.push(vm_file);
+    }
+
+    if vm_files.is_empty() {
+        return Err(VMError::NoVmFiles {
+            path: manifest_path.display().to_string(),
+        });
+    }
+    check_unique_names(&vm_files)?;
+
+    let mut translator = options.translator(true)?;
+    let mut output = String::new();
+
+    if bootstrap {
+        output.push_str(&generate_bootstrap());
+    }
+
+    for vm_file in &vm_files {
+        let asm = translator.file(vm_file)?;
+        output.push_str(&asm);
+    }
+
+    translator.finish(output)
+}
+
+/// Determine the output filename for a given input.
+///
+/// - Single file: Input.vm -> Input.asm
+/// - Directory: dir/ -> dir/dir.asm
+pub fn output_path(input: &Path) -> std::path::PathBuf {
+    if input.is_dir() {
+        let dir_name = input
+            .file_name()
+            .and_then(|s| s.to_str())
+            .unwrap_or("output");
+        input.join(format!("{}.asm", dir_name))
+    } else {
+        input.with_extension("asm")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_output_path_file() {
+        let path = Path::new("Test.vm");
+        assert_eq!(output_path(path), Path::new("Test.asm"));
+    }
+}
@@ -6,163 +6,569 @@
 //! # Usage Modes
 //!
 //! - Single file: `translate("source", "filename")` - No bootstrap
+//! - Many single files: `translate_files(&paths)` - In parallel, input order
 //! - Directory: `translate_directory(path)` - With bootstrap if Sys.vm exists
+//!   (`translate_directory_collecting` skips failing files and reports them)
+//! - Manifest: `translate_manifest(path)` - Listed files in order, see below
+//! - In memory: `translate_program(&[(name, source), ..])` - With bootstrap
+//! - With a source map: `translate_with_sourcemap(source, filename)`
+//!
+//! # Manifest Format
+//!
+//! One `.vm` path per line, relative to the manifest. Blank lines and lines
+//! starting with `#` are ignored. Bootstrap code is emitted if a `Sys.vm` is
+//! listed or the manifest contains a `!bootstrap` directive line.
+//!
+//! # Register Contract
+//!
+//! Generated assembly references only SP, LCL, ARG, THIS, THAT, the temp
+//! segment (RAM[5..12]) and the scratch registers listed in
+//! [`memory::SCRATCH_REGISTERS`]. The scratch registers can be relocated with
+//! [`TranslateOptions::scratch_base`], the temp segment with
+//! [`TranslateOptions::temp_base`].
+//!
+//! # Link Check
+//!
+//! Directory and manifest translation check that every `call` targets a
+//! function declared somewhere in the program (OS classes excepted) and warn
+//! when a function is called with differing argument counts. See [`link`].
+//!
+//! # Stack Check
+//!
+//! With [`TranslateOptions::stack_check`] the translator also warns about
+//! functions that consume values they never pushed or return without
+//! exactly one value on their stack. See [`stack_check`].
+//! [`TranslateOptions::function_check`] warns about code before a file's
+//! first `function`, where labels fall back to file scope and `return` has
+//! no frame to return from.
+//!
+//! # Emulation
+//!
+//! [`emulator::Emulator`] executes VM programs directly, without going
+//! through assembly, for quick checks of program behaviour.
+//!
+//! # Comparing Output
+//!
+//! [`asm_diff::compare`] checks our assembly against another translator's
+//! (such as the course's reference VMTranslator), ignoring comments and
+//! label names.
+//!
+//! # Features
+//!
+//! The file, directory and manifest functions need the `fs` feature, on by
+//! default. With `default-features = false` the crate contains only the
+//! in-memory API (`translate`, `translate_program`, the emulator), which
+//! builds for targets without a file system such as WASM.
 
+pub mod asm_diff;
 pub mod bootstrap;
 pub mod codegen;
+pub mod emulator;
 pub mod error;
+#[cfg(feature = "fs")]
+mod files;
+pub mod link;
 pub mod memory;
 pub mod parser;
-
-use std::fs;
-use std::path::Path;
+pub mod stack_check;
+pub mod vm_opt;
 
 use crate::bootstrap::generate_bootstrap;
 use crate::codegen::CodeGenerator;
-pub use crate::error::{Result, VMError};
-use crate::parser::parse_line;
-
-/// Translate a single VM source string to Hack assembly.
-///
-/// This is the backward-compatible single-file mode (no bootstrap).
-pub fn translate(source: &str, filename: &str) -> Result<String> {
-    let mut codegen = CodeGenerator::new();
-    codegen.set_filename(filename);
-
-    let estimated_size = source.lines().count() * 50;
-    let mut output = String::with_capacity(estimated_size);
+pub use crate::codegen::LabelScope;
+pub use crate::error::{OperandError, Result, VMError, VMWarning};
+use crate::link::{LinkChecker, OS_CLASSES};
+use crate::memory::{DEFAULT_SCRATCH_BASE, TEMP_BASE, is_scratch_register};
+pub use crate::parser::ParseOptions;
+use crate::parser::{
+    ArithmeticOp, Segment, VMCommand, is_separator, parse_line_with_options, unusual_whitespace,
+};
+use crate::stack_check::StackChecker;
+use std::fmt;
+use std::ops::Range;
+
+#[cfg(feature = "fs")]
+pub use crate::files::{
+    DirectoryOutcome, output_path, translate_directory, translate_directory_collecting,
+    translate_directory_collecting_with_options, translate_directory_report,
+    translate_directory_with_options, translate_file, translate_file_report,
+    translate_file_with_options, translate_files, translate_files_with_options, translate_manifest,
+    translate_manifest_report, translate_manifest_with_options, with_source,
+};
+
+/// Translation options.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TranslateOptions {
+    /// First scratch register (default 13, i.e. R13–R14).
+    ///
+    /// Any base in 5..=14 is accepted. Moving the scratch registers into
+    /// the temp segment (R5–R12) frees R13–R15 for other uses, but any
+    /// `push`/`pop temp` that touches the relocated registers is an error.
+    pub scratch_base: u8,
+    /// First register of the temp segment (default 5, i.e. R5–R12).
+    ///
+    /// The eight slots must fit between THAT (RAM[4]) and the screen. As
+    /// with [`scratch_base`](Self::scratch_base), a `push`/`pop temp` of a
+    /// slot that is a scratch register is an error.
+    pub temp_base: u16,
+    /// Check `call` targets and arities across a directory or manifest
+    /// (default true). Single-file translation is never checked.
+    pub link_check: bool,
+    /// Classes whose functions may be called without being defined
+    /// (default [`OS_CLASSES`]).
+    pub link_exempt: &'static [&'static str],
+    /// Simplify constant-condition branches and jump chains before code
+    /// generation, and translate `not` + `if-goto` as a single jump (default
+    /// false). See [`vm_opt`] and [`CodeGenerator::translate_not_if_goto`].
+    pub optimize: bool,
+    /// Naming of `label`, `goto` and `if-goto` outside any function
+    /// (default [`LabelScope::File`]).
+    pub label_scope: LabelScope,
+    /// Warn about functions whose stack depth looks wrong at `return` or
+    /// that consume more values than they pushed (default false). See
+    /// [`stack_check`].
+    pub stack_check: bool,
+    /// Copy full-line `//` comments from the VM source into the assembly,
+    /// in their original position (default false). Trailing comments after
+    /// a command are dropped, and with [`optimize`](Self::optimize) all
+    /// comments are, since commands are reordered before code generation.
+    pub keep_comments: bool,
+    /// Warn about commands before the first `function` of a file: the
+    /// first such command, and every `return`, `label`, `goto` and
+    /// `if-goto` (default false). Course programs without functions, such
+    /// as BasicLoop, are expected to trigger it.
+    pub function_check: bool,
+    /// How VM lines are parsed (default: commands and segments in
+    /// lowercase only).
+    pub parse: ParseOptions,
+    /// Put a `// ---- File: Main.vm ----` banner before each file's
+    /// assembly in directory, manifest and in-memory program translation
+    /// (default false). Single-file translation has no banner.
+    pub file_markers: bool,
+}
 
-    for (line_num, line) in source.lines().enumerate() {
-        if let Some(cmd) = parse_line(line, line_num + 1, filename)? {
-            codegen.translate(&cmd, &mut output);
+impl Default for TranslateOptions {
+    fn default() -> Self {
+        Self {
+            scratch_base: DEFAULT_SCRATCH_BASE,
+            temp_base: TEMP_BASE,
+            link_check: true,
+            link_exempt: OS_CLASSES,
+            optimize: false,
+            label_scope: LabelScope::File,
+            stack_check: false,
+            keep_comments: false,
+            function_check: false,
+            parse: ParseOptions::default(),
+            file_markers: false,
         }
     }
+}
 
-    Ok(output)
+impl TranslateOptions {
+    /// Create a translator configured with these options.
+    ///
+    /// `program` enables the link check (if configured): only whole
+    /// programs (directories and manifests) can be checked.
+    fn translator(&self, program: bool) -> Result<Translator> {
+        let codegen = CodeGenerator::builder()
+            .scratch_base(self.scratch_base)
+            .temp_base(self.temp_base)
+            .label_scope(self.label_scope)
+            .build_overlapping()?;
+        Ok(Translator {
+            codegen,
+            links: (program && self.link_check).then(LinkChecker::new),
+            stack: self.stack_check.then(StackChecker::new),
+            link_exempt: self.link_exempt,
+            optimize: self.optimize,
+            keep_comments: self.keep_comments && !self.optimize,
+            function_check: self.function_check,
+            parse_options: self.parse,
+            file_markers: program && self.file_markers,
+            warnings: Vec::new(),
+            normalizations: Vec::new(),
+            strict_labels: self.label_scope == LabelScope::Strict,
+            in_function: false,
+            source_map: None,
+        })
+    }
 }
 
-/// Translate a single .vm file to Hack assembly.
-pub fn translate_file(path: &Path) -> Result<String> {
-    let filename = path
-        .file_stem()
-        .and_then(|s| s.to_str())
-        .unwrap_or("Unknown");
+/// A line of VM source that produces output.
+enum SourceLine<'a> {
+    Command(VMCommand),
+    /// A full-line comment, kept with [`TranslateOptions::keep_comments`].
+    Comment(&'a str),
+}
 
-    let source = fs::read_to_string(path).map_err(|e| VMError::FileRead {
-        path: path.display().to_string(),
-        source: e,
-    })?;
+/// The commands among parsed lines, with their line numbers.
+fn commands<'l>(lines: &'l [(usize, SourceLine)]) -> impl Iterator<Item = (usize, &'l VMCommand)> {
+    lines
+        .iter()
+        .filter_map(|(line, source_line)| match source_line {
+            SourceLine::Command(cmd) => Some((*line, cmd)),
+            SourceLine::Comment(_) => None,
+        })
+}
 
-    translate(&source, filename)
+/// Translation state shared across the files of a program.
+struct Translator {
+    /// Shared so labels and return addresses stay unique across files.
+    codegen: CodeGenerator,
+    links: Option<LinkChecker>,
+    stack: Option<StackChecker>,
+    link_exempt: &'static [&'static str],
+    optimize: bool,
+    /// Forward comment-only lines to the output.
+    keep_comments: bool,
+    /// Warn about commands outside functions.
+    function_check: bool,
+    parse_options: ParseOptions,
+    /// Write a banner before each file's assembly.
+    file_markers: bool,
+    /// Function-check warnings, in source order.
+    warnings: Vec<VMWarning>,
+    /// Files with unusual whitespace, in translation order.
+    normalizations: Vec<Normalization>,
+    /// Reject flow commands outside functions ([`LabelScope::Strict`]).
+    strict_labels: bool,
+    /// Whether a `function` has been seen; like the code generator's
+    /// current function, this carries over into the next file.
+    in_function: bool,
+    /// Assembly lines of each command, if requested (unoptimized only).
+    source_map: Option<Vec<SourceMapEntry>>,
 }
 
-/// Translate a .vm file using the given code generator.
-///
-/// This allows sharing state across multiple files (e.g., call counter).
-fn translate_file_with_codegen(path: &Path, codegen: &mut CodeGenerator) -> Result<String> {
-    let filename = path
-        .file_stem()
-        .and_then(|s| s.to_str())
-        .unwrap_or("Unknown");
-
-    codegen.set_filename(filename);
-
-    let source = fs::read_to_string(path).map_err(|e| VMError::FileRead {
-        path: path.display().to_string(),
-        source: e,
-    })?;
-
-    let estimated_size = source.lines().count() * 50;
-    let mut output = String::with_capacity(estimated_size);
-
-    for (line_num, line) in source.lines().enumerate() {
-        if let Some(cmd) = parse_line(line, line_num + 1, filename)? {
-            codegen.translate(&cmd, &mut output);
-        }
+impl Translator {
+    /// Translate VM source, checking and recording each command.
+    ///
+    /// Runs in three phases: every line is parsed, then the analyses
+    /// (register and label checks, duplicate functions, link and stack
+    /// checks) see the whole command list, then code is generated. A
+    /// parse error anywhere in the file is reported before any analysis
+    /// error.
+    fn source(&mut self, source: &str, filename: &str) -> Result<String> {
+        let lines = self.parse(source, filename)?;
+        self.analyze(&lines, filename)?;
+        self.generate(&lines, filename, source.lines().count())
     }
 
-    Ok(output)
-}
+    /// Write the banner for the file `name` (with extension), if enabled.
+    fn file_marker(&self, name: &str, output: &mut String) {
+        if self.file_markers {
+            output.push_str("// ---- File: ");
+            output.push_str(name);
+            output.push_str(" ----\n");
+        }
+    }
 
-/// Translate all .vm files in a directory to a single .asm file.
-///
-/// - Generates bootstrap code if Sys.vm exists
-/// - Processes Sys.vm first, then other files alphabetically
-/// - Returns the combined assembly output
-pub fn translate_directory(dir_path: &Path) -> Result<String> {
-    // Find all .vm files
-    let mut vm_files: Vec<_> = fs::read_dir(dir_path)
-        .map_err(|e| VMError::FileRead {
-            path: dir_path.display().to_string(),
-            source: e,
-        })?
-        .filter_map(|entry| entry.ok())
-        .map(|entry| entry.path())
-        .filter(|path| path.extension().is_some_and(|ext| ext == "vm"))
-        .collect();
-
-    if vm_files.is_empty() {
-        return Err(VMError::NoVmFiles {
-            path: dir_path.display().to_string(),
-        });
+    /// Phase one: parse the source into commands and, if kept, comments.
+    fn parse<'a>(
+        &mut self,
+        source: &'a str,
+        filename: &str,
+    ) -> Result<Vec<(usize, SourceLine<'a>)>> {
+        let mut lines = Vec::new();
+        let mut characters = 0;
+        for (line_num, line) in source.lines().enumerate() {
+            characters += unusual_whitespace(line);
+            let trimmed = line.trim_matches(is_separator);
+            if self.keep_comments && trimmed.starts_with("//") {
+                lines.push((line_num + 1, SourceLine::Comment(trimmed)));
+            } else if let Some(cmd) =
+                parse_line_with_options(line, line_num + 1, filename, &self.parse_options)?
+            {
+                lines.push((line_num + 1, SourceLine::Command(cmd)));
+            }
+        }
+        if characters > 0 {
+            self.normalizations.push(Normalization {
+                file: filename.to_string(),
+                characters,
+            });
+        }
+        Ok(lines)
     }
 
-    // Sort files alphabetically
-    vm_files.sort();
+    /// Phase two: check the commands and record them for the program-wide
+    /// checks.
+    fn analyze(&mut self, lines: &[(usize, SourceLine)], filename: &str) -> Result<()> {
+        if self.function_check {
+            self.check_outside_functions(lines, filename);
+        }
+        for (line, cmd) in commands(lines) {
+            check_scratch_collision(cmd, &self.codegen, line, filename)?;
+            self.check_label_scope(cmd, line, filename)?;
+            if let VMCommand::Function { name, .. } = cmd {
+                self.codegen.declare_function(name, filename, line)?;
+            }
+            if let Some(links) = &mut self.links {
+                links.record(cmd, filename, line);
+            }
+            if let Some(stack) = &mut self.stack {
+                stack.record(cmd, filename, line);
+            }
+        }
+        Ok(())
+    }
 
-    // Check if Sys.vm exists
-    let sys_file = dir_path.join("Sys.vm");
-    let has_sys = sys_file.exists();
+    /// Phase three: generate assembly.
+    fn generate(
+        &mut self,
+        lines: &[(usize, SourceLine)],
+        filename: &str,
+        count: usize,
+    ) -> Result<String> {
+        self.codegen.set_filename(filename);
+        let mut output = String::with_capacity(count * 50);
+
+        if self.optimize {
+            let commands = vm_opt::optimize(commands(lines).map(|(_, cmd)| cmd.clone()).collect());
+            let mut commands = commands.iter().peekable();
+            while let Some(cmd) = commands.next() {
+                // `not` + `if-goto` jumps on the value before negation
+                if let VMCommand::Arithmetic(ArithmeticOp::Not) = cmd
+                    && let Some(VMCommand::IfGoto { label }) = commands.peek()
+                {
+                    self.codegen.translate_not_if_goto(label, &mut output);
+                    commands.next();
+                    continue;
+                }
+                self.codegen.translate(cmd, &mut output)?;
+            }
+            return Ok(output);
+        }
 
-    // Estimate output size
-    let total_lines: usize = vm_files
-        .iter()
-        .map(|f| {
-            fs::read_to_string(f)
-                .map(|s| s.lines().count())
-                .unwrap_or(0)
-        })
-        .sum();
-    let mut output = String::with_capacity(total_lines * 50 + 512);
+        for (line, source_line) in lines {
+            let cmd = match source_line {
+                SourceLine::Command(cmd) => cmd,
+                SourceLine::Comment(comment) => {
+                    output.push_str(comment);
+                    output.push('\n');
+                    continue;
+                }
+            };
+            let start = output.len();
+            self.codegen.translate(cmd, &mut output)?;
+            if let Some(map) = &mut self.source_map {
+                let first = map.last().map_or(1, |entry| entry.asm_lines.end);
+                let lines = output[start..].matches('\n').count();
+                map.push(SourceMapEntry {
+                    asm_lines: first..first + lines,
+                    vm_line: *line,
+                });
+            }
+        }
+        Ok(output)
+    }
 
-    let mut codegen = CodeGenerator::new();
+    /// Warn about the commands before the first `function` of a file.
+    fn check_outside_functions(&mut self, lines: &[(usize, SourceLine)], filename: &str) {
+        let before_function =
+            commands(lines).take_while(|(_, cmd)| !matches!(cmd, VMCommand::Function { .. }));
+        for (i, (line, cmd)) in before_function.enumerate() {
+            let flow = matches!(
+                cmd,
+                VMCommand::Return
+                    | VMCommand::Label { .. }
+                    | VMCommand::Goto { .. }
+                    | VMCommand::IfGoto { .. }
+            );
+            if i == 0 || flow {
+                self.warnings.push(VMWarning::OutsideFunction {
+                    line,
+                    file: filename.to_string(),
+                    command: cmd.keyword(),
+                });
+            }
+        }
+    }
 
-    // Generate bootstrap if Sys.vm exists
-    if has_sys {
-        output.push_str(&generate_bootstrap());
+    /// Track the current function and, in strict mode, reject flow
+    /// commands outside of one.
+    fn check_label_scope(&mut self, cmd: &VMCommand, line: usize, filename: &str) -> Result<()> {
+        match cmd {
+            VMCommand::Function { .. } => self.in_function = true,
+            VMCommand::Label { name: label }
+            | VMCommand::Goto { label }
+            | VMCommand::IfGoto { label }
+                if self.strict_labels && !self.in_function =>
+            {
+                return Err(VMError::LabelOutsideFunction {
+                    line,
+                    file: filename.to_string(),
+                    label: label.clone(),
+                });
+            }
+            _ => {}
+        }
+        Ok(())
     }
 
-    // Process Sys.vm first if it exists
-    if has_sys {
-        let asm = translate_file_with_codegen(&sys_file, &mut codegen)?;
-        output.push_str(&asm);
-        // Remove Sys.vm from the list
-        vm_files.retain(|f| f.file_name() != Some(std::ffi::OsStr::new("Sys.vm")));
+    /// Run the link check, if enabled, over a fully translated program,
+    /// and collect the warnings.
+    fn finish(self, asm: String) -> Result<Translation> {
+        let mut warnings = self.warnings;
+        if let Some(stack) = self.stack {
+            warnings.extend(stack.finish());
+        }
+        if let Some(links) = self.links {
+            warnings.extend(links.finish(self.link_exempt)?);
+        }
+        Ok(Translation {
+            asm,
+            warnings,
+            normalizations: self.normalizations,
+        })
     }
+}
 
-    // Process remaining files in alphabetical order
-    for vm_file in vm_files {
-        let asm = translate_file_with_codegen(&vm_file, &mut codegen)?;
-        output.push_str(&asm);
+/// Assembly for a whole program plus any non-fatal diagnostics.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Translation {
+    /// The combined Hack assembly.
+    pub asm: String,
+    /// Function-check warnings and then stack-check warnings, each in
+    /// source order, then link-check warnings in call-site order.
+    pub warnings: Vec<VMWarning>,
+    /// Files whose commands were separated by unusual whitespace, in
+    /// translation order.
+    pub normalizations: Vec<Normalization>,
+}
+
+/// A file with word separators other than space and tab, such as
+/// non-breaking spaces (U+00A0) or a byte order mark (U+FEFF), which were
+/// read as spaces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Normalization {
+    /// The file name as given to the translator (e.g. `Main`).
+    pub file: String,
+    /// Number of such characters in the file's code (comments excluded).
+    pub characters: usize,
+}
+
+impl fmt::Display for Normalization {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: read {} unusual whitespace character(s), such as non-breaking spaces or a byte order mark, as spaces",
+            self.file, self.characters
+        )
     }
+}
 
-    Ok(output)
+/// The assembly emitted for one VM command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceMapEntry {
+    /// 1-based assembly lines, end exclusive (`asm_lines.len()` lines).
+    pub asm_lines: Range<usize>,
+    /// 1-based line of the command in the VM source.
+    pub vm_line: usize,
 }
 
-/// Determine the output filename for a given input.
+/// Translate a single VM source string to Hack assembly.
 ///
-/// - Single file: Input.vm -> Input.asm
-/// - Directory: dir/ -> dir/dir.asm
-pub fn output_path(input: &Path) -> std::path::PathBuf {
-    if input.is_dir() {
-        let dir_name = input
-            .file_name()
-            .and_then(|s| s.to_str())
-            .unwrap_or("output");
-        input.join(format!("{}.asm", dir_name))
-    } else {
-        input.with_extension("asm")
+/// This is the backward-compatible single-file mode (no bootstrap).
+pub fn translate(source: &str, filename: &str) -> Result<String> {
+    translate_with_options(source, filename, &TranslateOptions::default())
+}
+
+/// Translate a single VM source string with the given options.
+pub fn translate_with_options(
+    source: &str,
+    filename: &str,
+    options: &TranslateOptions,
+) -> Result<String> {
+    translate_report(source, filename, options).map(|t| t.asm)
+}
+
+/// Translate a single VM source string, returning stack-check warnings.
+pub fn translate_report(
+    source: &str,
+    filename: &str,
+    options: &TranslateOptions,
+) -> Result<Translation> {
+    let mut translator = options.translator(false)?;
+    let asm = translator.source(source, filename)?;
+    translator.finish(asm)
+}
+
+/// Translate a single VM source string, mapping the assembly back to it.
+///
+/// Returns the same assembly as [`translate`] and one [`SourceMapEntry`] per
+/// VM command, in order; together the entries cover every assembly line.
+///
+/// ```
+/// use vm_translator::translate_with_sourcemap;
+///
+/// let (asm, map) = translate_with_sourcemap("push constant 7\n\nneg\n", "Main").unwrap();
+/// assert_eq!(map[1].vm_line, 3);
+/// let lines: Vec<&str> = asm.lines().collect();
+/// assert_eq!(lines[map[1].asm_lines.start - 1..map[1].asm_lines.end - 1], ["@SP", "A=M-1", "M=-M"]);
+/// ```
+pub fn translate_with_sourcemap(
+    source: &str,
+    filename: &str,
+) -> Result<(String, Vec<SourceMapEntry>)> {
+    let mut translator = TranslateOptions::default().translator(false)?;
+    translator.source_map = Some(Vec::new());
+    let asm = translator.source(source, filename)?;
+    Ok((asm, translator.source_map.unwrap_or_default()))
+}
+
+/// Reject temp accesses that would clobber a relocated scratch register.
+fn check_scratch_collision(
+    cmd: &VMCommand,
+    codegen: &CodeGenerator,
+    line: usize,
+    filename: &str,
+) -> Result<()> {
+    if let VMCommand::Push {
+        segment: Segment::Temp,
+        index,
+    }
+    | VMCommand::Pop {
+        segment: Segment::Temp,
+        index,
+    } = *cmd
+        && let register = codegen.temp_address(index)?
+        && is_scratch_register(register, codegen.scratch_base())
+    {
+        return Err(VMError::ScratchRegisterCollision {
+            line,
+            file: filename.to_string(),
+            index,
+            register,
+        });
+    }
+    Ok(())
+}
+
+/// Translate a whole program held in memory, with bootstrap code.
+///
+/// `sources` are `(name, source)` pairs translated in the given order; each
+/// name is the file stem (`"Main"`, not `"Main.vm"`) used for static
+/// variables. This is the in-memory counterpart of a manifest with a
+/// `!bootstrap` directive.
+pub fn translate_program(
+    sources: &[(&str, &str)],
+    options: &TranslateOptions,
+) -> Result<Translation> {
+    if sources.is_empty() {
+        return Err(VMError::NoVmFiles {
+            path: "<memory>".to_string(),
+        });
+    }
+
+    let mut translator = options.translator(true)?;
+    let mut output = generate_bootstrap();
+    for (name, source) in sources {
+        translator.file_marker(&format!("{name}.vm"), &mut output);
+        output.push_str(&translator.source(source, name)?);
     }
+
+    translator.finish(output)
 }
 
 #[cfg(test)]
@@ -215,8 +621,311 @@ mod tests {
     }
 
     #[test]
-    fn test_output_path_file() {
-        let path = Path::new("Test.vm");
-        assert_eq!(output_path(path), Path::new("Test.asm"));
+    fn test_duplicate_function_in_one_file() {
+        let source =
+            "function Foo.bar 0\nreturn\nfunction Foo.baz 0\nreturn\nfunction Foo.bar 1\nreturn";
+        let err = translate(source, "Foo").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Foo:5: function Foo.bar already declared at Foo:1"
+        );
+
+        let err = translate_program(
+            &[("Foo", "function Foo.bar 0\nreturn"), ("Bar", source)],
+            &TranslateOptions::default(),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            VMError::DuplicateFunction { ref first, .. } if *first == ("Foo".to_string(), 1)
+        ));
+    }
+
+    #[test]
+    fn test_sourcemap_covers_assembly() {
+        let source =
+            "// Foo\nfunction Foo.bar 1\n\npush local 0\ncall Foo.bar 1 // again\nlt\nreturn\n";
+        let (asm, map) = translate_with_sourcemap(source, "Foo").unwrap();
+        assert_eq!(asm, translate(source, "Foo").unwrap());
+
+        let vm_lines: Vec<usize> = map.iter().map(|entry| entry.vm_line).collect();
+        assert_eq!(vm_lines, [2, 4, 5, 6, 7]);
+        assert_eq!(map[0].asm_lines.start, 1);
+        for pair in map.windows(2) {
+            assert_eq!(pair[0].asm_lines.end, pair[1].asm_lines.start);
+        }
+        assert_eq!(map[4].asm_lines.end, asm.lines().count() + 1);
+
+        let lines: Vec<&str> = asm.lines().collect();
+        let call = &lines[map[2].asm_lines.start - 1..map[2].asm_lines.end - 1];
+        assert_eq!(call.last(), Some(&"(Foo.bar$ret.0)"));
+    }
+
+    #[test]
+    fn test_function_check() {
+        let options = TranslateOptions {
+            function_check: true,
+            ..TranslateOptions::default()
+        };
+        let source = "push constant 1\npop temp 0\nlabel LOOP\ngoto LOOP\nreturn\n\
+                      function Main.f 0\nlabel IN\npush constant 0\nreturn";
+        let warnings = translate_report(source, "Main", &options).unwrap().warnings;
+        let found: Vec<(usize, &str)> = warnings
+            .iter()
+            .map(|w| match w {
+                VMWarning::OutsideFunction { line, command, .. } => (*line, *command),
+                other => panic!("unexpected warning {other}"),
+            })
+            .collect();
+        assert_eq!(
+            found,
+            [(1, "push"), (3, "label"), (4, "goto"), (5, "return")]
+        );
+        assert_eq!(
+            warnings[3].to_string(),
+            "Main:5: return outside any function"
+        );
+
+        // Every file is checked from its own start
+        let program = [
+            ("Main", "function Main.main 0\npush constant 0\nreturn"),
+            (
+                "Foo",
+                "// header\ngoto END\nfunction Foo.f 0\nlabel END\nreturn",
+            ),
+        ];
+        let warnings = translate_program(&program, &options).unwrap().warnings;
+        assert!(matches!(
+            &warnings[..],
+            [VMWarning::OutsideFunction { line: 2, file, command: "goto" }] if file == "Foo"
+        ));
+
+        let clean = "function Main.f 0\npush constant 0\nreturn";
+        assert_eq!(
+            translate_report(clean, "Main", &options).unwrap().warnings,
+            vec![]
+        );
+        // Off by default
+        let warnings = translate_report(source, "Main", &TranslateOptions::default())
+            .unwrap()
+            .warnings;
+        assert_eq!(warnings, vec![]);
+    }
+
+    #[test]
+    fn test_unusual_whitespace_normalized() {
+        let plain = translate("push constant 7\npush constant 8\nadd\n", "Main").unwrap();
+
+        let nbsp = "push\u{a0}constant\u{a0}7\npush constant 8\nadd\u{a0}\n";
+        let translation = translate_report(nbsp, "Main", &TranslateOptions::default()).unwrap();
+        assert_eq!(translation.asm, plain);
+        assert_eq!(
+            translation.normalizations,
+            [Normalization {
+                file: "Main".to_string(),
+                characters: 3
+            }]
+        );
+
+        let bom = "\u{feff}push constant 7\npush constant 8\nadd\n";
+        let translation = translate_report(bom, "Main", &TranslateOptions::default()).unwrap();
+        assert_eq!(translation.asm, plain);
+        assert_eq!(translation.normalizations[0].characters, 1);
+        assert!(
+            translation.normalizations[0]
+                .to_string()
+                .starts_with("Main: read 1 unusual whitespace character(s)")
+        );
+
+        let translation =
+            translate_report("push constant 7\n", "Main", &TranslateOptions::default()).unwrap();
+        assert!(translation.normalizations.is_empty());
+    }
+
+    #[test]
+    fn test_final_line_without_newline() {
+        let source = "push constant 7\npush constant 8\nadd";
+        assert_eq!(
+            translate(source, "Main").unwrap(),
+            translate(&format!("{source}\n"), "Main").unwrap()
+        );
+        assert!(translate(source, "Main").unwrap().contains("M=D+M"));
+    }
+
+    #[test]
+    fn test_file_markers() {
+        let options = TranslateOptions {
+            file_markers: true,
+            ..TranslateOptions::default()
+        };
+        let program = [
+            (
+                "Sys",
+                "function Sys.init 0
+call Main.main 0
+return",
+            ),
+            (
+                "Main",
+                "function Main.main 0
+push constant 0
+return",
+            ),
+        ];
+        let asm = translate_program(&program, &options).unwrap().asm;
+        let sys = asm.find("// ---- File: Sys.vm ----\n(Sys.init)\n").unwrap();
+        let main = asm
+            .find("// ---- File: Main.vm ----\n(Main.main)\n")
+            .unwrap();
+        assert!(sys < main);
+
+        let plain = translate_program(&program, &TranslateOptions::default()).unwrap();
+        assert!(!plain.asm.contains("// ----"));
+        // A single file needs no banner
+        let asm = translate_with_options(program[1].1, "Main", &options).unwrap();
+        assert!(!asm.contains("// ----"));
+    }
+
+    #[test]
+    fn test_keep_comments() {
+        let source =
+            "// important: invariant\npush constant 1 // dropped\n  // indented\npop temp 0";
+        let options = TranslateOptions {
+            keep_comments: true,
+            ..TranslateOptions::default()
+        };
+        let asm = translate_with_options(source, "Foo", &options).unwrap();
+        assert!(asm.starts_with("// important: invariant\n@1\n"));
+        assert!(asm.contains("M=M+1\n// indented\n@SP\n"));
+        assert!(!asm.contains("dropped"));
+
+        let asm = translate_with_options(source, "Foo", &TranslateOptions::default()).unwrap();
+        assert!(!asm.contains("//"));
+
+        // A byte order mark before a leading comment is not part of it
+        let asm = translate_with_options(
+            "\u{feff}// header
+push constant 1",
+            "Foo",
+            &options,
+        )
+        .unwrap();
+        assert!(asm.starts_with("// header\n@1\n"));
+    }
+
+    #[test]
+    fn test_scratch_base_relocation() {
+        let source = "function Foo.bar 0\npush temp 0\npop local 0\nreturn";
+        let options = TranslateOptions {
+            scratch_base: 11,
+            ..TranslateOptions::default()
+        };
+        let asm = translate_with_options(source, "Foo", &options).unwrap();
+        assert!(asm.contains("@R11\nM=D"));
+        assert!(asm.contains("@R12\nA=M\n0;JMP"));
+        assert!(!asm.contains("@R13"));
+        assert!(!asm.contains("@R14"));
+    }
+
+    #[test]
+    fn test_scratch_base_temp_collision() {
+        let source = "push constant 1\npop temp 6";
+        let options = TranslateOptions {
+            scratch_base: 11,
+            ..TranslateOptions::default()
+        };
+        let err = translate_with_options(source, "Foo", &options).unwrap_err();
+        assert!(matches!(
+            err,
+            VMError::ScratchRegisterCollision {
+                line: 2,
+                index: 6,
+                register: 11,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_temp_base_relocation() {
+        let source = "push constant 1\npop temp 6\npush temp 7";
+        let options = TranslateOptions {
+            temp_base: 20,
+            ..TranslateOptions::default()
+        };
+        let asm = translate_with_options(source, "Foo", &options).unwrap();
+        assert!(asm.contains("@26\nM=D\n") && asm.contains("@27\nD=M\n"));
+
+        // Temp moved out of the way of relocated scratch registers
+        let options = TranslateOptions {
+            scratch_base: 11,
+            ..options
+        };
+        assert!(translate_with_options(source, "Foo", &options).is_ok());
+
+        // Temp moved onto the default scratch registers
+        let options = TranslateOptions {
+            temp_base: 10,
+            ..TranslateOptions::default()
+        };
+        assert!(translate_with_options("push temp 2", "Foo", &options).is_ok());
+        assert!(matches!(
+            translate_with_options("push temp 3", "Foo", &options),
+            Err(VMError::ScratchRegisterCollision {
+                index: 3,
+                register: 13,
+                ..
+            })
+        ));
+        let options = TranslateOptions {
+            temp_base: 4,
+            ..TranslateOptions::default()
+        };
+        assert!(matches!(
+            translate_with_options("push temp 0", "Foo", &options),
+            Err(VMError::InvalidTempBase { base: 4 })
+        ));
+    }
+
+    #[test]
+    fn test_invalid_scratch_base() {
+        let options = TranslateOptions {
+            scratch_base: 15,
+            ..TranslateOptions::default()
+        };
+        let err = translate_with_options("push constant 1", "Foo", &options).unwrap_err();
+        assert!(matches!(err, VMError::InvalidScratchBase { base: 15 }));
+    }
+
+    #[test]
+    fn test_label_scope_modes() {
+        let source = "push constant 1\nlabel LOOP\nif-goto LOOP";
+        let with_scope = |label_scope| TranslateOptions {
+            label_scope,
+            ..TranslateOptions::default()
+        };
+
+        let asm = translate_with_options(source, "Loop", &with_scope(LabelScope::File)).unwrap();
+        assert!(asm.contains("(Loop$LOOP)\n") && asm.contains("@Loop$LOOP\nD;JNE"));
+
+        let asm = translate_with_options(source, "Loop", &with_scope(LabelScope::Global)).unwrap();
+        assert!(asm.contains("(LOOP)\n") && asm.contains("@LOOP\nD;JNE"));
+
+        let err =
+            translate_with_options(source, "Loop", &with_scope(LabelScope::Strict)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Loop:2: label LOOP used outside a function"
+        );
+
+        // Inside a function all modes agree
+        let source = format!("function Loop.f 0\n{source}");
+        let scoped = translate(&source, "Loop").unwrap();
+        for scope in [LabelScope::Strict, LabelScope::Global] {
+            assert_eq!(
+                translate_with_options(&source, "Loop", &with_scope(scope)).unwrap(),
+                scoped
+            );
+        }
     }
 }
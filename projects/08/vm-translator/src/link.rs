@@ -0,0 +1,188 @@
+//! Cross-file `function`/`call` consistency checking.
+//!
+//! Single files are translated in isolation, so a call to a misspelled
+//! function or a call site that disagrees with its siblings about the
+//! argument count only shows up when the program misbehaves on the CPU
+//! emulator. When translating a directory or manifest the translator sees
+//! the whole program and can check that every call target is defined.
+
+use std::collections::HashMap;
+
+use crate::error::{Result, VMError, VMWarning};
+use crate::parser::VMCommand;
+
+/// Classes provided by the Jack OS, exempt from the undefined-call check.
+pub const OS_CLASSES: &[&str] = &[
+    "Array", "Keyboard", "Math", "Memory", "Output", "Screen", "String", "Sys",
+];
+
+/// A single `call` site.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CallSite {
+    name: String,
+    num_args: u16,
+    file: String,
+    line: usize,
+}
+
+/// Collects function declarations and call sites across a program.
+#[derive(Debug, Default, Clone)]
+pub struct LinkChecker {
+    /// Declared function name -> number of locals.
+    functions: HashMap<String, u16>,
+    calls: Vec<CallSite>,
+}
+
+impl LinkChecker {
+    /// Create an empty checker.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a parsed command; only `function` and `call` are of interest.
+    pub fn record(&mut self, cmd: &VMCommand, file: &str, line: usize) {
+        match cmd {
+            VMCommand::Function { name, num_locals } => {
+                self.functions.insert(name.clone(), *num_locals);
+            }
+            VMCommand::Call { name, num_args } => self.calls.push(CallSite {
+                name: name.clone(),
+                num_args: *num_args,
+                file: file.to_string(),
+                line,
+            }),
+            _ => {}
+        }
+    }
+
+    #[cfg(feature = "fs")]
+    /// The number of call sites recorded so far, for
+    /// [`rollback`](Self::rollback).
+    pub(crate) fn checkpoint(&self) -> usize {
+        self.calls.len()
+    }
+
+    #[cfg(feature = "fs")]
+    /// Forget the call sites recorded after `checkpoint`. Declarations are
+    /// kept: calls into an abandoned file are not its callers' fault.
+    pub(crate) fn rollback(&mut self, checkpoint: usize) {
+        self.calls.truncate(checkpoint);
+    }
+
+    /// Check the recorded program.
+    ///
+    /// The first call (in translation order) to a function that is neither
+    /// declared nor owned by one of the `exempt` classes is an error. A
+    /// function called with a different argument count than at its first
+    /// call site produces one warning, pointing at the first disagreeing site.
+    pub fn finish(&self, exempt: &[&str]) -> Result<Vec<VMWarning>> {
+        if let Some(call) = self
+            .calls
+            .iter()
+            .find(|call| !self.functions.contains_key(&call.name) && !is_exempt(&call.name, exempt))
+        {
+            return Err(VMError::UndefinedFunction {
+                line: call.line,
+                file: call.file.clone(),
+                name: call.name.clone(),
+            });
+        }
+
+        let mut first_seen: HashMap<&str, u16> = HashMap::new();
+        let mut warnings = Vec::new();
+        for call in &self.calls {
+            let expected = *first_seen.entry(&call.name).or_insert(call.num_args);
+            if expected != call.num_args
+                && !warnings
+                    .iter()
+                    .any(|w: &VMWarning| w.function_name() == call.name)
+            {
+                warnings.push(VMWarning::InconsistentArity {
+                    line: call.line,
+                    file: call.file.clone(),
+                    name: call.name.clone(),
+                    num_args: call.num_args,
+                    expected,
+                });
+            }
+        }
+        Ok(warnings)
+    }
+}
+
+/// Whether `name` (`Class.function`) belongs to one of the exempt classes.
+fn is_exempt(name: &str, exempt: &[&str]) -> bool {
+    name.split_once('.')
+        .is_some_and(|(class, _)| exempt.contains(&class))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_line;
+
+    fn check(files: &[(&str, &str)]) -> Result<Vec<VMWarning>> {
+        let mut links = LinkChecker::new();
+        for (file, source) in files {
+            for (i, line) in source.lines().enumerate() {
+                if let Some(cmd) = parse_line(line, i + 1, file)? {
+                    links.record(&cmd, file, i + 1);
+                }
+            }
+        }
+        links.finish(OS_CLASSES)
+    }
+
+    #[test]
+    fn test_defined_calls_pass() {
+        let warnings = check(&[
+            ("Main", "function Main.main 0\ncall Foo.bar 1\nreturn"),
+            ("Foo", "function Foo.bar 0\nreturn"),
+        ])
+        .unwrap();
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn test_undefined_call_is_error() {
+        let err = check(&[("Main", "function Main.main 0\n\ncall Foo.baz 0\nreturn")]).unwrap_err();
+        assert!(matches!(
+            &err,
+            VMError::UndefinedFunction { line: 3, name, .. } if name == "Foo.baz"
+        ));
+    }
+
+    #[test]
+    fn test_os_calls_exempt() {
+        let warnings = check(&[(
+            "Main",
+            "function Main.main 0\ncall Math.multiply 2\ncall Sys.halt 0\nreturn",
+        )])
+        .unwrap();
+        assert!(warnings.is_empty());
+        assert!(is_exempt("Output.printInt", OS_CLASSES));
+        assert!(!is_exempt("Outputs.printInt", OS_CLASSES));
+    }
+
+    #[test]
+    fn test_inconsistent_arity_warns_once() {
+        let warnings = check(&[
+            ("Foo", "function Foo.bar 0\nreturn"),
+            (
+                "Main",
+                "function Main.main 0\ncall Foo.bar 1\ncall Foo.bar 2\ncall Foo.bar 3\nreturn",
+            ),
+        ])
+        .unwrap();
+        assert_eq!(
+            warnings,
+            vec![VMWarning::InconsistentArity {
+                line: 3,
+                file: "Main".to_string(),
+                name: "Foo.bar".to_string(),
+                num_args: 2,
+                expected: 1,
+            }]
+        );
+    }
+}
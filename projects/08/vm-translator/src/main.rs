@@ -10,15 +10,32 @@
 //!
 //! # Directory (with bootstrap)
 //! vm-translator FibonacciElement/
+//!
+//! # Explicit file list
+//! vm-translator --manifest build.txt
+//!
+//! # Compare with the reference translator's output, ignoring label names
+//! vm-translator SimpleAdd.vm --diff-reference Reference.asm
+//!
+//! # Execute on the VM emulator and inspect RAM
+//! vm-translator --run FibonacciElement/ --steps 100000 --inspect 256..262
 //! ```
 
 use std::env;
 use std::fs;
+use std::io::IsTerminal;
+use std::ops::Range;
 use std::path::Path;
 use std::process;
 use std::time::Instant;
 
-use vm_translator::{VMError, output_path, translate_directory, translate_file};
+use vm_translator::asm_diff::{self, AsmInstruction};
+use vm_translator::emulator::{Emulator, RAM_SIZE};
+use vm_translator::{
+    LabelScope, Normalization, ParseOptions, TranslateOptions, Translation, VMError, output_path,
+    translate_directory_collecting_with_options, translate_directory_report, translate_file_report,
+    translate_manifest_report, translate_with_sourcemap,
+};
 
 fn main() {
     let args: Vec<String> = env::args().collect();
@@ -29,31 +46,175 @@ fn main() {
             env!("CARGO_PKG_VERSION")
         );
         eprintln!();
-        eprintln!("Usage: vm-translator <file.vm | directory> [-v]");
+        eprintln!("Usage: vm-translator <file.vm | directory> [-v] [-O] [--scratch-base N]");
+        eprintln!("       vm-translator --manifest <build.txt> [-v] [-O] [--scratch-base N]");
+        eprintln!("       vm-translator --run <file.vm | directory> [--steps N] [--inspect RANGE]");
+        eprintln!("       vm-translator <file.vm | directory> --diff-reference <file.asm>");
         eprintln!();
         eprintln!("Options:");
-        eprintln!("  -v, --verbose    Show detailed output");
+        eprintln!("  -v, --verbose         Show detailed output");
+        eprintln!("  --scratch-base N      Use R<N> and R<N+1> as scratch registers (default 13)");
+        eprintln!("  --manifest FILE       Translate the .vm files listed in FILE, in order");
+        eprintln!("  --no-link-check       Don't check call targets and argument counts");
+        eprintln!("  --keep-partial        Write a directory's .asm even if some files fail");
+        eprintln!("  --stack-check         Warn about unbalanced stack use in functions");
+        eprintln!("  --function-check      Warn about commands before a file's first function");
+        eprintln!("  --keep-comments       Copy full-line // comments into the .asm");
+        eprintln!("  --file-markers        Mark where each .vm file's code starts in the .asm");
+        eprintln!(
+            "  --lenient             Accept commands and segments in any case (Push Constant 7)"
+        );
+        eprintln!("  -O, --optimize        Fold constant branches and thread jump chains");
+        eprintln!("  --spec-strict         Reject label/goto/if-goto outside a function");
+        eprintln!(
+            "  --spec-global         Don't prefix labels outside a function (reference tool)"
+        );
+        eprintln!("  --run                 Execute the program on the VM emulator instead");
+        eprintln!(
+            "  --steps N             Emulator step limit (default {})",
+            DEFAULT_STEPS
+        );
+        eprintln!("  --inspect RANGE       Print RAM[RANGE] after the run: N, A..B or A..=B");
+        eprintln!("  --no-color            Don't color error output (default: color on a TTY)");
+        eprintln!("  --diff-reference FILE Compare with FILE, ignoring comments and label names");
+        eprintln!(
+            "  --diff-limit N        Differences to show (default {})",
+            DEFAULT_DIFF_LIMIT
+        );
         eprintln!();
         eprintln!("Examples:");
         eprintln!("  vm-translator SimpleAdd.vm          # Single file");
         eprintln!("  vm-translator FibonacciElement/     # Directory with bootstrap");
+        eprintln!("  vm-translator --manifest build.txt  # Listed files, listed order");
+        eprintln!("  vm-translator --run Fib/ --inspect 256..262  # Emulate, print RAM");
         process::exit(1);
     }
 
     let verbose = args.iter().any(|a| a == "-v" || a == "--verbose");
-    let input_path = Path::new(&args[1]);
+    let color = !args.iter().any(|a| a == "--no-color") && std::io::stderr().is_terminal();
+    let mut options = TranslateOptions {
+        link_check: !args.iter().any(|a| a == "--no-link-check"),
+        optimize: args.iter().any(|a| a == "-O" || a == "--optimize"),
+        stack_check: args.iter().any(|a| a == "--stack-check"),
+        keep_comments: args.iter().any(|a| a == "--keep-comments"),
+        function_check: args.iter().any(|a| a == "--function-check"),
+        file_markers: args.iter().any(|a| a == "--file-markers"),
+        parse: ParseOptions {
+            case_insensitive: args.iter().any(|a| a == "--lenient"),
+        },
+        ..TranslateOptions::default()
+    };
+    let spec_strict = args.iter().any(|a| a == "--spec-strict");
+    let spec_global = args.iter().any(|a| a == "--spec-global");
+    options.label_scope = match (spec_strict, spec_global) {
+        (true, true) => {
+            eprintln!("Error: --spec-strict and --spec-global are mutually exclusive");
+            process::exit(1);
+        }
+        (true, false) => LabelScope::Strict,
+        (false, true) => LabelScope::Global,
+        (false, false) => LabelScope::File,
+    };
+    if let Some(pos) = args.iter().position(|a| a == "--scratch-base") {
+        match args.get(pos + 1).and_then(|v| v.parse().ok()) {
+            Some(base) => options.scratch_base = base,
+            None => {
+                eprintln!("Error: --scratch-base requires a register number");
+                process::exit(1);
+            }
+        }
+    }
+    let manifest = match args.iter().position(|a| a == "--manifest") {
+        Some(pos) => match args.get(pos + 1) {
+            Some(path) => Some(Path::new(path)),
+            None => {
+                eprintln!("Error: --manifest requires a file");
+                process::exit(1);
+            }
+        },
+        None => None,
+    };
+    let input_path = match manifest.or_else(|| positional(&args).map(Path::new)) {
+        Some(path) => path,
+        None => {
+            eprintln!("Error: missing input path");
+            process::exit(1);
+        }
+    };
 
     if !input_path.exists() {
         eprintln!("Error: Path not found: {}", input_path.display());
         process::exit(1);
     }
 
+    if args.iter().any(|a| a == "--run") {
+        let steps = match args.iter().position(|a| a == "--steps") {
+            Some(pos) => match args.get(pos + 1).and_then(|v| v.parse().ok()) {
+                Some(steps) => steps,
+                None => {
+                    eprintln!("Error: --steps requires a number");
+                    process::exit(1);
+                }
+            },
+            None => DEFAULT_STEPS,
+        };
+        let mut ranges = Vec::new();
+        for (pos, _) in args.iter().enumerate().filter(|(_, a)| *a == "--inspect") {
+            match args.get(pos + 1).and_then(|v| parse_range(v)) {
+                Some(range) => ranges.push(range),
+                None => {
+                    eprintln!("Error: --inspect requires a RAM range such as 256..262");
+                    process::exit(1);
+                }
+            }
+        }
+        if let Err(e) = run_mode(input_path, &options, steps, &ranges, verbose) {
+            report_error(&e, input_path, manifest.is_some(), color);
+            process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(pos) = args.iter().position(|a| a == "--diff-reference") {
+        let Some(reference) = args.get(pos + 1) else {
+            eprintln!("Error: --diff-reference requires a file");
+            process::exit(1);
+        };
+        let limit = match args.iter().position(|a| a == "--diff-limit") {
+            Some(pos) => match args.get(pos + 1).and_then(|v| v.parse().ok()) {
+                Some(limit) => limit,
+                None => {
+                    eprintln!("Error: --diff-limit requires a number");
+                    process::exit(1);
+                }
+            },
+            None => DEFAULT_DIFF_LIMIT,
+        };
+        match diff_mode(
+            input_path,
+            manifest.is_some(),
+            Path::new(reference),
+            &options,
+            limit,
+        ) {
+            Ok(true) => return,
+            Ok(false) => process::exit(1),
+            Err(e) => {
+                report_error(&e, input_path, manifest.is_some(), color);
+                process::exit(1);
+            }
+        }
+    }
+
     let start = Instant::now();
 
-    let result = if input_path.is_dir() {
-        translate_directory_mode(input_path, verbose)
+    let result = if manifest.is_some() {
+        translate_manifest_mode(input_path, &options, verbose)
+    } else if input_path.is_dir() {
+        let keep_partial = args.iter().any(|a| a == "--keep-partial");
+        translate_directory_mode(input_path, &options, verbose, keep_partial, color)
     } else if input_path.extension().is_some_and(|ext| ext == "vm") {
-        translate_file_mode(input_path, verbose)
+        translate_file_mode(input_path, &options, verbose)
     } else {
         Err(VMError::InvalidPath {
             path: input_path.display().to_string(),
@@ -74,18 +235,51 @@ fn main() {
             }
         }
         Err(e) => {
-            eprintln!("Error: {}", e);
+            report_error(&e, input_path, manifest.is_some(), color);
             process::exit(1);
         }
     }
 }
 
-fn translate_file_mode(input: &Path, verbose: bool) -> Result<std::path::PathBuf, VMError> {
+/// Print an error, with an excerpt of the offending source when the error
+/// has a line and its file can be found again.
+fn report_error(err: &VMError, input: &Path, is_manifest: bool, color: bool) {
+    let source = error_source(err, input, is_manifest).unwrap_or_default();
+    eprint!("{}", err.render_with_source(&source, color));
+}
+
+/// Read back the file an error refers to. Errors name translated files by
+/// stem (`Main`) and manifest errors by the manifest's path.
+fn error_source(err: &VMError, input: &Path, is_manifest: bool) -> Option<String> {
+    let (file, _) = err.location()?;
+    let path = if Path::new(file).is_file() {
+        Path::new(file).to_path_buf()
+    } else if input.is_dir() {
+        input.join(format!("{file}.vm"))
+    } else if is_manifest {
+        // The listed file with that stem, relative to the manifest
+        let base_dir = input.parent().unwrap_or(Path::new(""));
+        fs::read_to_string(input)
+            .ok()?
+            .lines()
+            .map(|entry| base_dir.join(entry.trim()))
+            .find(|path| path.file_stem().is_some_and(|stem| stem == file))?
+    } else {
+        input.to_path_buf()
+    };
+    fs::read_to_string(path).ok()
+}
+
+fn translate_file_mode(
+    input: &Path,
+    options: &TranslateOptions,
+    verbose: bool,
+) -> Result<std::path::PathBuf, VMError> {
     if verbose {
         eprintln!("Translating single file: {}", input.display());
     }
 
-    let asm = translate_file(input)?;
+    let asm = report_warnings(translate_file_report(input, options)?, verbose);
     let output = output_path(input);
 
     fs::write(&output, &asm).map_err(|e| VMError::FileWrite {
@@ -101,7 +295,16 @@ fn translate_file_mode(input: &Path, verbose: bool) -> Result<std::path::PathBuf
     Ok(output)
 }
 
-fn translate_directory_mode(input: &Path, verbose: bool) -> Result<std::path::PathBuf, VMError> {
+/// Translate every file that can be, reporting each failure. Writes the
+/// `.asm` only if all files translated, unless `keep_partial`; exits with
+/// status 1 after reporting per-file errors.
+fn translate_directory_mode(
+    input: &Path,
+    options: &TranslateOptions,
+    verbose: bool,
+    keep_partial: bool,
+    color: bool,
+) -> Result<std::path::PathBuf, VMError> {
     if verbose {
         eprintln!("Translating directory: {}", input.display());
 
@@ -130,8 +333,58 @@ fn translate_directory_mode(input: &Path, verbose: bool) -> Result<std::path::Pa
         }
     }
 
-    let asm = translate_directory(input)?;
+    let outcome = translate_directory_collecting_with_options(input, options)?;
+    for warning in &outcome.warnings {
+        eprintln!("Warning: {}", warning);
+    }
+    if verbose {
+        report_normalizations(&outcome.normalizations);
+    }
+    for (_, err) in &outcome.errors {
+        report_error(err, input, false, color);
+    }
     let output = output_path(input);
+    if !outcome.is_ok() && !keep_partial {
+        eprintln!(
+            "{} error(s); {} not written (--keep-partial writes the files that translated)",
+            outcome.errors.len(),
+            output.display()
+        );
+        process::exit(1);
+    }
+
+    fs::write(&output, &outcome.asm).map_err(|e| VMError::FileWrite {
+        path: output.display().to_string(),
+        source: e,
+    })?;
+
+    if verbose {
+        let lines = outcome.asm.lines().count();
+        eprintln!("Generated {} lines of assembly", lines);
+    }
+    if !outcome.is_ok() {
+        eprintln!(
+            "{} error(s); wrote partial output to {}",
+            outcome.errors.len(),
+            output.display()
+        );
+        process::exit(1);
+    }
+
+    Ok(output)
+}
+
+fn translate_manifest_mode(
+    manifest: &Path,
+    options: &TranslateOptions,
+    verbose: bool,
+) -> Result<std::path::PathBuf, VMError> {
+    if verbose {
+        eprintln!("Translating manifest: {}", manifest.display());
+    }
+
+    let asm = report_warnings(translate_manifest_report(manifest, options)?, verbose);
+    let output = manifest.with_extension("asm");
 
     fs::write(&output, &asm).map_err(|e| VMError::FileWrite {
         path: output.display().to_string(),
@@ -145,3 +398,158 @@ fn translate_directory_mode(input: &Path, verbose: bool) -> Result<std::path::Pa
 
     Ok(output)
 }
+
+/// Default emulator step limit for `--run`.
+const DEFAULT_STEPS: u64 = 1_000_000;
+
+/// Default number of differences `--diff-reference` shows.
+const DEFAULT_DIFF_LIMIT: usize = 10;
+
+/// Options that take a value.
+const VALUE_OPTIONS: [&str; 6] = [
+    "--scratch-base",
+    "--manifest",
+    "--steps",
+    "--inspect",
+    "--diff-reference",
+    "--diff-limit",
+];
+
+/// The first argument that is neither an option nor an option's value.
+fn positional(args: &[String]) -> Option<&str> {
+    let mut rest = args.iter().skip(1);
+    while let Some(arg) = rest.next() {
+        if VALUE_OPTIONS.contains(&arg.as_str()) {
+            rest.next();
+        } else if !arg.starts_with('-') {
+            return Some(arg);
+        }
+    }
+    None
+}
+
+/// Parse a RAM range: `N`, `A..B` (exclusive) or `A..=B`.
+fn parse_range(spec: &str) -> Option<Range<usize>> {
+    let range = if let Some((start, end)) = spec.split_once("..=") {
+        start.parse().ok()?..end.parse::<usize>().ok()? + 1
+    } else if let Some((start, end)) = spec.split_once("..") {
+        start.parse().ok()?..end.parse().ok()?
+    } else {
+        let address: usize = spec.parse().ok()?;
+        address..address + 1
+    };
+    (range.start < range.end && range.end <= RAM_SIZE).then_some(range)
+}
+
+/// Translate for validation only, then execute on the emulator and print
+/// the requested RAM ranges and the final stack pointer.
+fn run_mode(
+    input: &Path,
+    options: &TranslateOptions,
+    steps: u64,
+    ranges: &[Range<usize>],
+    verbose: bool,
+) -> Result<(), VMError> {
+    if input.is_dir() {
+        report_warnings(translate_directory_report(input, options)?, verbose);
+    } else if input.extension().is_some_and(|ext| ext == "vm") {
+        report_warnings(translate_file_report(input, options)?, verbose);
+    } else {
+        return Err(VMError::InvalidPath {
+            path: input.display().to_string(),
+        });
+    }
+
+    let mut emulator = Emulator::from_path_with_options(input, &options.parse)?;
+    let executed = emulator.run(steps)?;
+    if verbose {
+        eprintln!("Halted after {} steps", executed);
+    }
+
+    for range in ranges {
+        for address in range.clone() {
+            println!("RAM[{}] = {}", address, emulator.ram()[address]);
+        }
+    }
+    println!("SP = {}", emulator.sp());
+    Ok(())
+}
+
+/// Translate without writing the `.asm`, then compare the assembly with
+/// `reference` and print the first `limit` differences. Returns whether the
+/// two are equivalent.
+///
+/// Differences show the VM line they come from when a single file is
+/// translated with options that leave the source map valid.
+fn diff_mode(
+    input: &Path,
+    is_manifest: bool,
+    reference: &Path,
+    options: &TranslateOptions,
+    limit: usize,
+) -> Result<bool, VMError> {
+    let is_file = !is_manifest && input.extension().is_some_and(|ext| ext == "vm");
+    let ours = if is_manifest {
+        report_warnings(translate_manifest_report(input, options)?, false)
+    } else if input.is_dir() {
+        report_warnings(translate_directory_report(input, options)?, false)
+    } else if is_file {
+        report_warnings(translate_file_report(input, options)?, false)
+    } else {
+        return Err(VMError::InvalidPath {
+            path: input.display().to_string(),
+        });
+    };
+    let reference_asm = fs::read_to_string(reference).map_err(|e| VMError::FileRead {
+        path: reference.display().to_string(),
+        source: e,
+    })?;
+
+    let source_map = is_file
+        .then(|| fs::read_to_string(input).ok())
+        .flatten()
+        .and_then(|source| {
+            let name = input.file_stem()?.to_str()?;
+            translate_with_sourcemap(&source, name).ok()
+        })
+        .filter(|(asm, _)| *asm == ours)
+        .map(|(_, map)| map)
+        .unwrap_or_default();
+
+    let differences = asm_diff::compare(&ours, &reference_asm, &source_map, limit);
+    let show = |instruction: &Option<AsmInstruction>| match instruction {
+        Some(instruction) => format!("(line {}): {}", instruction.line, instruction.original),
+        None => "(end of file)".to_string(),
+    };
+    for diff in &differences {
+        match diff.vm_line {
+            Some(line) => println!("instruction {} differs (VM line {}):", diff.index, line),
+            None => println!("instruction {} differs:", diff.index),
+        }
+        println!("  ours      {}", show(&diff.ours));
+        println!("  reference {}", show(&diff.reference));
+    }
+    if differences.is_empty() {
+        println!("equivalent to {}", reference.display());
+    }
+    Ok(differences.is_empty())
+}
+
+/// Print link- and stack-check warnings to stderr, and with `verbose`
+/// whitespace normalization notes, and return the assembly.
+fn report_warnings(translation: Translation, verbose: bool) -> String {
+    for warning in &translation.warnings {
+        eprintln!("Warning: {}", warning);
+    }
+    if verbose {
+        report_normalizations(&translation.normalizations);
+    }
+    translation.asm
+}
+
+/// Print a note for each file with unusual whitespace.
+fn report_normalizations(normalizations: &[Normalization]) {
+    for normalization in normalizations {
+        eprintln!("Note: {}", normalization);
+    }
+}
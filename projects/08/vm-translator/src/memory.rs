@@ -41,11 +41,64 @@ pub fn segment_access(segment: Segment) -> SegmentAccess {
     }
 }
 
+/// First RAM address of the temp segment (RAM[5..12]).
+pub const TEMP_BASE: u16 = 5;
+
+/// Number of slots in the temp segment.
+pub const TEMP_SIZE: u16 = 8;
+
+/// First RAM address of the memory-mapped screen.
+pub const SCREEN_BASE: u16 = 16384;
+
+/// Default first scratch register used by generated code.
+pub const DEFAULT_SCRATCH_BASE: u8 = 13;
+
+/// Number of consecutive scratch registers used by generated code.
+///
+/// Slot 0 holds the target address in `pop` to an indirect segment and the
+/// frame pointer in `return`; slot 1 holds the return address in `return`.
+pub const SCRATCH_REGISTER_COUNT: u8 = 2;
+
+/// Scratch registers referenced by generated code with the default base.
+///
+/// Apart from these, output only references the named pointers (SP, LCL,
+/// ARG, THIS, THAT) and numeric temp addresses.
+pub const SCRATCH_REGISTERS: [&str; SCRATCH_REGISTER_COUNT as usize] = ["R13", "R14"];
+
+/// Check that scratch registers starting at `base` fit within R5–R15.
+#[inline]
+pub fn is_valid_scratch_base(base: u8) -> bool {
+    (TEMP_BASE as u8..=16 - SCRATCH_REGISTER_COUNT).contains(&base)
+}
+
+/// Check whether temp `index` maps onto a scratch register at `base`.
+///
+/// Only meaningful when the scratch registers have been relocated into the
+/// temp segment (R5–R12).
+#[inline]
+pub fn temp_collides_with_scratch(index: u16, base: u8) -> bool {
+    is_scratch_register(temp_address(index), base)
+}
+
+/// Check whether RAM `address` is a scratch register at `base`.
+#[inline]
+pub fn is_scratch_register(address: u16, base: u8) -> bool {
+    let base = u16::from(base);
+    (base..base + u16::from(SCRATCH_REGISTER_COUNT)).contains(&address)
+}
+
+/// Check that a temp segment starting at `base` stays clear of SP–THAT and
+/// below the memory-mapped I/O at 16384.
+#[inline]
+pub fn is_valid_temp_base(base: u16) -> bool {
+    (TEMP_BASE..=SCREEN_BASE - TEMP_SIZE).contains(&base)
+}
+
 /// Calculate the RAM address for temp segment.
 /// Temp segment is RAM[5..12], so temp i maps to RAM[5+i].
 #[inline]
 pub fn temp_address(index: u16) -> u16 {
-    5 + index
+    TEMP_BASE + index
 }
 
 /// Get the symbol for pointer segment.
@@ -86,6 +139,23 @@ mod tests {
         assert_eq!(temp_address(7), 12);
     }
 
+    #[test]
+    fn test_scratch_base_validation() {
+        assert!(is_valid_scratch_base(DEFAULT_SCRATCH_BASE));
+        assert!(is_valid_scratch_base(5));
+        assert!(is_valid_scratch_base(14));
+        assert!(!is_valid_scratch_base(4));
+        assert!(!is_valid_scratch_base(15));
+    }
+
+    #[test]
+    fn test_temp_collides_with_scratch() {
+        assert!(!temp_collides_with_scratch(7, DEFAULT_SCRATCH_BASE));
+        assert!(temp_collides_with_scratch(6, 11));
+        assert!(temp_collides_with_scratch(7, 11));
+        assert!(!temp_collides_with_scratch(5, 11));
+    }
+
     #[test]
     fn test_pointer_symbol() {
         assert_eq!(pointer_symbol(0), "THIS");
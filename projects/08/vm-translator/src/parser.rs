@@ -2,7 +2,8 @@
 //!
 //! Parses VM bytecode into typed command structures with full validation.
 
-use crate::error::{Result, VMError};
+use crate::error::{OperandError, Result, VMError};
+use crate::memory::TEMP_SIZE;
 
 /// Arithmetic and logical operations.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -31,6 +32,60 @@ pub enum Segment {
     Static,
 }
 
+impl Segment {
+    /// The segment name as written in VM code.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Segment::Constant => "constant",
+            Segment::Local => "local",
+            Segment::Argument => "argument",
+            Segment::This => "this",
+            Segment::That => "that",
+            Segment::Pointer => "pointer",
+            Segment::Temp => "temp",
+            Segment::Static => "static",
+        }
+    }
+
+    /// Parse a segment name, ignoring ASCII case.
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<Self> {
+        let mut lower = [0u8; KEYWORD_MAX_LEN];
+        match ascii_lowercase(s, &mut lower)? {
+            "constant" => Some(Segment::Constant),
+            "local" => Some(Segment::Local),
+            "argument" => Some(Segment::Argument),
+            "this" => Some(Segment::This),
+            "that" => Some(Segment::That),
+            "pointer" => Some(Segment::Pointer),
+            "temp" => Some(Segment::Temp),
+            "static" => Some(Segment::Static),
+            _ => None,
+        }
+    }
+}
+
+/// Check an index against the fixed-size segments: `temp` has 8 slots and
+/// `pointer` 2; the other segments accept any index.
+///
+/// The parser applies the same check. Errors returned here have no
+/// location: they are [`VMError::InvalidOperand`].
+pub fn validate_index(segment: Segment, index: u16) -> Result<()> {
+    match segment {
+        Segment::Pointer if index > 1 => Err(OperandError::PointerIndex(index).into()),
+        Segment::Temp if index >= TEMP_SIZE => Err(OperandError::TempIndex(index).into()),
+        _ => Ok(()),
+    }
+}
+
+/// [`validate_index`] for a `pop`, which also cannot target `constant`.
+pub fn validate_pop(segment: Segment, index: u16) -> Result<()> {
+    if segment == Segment::Constant {
+        return Err(OperandError::PopToConstant.into());
+    }
+    validate_index(segment, index)
+}
+
 /// VM command variants.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum VMCommand {
@@ -52,22 +107,105 @@ pub enum VMCommand {
     Return,
 }
 
+impl VMCommand {
+    /// The command's first word, e.g. `push` or `if-goto`.
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            VMCommand::Arithmetic(op) => match op {
+                ArithmeticOp::Add => "add",
+                ArithmeticOp::Sub => "sub",
+                ArithmeticOp::Neg => "neg",
+                ArithmeticOp::Eq => "eq",
+                ArithmeticOp::Lt => "lt",
+                ArithmeticOp::Gt => "gt",
+                ArithmeticOp::And => "and",
+                ArithmeticOp::Or => "or",
+                ArithmeticOp::Not => "not",
+            },
+            VMCommand::Push { .. } => "push",
+            VMCommand::Pop { .. } => "pop",
+            VMCommand::Label { .. } => "label",
+            VMCommand::Goto { .. } => "goto",
+            VMCommand::IfGoto { .. } => "if-goto",
+            VMCommand::Function { .. } => "function",
+            VMCommand::Call { .. } => "call",
+            VMCommand::Return => "return",
+        }
+    }
+}
+
+/// Parser options.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// Accept command words and segment names in any case, e.g.
+    /// `Push Constant 7` (default false: lowercase only, like the reference
+    /// VM emulator). Function and label names always keep their case.
+    pub case_insensitive: bool,
+}
+
+/// Every command word.
+const COMMANDS: [&str; 17] = [
+    "add", "sub", "neg", "eq", "lt", "gt", "and", "or", "not", "push", "pop", "label", "goto",
+    "if-goto", "function", "call", "return",
+];
+
 /// Parse a single VM line into a command.
 ///
 /// Returns `Ok(None)` for empty lines and comments.
 /// Returns `Ok(Some(cmd))` for valid commands.
-/// Returns `Err` for invalid syntax.
+/// Returns `Err` for invalid syntax; the error carries the column of the
+/// offending word (see [`VMError::column`]).
 pub fn parse_line(line: &str, line_num: usize, filename: &str) -> Result<Option<VMCommand>> {
-    // Strip comments and whitespace
-    let line = line.split("//").next().unwrap_or("").trim();
-    if line.is_empty() {
+    parse_line_with_options(line, line_num, filename, &ParseOptions::default())
+}
+
+/// [`parse_line`] with the given options.
+///
+/// Without [`ParseOptions::case_insensitive`], a command word or segment
+/// name that is only wrongly capitalized is a [`VMError::WrongCase`].
+pub fn parse_line_with_options(
+    line: &str,
+    line_num: usize,
+    filename: &str,
+    options: &ParseOptions,
+) -> Result<Option<VMCommand>> {
+    // Strip comments; columns are counted from the start of the raw line
+    let code = line.split("//").next().unwrap_or("");
+    if code.trim_matches(is_separator).is_empty() {
         return Ok(None);
     }
 
-    let parts: Vec<&str> = line.split_whitespace().collect();
-    let cmd = parts[0].to_lowercase();
-
-    match cmd.as_str() {
+    // Commands take at most two arguments; anything after them is ignored.
+    // A fixed array keeps the hot path free of heap allocations.
+    let mut words = [Word::default(); 3];
+    let mut count = 0;
+    for text in code
+        .split(is_separator)
+        .filter(|word| !word.is_empty())
+        .take(words.len())
+    {
+        let offset = text.as_ptr() as usize - code.as_ptr() as usize;
+        words[count] = Word {
+            text,
+            column: code[..offset].chars().count() + 1,
+        };
+        count += 1;
+    }
+    let parts = &words[..count];
+    let at = Location {
+        line: line_num,
+        file: filename,
+        // Where a missing argument would go
+        end_column: code.trim_end_matches(is_separator).chars().count() + 2,
+        case_insensitive: options.case_insensitive,
+    };
+
+    let mut lower = [0u8; KEYWORD_MAX_LEN];
+    let keyword = ascii_lowercase(parts[0].text, &mut lower).unwrap_or("");
+    if let Some(expected) = COMMANDS.iter().find(|c| **c == keyword) {
+        at.check_case(parts[0], "command", expected)?;
+    }
+    match keyword {
         // Arithmetic/logical commands
         "add" => Ok(Some(VMCommand::Arithmetic(ArithmeticOp::Add))),
         "sub" => Ok(Some(VMCommand::Arithmetic(ArithmeticOp::Sub))),
@@ -80,82 +218,168 @@ pub fn parse_line(line: &str, line_num: usize, filename: &str) -> Result<Option<
         "not" => Ok(Some(VMCommand::Arithmetic(ArithmeticOp::Not))),
 
         // Memory access commands
-        "push" => parse_push(&parts, line_num, filename),
-        "pop" => parse_pop(&parts, line_num, filename),
+        "push" => parse_push(parts, &at),
+        "pop" => parse_pop(parts, &at),
 
         // Program flow commands
-        "label" => parse_label(&parts, line_num, filename),
-        "goto" => parse_goto(&parts, line_num, filename),
-        "if-goto" => parse_if_goto(&parts, line_num, filename),
+        "label" => parse_label(parts, &at),
+        "goto" => parse_goto(parts, &at),
+        "if-goto" => parse_if_goto(parts, &at),
 
         // Function commands
-        "function" => parse_function(&parts, line_num, filename),
-        "call" => parse_call(&parts, line_num, filename),
+        "function" => parse_function(parts, &at),
+        "call" => parse_call(parts, &at),
         "return" => Ok(Some(VMCommand::Return)),
 
         _ => Err(VMError::InvalidCommand {
-            line: line_num,
-            file: filename.to_string(),
-            command: cmd,
+            line: at.line,
+            column: parts[0].column,
+            file: at.file.to_string(),
+            command: parts[0].text.to_lowercase(),
         }),
     }
 }
 
-fn parse_push(parts: &[&str], line_num: usize, filename: &str) -> Result<Option<VMCommand>> {
-    if parts.len() < 3 {
-        return Err(VMError::MissingArgument {
-            line: line_num,
-            file: filename.to_string(),
-            command: "push".to_string(),
-        });
-    }
+/// Byte order mark, which editors may leave at the start of a file.
+const BYTE_ORDER_MARK: char = '\u{FEFF}';
 
-    let segment = parse_segment(parts[1], line_num, filename)?;
-    let index = parse_index(parts[2], line_num, filename)?;
-    validate_segment_index(segment, index, line_num, filename)?;
+/// Check if `c` separates words: any Unicode whitespace (which includes the
+/// non-breaking space U+00A0), or a byte order mark.
+pub(crate) fn is_separator(c: char) -> bool {
+    c.is_whitespace() || c == BYTE_ORDER_MARK
+}
 
-    Ok(Some(VMCommand::Push { segment, index }))
+/// Count the word separators in the code of `line` (before any `//`) other
+/// than space, tab, CR and LF, such as non-breaking spaces and byte order
+/// marks, which [`parse_line`] reads as spaces.
+pub fn unusual_whitespace(line: &str) -> usize {
+    let code = line.split("//").next().unwrap_or("");
+    code.chars()
+        .filter(|&c| is_separator(c) && !matches!(c, ' ' | '\t' | '\r' | '\n'))
+        .count()
 }
 
-fn parse_pop(parts: &[&str], line_num: usize, filename: &str) -> Result<Option<VMCommand>> {
-    if parts.len() < 3 {
-        return Err(VMError::MissingArgument {
-            line: line_num,
-            file: filename.to_string(),
-            command: "pop".to_string(),
-        });
+/// A word of a VM line with its 1-based column.
+#[derive(Debug, Clone, Copy, Default)]
+struct Word<'a> {
+    text: &'a str,
+    column: usize,
+}
+
+/// The line being parsed, for error reporting.
+struct Location<'a> {
+    line: usize,
+    file: &'a str,
+    /// Column just past the last word.
+    end_column: usize,
+    /// [`ParseOptions::case_insensitive`]
+    case_insensitive: bool,
+}
+
+impl Location<'_> {
+    /// Place an error from [`validate_pop`] on the segment or index word of
+    /// a `push`/`pop` line.
+    fn locate(&self, err: VMError, parts: &[Word]) -> VMError {
+        let (line, file) = (self.line, self.file.to_string());
+        match err {
+            VMError::InvalidOperand(OperandError::PopToConstant) => VMError::PopToConstant {
+                line,
+                column: parts[1].column,
+                file,
+            },
+            VMError::InvalidOperand(OperandError::PointerIndex(index)) => {
+                VMError::InvalidPointerIndex {
+                    line,
+                    column: parts[2].column,
+                    file,
+                    index,
+                }
+            }
+            VMError::InvalidOperand(OperandError::TempIndex(index)) => VMError::InvalidTempIndex {
+                line,
+                column: parts[2].column,
+                file,
+                index,
+            },
+            other => other,
+        }
     }
 
-    let segment = parse_segment(parts[1], line_num, filename)?;
+    /// Error for a keyword `word` written other than as `expected`, unless
+    /// case is ignored.
+    fn check_case(&self, word: Word, kind: &'static str, expected: &'static str) -> Result<()> {
+        if self.case_insensitive || word.text == expected {
+            return Ok(());
+        }
+        Err(VMError::WrongCase {
+            line: self.line,
+            column: word.column,
+            file: self.file.to_string(),
+            kind,
+            word: word.text.to_string(),
+            expected,
+        })
+    }
 
-    // Cannot pop to constant
-    if segment == Segment::Constant {
-        return Err(VMError::PopToConstant {
-            line: line_num,
-            file: filename.to_string(),
-        });
+    /// Error for a command with fewer than `needed` words.
+    fn check_arguments(&self, parts: &[Word], needed: usize, command: &str) -> Result<()> {
+        if parts.len() < needed {
+            return Err(VMError::MissingArgument {
+                line: self.line,
+                column: self.end_column,
+                file: self.file.to_string(),
+                command: command.to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Length of the longest command or segment keyword (`function`, `argument`).
+const KEYWORD_MAX_LEN: usize = 8;
+
+/// Lowercase a short ASCII word into `buf` without allocating.
+///
+/// Returns `None` for words that cannot be a keyword (too long or non-ASCII).
+fn ascii_lowercase<'a>(word: &str, buf: &'a mut [u8; KEYWORD_MAX_LEN]) -> Option<&'a str> {
+    if word.len() > buf.len() || !word.is_ascii() {
+        return None;
     }
+    let lower = &mut buf[..word.len()];
+    lower.copy_from_slice(word.as_bytes());
+    lower.make_ascii_lowercase();
+    std::str::from_utf8(lower).ok()
+}
+
+fn parse_push(parts: &[Word], at: &Location) -> Result<Option<VMCommand>> {
+    at.check_arguments(parts, 3, "push")?;
+
+    let segment = parse_segment(parts[1], at)?;
+    let index = parse_index(parts[2], at)?;
+    validate_index(segment, index).map_err(|e| at.locate(e, parts))?;
+
+    Ok(Some(VMCommand::Push { segment, index }))
+}
+
+fn parse_pop(parts: &[Word], at: &Location) -> Result<Option<VMCommand>> {
+    at.check_arguments(parts, 3, "pop")?;
 
-    let index = parse_index(parts[2], line_num, filename)?;
-    validate_segment_index(segment, index, line_num, filename)?;
+    let segment = parse_segment(parts[1], at)?;
+    let index = parse_index(parts[2], at)?;
+    validate_pop(segment, index).map_err(|e| at.locate(e, parts))?;
 
     Ok(Some(VMCommand::Pop { segment, index }))
 }
 
-fn parse_label(parts: &[&str], line_num: usize, filename: &str) -> Result<Option<VMCommand>> {
-    if parts.len() < 2 {
-        return Err(VMError::MissingArgument {
-            line: line_num,
-            file: filename.to_string(),
-            command: "label".to_string(),
-        });
-    }
+fn parse_label(parts: &[Word], at: &Location) -> Result<Option<VMCommand>> {
+    at.check_arguments(parts, 2, "label")?;
 
-    let name = parts[1].to_string();
+    let name = parts[1].text.to_string();
     if name.is_empty() {
         return Err(VMError::InvalidLabelName {
-            line: line_num,
-            file: filename.to_string(),
+            line: at.line,
+            column: parts[1].column,
+            file: at.file.to_string(),
             name,
         });
     }
@@ -163,117 +387,69 @@ fn parse_label(parts: &[&str], line_num: usize, filename: &str) -> Result<Option
     Ok(Some(VMCommand::Label { name }))
 }
 
-fn parse_goto(parts: &[&str], line_num: usize, filename: &str) -> Result<Option<VMCommand>> {
-    if parts.len() < 2 {
-        return Err(VMError::MissingArgument {
-            line: line_num,
-            file: filename.to_string(),
-            command: "goto".to_string(),
-        });
-    }
+fn parse_goto(parts: &[Word], at: &Location) -> Result<Option<VMCommand>> {
+    at.check_arguments(parts, 2, "goto")?;
 
     Ok(Some(VMCommand::Goto {
-        label: parts[1].to_string(),
+        label: parts[1].text.to_string(),
     }))
 }
 
-fn parse_if_goto(parts: &[&str], line_num: usize, filename: &str) -> Result<Option<VMCommand>> {
-    if parts.len() < 2 {
-        return Err(VMError::MissingArgument {
-            line: line_num,
-            file: filename.to_string(),
-            command: "if-goto".to_string(),
-        });
-    }
+fn parse_if_goto(parts: &[Word], at: &Location) -> Result<Option<VMCommand>> {
+    at.check_arguments(parts, 2, "if-goto")?;
 
     Ok(Some(VMCommand::IfGoto {
-        label: parts[1].to_string(),
+        label: parts[1].text.to_string(),
     }))
 }
 
-fn parse_function(parts: &[&str], line_num: usize, filename: &str) -> Result<Option<VMCommand>> {
-    if parts.len() < 3 {
-        return Err(VMError::MissingArgument {
-            line: line_num,
-            file: filename.to_string(),
-            command: "function".to_string(),
-        });
-    }
+fn parse_function(parts: &[Word], at: &Location) -> Result<Option<VMCommand>> {
+    at.check_arguments(parts, 3, "function")?;
 
-    let name = parts[1].to_string();
+    let name = parts[1].text.to_string();
     if name.is_empty() {
         return Err(VMError::InvalidFunctionName {
-            line: line_num,
-            file: filename.to_string(),
+            line: at.line,
+            column: parts[1].column,
+            file: at.file.to_string(),
             name,
         });
     }
 
-    let num_locals = parse_index(parts[2], line_num, filename)?;
+    let num_locals = parse_index(parts[2], at)?;
 
     Ok(Some(VMCommand::Function { name, num_locals }))
 }
 
-fn parse_call(parts: &[&str], line_num: usize, filename: &str) -> Result<Option<VMCommand>> {
-    if parts.len() < 3 {
-        return Err(VMError::MissingArgument {
-            line: line_num,
-            file: filename.to_string(),
-            command: "call".to_string(),
-        });
-    }
+fn parse_call(parts: &[Word], at: &Location) -> Result<Option<VMCommand>> {
+    at.check_arguments(parts, 3, "call")?;
 
-    let name = parts[1].to_string();
-    let num_args = parse_index(parts[2], line_num, filename)?;
+    let name = parts[1].text.to_string();
+    let num_args = parse_index(parts[2], at)?;
 
     Ok(Some(VMCommand::Call { name, num_args }))
 }
 
-fn parse_segment(s: &str, line_num: usize, filename: &str) -> Result<Segment> {
-    match s.to_lowercase().as_str() {
-        "constant" => Ok(Segment::Constant),
-        "local" => Ok(Segment::Local),
-        "argument" => Ok(Segment::Argument),
-        "this" => Ok(Segment::This),
-        "that" => Ok(Segment::That),
-        "pointer" => Ok(Segment::Pointer),
-        "temp" => Ok(Segment::Temp),
-        "static" => Ok(Segment::Static),
-        _ => Err(VMError::InvalidSegment {
-            line: line_num,
-            file: filename.to_string(),
-            segment: s.to_string(),
-        }),
-    }
-}
-
-fn parse_index(s: &str, line_num: usize, filename: &str) -> Result<u16> {
-    s.parse::<u16>().map_err(|_| VMError::InvalidNumber {
-        line: line_num,
-        file: filename.to_string(),
-        value: s.to_string(),
-    })
+fn parse_segment(word: Word, at: &Location) -> Result<Segment> {
+    let segment = Segment::from_str(word.text).ok_or_else(|| VMError::InvalidSegment {
+        line: at.line,
+        column: word.column,
+        file: at.file.to_string(),
+        segment: word.text.to_string(),
+    })?;
+    at.check_case(word, "segment", segment.as_str())?;
+    Ok(segment)
 }
 
-fn validate_segment_index(
-    segment: Segment,
-    index: u16,
-    line_num: usize,
-    filename: &str,
-) -> Result<()> {
-    match segment {
-        Segment::Pointer if index > 1 => Err(VMError::InvalidPointerIndex {
-            line: line_num,
-            file: filename.to_string(),
-            index,
-        }),
-        Segment::Temp if index > 7 => Err(VMError::InvalidTempIndex {
-            line: line_num,
-            file: filename.to_string(),
-            index,
-        }),
-        _ => Ok(()),
-    }
+fn parse_index(word: Word, at: &Location) -> Result<u16> {
+    word.text
+        .parse::<u16>()
+        .map_err(|_| VMError::InvalidNumber {
+            line: at.line,
+            column: word.column,
+            file: at.file.to_string(),
+            value: word.text.to_string(),
+        })
 }
 
 #[cfg(test)]
@@ -418,4 +594,130 @@ mod tests {
         assert!(parse_line("push temp 7", 1, "Test.vm").is_ok());
         assert!(parse_line("push temp 8", 1, "Test.vm").is_err());
     }
+
+    #[test]
+    fn test_wrong_case_is_rejected_with_hint() {
+        let err = parse_line("Push constant 7", 3, "Test").unwrap_err();
+        assert!(matches!(
+            err,
+            VMError::WrongCase {
+                column: 1,
+                kind: "command",
+                expected: "push",
+                ..
+            }
+        ));
+        assert_eq!(
+            err.to_string(),
+            "Test:3: invalid command: Push (did you mean 'push'? commands are lowercase)"
+        );
+
+        let err = parse_line("push CONSTANT 7", 3, "Test").unwrap_err();
+        assert_eq!(err.column(), Some(6));
+        assert_eq!(
+            err.to_string(),
+            "Test:3: invalid segment: CONSTANT (did you mean 'constant'? segments are lowercase)"
+        );
+
+        // A misspelling is not a case problem
+        assert!(matches!(
+            parse_line("Pushh constant 7", 1, "Test"),
+            Err(VMError::InvalidCommand { .. })
+        ));
+    }
+
+    #[test]
+    fn test_case_insensitive_parsing() {
+        let lenient = ParseOptions {
+            case_insensitive: true,
+        };
+        let parse = |line| parse_line_with_options(line, 1, "Test", &lenient).unwrap();
+        assert_eq!(
+            parse("PUSH Constant 7"),
+            Some(VMCommand::Push {
+                segment: Segment::Constant,
+                index: 7
+            })
+        );
+        assert_eq!(parse("Add"), Some(VMCommand::Arithmetic(ArithmeticOp::Add)));
+        // Names keep their case
+        assert_eq!(
+            parse("If-Goto Loop"),
+            Some(VMCommand::IfGoto {
+                label: "Loop".to_string()
+            })
+        );
+        assert_eq!(
+            parse("FUNCTION Main.Run 2"),
+            Some(VMCommand::Function {
+                name: "Main.Run".to_string(),
+                num_locals: 2
+            })
+        );
+    }
+
+    #[test]
+    fn test_segment_from_str() {
+        assert_eq!(Segment::from_str("local"), Some(Segment::Local));
+        assert_eq!(Segment::from_str("TEMP"), Some(Segment::Temp));
+        assert_eq!(Segment::from_str("locol"), None);
+        assert_eq!(Segment::from_str("argumentative"), None);
+        assert_eq!(Segment::from_str(""), None);
+    }
+
+    #[test]
+    fn test_validate_index_helpers() {
+        assert!(validate_index(Segment::Pointer, 1).is_ok());
+        assert!(matches!(
+            validate_index(Segment::Pointer, 2),
+            Err(VMError::InvalidOperand(OperandError::PointerIndex(2)))
+        ));
+        assert!(validate_index(Segment::Temp, 7).is_ok());
+        assert!(matches!(
+            validate_index(Segment::Temp, 8),
+            Err(VMError::InvalidOperand(OperandError::TempIndex(8)))
+        ));
+        assert!(validate_index(Segment::Constant, 32767).is_ok());
+        assert!(validate_index(Segment::Local, u16::MAX).is_ok());
+
+        assert!(validate_pop(Segment::Local, 3).is_ok());
+        assert!(matches!(
+            validate_pop(Segment::Constant, 5),
+            Err(VMError::InvalidOperand(OperandError::PopToConstant))
+        ));
+        assert!(validate_pop(Segment::Temp, 8).is_err());
+        let err = validate_index(Segment::Temp, 8).unwrap_err();
+        assert_eq!(err.location(), None);
+        assert_eq!(err.to_string(), "invalid temp index 8 (must be 0-7)");
+    }
+
+    #[test]
+    fn test_unusual_whitespace_separates_words() {
+        let cmd = parse_line("push\u{a0}constant\u{a0}7", 1, "Test").unwrap();
+        assert_eq!(
+            cmd,
+            Some(VMCommand::Push {
+                segment: Segment::Constant,
+                index: 7
+            })
+        );
+        let cmd = parse_line("\u{feff}add", 1, "Test").unwrap();
+        assert_eq!(cmd, Some(VMCommand::Arithmetic(ArithmeticOp::Add)));
+        assert_eq!(parse_line("\u{feff}// header", 1, "Test").unwrap(), None);
+
+        assert_eq!(unusual_whitespace("push\u{a0}constant\u{a0}7"), 2);
+        assert_eq!(unusual_whitespace("\u{feff}push constant 7\t// \u{a0}"), 1);
+        assert_eq!(unusual_whitespace("push constant 7\r"), 0);
+    }
+
+    #[test]
+    fn test_error_columns() {
+        let column = |line: &str| parse_line(line, 1, "Test").unwrap_err().column();
+        assert_eq!(column("  push locol 0"), Some(8));
+        assert_eq!(column("foo 1"), Some(1));
+        assert_eq!(column("push temp 8"), Some(11));
+        assert_eq!(column("pop constant 1"), Some(5));
+        assert_eq!(column("call Foo.bar x // comment"), Some(14));
+        assert_eq!(column("push local  // comment"), Some(12));
+    }
 }
@@ -0,0 +1,273 @@
+//! Static stack-depth check for hand-written VM code.
+//!
+//! A VM function starts with an empty working stack and must leave exactly
+//! one value on it, the return value, when it reaches `return`. The checker
+//! follows each function's commands, tracking how many values they push and
+//! consume, and warns when a command consumes more values than the function
+//! pushed or when `return` finds anything but one value.
+//!
+//! Branches make this approximate. The depth after `goto` and `return` is
+//! unknown until a `label` reached by an earlier jump, whose depth at the
+//! jump is used; code whose depth is unknown is not checked. A function gets
+//! at most one warning, since one mistake throws off every later depth.
+
+use std::collections::HashMap;
+
+use crate::error::VMWarning;
+use crate::parser::{ArithmeticOp, VMCommand};
+
+#[cfg(feature = "fs")]
+/// State of a [`StackChecker`] before a file, from
+/// [`StackChecker::checkpoint`].
+#[derive(Debug)]
+pub(crate) struct StackCheckpoint {
+    function: Option<String>,
+    depth: Option<u16>,
+    labels: HashMap<String, u16>,
+    warnings: usize,
+}
+
+/// Follows the stack depth through the commands of a program.
+#[derive(Debug, Default, Clone)]
+pub struct StackChecker {
+    /// Function being checked; `None` before the first `function` and after
+    /// a warning.
+    function: Option<String>,
+    /// Values on the working stack, if known.
+    depth: Option<u16>,
+    /// Depth at the jumps seen so far to each label of the function.
+    labels: HashMap<String, u16>,
+    warnings: Vec<VMWarning>,
+}
+
+impl StackChecker {
+    /// Create an empty checker.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Account for a parsed command.
+    pub fn record(&mut self, cmd: &VMCommand, file: &str, line: usize) {
+        if let VMCommand::Function { name, .. } = cmd {
+            self.function = Some(name.clone());
+            self.depth = Some(0);
+            self.labels.clear();
+            return;
+        }
+        let Some(function) = &self.function else {
+            return;
+        };
+        if let VMCommand::Label { name } = cmd {
+            self.depth = self.depth.or_else(|| self.labels.get(name).copied());
+            return;
+        }
+        let Some(depth) = self.depth else {
+            return;
+        };
+
+        if let VMCommand::Return = cmd {
+            if depth != 1 {
+                self.warn(VMWarning::UnbalancedReturn {
+                    line,
+                    file: file.to_string(),
+                    function: function.clone(),
+                    depth,
+                });
+            }
+            self.depth = None;
+            return;
+        }
+
+        let (consumed, produced) = stack_effect(cmd);
+        if depth < consumed {
+            self.warn(VMWarning::StackUnderflow {
+                line,
+                file: file.to_string(),
+                function: function.clone(),
+                needed: consumed,
+                depth,
+            });
+            return;
+        }
+        let after = depth - consumed + produced;
+        self.depth = match cmd {
+            VMCommand::Goto { label } | VMCommand::IfGoto { label } => {
+                self.labels.entry(label.clone()).or_insert(after);
+                matches!(cmd, VMCommand::IfGoto { .. }).then_some(after)
+            }
+            _ => Some(after),
+        };
+    }
+
+    #[cfg(feature = "fs")]
+    /// The state before a file, for [`rollback`](Self::rollback). Only the
+    /// labels of the current function are copied.
+    pub(crate) fn checkpoint(&self) -> StackCheckpoint {
+        StackCheckpoint {
+            function: self.function.clone(),
+            depth: self.depth,
+            labels: self.labels.clone(),
+            warnings: self.warnings.len(),
+        }
+    }
+
+    #[cfg(feature = "fs")]
+    /// Forget everything recorded after `checkpoint`.
+    pub(crate) fn rollback(&mut self, checkpoint: StackCheckpoint) {
+        self.function = checkpoint.function;
+        self.depth = checkpoint.depth;
+        self.labels = checkpoint.labels;
+        self.warnings.truncate(checkpoint.warnings);
+    }
+
+    /// The warnings found so far, in source order.
+    pub fn finish(self) -> Vec<VMWarning> {
+        self.warnings
+    }
+
+    /// Record a warning and stop checking the current function.
+    fn warn(&mut self, warning: VMWarning) {
+        self.warnings.push(warning);
+        self.function = None;
+        self.depth = None;
+    }
+}
+
+/// Values a command pops and pushes; `return` is checked separately.
+fn stack_effect(cmd: &VMCommand) -> (u16, u16) {
+    match cmd {
+        VMCommand::Arithmetic(ArithmeticOp::Neg | ArithmeticOp::Not) => (1, 1),
+        VMCommand::Arithmetic(_) => (2, 1),
+        VMCommand::Push { .. } => (0, 1),
+        VMCommand::Pop { .. } | VMCommand::IfGoto { .. } => (1, 0),
+        VMCommand::Call { num_args, .. } => (*num_args, 1),
+        VMCommand::Label { .. }
+        | VMCommand::Goto { .. }
+        | VMCommand::Function { .. }
+        | VMCommand::Return => (0, 0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_line;
+
+    fn check(source: &str) -> Vec<VMWarning> {
+        let mut checker = StackChecker::new();
+        for (i, line) in source.lines().enumerate() {
+            if let Some(cmd) = parse_line(line, i + 1, "Main").unwrap() {
+                checker.record(&cmd, "Main", i + 1);
+            }
+        }
+        checker.finish()
+    }
+
+    #[test]
+    fn test_balanced_functions_pass() {
+        let source = "\
+function Main.max 0
+push argument 0
+push argument 1
+gt
+if-goto FIRST
+push argument 1
+return
+label FIRST
+push argument 0
+return
+function Main.loop 1
+label LOOP
+push local 0
+push constant 1
+add
+pop local 0
+goto LOOP
+function Main.main 0
+call Main.loop 0
+pop temp 0
+push constant 3
+push constant 4
+call Main.max 2
+return";
+        assert_eq!(check(source), vec![]);
+    }
+
+    #[test]
+    fn test_return_without_value() {
+        let warnings = check("function Main.f 0\npush constant 1\npop temp 0\nreturn");
+        assert_eq!(
+            warnings,
+            vec![VMWarning::UnbalancedReturn {
+                line: 4,
+                file: "Main".to_string(),
+                function: "Main.f".to_string(),
+                depth: 0,
+            }]
+        );
+        assert_eq!(
+            warnings[0].to_string(),
+            "Main:4: Main.f returns with 0 value(s) on its stack instead of 1"
+        );
+    }
+
+    #[test]
+    fn test_leftover_value_at_return() {
+        // `do Main.g()` without discarding the result
+        let warnings = check("function Main.f 0\ncall Main.g 0\npush constant 0\nreturn");
+        assert!(matches!(
+            warnings[..],
+            [VMWarning::UnbalancedReturn {
+                line: 4,
+                depth: 2,
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn test_underflow_warns_once_per_function() {
+        let source = "\
+function Main.f 0
+push constant 1
+add
+add
+return
+function Main.g 0
+return";
+        let warnings = check(source);
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(
+            warnings[0].to_string(),
+            "Main:3: Main.f consumes 2 value(s) with 1 on its stack"
+        );
+        assert!(matches!(
+            warnings[1],
+            VMWarning::UnbalancedReturn { line: 7, .. }
+        ));
+    }
+
+    #[test]
+    fn test_depth_after_jump_comes_from_label() {
+        // The pushes after `goto` are unreachable until END, whose depth is
+        // known from the jump
+        let source = "\
+function Main.f 0
+push constant 1
+goto END
+push constant 2
+push constant 3
+label END
+return";
+        assert_eq!(check(source), vec![]);
+
+        // A label reached only by a later backward jump has unknown depth
+        let source = "function Main.f 0\ngoto SKIP\nlabel BACK\nreturn\nlabel SKIP\ngoto BACK";
+        assert_eq!(check(source), vec![]);
+    }
+
+    #[test]
+    fn test_code_outside_functions_is_not_checked() {
+        assert_eq!(check("add\npop temp 0\nreturn"), vec![]);
+    }
+}
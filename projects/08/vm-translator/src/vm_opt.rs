@@ -0,0 +1,291 @@
+//! VM-level control-flow simplification.
+//!
+//! Runs on parsed commands before code generation when
+//! [`TranslateOptions::optimize`](crate::TranslateOptions::optimize) is set:
+//!
+//! - `push constant c` (followed by any `neg`/`not`) then `if-goto L` becomes
+//!   `goto L` when the condition is non-zero and disappears when it is zero.
+//! - `goto A` / `if-goto A` where `label A` is followed by `goto B` is
+//!   retargeted to `B`, following chains up to a cycle.
+//! - `goto A` immediately followed by `label A` is removed.
+//! - `neg` directly followed by `neg`, or `not` by `not`, is removed: both
+//!   are their own inverse.
+//!
+//! Labels are scoped to their function, so the commands are split at each
+//! `function` and every segment is optimized on its own. Labels are never
+//! removed; other jumps may still target them.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::parser::{ArithmeticOp, Segment, VMCommand};
+
+/// Simplify jumps in a translated file's commands.
+pub fn optimize(commands: Vec<VMCommand>) -> Vec<VMCommand> {
+    let mut output = Vec::with_capacity(commands.len());
+    let mut segment = Vec::new();
+
+    for cmd in commands {
+        if matches!(cmd, VMCommand::Function { .. }) {
+            output.extend(optimize_segment(std::mem::take(&mut segment)));
+        }
+        segment.push(cmd);
+    }
+    output.extend(optimize_segment(segment));
+    output
+}
+
+/// Optimize the commands of a single function (or the code before the first).
+fn optimize_segment(commands: Vec<VMCommand>) -> Vec<VMCommand> {
+    let commands = cancel_unary_pairs(commands);
+    let commands = fold_constant_conditions(commands);
+    let commands = thread_jumps(commands);
+    remove_jumps_to_next(commands)
+}
+
+/// Remove adjacent `neg neg` and `not not` pairs.
+///
+/// Pairs exposed by removing another pair cancel too: `neg not not neg` is
+/// removed entirely.
+fn cancel_unary_pairs(commands: Vec<VMCommand>) -> Vec<VMCommand> {
+    let mut output: Vec<VMCommand> = Vec::with_capacity(commands.len());
+
+    for cmd in commands {
+        if let VMCommand::Arithmetic(op @ (ArithmeticOp::Neg | ArithmeticOp::Not)) = cmd
+            && output.last() == Some(&VMCommand::Arithmetic(op))
+        {
+            output.pop();
+            continue;
+        }
+        output.push(cmd);
+    }
+    output
+}
+
+/// Replace `if-goto` on a constant condition with `goto` or nothing.
+fn fold_constant_conditions(commands: Vec<VMCommand>) -> Vec<VMCommand> {
+    let mut output: Vec<VMCommand> = Vec::with_capacity(commands.len());
+
+    for cmd in commands {
+        let VMCommand::IfGoto { label } = cmd else {
+            output.push(cmd);
+            continue;
+        };
+        match constant_suffix(&output) {
+            Some((start, value)) => {
+                output.truncate(start);
+                if value != 0 {
+                    output.push(VMCommand::Goto { label });
+                }
+            }
+            None => output.push(VMCommand::IfGoto { label }),
+        }
+    }
+    output
+}
+
+/// Find a trailing `push constant c` followed only by `neg`/`not`.
+///
+/// Returns the index of the push and the value left on the stack.
+fn constant_suffix(commands: &[VMCommand]) -> Option<(usize, u16)> {
+    let unary = commands
+        .iter()
+        .rev()
+        .take_while(|cmd| {
+            matches!(
+                cmd,
+                VMCommand::Arithmetic(ArithmeticOp::Neg | ArithmeticOp::Not)
+            )
+        })
+        .count();
+    let start = commands.len().checked_sub(unary + 1)?;
+    let VMCommand::Push {
+        segment: Segment::Constant,
+        index,
+    } = commands[start]
+    else {
+        return None;
+    };
+
+    let value = commands[start + 1..]
+        .iter()
+        .fold(index, |value, cmd| match cmd {
+            VMCommand::Arithmetic(ArithmeticOp::Neg) => value.wrapping_neg(),
+            _ => !value,
+        });
+    Some((start, value))
+}
+
+/// Retarget jumps to labels that only jump onward.
+fn thread_jumps(mut commands: Vec<VMCommand>) -> Vec<VMCommand> {
+    // label -> target of the `goto` that immediately follows it
+    let mut forwards: HashMap<String, String> = HashMap::new();
+    for (i, cmd) in commands.iter().enumerate() {
+        if let VMCommand::Label { name } = cmd
+            && let Some(VMCommand::Goto { label }) = commands[i + 1..]
+                .iter()
+                .find(|next| !matches!(next, VMCommand::Label { .. }))
+        {
+            forwards.insert(name.clone(), label.clone());
+        }
+    }
+    if forwards.is_empty() {
+        return commands;
+    }
+
+    for cmd in &mut commands {
+        if let VMCommand::Goto { label } | VMCommand::IfGoto { label } = cmd
+            && let Some(target) = resolve(&forwards, label)
+        {
+            *label = target;
+        }
+    }
+    commands
+}
+
+/// Follow a chain of forwarding labels to its end.
+///
+/// Returns `None` if `label` does not forward anywhere or the chain loops
+/// back on itself (the jump is then left as written).
+fn resolve(forwards: &HashMap<String, String>, label: &str) -> Option<String> {
+    let mut seen = HashSet::from([label]);
+    let mut current = forwards.get(label)?;
+    while let Some(next) = forwards.get(current) {
+        if !seen.insert(current) {
+            return None;
+        }
+        current = next;
+    }
+    (current != label).then(|| current.clone())
+}
+
+/// Remove `goto A` when `label A` follows it (possibly after other labels).
+fn remove_jumps_to_next(commands: Vec<VMCommand>) -> Vec<VMCommand> {
+    let keep: Vec<bool> = commands
+        .iter()
+        .enumerate()
+        .map(|(i, cmd)| {
+            let VMCommand::Goto { label } = cmd else {
+                return true;
+            };
+            !commands[i + 1..]
+                .iter()
+                .map_while(|next| match next {
+                    VMCommand::Label { name } => Some(name),
+                    _ => None,
+                })
+                .any(|name| name == label)
+        })
+        .collect();
+
+    commands
+        .into_iter()
+        .zip(keep)
+        .filter_map(|(cmd, keep)| keep.then_some(cmd))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_line;
+
+    fn parse(source: &str) -> Vec<VMCommand> {
+        source
+            .lines()
+            .enumerate()
+            .filter_map(|(i, line)| parse_line(line, i + 1, "Test").unwrap())
+            .collect()
+    }
+
+    fn assert_optimizes(source: &str, expected: &str) {
+        assert_eq!(optimize(parse(source)), parse(expected), "{source}");
+    }
+
+    #[test]
+    fn test_always_taken_if_goto_becomes_goto() {
+        assert_optimizes(
+            "push constant 0\nnot\nif-goto L\npush constant 1\nlabel L",
+            "goto L\npush constant 1\nlabel L",
+        );
+        assert_optimizes(
+            "push constant 5\nif-goto L\npush constant 1\nlabel L",
+            "goto L\npush constant 1\nlabel L",
+        );
+    }
+
+    #[test]
+    fn test_never_taken_if_goto_is_removed() {
+        assert_optimizes(
+            "push constant 0\nif-goto L\npush constant 1\nlabel L",
+            "push constant 1\nlabel L",
+        );
+        assert_optimizes(
+            "push constant 0\nneg\nif-goto L\npush constant 1\nlabel L",
+            "push constant 1\nlabel L",
+        );
+    }
+
+    #[test]
+    fn test_non_constant_condition_kept() {
+        let source = "push local 0\nnot\nif-goto L\npush constant 1\nlabel L";
+        assert_optimizes(source, source);
+    }
+
+    #[test]
+    fn test_goto_to_next_label_removed() {
+        assert_optimizes(
+            "goto A\nlabel B\nlabel A\npush constant 1",
+            "label B\nlabel A\npush constant 1",
+        );
+    }
+
+    #[test]
+    fn test_goto_chain_threaded() {
+        assert_optimizes(
+            "goto A\npush constant 1\nlabel A\ngoto B\npush constant 2\nlabel B\ngoto C\nlabel C",
+            "goto C\npush constant 1\nlabel A\ngoto C\npush constant 2\nlabel B\nlabel C",
+        );
+        assert_optimizes(
+            "push local 0\nif-goto A\npush constant 1\nlabel A\ngoto B\npush constant 2\nlabel B",
+            "push local 0\nif-goto B\npush constant 1\nlabel A\ngoto B\npush constant 2\nlabel B",
+        );
+    }
+
+    #[test]
+    fn test_goto_cycle_left_alone() {
+        assert_optimizes("label A\ngoto A", "label A\ngoto A");
+        assert_optimizes(
+            "goto X\npush constant 1\nlabel X\ngoto Y\npush constant 2\nlabel Y\ngoto X",
+            "goto X\npush constant 1\nlabel X\ngoto Y\npush constant 2\nlabel Y\ngoto X",
+        );
+    }
+
+    #[test]
+    fn test_unary_pairs_cancel() {
+        assert_optimizes(
+            "push local 0\nneg\nneg\npop local 1",
+            "push local 0\npop local 1",
+        );
+        assert_optimizes(
+            "push local 0\nnot\nnot\npop local 1",
+            "push local 0\npop local 1",
+        );
+        assert_optimizes("push local 0\nneg\nnot\nnot\nneg\nneg", "push local 0\nneg");
+    }
+
+    #[test]
+    fn test_unary_pairs_need_adjacent_equal_ops() {
+        let source = "push local 0\nneg\nnot\nneg\nlabel L\nneg\nnot";
+        assert_optimizes(source, source);
+    }
+
+    #[test]
+    fn test_functions_optimized_separately() {
+        // `L` in g is a different label from `L` in f
+        let source = "function f 0\nlabel L\ngoto END\nlabel END\nreturn\n\
+                      function g 0\ngoto L\npush constant 1\nlabel L\nreturn";
+        let expected = "function f 0\nlabel L\nlabel END\nreturn\n\
+                        function g 0\ngoto L\npush constant 1\nlabel L\nreturn";
+        assert_optimizes(source, expected);
+    }
+}
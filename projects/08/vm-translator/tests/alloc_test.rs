@@ -0,0 +1,71 @@
+//! Allocation counting for the per-command translation path.
+//!
+//! Installs a counting global allocator, so it only builds with
+//! `cargo test --features alloc-count`.
+
+#![cfg(feature = "alloc-count")]
+
+use alloc_counter::CountingAllocator;
+use vm_translator::codegen::CodeGenerator;
+use vm_translator::parser::{ParseOptions, parse_line_with_options};
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+#[test]
+fn test_push_pop_arithmetic_do_not_allocate() {
+    let commands = [
+        "push constant 17",
+        "push local 3",
+        "push argument 2",
+        "push this 1",
+        "push that 6",
+        "push pointer 1",
+        "push temp 7",
+        "push static 4",
+        "pop local 0",
+        "pop argument 1",
+        "pop this 2",
+        "pop that 3",
+        "pop pointer 0",
+        "pop temp 5",
+        "pop static 9",
+        "add",
+        "sub",
+        "neg",
+        "eq",
+        "gt",
+        "lt",
+        "and",
+        "or",
+        "NOT",
+    ];
+
+    let mut codegen = CodeGenerator::new();
+    codegen.set_filename("Alloc");
+    let mut buf = String::with_capacity(4096);
+    // Lenient, so `NOT` covers case folding too
+    let lenient = ParseOptions {
+        case_insensitive: true,
+    };
+
+    // Warm up: the output buffer reaches its working capacity
+    for line in commands {
+        let cmd = parse_line_with_options(line, 1, "Alloc.vm", &lenient)
+            .unwrap()
+            .unwrap();
+        codegen.translate(&cmd, &mut buf).unwrap();
+        buf.clear();
+    }
+
+    for line in commands {
+        let ((), allocated) = alloc_counter::count(|| {
+            let cmd = parse_line_with_options(line, 1, "Alloc.vm", &lenient)
+                .unwrap()
+                .unwrap();
+            codegen.translate(&cmd, &mut buf).unwrap();
+        });
+        assert_eq!(allocated, 0, "'{line}' allocated {allocated} time(s)");
+        buf.clear();
+    }
+}
@@ -4,7 +4,8 @@
 //! never panics and handles all input gracefully.
 
 use proptest::prelude::*;
-use vm_translator::translate;
+use vm_translator::memory::SCRATCH_REGISTERS;
+use vm_translator::{TranslateOptions, translate, translate_with_options};
 
 /// Generate arbitrary arithmetic commands
 fn arb_arithmetic() -> impl Strategy<Value = String> {
@@ -138,6 +139,18 @@ fn arb_valid_function() -> impl Strategy<Value = String> {
         })
 }
 
+/// Collect every `@R<n>` register reference in the generated assembly.
+fn referenced_registers(asm: &str) -> Vec<String> {
+    asm.lines()
+        .filter_map(|line| line.strip_prefix('@'))
+        .filter(|sym| {
+            sym.strip_prefix('R')
+                .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+        })
+        .map(str::to_string)
+        .collect()
+}
+
 proptest! {
     /// Test that translator never panics on arbitrary input
     #[test]
@@ -402,4 +415,34 @@ proptest! {
             prop_assert!(asm.contains("@R14\nA=M\n0;JMP"), "Should jump to retAddr");
         }
     }
+
+    /// Test that output only uses the documented scratch registers
+    #[test]
+    fn test_register_contract(function_code in arb_valid_function()) {
+        let asm = translate(&function_code, "Test").unwrap();
+        for reg in referenced_registers(&asm) {
+            prop_assert!(
+                SCRATCH_REGISTERS.contains(&reg.as_str()),
+                "Undocumented register {} in output", reg
+            );
+        }
+    }
+
+    /// Test that relocated scratch registers are the only ones referenced
+    #[test]
+    fn test_relocated_register_contract(
+        function_code in arb_valid_function(),
+        base in 5u8..=14,
+    ) {
+        let options = TranslateOptions {
+            scratch_base: base,
+            ..TranslateOptions::default()
+        };
+        if let Ok(asm) = translate_with_options(&function_code, "Test", &options) {
+            let allowed = [format!("R{}", base), format!("R{}", base + 1)];
+            for reg in referenced_registers(&asm) {
+                prop_assert!(allowed.contains(&reg), "Register {} outside R{}-R{}", reg, base, base + 1);
+            }
+        }
+    }
 }
@@ -3,7 +3,14 @@
 //! Tests all 11 nand2tetris test programs (5 from P07 + 6 from P08).
 
 use std::path::Path;
-use vm_translator::{translate, translate_directory};
+use vm_translator::codegen::CodeGenerator;
+use vm_translator::emulator::Emulator;
+use vm_translator::parser::parse_line;
+use vm_translator::{
+    LabelScope, ParseOptions, TranslateOptions, VMError, VMWarning, translate, translate_directory,
+    translate_directory_collecting, translate_directory_report, translate_directory_with_options,
+    translate_file, translate_files, translate_manifest, translate_program, translate_with_options,
+};
 
 // =============================================================================
 // In-Memory Tests (Always Run)
@@ -435,29 +442,83 @@ fn test_basic_loop_file() {
     let vm_source = std::fs::read_to_string("../ProgramFlow/BasicLoop/BasicLoop.vm")
         .expect("Failed to read BasicLoop.vm");
 
-    let asm_output = translate(&vm_source, "BasicLoop").expect("Translation failed");
+    // No functions: labels are file-scoped by default
+    let options = TranslateOptions {
+        label_scope: LabelScope::File,
+        ..TranslateOptions::default()
+    };
+    let asm_output =
+        translate_with_options(&vm_source, "BasicLoop", &options).expect("Translation failed");
 
     // Verify branching commands
     assert!(
-        asm_output.contains("$LOOP_START"),
+        asm_output.contains("(BasicLoop$LOOP_START)"),
         "Should contain LOOP_START label"
     );
     assert!(asm_output.contains("D;JNE"), "Should contain if-goto (JNE)");
 
+    // The reference translator's naming
+    let global = TranslateOptions {
+        label_scope: LabelScope::Global,
+        ..TranslateOptions::default()
+    };
+    let reference = translate_with_options(&vm_source, "BasicLoop", &global).unwrap();
+    assert!(reference.contains("(LOOP_START)\n") && reference.contains("@LOOP_START\nD;JNE"));
+    assert!(!reference.contains("BasicLoop$"));
+
     std::fs::write("../ProgramFlow/BasicLoop/BasicLoop.asm", &asm_output)
         .expect("Failed to write output");
 }
 
+#[test]
+fn test_lenient_parsing_keeps_label_case() {
+    let source =
+        "Function Main.main 0\nLabel Loop\nPush Constant 1\nIf-Goto Loop\nPUSH CONSTANT 0\nReturn";
+    assert!(matches!(
+        translate(source, "Main"),
+        Err(VMError::WrongCase { line: 1, .. })
+    ));
+
+    let options = TranslateOptions {
+        parse: ParseOptions {
+            case_insensitive: true,
+        },
+        ..TranslateOptions::default()
+    };
+    let asm = translate_with_options(source, "Main", &options).unwrap();
+    assert!(asm.contains("(Main.main$Loop)") && asm.contains("@Main.main$Loop\nD;JNE"));
+    let lowercase =
+        "function Main.main 0\nlabel Loop\npush constant 1\nif-goto Loop\npush constant 0\nreturn";
+    assert_eq!(asm, translate(lowercase, "Main").unwrap());
+}
+
 #[test]
 fn test_fibonacci_series_file() {
     let vm_source = std::fs::read_to_string("../ProgramFlow/FibonacciSeries/FibonacciSeries.vm")
         .expect("Failed to read FibonacciSeries.vm");
 
-    let asm_output = translate(&vm_source, "FibonacciSeries").expect("Translation failed");
+    // No functions: labels are file-scoped by default
+    let options = TranslateOptions {
+        label_scope: LabelScope::File,
+        ..TranslateOptions::default()
+    };
+    let asm_output = translate_with_options(&vm_source, "FibonacciSeries", &options)
+        .expect("Translation failed");
+
+    // Strict mode rejects the first flow command
+    let strict = TranslateOptions {
+        label_scope: LabelScope::Strict,
+        ..TranslateOptions::default()
+    };
+    let err = translate_with_options(&vm_source, "FibonacciSeries", &strict).unwrap_err();
+    assert!(matches!(
+        err,
+        VMError::LabelOutsideFunction { line: 24, ref label, .. } if label == "MAIN_LOOP_START"
+    ));
 
     // Verify branching commands
     assert!(
-        asm_output.contains("$MAIN_LOOP_START"),
+        asm_output.contains("FibonacciSeries$MAIN_LOOP_START"),
         "Should contain MAIN_LOOP_START"
     );
     assert!(
@@ -549,6 +610,33 @@ fn test_nested_call_file() {
         .expect("Failed to write output");
 }
 
+#[test]
+fn test_directory_file_markers() {
+    let dir_path = Path::new("../FunctionCalls/FibonacciElement");
+    let options = TranslateOptions {
+        file_markers: true,
+        ..TranslateOptions::default()
+    };
+    let marked = translate_directory_with_options(dir_path, &options).unwrap();
+    let plain = translate_directory(dir_path).unwrap();
+
+    let sys = marked
+        .find("// ---- File: Sys.vm ----\n(Sys.init)\n")
+        .unwrap();
+    let main = marked
+        .find("// ---- File: Main.vm ----\n(Main.fibonacci)\n")
+        .unwrap();
+    assert!(sys < main);
+    // Bootstrap comes before the first banner, and nothing else changes
+    assert!(marked.starts_with("@256\n"));
+    let stripped: String = marked
+        .lines()
+        .filter(|line| !line.starts_with("// ---- File: "))
+        .flat_map(|line| [line, "\n"])
+        .collect();
+    assert_eq!(stripped, plain);
+}
+
 #[test]
 fn test_fibonacci_element_file() {
     let dir_path = Path::new("../FunctionCalls/FibonacciElement");
@@ -626,3 +714,712 @@ fn test_statics_test_file() {
     std::fs::write("../FunctionCalls/StaticsTest/StaticsTest.asm", &asm_output)
         .expect("Failed to write output");
 }
+
+// =============================================================================
+// Manifest Tests
+// =============================================================================
+
+/// Create a scratch directory with the given files for a manifest test.
+fn manifest_dir(name: &str, files: &[(&str, &str)]) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("vm-translator-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).expect("Failed to create temp dir");
+    for (file, contents) in files {
+        std::fs::write(dir.join(file), contents).expect("Failed to write temp file");
+    }
+    dir
+}
+
+#[test]
+fn test_manifest_listed_order_without_bootstrap() {
+    let dir = manifest_dir(
+        "order",
+        &[
+            ("A.vm", "function A.f 0\nreturn"),
+            ("B.vm", "function B.f 0\nreturn"),
+            ("Stale.vm", "function Stale.f 0\nreturn"),
+            ("build.txt", "# build order\nB.vm\n\n  A.vm  \n"),
+        ],
+    );
+
+    let asm = translate_manifest(&dir.join("build.txt")).unwrap();
+    let b = asm.find("(B.f)").expect("B.f missing");
+    let a = asm.find("(A.f)").expect("A.f missing");
+    assert!(b < a, "Files should be translated in manifest order");
+    assert!(!asm.contains("Stale.f"), "Unlisted files are excluded");
+    assert!(
+        !asm.contains("Sys.init"),
+        "No bootstrap without Sys or directive"
+    );
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_manifest_bootstrap_directive() {
+    let dir = manifest_dir(
+        "directive",
+        &[
+            ("Main.vm", "function Main.main 0\nreturn"),
+            ("build.txt", "!bootstrap\nMain.vm\n"),
+        ],
+    );
+
+    let asm = translate_manifest(&dir.join("build.txt")).unwrap();
+    assert!(asm.starts_with("@256"), "Bootstrap should come first");
+    assert!(asm.contains("@Sys.init"));
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_manifest_sys_module_enables_bootstrap() {
+    let dir = manifest_dir(
+        "sys",
+        &[
+            ("Sys.vm", "function Sys.init 0\nreturn"),
+            ("build.txt", "Sys.vm\n"),
+        ],
+    );
+
+    let asm = translate_manifest(&dir.join("build.txt")).unwrap();
+    assert!(asm.starts_with("@256"));
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_manifest_missing_file_reports_line() {
+    let dir = manifest_dir(
+        "missing",
+        &[
+            ("A.vm", "function A.f 0\nreturn"),
+            ("build.txt", "# header\nA.vm\nGone.vm\n"),
+        ],
+    );
+
+    let err = translate_manifest(&dir.join("build.txt")).unwrap_err();
+    assert!(matches!(
+        &err,
+        VMError::ManifestFileNotFound { line: 3, path, .. } if path == "Gone.vm"
+    ));
+    assert!(
+        err.to_string()
+            .contains("build.txt:3: file not found: Gone.vm")
+    );
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+// =============================================================================
+// Link Check Tests
+// =============================================================================
+
+#[test]
+fn test_link_check_undefined_target() {
+    let dir = manifest_dir(
+        "link-undefined",
+        &[
+            (
+                "Main.vm",
+                "function Main.main 0\ncall Foo.missing 0\nreturn",
+            ),
+            ("Foo.vm", "function Foo.bar 0\nreturn"),
+        ],
+    );
+
+    let err = translate_directory(&dir).unwrap_err();
+    assert!(matches!(
+        &err,
+        VMError::UndefinedFunction { line: 2, file, name } if file == "Main" && name == "Foo.missing"
+    ));
+
+    let options = TranslateOptions {
+        link_check: false,
+        ..TranslateOptions::default()
+    };
+    let translation = translate_directory_report(&dir, &options).unwrap();
+    assert!(translation.asm.contains("@Foo.missing"));
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_link_check_inconsistent_arity() {
+    let dir = manifest_dir(
+        "link-arity",
+        &[
+            ("Foo.vm", "function Foo.bar 0\nreturn"),
+            (
+                "Main.vm",
+                "function Main.main 0\ncall Foo.bar 1\ncall Foo.bar 2\nreturn",
+            ),
+        ],
+    );
+
+    let translation = translate_directory_report(&dir, &TranslateOptions::default()).unwrap();
+    assert_eq!(translation.warnings.len(), 1);
+    let VMWarning::InconsistentArity {
+        name,
+        num_args,
+        expected,
+        ..
+    } = &translation.warnings[0]
+    else {
+        panic!("expected an arity warning: {:?}", translation.warnings);
+    };
+    assert_eq!((name.as_str(), *num_args, *expected), ("Foo.bar", 2, 1));
+    assert!(
+        translation.warnings[0]
+            .to_string()
+            .starts_with("Main:3: Foo.bar called with 2 argument(s)")
+    );
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_link_check_os_call_exempt() {
+    let dir = manifest_dir(
+        "link-os",
+        &[(
+            "Main.vm",
+            "function Main.main 0\npush constant 2\npush constant 3\ncall Math.multiply 2\ncall Sys.halt 0\nreturn",
+        )],
+    );
+
+    let translation = translate_directory_report(&dir, &TranslateOptions::default()).unwrap();
+    assert!(translation.warnings.is_empty());
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_duplicate_function_across_files() {
+    let dir = manifest_dir(
+        "duplicate",
+        &[
+            ("Main.vm", "function Main.main 0\nreturn"),
+            (
+                "MainOld.vm",
+                "// stale copy\nfunction Main.helper 0\nreturn\nfunction Main.main 0\nreturn",
+            ),
+        ],
+    );
+
+    let err = translate_directory(&dir).unwrap_err();
+    assert!(matches!(
+        &err,
+        VMError::DuplicateFunction { name, first, second }
+            if name == "Main.main"
+                && *first == ("Main".to_string(), 1)
+                && *second == ("MainOld".to_string(), 4)
+    ));
+    assert_eq!(
+        err.to_string(),
+        "MainOld:4: function Main.main already declared at Main:1"
+    );
+    assert_eq!(err.location(), Some(("MainOld", 4)));
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_forward_calls_within_a_file() {
+    let main = "function Main.main 0\npush constant 1\ncall Main.helper 1\npop temp 0\n\
+                push constant 2\ncall Main.helper 1\nreturn\n\
+                function Main.helper 0\npush argument 0\nreturn";
+    let options = TranslateOptions {
+        stack_check: true,
+        ..TranslateOptions::default()
+    };
+    let translation = translate_program(&[("Main", main)], &options).unwrap();
+    assert_eq!(translation.warnings, vec![]);
+    assert!(translation.asm.contains("@Main.helper\n0;JMP"));
+}
+
+/// Translate `source` the way the translator did before it parsed whole
+/// files up front: parse and generate one line at a time.
+fn translate_single_pass(source: &str, filename: &str) -> String {
+    let mut codegen = CodeGenerator::new();
+    codegen.set_filename(filename);
+    let mut asm = String::new();
+    for (i, line) in source.lines().enumerate() {
+        if let Some(cmd) = parse_line(line, i + 1, filename).unwrap() {
+            codegen.translate(&cmd, &mut asm).unwrap();
+        }
+    }
+    asm
+}
+
+#[test]
+fn test_output_matches_single_pass() {
+    let files = [
+        "../ProgramFlow/BasicLoop/BasicLoop.vm",
+        "../ProgramFlow/FibonacciSeries/FibonacciSeries.vm",
+        "../FunctionCalls/SimpleFunction/SimpleFunction.vm",
+        "../FunctionCalls/NestedCall/Sys.vm",
+        "../FunctionCalls/FibonacciElement/Main.vm",
+        "../FunctionCalls/StaticsTest/Class1.vm",
+        // Compiled Jack, with `not` before `if-goto`
+        "../../11/Average/Main.vm",
+    ];
+    for path in files {
+        let source = std::fs::read_to_string(path).unwrap();
+        let name = Path::new(path).file_stem().unwrap().to_str().unwrap();
+        assert_eq!(
+            translate(&source, name).unwrap(),
+            translate_single_pass(&source, name),
+            "{path}"
+        );
+    }
+}
+
+#[test]
+fn test_translate_program_matches_bootstrapped_manifest() {
+    let main = "function Main.main 0\ncall Foo.bar 0\nreturn";
+    let foo = "function Foo.bar 0\npush static 0\nreturn";
+    let dir = manifest_dir(
+        "program",
+        &[
+            ("Main.vm", main),
+            ("Foo.vm", foo),
+            ("build.txt", "!bootstrap\nMain.vm\nFoo.vm\n"),
+        ],
+    );
+
+    let translation = translate_program(
+        &[("Main", main), ("Foo", foo)],
+        &TranslateOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        translation.asm,
+        translate_manifest(&dir.join("build.txt")).unwrap()
+    );
+    assert!(translation.asm.starts_with("@256\n"));
+    assert!(translation.asm.contains("@Foo.0"));
+
+    // Programs are link-checked
+    let err = translate_program(&[("Main", main)], &TranslateOptions::default()).unwrap_err();
+    assert!(matches!(err, VMError::UndefinedFunction { name, .. } if name == "Foo.bar"));
+    assert!(matches!(
+        translate_program(&[], &TranslateOptions::default()),
+        Err(VMError::NoVmFiles { .. })
+    ));
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+// =============================================================================
+// VM Optimization Tests
+// =============================================================================
+
+fn optimized() -> TranslateOptions {
+    TranslateOptions {
+        optimize: true,
+        ..TranslateOptions::default()
+    }
+}
+
+#[test]
+fn test_optimize_folds_constant_branches() {
+    let source = "push constant 0\nnot\nif-goto LOOP\npush constant 0\nif-goto END\n\
+                  push constant 7\nlabel LOOP\ngoto END\npush constant 8\nlabel END";
+    let plain = translate(source, "Test").unwrap();
+    let asm = translate_with_options(source, "Test", &optimized()).unwrap();
+
+    assert!(plain.contains("D;JNE"));
+    assert!(!asm.contains("D;JNE"), "No conditional jumps remain");
+    assert!(
+        asm.starts_with("@Test$END\n0;JMP"),
+        "goto LOOP threads to END"
+    );
+    assert!(asm.lines().count() < plain.lines().count());
+}
+
+#[test]
+fn test_optimize_off_is_byte_identical() {
+    let source = "function Main.f 0\npush constant 0\nnot\nif-goto L\nlabel L\ngoto L\nreturn";
+    let options = TranslateOptions {
+        optimize: false,
+        ..TranslateOptions::default()
+    };
+    assert_eq!(
+        translate_with_options(source, "Main", &options).unwrap(),
+        translate(source, "Main").unwrap()
+    );
+
+    for dir in ["FibonacciElement", "NestedCall", "StaticsTest"] {
+        let path = Path::new("../FunctionCalls").join(dir);
+        if !path.exists() {
+            continue;
+        }
+        assert_eq!(
+            translate_directory_with_options(&path, &options).unwrap(),
+            translate_directory(&path).unwrap(),
+            "{dir}"
+        );
+    }
+}
+
+#[test]
+fn test_optimize_fuses_not_if_goto() {
+    // `while (i < 5) { let i = i + 1; }` as the Jack compiler emits it
+    let source = "function Main.f 1\nlabel WHILE_EXP0\npush local 0\npush constant 5\nlt\n\
+                  not\nif-goto WHILE_END0\npush local 0\npush constant 1\nadd\npop local 0\n\
+                  goto WHILE_EXP0\nlabel WHILE_END0\npush local 0\nreturn";
+    let plain = translate(source, "Main").unwrap();
+    let asm = translate_with_options(source, "Main", &optimized()).unwrap();
+
+    assert!(plain.contains("M=!M"));
+    assert!(!asm.contains("M=!M"), "The negation is never materialized");
+    assert!(asm.contains("@SP\nAM=M-1\nD=M+1\n@Main.f$WHILE_END0\nD;JNE\n"));
+    assert_eq!(asm.lines().count() + 3, plain.lines().count());
+
+    // A label in between is a jump target, so the pair is not fused
+    let source = "push local 0\nnot\nlabel L\nif-goto L";
+    let asm = translate_with_options(source, "Test", &optimized()).unwrap();
+    assert!(asm.contains("M=!M") && !asm.contains("D=M+1"));
+
+    // The loop programs have no `not` before `if-goto`
+    for (dir, name) in [
+        ("BasicLoop", "BasicLoop"),
+        ("FibonacciSeries", "FibonacciSeries"),
+    ] {
+        let path = Path::new("../ProgramFlow")
+            .join(dir)
+            .join(format!("{name}.vm"));
+        let source = std::fs::read_to_string(&path).unwrap();
+        let asm = translate_with_options(&source, name, &optimized()).unwrap();
+        assert!(!asm.contains("D=M+1"), "{dir}");
+    }
+
+    // Compiled Jack loops and ifs do
+    let source = std::fs::read_to_string("../../11/Average/Main.vm").unwrap();
+    let plain = translate(&source, "Main").unwrap();
+    let asm = translate_with_options(&source, "Main", &optimized()).unwrap();
+    assert!(source.contains("not\nif-goto Main.main$WHILE_END_1\n"));
+    assert!(asm.contains("@SP\nAM=M-1\nD=M+1\n@Main.main$Main.main$WHILE_END_1\nD;JNE\n"));
+    assert_eq!(
+        asm.matches("M=!M").count() + 1,
+        plain.matches("M=!M").count()
+    );
+}
+
+#[test]
+fn test_optimize_cancels_double_negation() {
+    let source = "push constant 7\nneg\nneg\npush local 0\nnot\nnot\nadd";
+    let asm = translate_with_options(source, "Test", &optimized()).unwrap();
+    assert_eq!(
+        asm,
+        translate_with_options("push constant 7\npush local 0\nadd", "Test", &optimized()).unwrap()
+    );
+    assert!(!asm.contains("M=-M") && !asm.contains("M=!M"));
+
+    // A lone `neg` is kept
+    let asm = translate_with_options("push constant 7\nneg", "Test", &optimized()).unwrap();
+    assert_eq!(asm, translate("push constant 7\nneg", "Test").unwrap());
+    assert!(asm.contains("M=-M"));
+}
+
+#[test]
+fn test_optimize_does_not_cross_functions() {
+    // Each function has its own `L`; f's forwarding label must not retarget g's jump
+    let source = "function Main.f 0\nlabel L\ngoto DONE\nlabel DONE\nreturn\n\
+                  function Main.g 0\ngoto L\npush constant 1\nlabel L\nreturn";
+    let asm = translate_with_options(source, "Main", &optimized()).unwrap();
+    assert!(asm.contains("@Main.g$L\n0;JMP"));
+    assert!(!asm.contains("@Main.f$DONE\n0;JMP"));
+}
+
+// =============================================================================
+// Emulator Tests
+// =============================================================================
+
+#[test]
+fn test_emulate_fibonacci_element() {
+    let dir = Path::new("../FunctionCalls/FibonacciElement");
+    if !dir.exists() {
+        return;
+    }
+    // Main.fibonacci(4), per FibonacciElement.cmp
+    let mut emulator = Emulator::from_path(dir).unwrap();
+    emulator.run(100_000).unwrap();
+    assert_eq!(emulator.sp(), 262);
+    assert_eq!(emulator.ram()[261], 3);
+
+    // The same program computing fib(6)
+    let main = std::fs::read_to_string(dir.join("Main.vm")).unwrap();
+    let sys = "function Sys.init 0\npush constant 6\ncall Main.fibonacci 1\n\
+               label END\ngoto END";
+    let mut emulator = Emulator::new(&[("Sys", sys), ("Main", &main)], true).unwrap();
+    emulator.run(100_000).unwrap();
+    assert_eq!(emulator.sp(), 262);
+    assert_eq!(emulator.ram()[261], 8);
+}
+
+#[test]
+fn test_emulate_statics_test() {
+    let dir = Path::new("../FunctionCalls/StaticsTest");
+    if !dir.exists() {
+        return;
+    }
+    let mut emulator = Emulator::from_path(dir).unwrap();
+    emulator.run(100_000).unwrap();
+    assert_eq!(emulator.sp(), 263);
+    assert_eq!(emulator.ram()[261..263], [-2, 8]);
+}
+
+#[test]
+fn test_stack_check() {
+    let options = TranslateOptions {
+        stack_check: true,
+        ..TranslateOptions::default()
+    };
+    for dir in [
+        "../FunctionCalls/FibonacciElement",
+        "../FunctionCalls/NestedCall",
+        "../FunctionCalls/StaticsTest",
+    ] {
+        let translation = translate_directory_report(Path::new(dir), &options).unwrap();
+        assert_eq!(translation.warnings, vec![], "{dir}");
+    }
+
+    let dir = manifest_dir(
+        "stack-check",
+        &[(
+            "Main.vm",
+            "function Main.main 0\ncall Main.f 0\nreturn\nfunction Main.f 0\nreturn",
+        )],
+    );
+    let translation = translate_directory_report(&dir, &options).unwrap();
+    assert_eq!(
+        translation
+            .warnings
+            .iter()
+            .map(|w| w.to_string())
+            .collect::<Vec<_>>(),
+        ["Main:5: Main.f returns with 0 value(s) on its stack instead of 1"]
+    );
+    // Off by default
+    let translation = translate_directory_report(&dir, &TranslateOptions::default()).unwrap();
+    assert!(translation.warnings.is_empty());
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_translate_files_in_input_order() {
+    let paths: Vec<std::path::PathBuf> = [
+        "../StackTest/StackTest.vm",
+        "../SimpleAdd/SimpleAdd.vm",
+        "../Missing/Missing.vm",
+        "../BasicTest/BasicTest.vm",
+        "../PointerTest/PointerTest.vm",
+    ]
+    .iter()
+    .map(Into::into)
+    .collect();
+
+    let results = translate_files(&paths);
+    assert_eq!(
+        results.iter().map(|(path, _)| path).collect::<Vec<_>>(),
+        paths.iter().collect::<Vec<_>>()
+    );
+    for (path, result) in &results {
+        match result {
+            Ok(asm) => assert_eq!(*asm, translate_file(path).unwrap(), "{}", path.display()),
+            Err(err) => {
+                assert!(path.ends_with("Missing.vm"));
+                assert!(matches!(err, VMError::FileRead { .. }));
+            }
+        }
+    }
+    assert_eq!(results.iter().filter(|(_, r)| r.is_ok()).count(), 4);
+}
+
+/// A directory where `Bad.vm` fails on its second line, after declaring
+/// `Bad.f`, and `Main.vm` calls `Bad.f`.
+fn partial_dir(name: &str) -> std::path::PathBuf {
+    manifest_dir(
+        name,
+        &[
+            ("Bad.vm", "function Bad.f 0\npush locol 0\nreturn"),
+            (
+                "Main.vm",
+                "function Main.main 0\ncall Bad.f 0\npush constant 7\nreturn",
+            ),
+        ],
+    )
+}
+
+#[test]
+fn test_translate_directory_collecting() {
+    let dir = partial_dir("collecting");
+
+    // The all-or-nothing contract is unchanged
+    assert!(matches!(
+        translate_directory(&dir),
+        Err(VMError::InvalidSegment { .. })
+    ));
+
+    let outcome = translate_directory_collecting(&dir).unwrap();
+    assert!(!outcome.is_ok());
+    assert!(!outcome.bootstrap);
+    let errors: Vec<_> = outcome
+        .errors
+        .iter()
+        .map(|(file, err)| (file.as_str(), err.to_string()))
+        .collect();
+    // Main.vm calls Bad.f, which the skipped file declares: not Main's error
+    assert_eq!(
+        errors,
+        [("Bad.vm", "Bad:2: invalid segment: locol".to_string())]
+    );
+    assert!(outcome.asm.contains("(Main.main)"));
+    assert!(outcome.asm.contains("@7"));
+    assert!(!outcome.asm.contains("Bad.f)"));
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_collecting_names_only_the_broken_file() {
+    let dir = manifest_dir(
+        "collecting-calls",
+        &[
+            (
+                "Sys.vm",
+                "function Sys.init 0\ncall Main.f 0\ncall Main.g 0\nlabel END\ngoto END",
+            ),
+            (
+                "Main.vm",
+                "function Main.f 0\npush constant 1\nreturn\nbogus\nfunction Main.g 0\nreturn",
+            ),
+        ],
+    );
+
+    let outcome = translate_directory_collecting(&dir).unwrap();
+    let files: Vec<&str> = outcome
+        .errors
+        .iter()
+        .map(|(file, _)| file.as_str())
+        .collect();
+    assert_eq!(files, ["Main.vm"]);
+    assert!(matches!(
+        &outcome.errors[0].1,
+        VMError::InvalidCommand { line: 4, .. }
+    ));
+    assert!(outcome.asm.contains("(Sys.init)") && !outcome.asm.contains("(Main.f)"));
+
+    // Calls to functions no file declares are still reported
+    std::fs::write(
+        dir.join("Sys.vm"),
+        "function Sys.init 0\ncall Main.f 0\ncall Main.h 0\nlabel END\ngoto END",
+    )
+    .unwrap();
+    let outcome = translate_directory_collecting(&dir).unwrap();
+    let errors: Vec<String> = outcome
+        .errors
+        .iter()
+        .map(|(_, err)| err.to_string())
+        .collect();
+    assert_eq!(errors.len(), 2, "{errors:?}");
+    assert_eq!(errors[1], "Sys:3: call to undefined function Main.h");
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_cli_keep_partial() {
+    let dir = partial_dir("keep-partial");
+    let asm = dir.join(format!(
+        "{}.asm",
+        dir.file_name().unwrap().to_string_lossy()
+    ));
+    let run = |args: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_vm-translator"))
+            .args(args)
+            .arg(&dir)
+            .output()
+            .unwrap()
+    };
+
+    let output = run(&[]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Bad:2: invalid segment: locol"), "{stderr}");
+    assert!(!stderr.contains("undefined function"), "{stderr}");
+    assert!(!asm.exists());
+
+    let output = run(&["--keep-partial"]);
+    assert!(!output.status.success());
+    let written = std::fs::read_to_string(&asm).unwrap();
+    assert!(written.contains("(Main.main)") && !written.contains("(Bad.f)"));
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_translate_file_matches_in_memory_translation() {
+    let source = "// generated\nfunction Table.get 0\npush constant 7\nreturn\n";
+    let dir = manifest_dir(
+        "source-read",
+        &[("Table.vm", source), ("Empty.vm", ""), ("Bad.vm", "")],
+    );
+    std::fs::write(dir.join("Bad.vm"), b"push constant 1\n\xff\n").unwrap();
+
+    // Mapped with the `mmap` feature, read into a String without it
+    assert_eq!(
+        translate_file(&dir.join("Table.vm")).unwrap(),
+        translate(source, "Table").unwrap()
+    );
+    assert_eq!(translate_file(&dir.join("Empty.vm")).unwrap(), "");
+    match translate_file(&dir.join("Bad.vm")) {
+        Err(VMError::FileRead { source, .. }) => {
+            assert_eq!(source.kind(), std::io::ErrorKind::InvalidData)
+        }
+        other => panic!("expected a read error, got {other:?}"),
+    }
+}
+
+#[test]
+fn test_manifest_rejects_files_sharing_a_name() {
+    let dir = manifest_dir("stems", &[("build.txt", "a/Main.vm\nb/Main.vm\n")]);
+    for sub in ["a", "b"] {
+        std::fs::create_dir_all(dir.join(sub)).unwrap();
+        std::fs::write(
+            dir.join(sub).join("Main.vm"),
+            "function Main.f 0\npush static 0\nreturn",
+        )
+        .unwrap();
+    }
+
+    let err = translate_manifest(&dir.join("build.txt")).unwrap_err();
+    match &err {
+        VMError::DuplicateFileStem {
+            stem,
+            first,
+            second,
+        } => {
+            assert_eq!(stem, "Main");
+            assert_eq!(*first, dir.join("a/Main.vm").display().to_string());
+            assert_eq!(*second, dir.join("b/Main.vm").display().to_string());
+        }
+        other => panic!("expected a name collision, got {other:?}"),
+    }
+    assert!(
+        err.to_string()
+            .ends_with("are both named Main; their statics would collide")
+    );
+
+    // Distinct names in different directories are fine
+    std::fs::write(
+        dir.join("b/Other.vm"),
+        "function Other.f 0\npush static 0\nreturn",
+    )
+    .unwrap();
+    std::fs::write(dir.join("build.txt"), "a/Main.vm\nb/Other.vm\n").unwrap();
+    assert!(translate_manifest(&dir.join("build.txt")).is_ok());
+}
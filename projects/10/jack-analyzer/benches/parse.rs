@@ -0,0 +1,85 @@
+//! Tokenizer and parser benchmarks.
+//!
+//! Run with `cargo bench`; each group first prints the allocations of one
+//! tokenization or parse of a large class (see [`alloc_counter`]). Tokens
+//! borrow their text from the source, so tokenizing allocates little beyond
+//! the token vector. The AST needs one allocation per stored name, string
+//! and list; anything above that is parser overhead.
+
+use alloc_counter::CountingAllocator;
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use jack_analyzer::parser::Parser;
+use jack_analyzer::tokenizer::JackTokenizer;
+use std::hint::black_box;
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+const SUBROUTINES: usize = 200;
+
+/// One class with many subroutines exercising every statement.
+fn big_class_source() -> String {
+    let mut source =
+        String::from("class Big {\n    field int count, total;\n    field Array data;\n");
+    for i in 0..SUBROUTINES {
+        source.push_str(&format!(
+            "    method int m{i}(int a, Big b) {{
+        var int i, sum;
+        var String name;
+        let i = 0;
+        let name = \"subroutine {i}\";
+        while (i < a) {{
+            if ((i > count) & ~(data[i] = -1)) {{
+                let sum = sum + (data[i] * 2);
+            }} else {{
+                let data[i] = b.m{}(i, this);
+            }}
+            do Output.printString(name);
+            let i = i + 1;
+        }}
+        return sum;
+    }}
+",
+            (i + 1) % SUBROUTINES
+        ));
+    }
+    source.push_str("}\n");
+    source
+}
+
+fn bench_tokenize(c: &mut Criterion) {
+    let source = big_class_source();
+
+    let (tokens, allocations) =
+        alloc_counter::count(|| JackTokenizer::new(&source).tokenize().unwrap());
+    black_box(tokens);
+    println!("tokenize/{SUBROUTINES}_subroutines: {allocations} allocations per class");
+
+    let mut group = c.benchmark_group("tokenize");
+    group.throughput(Throughput::Bytes(source.len() as u64));
+    group.bench_function(format!("{SUBROUTINES}_subroutines"), |b| {
+        b.iter(|| JackTokenizer::new(black_box(&source)).tokenize().unwrap())
+    });
+    group.finish();
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let source = big_class_source();
+    let tokens = JackTokenizer::new(&source)
+        .tokenize()
+        .expect("benchmark class tokenizes");
+
+    let (class, allocations) = alloc_counter::count(|| Parser::new(&tokens).parse().unwrap());
+    black_box(class);
+    println!("parse/{SUBROUTINES}_subroutines: {allocations} allocations per class");
+
+    let mut group = c.benchmark_group("parse");
+    group.throughput(Throughput::Elements(tokens.len() as u64));
+    group.bench_function(format!("{SUBROUTINES}_subroutines"), |b| {
+        b.iter(|| Parser::new(black_box(&tokens)).parse().unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_tokenize, bench_parse);
+criterion_main!(benches);
@@ -3,11 +3,15 @@
 //! These AST nodes are designed to support:
 //! 1. XML output generation (Project 10)
 //! 2. Visitor pattern for code generation (Project 11)
+//!
+//! [`pretty`] renders a class as a compact indented tree for debugging.
 
 use crate::token::{Keyword, Span};
+use std::fmt::Write;
 
 /// A complete Jack class.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Class {
     pub name: String,
     pub class_var_decs: Vec<ClassVarDec>,
@@ -17,6 +21,7 @@ pub struct Class {
 
 /// Class variable declaration (static or field).
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ClassVarDec {
     pub kind: ClassVarKind,
     pub var_type: Type,
@@ -26,6 +31,7 @@ pub struct ClassVarDec {
 
 /// Kind of class variable.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ClassVarKind {
     Static,
     Field,
@@ -42,6 +48,7 @@ impl ClassVarKind {
 
 /// Type specification.
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Type {
     Int,
     Char,
@@ -62,6 +69,7 @@ impl Type {
 
 /// Subroutine declaration (constructor, function, or method).
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SubroutineDec {
     pub kind: SubroutineKind,
     pub return_type: ReturnType,
@@ -73,6 +81,7 @@ pub struct SubroutineDec {
 
 /// Kind of subroutine.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum SubroutineKind {
     Constructor,
     Function,
@@ -91,6 +100,7 @@ impl SubroutineKind {
 
 /// Return type (void or a type).
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ReturnType {
     Void,
     Type(Type),
@@ -107,6 +117,7 @@ impl ReturnType {
 
 /// Subroutine parameter.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Parameter {
     pub var_type: Type,
     pub name: String,
@@ -114,14 +125,27 @@ pub struct Parameter {
 
 /// Subroutine body.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SubroutineBody {
     pub var_decs: Vec<VarDec>,
     pub statements: Vec<Statement>,
     pub span: Span,
 }
 
+/// Prefix of compiler-synthesized local variable names.
+///
+/// `$` cannot start a Jack identifier, so synthesized names never collide
+/// with user variables.
+pub const SYNTHETIC_PREFIX: char = '$';
+
+/// Check if a variable name was synthesized by the parser (not in the source).
+pub fn is_synthetic_name(name: &str) -> bool {
+    name.starts_with(SYNTHETIC_PREFIX)
+}
+
 /// Local variable declaration.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct VarDec {
     pub var_type: Type,
     pub names: Vec<String>,
@@ -130,6 +154,7 @@ pub struct VarDec {
 
 /// Statement types.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Statement {
     Let(LetStatement),
     If(IfStatement),
@@ -139,16 +164,22 @@ pub enum Statement {
 }
 
 /// Let statement: let varName[expr]? = expr;
+///
+/// With the compound-assignment extension, `let x += e;` is desugared by the
+/// parser into `let x = x + (e);` and `compound` records the original operator.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LetStatement {
     pub var_name: String,
     pub index: Option<Box<Expression>>,
     pub value: Expression,
+    pub compound: Option<BinaryOp>,
     pub span: Span,
 }
 
 /// If statement: if (expr) { statements } (else { statements })?
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct IfStatement {
     pub condition: Expression,
     pub then_statements: Vec<Statement>,
@@ -158,6 +189,7 @@ pub struct IfStatement {
 
 /// While statement: while (expr) { statements }
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct WhileStatement {
     pub condition: Expression,
     pub statements: Vec<Statement>,
@@ -166,6 +198,7 @@ pub struct WhileStatement {
 
 /// Do statement: do subroutineCall;
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DoStatement {
     pub call: SubroutineCall,
     pub span: Span,
@@ -173,6 +206,7 @@ pub struct DoStatement {
 
 /// Return statement: return expr?;
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ReturnStatement {
     pub value: Option<Expression>,
     pub span: Span,
@@ -180,14 +214,34 @@ pub struct ReturnStatement {
 
 /// Expression: term (op term)*
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Expression {
     pub term: Term,
     pub ops: Vec<(BinaryOp, Term)>,
     pub span: Span,
 }
 
+/// Check if an expression calls a subroutine anywhere, so evaluating it may
+/// have side effects.
+pub fn expression_has_calls(expr: &Expression) -> bool {
+    fn term_has_calls(term: &Term) -> bool {
+        match term {
+            Term::SubroutineCall(_) => true,
+            Term::ArrayAccess(_, index, _) => expression_has_calls(index),
+            Term::Parenthesized(inner, _) => expression_has_calls(inner),
+            Term::UnaryOp(_, inner, _) => term_has_calls(inner),
+            Term::IntegerConstant(..)
+            | Term::StringConstant(..)
+            | Term::KeywordConstant(..)
+            | Term::VarName(..) => false,
+        }
+    }
+    term_has_calls(&expr.term) || expr.ops.iter().any(|(_, term)| term_has_calls(term))
+}
+
 /// Binary operators.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BinaryOp {
     Add, // +
     Sub, // -
@@ -216,6 +270,17 @@ impl BinaryOp {
         }
     }
 
+    /// Binding strength in precedence mode: `* /` over `+ -` over
+    /// `< > =` over `& |`. Standard Jack ignores this.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            BinaryOp::Mul | BinaryOp::Div => 3,
+            BinaryOp::Add | BinaryOp::Sub => 2,
+            BinaryOp::Lt | BinaryOp::Gt | BinaryOp::Eq => 1,
+            BinaryOp::And | BinaryOp::Or => 0,
+        }
+    }
+
     #[allow(dead_code)]
     pub fn as_char(&self) -> char {
         match self {
@@ -234,6 +299,7 @@ impl BinaryOp {
 
 /// Unary operators.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum UnaryOp {
     Neg, // -
     Not, // ~
@@ -259,6 +325,7 @@ impl UnaryOp {
 
 /// Term in an expression.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Term {
     IntegerConstant(u16, Span),
     StringConstant(String, Span),
@@ -283,10 +350,18 @@ impl Term {
             Term::UnaryOp(_, _, span) => span,
         }
     }
+
+    /// Check if this is a parenthesized term synthesized by the parser (not
+    /// in the source). Synthesized groups carry the span of the expression
+    /// they wrap; a source `(` always starts before its inner expression.
+    pub fn is_synthetic_group(&self) -> bool {
+        matches!(self, Term::Parenthesized(expr, span) if *span == expr.span)
+    }
 }
 
 /// Keyword constants.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum KeywordConstant {
     True,
     False,
@@ -317,6 +392,7 @@ impl KeywordConstant {
 
 /// Subroutine call.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SubroutineCall {
     /// Optional class/variable name for method calls.
     pub receiver: Option<String>,
@@ -426,3 +502,309 @@ pub trait AstWalker: AstVisitor {
         }
     }
 }
+
+/// Render `class` as an indented tree, one line per declaration or
+/// statement, for debugging.
+///
+/// Expressions stay on their statement's line, with binary operators folded
+/// left to right as Jack evaluates them. Groups the parser synthesized (see
+/// [`Term::is_synthetic_group`]) show as `Group(..)`, source parentheses as
+/// `Paren(..)`.
+///
+/// ```
+/// use jack_analyzer::{analyze_source, ast};
+///
+/// let source = "class Main { function void main() { let x = 1 + 2; return; } }";
+/// let class = analyze_source(source, "Main.jack").class.unwrap();
+/// assert_eq!(
+///     ast::pretty(&class),
+///     "Class Main\n  Subroutine function void main\n    \
+///      Let x = BinOp(+, Int 1, Int 2)\n    Return\n"
+/// );
+/// ```
+pub fn pretty(class: &Class) -> String {
+    Pretty::new(false).class(class)
+}
+
+/// [`pretty`], with each node's `line:column` at the end of its line.
+pub fn pretty_with_spans(class: &Class) -> String {
+    Pretty::new(true).class(class)
+}
+
+/// Tree printer state.
+struct Pretty {
+    out: String,
+    spans: bool,
+    depth: usize,
+}
+
+impl Pretty {
+    fn new(spans: bool) -> Self {
+        Self {
+            out: String::new(),
+            spans,
+            depth: 0,
+        }
+    }
+
+    fn class(mut self, class: &Class) -> String {
+        self.line(&format!("Class {}", class.name), Some(&class.span));
+        self.depth += 1;
+        for dec in &class.class_var_decs {
+            let line = format!(
+                "ClassVar {} {} {}",
+                dec.kind.as_str(),
+                dec.var_type.as_str(),
+                dec.names.join(", ")
+            );
+            self.line(&line, Some(&dec.span));
+        }
+        for sub in &class.subroutine_decs {
+            let line = format!(
+                "Subroutine {} {} {}",
+                sub.kind.as_str(),
+                sub.return_type.as_str(),
+                sub.name
+            );
+            self.line(&line, Some(&sub.span));
+            self.depth += 1;
+            for param in &sub.parameters {
+                let line = format!("Param {} {}", param.var_type.as_str(), param.name);
+                self.line(&line, None);
+            }
+            for dec in &sub.body.var_decs {
+                let line = format!("Var {} {}", dec.var_type.as_str(), dec.names.join(", "));
+                self.line(&line, Some(&dec.span));
+            }
+            self.statements(&sub.body.statements);
+            self.depth -= 1;
+        }
+        self.out
+    }
+
+    fn statements(&mut self, statements: &[Statement]) {
+        for stmt in statements {
+            self.statement(stmt);
+        }
+    }
+
+    fn statement(&mut self, stmt: &Statement) {
+        match stmt {
+            Statement::Let(s) => {
+                let mut line = format!("Let {}", s.var_name);
+                if let Some(index) = &s.index {
+                    line = format!("{line}[{}]", expression(index));
+                }
+                line = format!("{line} = {}", expression(&s.value));
+                if let Some(op) = s.compound {
+                    line = format!("{line} (from {}=)", op.as_char());
+                }
+                self.line(&line, Some(&s.span));
+            }
+            Statement::If(s) => {
+                self.line(&format!("If {}", expression(&s.condition)), Some(&s.span));
+                self.block(&s.then_statements);
+                if let Some(else_statements) = &s.else_statements {
+                    self.line("Else", None);
+                    self.block(else_statements);
+                }
+            }
+            Statement::While(s) => {
+                let line = format!("While {}", expression(&s.condition));
+                self.line(&line, Some(&s.span));
+                self.block(&s.statements);
+            }
+            Statement::Do(s) => self.line(&format!("Do {}", call(&s.call)), Some(&s.span)),
+            Statement::Return(s) => {
+                let line = match &s.value {
+                    Some(value) => format!("Return {}", expression(value)),
+                    None => "Return".to_string(),
+                };
+                self.line(&line, Some(&s.span));
+            }
+        }
+    }
+
+    /// Statements nested one level deeper.
+    fn block(&mut self, statements: &[Statement]) {
+        self.depth += 1;
+        self.statements(statements);
+        self.depth -= 1;
+    }
+
+    fn line(&mut self, text: &str, span: Option<&Span>) {
+        for _ in 0..self.depth {
+            self.out.push_str("  ");
+        }
+        self.out.push_str(text);
+        if let Some(span) = span.filter(|_| self.spans) {
+            let _ = write!(self.out, " @{span}");
+        }
+        self.out.push('\n');
+    }
+}
+
+/// An expression on one line, folding its operators left to right.
+fn expression(expr: &Expression) -> String {
+    expr.ops.iter().fold(term(&expr.term), |left, (op, right)| {
+        format!("BinOp({}, {left}, {})", op.as_char(), term(right))
+    })
+}
+
+fn term(term: &Term) -> String {
+    match term {
+        Term::IntegerConstant(value, _) => format!("Int {value}"),
+        Term::StringConstant(value, _) => format!("Str {value:?}"),
+        Term::KeywordConstant(keyword, _) => format!("Keyword {}", keyword.as_str()),
+        Term::VarName(name, _) => format!("Var {name}"),
+        Term::ArrayAccess(name, index, _) => format!("Index({name}, {})", expression(index)),
+        Term::SubroutineCall(c) => format!("Call {}", call(c)),
+        Term::Parenthesized(inner, _) if term.is_synthetic_group() => {
+            format!("Group({})", expression(inner))
+        }
+        Term::Parenthesized(inner, _) => format!("Paren({})", expression(inner)),
+        Term::UnaryOp(op, inner, _) => format!("Unary({}, {})", op.as_char(), self::term(inner)),
+    }
+}
+
+/// `receiver.name(args)` or `name(args)`.
+fn call(call: &SubroutineCall) -> String {
+    let args: Vec<String> = call.arguments.iter().map(expression).collect();
+    match &call.receiver {
+        Some(receiver) => format!("{receiver}.{}({})", call.name, args.join(", ")),
+        None => format!("{}({})", call.name, args.join(", ")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::tokenizer::JackTokenizer;
+    use crate::{AnalyzeOptions, analyze_source_with_options};
+    use pretty_assertions::assert_eq;
+
+    /// Every statement, term and declaration kind.
+    const FIXTURE: &str = "\
+class Point {
+    static int count;
+    field int x, y;
+
+    constructor Point new(int ax, int ay) {
+        let x = ax;
+        let y = ay;
+        let count = count + 1;
+        return this;
+    }
+
+    method void draw(Array pixels) {
+        var int i;
+        var String label;
+        let i = 0;
+        let label = \"p\";
+        let i = i + Math.abs(x);
+        let i -= 1;
+        while (~(i > 3)) {
+            let pixels[i + 1] = -x * (y - i);
+            let i = i + 1;
+        }
+        if (pixels[0] = null) {
+            do Output.printString(label);
+        } else {
+            do draw(pixels);
+        }
+        if (true) {
+            return;
+        }
+        return;
+    }
+}
+";
+
+    const EXPECTED: &str = "\
+Class Point
+  ClassVar static int count
+  ClassVar field int x, y
+  Subroutine constructor Point new
+    Param int ax
+    Param int ay
+    Let x = Var ax
+    Let y = Var ay
+    Let count = BinOp(+, Var count, Int 1)
+    Return Keyword this
+  Subroutine method void draw
+    Param Array pixels
+    Var int i
+    Var String label
+    Let i = Int 0
+    Let label = Str \"p\"
+    Let i = BinOp(+, Var i, Call Math.abs(Var x))
+    Let i = BinOp(-, Var i, Group(Int 1)) (from -=)
+    While Unary(~, Paren(BinOp(>, Var i, Int 3)))
+      Let pixels[BinOp(+, Var i, Int 1)] = BinOp(*, Unary(-, Var x), Paren(BinOp(-, Var y, Var i)))
+      Let i = BinOp(+, Var i, Int 1)
+    If BinOp(=, Index(pixels, Int 0), Keyword null)
+      Do Output.printString(Var label)
+    Else
+      Do draw(Var pixels)
+    If Keyword true
+      Return
+    Return
+";
+
+    /// `FIXTURE` parsed with the `-=` extension enabled.
+    fn fixture() -> Class {
+        let tokens = JackTokenizer::new(FIXTURE)
+            .with_extensions(true)
+            .tokenize()
+            .unwrap();
+        Parser::new(&tokens).parse().unwrap()
+    }
+
+    #[test]
+    fn test_pretty_covers_every_construct() {
+        let class = fixture();
+        assert_eq!(pretty(&class), EXPECTED);
+    }
+
+    #[test]
+    fn test_pretty_with_spans() {
+        let class = fixture();
+        let text = pretty_with_spans(&class);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "Class Point @1:1");
+        assert_eq!(lines[1], "  ClassVar static int count @2:5");
+        assert_eq!(lines[8], "    Let count = BinOp(+, Var count, Int 1) @8:9");
+        // Parameters and `Else` have no span of their own
+        assert_eq!(lines[4], "    Param int ax");
+        assert_eq!(lines[23], "    Else");
+        // Otherwise the same tree
+        let stripped: Vec<&str> = lines
+            .iter()
+            .map(|l| l.split(" @").next().unwrap())
+            .collect();
+        assert_eq!(stripped, EXPECTED.lines().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_pretty_shows_synthetic_groups() {
+        let source = "class Main { function int f() { return 2 + 3 * 4; } }";
+        let options = AnalyzeOptions {
+            precedence: true,
+            ..Default::default()
+        };
+        let class = analyze_source_with_options(source, "Main.jack", options)
+            .class
+            .unwrap();
+        assert!(pretty(&class).contains("Return BinOp(+, Int 2, Group(BinOp(*, Int 3, Int 4)))\n"));
+    }
+
+    #[test]
+    fn test_expression_has_calls() {
+        let has_calls =
+            |source| expression_has_calls(&crate::parse_expression_snippet(source).unwrap());
+        assert!(has_calls("Keyboard.keyPressed()"));
+        assert!(has_calls("1 + -(a[f(x)] * 2)"));
+        assert!(!has_calls("~(a[i + 1] = \"s\") & x"));
+    }
+}
@@ -0,0 +1,369 @@
+//! Token stream comparison for grading tokenizer implementations.
+//!
+//! Comparing a student's `*T.xml` against ours text-wise fails on harmless
+//! differences: indentation, padding inside tags, line breaks, or escaping
+//! `>` as `&gt;` versus leaving it bare. [`parse_token_xml`] reduces a token
+//! file to `(tag, value)` pairs with values unescaped and trimmed, and
+//! [`diff_token_streams`] compares two such streams position by position.
+//!
+//! ```
+//! use jack_analyzer::compare::{diff_token_streams, parse_token_xml};
+//!
+//! let ours = parse_token_xml("<tokens>\n<symbol> &gt; </symbol>\n</tokens>\n").unwrap();
+//! let theirs = parse_token_xml("<tokens><symbol>></symbol></tokens>").unwrap();
+//! assert!(diff_token_streams(&ours, &theirs, 10).is_empty());
+//! ```
+
+use crate::token::SpannedToken;
+use std::fmt;
+use thiserror::Error;
+
+/// A token as `(tag, value)`, e.g. `("symbol", "<")`.
+pub type TokenPair = (String, String);
+
+/// Number of differences the CLI reports before stopping.
+pub const DEFAULT_DIFF_LIMIT: usize = 10;
+
+/// A token file that is not well-formed.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("line {line}: {message}")]
+pub struct XmlParseError {
+    /// 1-based line of the problem.
+    pub line: usize,
+    pub message: String,
+}
+
+/// One position where two token streams disagree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenDiff {
+    /// 0-based token index.
+    pub index: usize,
+    /// Our token, `None` past the end of our stream.
+    pub ours: Option<TokenPair>,
+    /// Their token, `None` past the end of their stream.
+    pub theirs: Option<TokenPair>,
+}
+
+impl fmt::Display for TokenDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let side = |token: &Option<TokenPair>| match token {
+            Some((tag, value)) => format!("<{tag}> {value} </{tag}>"),
+            None => "end of tokens".to_string(),
+        };
+        write!(
+            f,
+            "token {}: expected {}, found {}",
+            self.index + 1,
+            side(&self.ours),
+            side(&self.theirs)
+        )
+    }
+}
+
+/// Our tokens as `(tag, value)` pairs, comparable with [`parse_token_xml`]
+/// output.
+pub fn token_pairs(tokens: &[SpannedToken]) -> Vec<TokenPair> {
+    tokens
+        .iter()
+        .map(|t| {
+            (
+                t.token.xml_tag().to_string(),
+                unescape(&t.token.xml_value()),
+            )
+        })
+        .collect()
+}
+
+/// Parse a `*T.xml` token file into `(tag, value)` pairs.
+///
+/// Expects a `<tokens>` root holding only `<tag>value</tag>` elements.
+/// Whitespace between elements and around values is ignored, except in a
+/// `stringConstant`, whose spaces are its content: there only the one
+/// padding space on each side (`<stringConstant> x </stringConstant>`) is
+/// dropped. The entities `&lt;`, `&gt;`, `&amp;`, `&quot;`, `&apos;` and `&#N;` are
+/// decoded. Tag names are not checked, so a misspelled tag shows up as a
+/// difference rather than an error.
+pub fn parse_token_xml(xml: &str) -> Result<Vec<TokenPair>, XmlParseError> {
+    let mut reader = Reader { xml, pos: 0 };
+    match reader.next_tag()? {
+        Some(Tag::Open("tokens")) => {}
+        Some(_) => return Err(reader.error("expected <tokens>")),
+        None => return Err(reader.error("missing <tokens> root element")),
+    }
+
+    let mut tokens = Vec::new();
+    loop {
+        match reader.next_tag()? {
+            Some(Tag::Close("tokens")) => break,
+            Some(Tag::Open(tag)) => {
+                let value = reader.text_until('<');
+                match reader.next_tag()? {
+                    Some(Tag::Close(name)) if name == tag => {}
+                    _ => return Err(reader.error(&format!("expected </{tag}>"))),
+                }
+                let value = if tag == "stringConstant" {
+                    strip_padding(value)
+                } else {
+                    value.trim()
+                };
+                tokens.push((tag.to_string(), unescape(value)));
+            }
+            Some(Tag::Close(name)) => {
+                return Err(reader.error(&format!("unexpected </{name}>")));
+            }
+            None => return Err(reader.error("missing </tokens>")),
+        }
+    }
+
+    if !reader.text_until('\0').trim().is_empty() {
+        return Err(reader.error("content after </tokens>"));
+    }
+    Ok(tokens)
+}
+
+/// Compare two token streams position by position.
+///
+/// Returns up to `limit` differing positions, in order. A missing or extra
+/// token shifts every later position, so the first difference is usually
+/// the one to look at.
+pub fn diff_token_streams(
+    ours: &[TokenPair],
+    theirs: &[TokenPair],
+    limit: usize,
+) -> Vec<TokenDiff> {
+    (0..ours.len().max(theirs.len()))
+        .filter(|&i| ours.get(i) != theirs.get(i))
+        .take(limit)
+        .map(|index| TokenDiff {
+            index,
+            ours: ours.get(index).cloned(),
+            theirs: theirs.get(index).cloned(),
+        })
+        .collect()
+}
+
+/// `value` without one space of padding on each side, if present.
+fn strip_padding(value: &str) -> &str {
+    let value = value.strip_prefix(' ').unwrap_or(value);
+    value.strip_suffix(' ').unwrap_or(value)
+}
+
+/// Decode the XML entities used in token files.
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let decoded = rest.find(';').and_then(|end| {
+            let c = match &rest[1..end] {
+                "lt" => '<',
+                "gt" => '>',
+                "amp" => '&',
+                "quot" => '"',
+                "apos" => '\'',
+                entity => char::from_u32(entity.strip_prefix('#')?.parse().ok()?)?,
+            };
+            Some((c, end + 1))
+        });
+        match decoded {
+            Some((c, len)) => {
+                out.push(c);
+                rest = &rest[len..];
+            }
+            // Not an entity: keep the `&` as written
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+enum Tag<'a> {
+    Open(&'a str),
+    Close(&'a str),
+}
+
+/// Cursor over a token file.
+struct Reader<'a> {
+    xml: &'a str,
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    /// Text up to (not including) the next `end`, or the rest of the input.
+    fn text_until(&mut self, end: char) -> &'a str {
+        let rest = &self.xml[self.pos..];
+        let len = rest.find(end).unwrap_or(rest.len());
+        self.pos += len;
+        &rest[..len]
+    }
+
+    /// Skip whitespace and read the next `<name>` or `</name>`; `None` at
+    /// the end of input. Text before the tag is an error.
+    fn next_tag(&mut self) -> Result<Option<Tag<'a>>, XmlParseError> {
+        let text = self.text_until('<');
+        if !text.trim().is_empty() {
+            // Report the line the text starts on
+            self.pos -= text.trim_start().len();
+            return Err(self.error(&format!("unexpected text '{}'", text.trim())));
+        }
+        if self.pos == self.xml.len() {
+            return Ok(None);
+        }
+        let rest = &self.xml[self.pos + 1..];
+        let Some(len) = rest.find('>') else {
+            return Err(self.error("unterminated tag"));
+        };
+        let (close, name) = match rest[..len].strip_prefix('/') {
+            Some(name) => (true, name),
+            None => (false, &rest[..len]),
+        };
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(self.error(&format!("invalid tag <{}>", &rest[..len])));
+        }
+        self.pos += len + 2;
+        Ok(Some(if close {
+            Tag::Close(name)
+        } else {
+            Tag::Open(name)
+        }))
+    }
+
+    /// An error at the current position.
+    fn error(&self, message: &str) -> XmlParseError {
+        XmlParseError {
+            line: self.xml[..self.pos].matches('\n').count() + 1,
+            message: message.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::JackTokenizer;
+    use crate::xml::{XmlStyle, tokens_to_xml};
+
+    const SOURCE: &str = "class Main { function void main() { let x = a < \"b&c\"; return; } }";
+
+    fn ours() -> Vec<TokenPair> {
+        token_pairs(&JackTokenizer::new(SOURCE).tokenize().unwrap())
+    }
+
+    fn pair(tag: &str, value: &str) -> TokenPair {
+        (tag.to_string(), value.to_string())
+    }
+
+    #[test]
+    fn test_identical_streams() {
+        let tokens = JackTokenizer::new(SOURCE).tokenize().unwrap();
+        for style in [XmlStyle::Course, XmlStyle::Compact] {
+            let theirs = parse_token_xml(&tokens_to_xml(&tokens, style)).unwrap();
+            assert_eq!(theirs, ours());
+            assert_eq!(diff_token_streams(&ours(), &theirs, 10), vec![]);
+        }
+        assert!(ours().contains(&pair("symbol", "<")));
+        assert!(ours().contains(&pair("stringConstant", "b&c")));
+    }
+
+    #[test]
+    fn test_value_mismatch() {
+        let theirs: Vec<TokenPair> = ours()
+            .into_iter()
+            .map(|(tag, value)| match value.as_str() {
+                "main" => (tag, "Main".to_string()),
+                "x" => ("keyword".to_string(), value),
+                _ => (tag, value),
+            })
+            .collect();
+        let diffs = diff_token_streams(&ours(), &theirs, 10);
+        assert_eq!(diffs.len(), 2);
+        assert_eq!(diffs[0].index, 5);
+        assert_eq!(
+            diffs[0].to_string(),
+            "token 6: expected <identifier> main </identifier>, found <identifier> Main </identifier>"
+        );
+        assert_eq!(diffs[1].theirs, Some(pair("keyword", "x")));
+        assert_eq!(diff_token_streams(&ours(), &theirs, 1).len(), 1);
+    }
+
+    #[test]
+    fn test_extra_trailing_token() {
+        let mut theirs = ours();
+        theirs.push(pair("symbol", ";"));
+        let diffs = diff_token_streams(&ours(), &theirs, 10);
+        assert_eq!(
+            diffs,
+            vec![TokenDiff {
+                index: ours().len(),
+                ours: None,
+                theirs: Some(pair("symbol", ";")),
+            }]
+        );
+        assert!(diffs[0].to_string().contains("expected end of tokens"));
+    }
+
+    #[test]
+    fn test_student_formatting_is_normalized() {
+        let theirs = parse_token_xml(
+            "<tokens>\r\n  <symbol>&amp;</symbol><stringConstant> a &#62; b </stringConstant>\r\n</tokens>",
+        )
+        .unwrap();
+        assert_eq!(
+            theirs,
+            [pair("symbol", "&"), pair("stringConstant", "a > b")]
+        );
+        // Unpadded values are read as they are
+        assert_eq!(
+            parse_token_xml("<tokens><stringConstant>a</stringConstant></tokens>").unwrap(),
+            [pair("stringConstant", "a")]
+        );
+        assert_eq!(parse_token_xml("<tokens></tokens>").unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_padded_string_constant() {
+        let source = "class Main { function void main() { do f(\" x \", \"  \"); return; } }";
+        let tokens = JackTokenizer::new(source).tokenize().unwrap();
+        let theirs = parse_token_xml(&tokens_to_xml(&tokens, XmlStyle::Course)).unwrap();
+        assert!(theirs.contains(&pair("stringConstant", " x ")));
+        assert!(theirs.contains(&pair("stringConstant", "  ")));
+        assert_eq!(theirs, token_pairs(&tokens));
+
+        let theirs = parse_token_xml("<tokens><stringConstant>  x  </stringConstant></tokens>");
+        assert_eq!(theirs.unwrap(), [pair("stringConstant", " x ")]);
+    }
+
+    #[test]
+    fn test_malformed_student_xml() {
+        let cases = [
+            ("", "line 1: missing <tokens> root element"),
+            (
+                "<tokens>\n<symbol> ; </symbol>\n",
+                "line 3: missing </tokens>",
+            ),
+            (
+                "<tokens>\n<symbol> ; </keyword>\n</tokens>",
+                "line 2: expected </symbol>",
+            ),
+            (
+                "<tokens>\nclass\n</tokens>",
+                "line 2: unexpected text 'class'",
+            ),
+            (
+                "<tokens>\n<symbol ; </symbol>",
+                "line 2: invalid tag <symbol ; </symbol>",
+            ),
+            ("<tokens></tokens>\n<x>", "line 2: content after </tokens>"),
+            ("<class></class>", "line 1: expected <tokens>"),
+        ];
+        for (xml, message) in cases {
+            let err = parse_token_xml(xml).unwrap_err();
+            assert_eq!(err.to_string(), message, "{xml:?}");
+        }
+    }
+}
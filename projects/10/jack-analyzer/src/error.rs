@@ -1,5 +1,6 @@
 //! Error types and diagnostics for the Jack analyzer.
 
+use crate::preprocess::SourceMap;
 use crate::token::Span;
 use std::fmt;
 use std::path::PathBuf;
@@ -31,6 +32,13 @@ pub enum JackError {
         #[source]
         source: std::io::Error,
     },
+
+    #[error("Include error at {}:{line}: {message}", .path.display())]
+    Include {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
 }
 
 impl JackError {
@@ -71,15 +79,46 @@ impl JackError {
         }
     }
 
+    /// Create an include directive error.
+    pub fn include(path: impl Into<PathBuf>, line: usize, message: impl Into<String>) -> Self {
+        JackError::Include {
+            path: path.into(),
+            line,
+            message: message.into(),
+        }
+    }
+
     /// Get the span of this error, if any.
     pub fn span(&self) -> Option<&Span> {
         match self {
             JackError::Lexical { span, .. } => Some(span),
             JackError::Syntax { span, .. } => Some(span),
-            JackError::Io { .. } => None,
+            JackError::Io { .. } | JackError::Include { .. } => None,
         }
     }
 
+    /// Stable diagnostic code: `JA0001` lexical, `JA0002` syntax, `JA0003`
+    /// I/O, `JA0004` include.
+    pub fn code(&self) -> &'static str {
+        match self {
+            JackError::Lexical { .. } => "JA0001",
+            JackError::Syntax { .. } => "JA0002",
+            JackError::Io { .. } => "JA0003",
+            JackError::Include { .. } => "JA0004",
+        }
+    }
+
+    /// This error as a one-line JSON diagnostic, see [`json_diagnostic`].
+    pub fn to_json(&self, file: &str) -> String {
+        json_diagnostic(
+            file,
+            self.span(),
+            self.code(),
+            &self.to_string(),
+            Severity::Error,
+        )
+    }
+
     /// Chain this error with a cause.
     pub fn with_cause(self, cause: JackError) -> Self {
         match self {
@@ -180,6 +219,7 @@ pub struct Diagnostic<'a> {
     error: &'a JackError,
     source: Option<&'a str>,
     filename: Option<&'a str>,
+    source_map: Option<&'a SourceMap>,
 }
 
 impl<'a> Diagnostic<'a> {
@@ -188,9 +228,35 @@ impl<'a> Diagnostic<'a> {
             error,
             source: None,
             filename: None,
+            source_map: None,
         }
     }
 
+    /// Report locations in the files `source` was preprocessed from.
+    pub fn with_source_map(mut self, source_map: &'a SourceMap) -> Self {
+        self.source_map = Some(source_map);
+        self
+    }
+
+    /// Write the location and source excerpt of `span`.
+    fn write_location(&self, f: &mut fmt::Formatter<'_>, span: &Span) -> fmt::Result {
+        let filename = self.filename.unwrap_or("<input>");
+        let (file, line) = match self.source_map.and_then(|map| map.locate(span.line)) {
+            Some((path, line)) => (path.display().to_string(), line),
+            None => (filename.to_string(), span.line),
+        };
+        writeln!(f, "  --> {}:{}:{}", file, line, span.column)?;
+
+        if let Some(source) = self.source
+            && let Some(text) = source.lines().nth(span.line - 1)
+        {
+            writeln!(f, "   |")?;
+            writeln!(f, "{:3} | {}", line, text)?;
+            writeln!(f, "   | {:>width$}^", "", width = span.column - 1)?;
+        }
+        Ok(())
+    }
+
     pub fn with_source(mut self, source: &'a str) -> Self {
         self.source = Some(source);
         self
@@ -204,8 +270,6 @@ impl<'a> Diagnostic<'a> {
 
 impl fmt::Display for Diagnostic<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let filename = self.filename.unwrap_or("<input>");
-
         match self.error {
             JackError::Lexical {
                 span,
@@ -213,15 +277,7 @@ impl fmt::Display for Diagnostic<'_> {
                 cause,
             } => {
                 writeln!(f, "error: {}", message)?;
-                writeln!(f, "  --> {}:{}:{}", filename, span.line, span.column)?;
-
-                if let Some(source) = self.source
-                    && let Some(line) = source.lines().nth(span.line - 1)
-                {
-                    writeln!(f, "   |")?;
-                    writeln!(f, "{:3} | {}", span.line, line)?;
-                    writeln!(f, "   | {:>width$}^", "", width = span.column - 1)?;
-                }
+                self.write_location(f, span)?;
 
                 if let Some(cause) = cause {
                     writeln!(f, "   = caused by: {}", cause)?;
@@ -234,15 +290,7 @@ impl fmt::Display for Diagnostic<'_> {
                 cause,
             } => {
                 writeln!(f, "error: {}", message)?;
-                writeln!(f, "  --> {}:{}:{}", filename, span.line, span.column)?;
-
-                if let Some(source) = self.source
-                    && let Some(line) = source.lines().nth(span.line - 1)
-                {
-                    writeln!(f, "   |")?;
-                    writeln!(f, "{:3} | {}", span.line, line)?;
-                    writeln!(f, "   | {:>width$}^", "", width = span.column - 1)?;
-                }
+                self.write_location(f, span)?;
 
                 if !expected.is_empty() {
                     writeln!(f, "   = expected: {}", expected.join(", "))?;
@@ -255,14 +303,94 @@ impl fmt::Display for Diagnostic<'_> {
             JackError::Io { path, source } => {
                 writeln!(f, "error: IO error for {}: {}", path.display(), source)?;
             }
+            JackError::Include {
+                path,
+                line,
+                message,
+            } => {
+                writeln!(f, "error: {}", message)?;
+                writeln!(f, "  --> {}:{}", path.display(), line)?;
+            }
         }
 
         Ok(())
     }
 }
 
+/// Severity of a machine-readable diagnostic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+/// Format a diagnostic as a single-line JSON object with the keys `file`,
+/// `line`, `col`, `code`, `message` and `severity`.
+///
+/// `line` and `col` are `null` for diagnostics without a location.
+pub fn json_diagnostic(
+    file: &str,
+    span: Option<&Span>,
+    code: &str,
+    message: &str,
+    severity: Severity,
+) -> String {
+    let (line, col) = match span {
+        Some(span) => (span.line.to_string(), span.column.to_string()),
+        None => ("null".to_string(), "null".to_string()),
+    };
+    format!(
+        "{{\"file\":{},\"line\":{},\"col\":{},\"code\":{},\"message\":{},\"severity\":\"{}\"}}",
+        json_string(file),
+        line,
+        col,
+        json_string(code),
+        json_string(message),
+        severity.as_str()
+    )
+}
+
+/// Quote and escape a string for JSON.
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c < ' ' => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
 /// Format multiple errors with context.
 pub fn format_errors(errors: &[JackError], source: &str, filename: &str) -> String {
+    format_errors_with_map(errors, source, filename, None)
+}
+
+/// Format multiple errors with context, reporting locations through
+/// `source_map` for preprocessed source (see [`crate::preprocess`]).
+pub fn format_errors_with_map(
+    errors: &[JackError],
+    source: &str,
+    filename: &str,
+    source_map: Option<&SourceMap>,
+) -> String {
     let mut output = String::new();
     let total = errors.len();
 
@@ -271,12 +399,13 @@ pub fn format_errors(errors: &[JackError], source: &str, filename: &str) -> Stri
             output.push('\n');
         }
         output.push_str(&format!("Error {} of {}:\n", i + 1, total));
-        output.push_str(
-            &Diagnostic::new(error)
-                .with_source(source)
-                .with_filename(filename)
-                .to_string(),
-        );
+        let mut diagnostic = Diagnostic::new(error)
+            .with_source(source)
+            .with_filename(filename);
+        if let Some(map) = source_map {
+            diagnostic = diagnostic.with_source_map(map);
+        }
+        output.push_str(&diagnostic.to_string());
     }
 
     output
@@ -302,4 +431,19 @@ mod tests {
         acc.push(JackError::lexical(Span::new(0, 1, 1, 1), "error 4"));
         assert_eq!(acc.len(), 3);
     }
+
+    #[test]
+    fn test_json_diagnostic() {
+        let error = JackError::syntax(Span::new(0, 1, 3, 7), "expected \"(\"");
+        assert_eq!(
+            error.to_json("Main"),
+            r#"{"file":"Main","line":3,"col":7,"code":"JA0002","message":"Syntax error at 3:7: expected \"(\"","severity":"error"}"#
+        );
+
+        let json = json_diagnostic("a\\b", None, "X1", "tab\there\u{1}", Severity::Warning);
+        assert_eq!(
+            json,
+            r#"{"file":"a\\b","line":null,"col":null,"code":"X1","message":"tab\there\u0001","severity":"warning"}"#
+        );
+    }
 }
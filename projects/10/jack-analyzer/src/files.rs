@@ -0,0 +1,224 @@
+//! File-system entry points: single files, directories and output files.
+//!
+//! Compiled only with the `fs` feature (on by default).
+
+use crate::ast::Class;
+use crate::error::JackError;
+use crate::preprocess::preprocess_source;
+use crate::token::SpannedToken;
+use crate::xml::{XmlStyle, XmlWriter, tokens_to_xml_to};
+use crate::{AnalysisResult, AnalyzeOptions, analyze_source_with, analyze_source_with_options};
+use rayon::prelude::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Analyze a single Jack file.
+///
+/// Returns an `AnalysisResult` containing the token XML, parse tree XML,
+/// and any errors encountered. `//!include` directives are expanded first;
+/// see [`crate::preprocess`].
+pub fn analyze_file(path: &Path) -> AnalysisResult {
+    analyze_file_with_options(path, AnalyzeOptions::default())
+}
+
+/// Analyze a single Jack file with custom options.
+pub fn analyze_file_with_options(path: &Path, options: AnalyzeOptions) -> AnalysisResult {
+    analyze_file_with(path, |source, filename| {
+        analyze_source_with_options(source, filename, options)
+    })
+}
+
+/// Analyze a single Jack file and stream its XML straight to `*T.xml` and
+/// `*.xml` in `output_dir`.
+///
+/// Produces the same files as [`analyze_file_with_options`] followed by
+/// [`write_results`], without holding either document in memory, so the
+/// returned result's `token_xml` and `parse_xml` are empty. Nothing is
+/// written if the file has errors; a failed write is added to the errors.
+pub fn analyze_file_to(path: &Path, output_dir: &Path, options: AnalyzeOptions) -> AnalysisResult {
+    analyze_file_with(path, |source, filename| {
+        analyze_source_with(source, filename, options, |tokens, class| {
+            if let Some(class) = class {
+                write_xml_to(tokens, class, filename, output_dir)?;
+            }
+            Ok((String::new(), String::new()))
+        })
+    })
+}
+
+/// Read `path`, splicing in `//!include` files, and `analyze` its source.
+fn analyze_file_with(
+    path: &Path,
+    analyze: impl FnOnce(&str, &str) -> AnalysisResult,
+) -> AnalysisResult {
+    let filename = path
+        .file_name()
+        .and_then(|s| s.to_str())
+        .unwrap_or("unknown")
+        .to_string();
+
+    // Read the source file, splicing in `//!include` files
+    let (source, source_map) = match preprocess_source(path) {
+        Ok(preprocessed) => preprocessed,
+        Err(e) => {
+            return AnalysisResult {
+                filename,
+                source: String::new(),
+                token_xml: String::new(),
+                parse_xml: String::new(),
+                class: None,
+                errors: vec![e],
+                source_map: None,
+            };
+        }
+    };
+
+    let mut result = analyze(&source, &filename);
+    // Only worth keeping when lines came from more than one file
+    result.source_map = (source_map.files().len() > 1).then_some(source_map);
+    result
+}
+
+/// Analyze all Jack files in a directory.
+///
+/// Uses parallel processing via Rayon to analyze multiple files concurrently.
+/// Results are in [`AnalyzeOptions::file_order`], alphabetical by default.
+pub fn analyze_directory(dir: &Path) -> Vec<AnalysisResult> {
+    analyze_directory_with_options(dir, AnalyzeOptions::default())
+}
+
+/// Analyze all Jack files in a directory with custom options.
+pub fn analyze_directory_with_options(dir: &Path, options: AnalyzeOptions) -> Vec<AnalysisResult> {
+    analyze_directory_with_progress(dir, options, |_| {})
+}
+
+/// Analyze all Jack files in a directory, calling `on_file` with each
+/// file's [`AnalysisResult::filename`] as soon as it is analyzed.
+///
+/// Files are analyzed in parallel, so `on_file` may be called from several
+/// threads and in any order. The returned results are still in
+/// [`AnalyzeOptions::file_order`].
+pub fn analyze_directory_with_progress(
+    dir: &Path,
+    options: AnalyzeOptions,
+    on_file: impl Fn(&str) + Sync,
+) -> Vec<AnalysisResult> {
+    analyze_each(dir, options, |path| {
+        let result = analyze_file_with_options(path, options);
+        on_file(&result.filename);
+        result
+    })
+}
+
+/// Analyze all Jack files in a directory, streaming each file's XML to
+/// `output_dir` as [`analyze_file_to`] does.
+pub fn analyze_directory_to(
+    dir: &Path,
+    output_dir: &Path,
+    options: AnalyzeOptions,
+) -> Vec<AnalysisResult> {
+    analyze_each(dir, options, |path| {
+        analyze_file_to(path, output_dir, options)
+    })
+}
+
+/// Run `analyze_one` on every Jack file in `dir`, in parallel, returning
+/// the results in [`AnalyzeOptions::file_order`].
+fn analyze_each(
+    dir: &Path,
+    options: AnalyzeOptions,
+    analyze_one: impl Fn(&Path) -> AnalysisResult + Sync,
+) -> Vec<AnalysisResult> {
+    let mut jack_files: Vec<_> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|ext| ext == "jack"))
+            .collect(),
+        Err(e) => {
+            return vec![AnalysisResult {
+                filename: dir.to_string_lossy().to_string(),
+                source: String::new(),
+                token_xml: String::new(),
+                parse_xml: String::new(),
+                class: None,
+                errors: vec![JackError::io(dir, e)],
+                source_map: None,
+            }];
+        }
+    };
+
+    if jack_files.is_empty() {
+        return Vec::new();
+    }
+    options.file_order.sort(&mut jack_files);
+
+    par_map(&jack_files, options.threads, |path| analyze_one(path))
+}
+
+/// Map `items` in parallel, on a dedicated pool of `threads` if requested
+/// (`None` uses rayon's global pool).
+///
+/// If the pool cannot be built, the items are mapped sequentially, staying
+/// within the requested bound. Shared with the compiler's directory
+/// compilation.
+pub fn par_map<T: Sync, R: Send>(
+    items: &[T],
+    threads: Option<usize>,
+    f: impl Fn(&T) -> R + Sync,
+) -> Vec<R> {
+    let run = || items.par_iter().map(&f).collect();
+    match threads {
+        None => run(),
+        Some(n) => match rayon::ThreadPoolBuilder::new().num_threads(n).build() {
+            Ok(pool) => pool.install(run),
+            // Stay within the requested bound rather than use the global pool
+            Err(_) => items.iter().map(&f).collect(),
+        },
+    }
+}
+
+/// Write analysis results to output files.
+///
+/// Creates *T.xml (tokens) and *.xml (parse tree) files from the XML held
+/// in `result`. [`analyze_file_to`] writes the same files without building
+/// the XML in memory first.
+pub fn write_results(result: &AnalysisResult, output_dir: &Path) -> Result<(), JackError> {
+    let (token_path, parse_path) = output_paths(&result.filename, output_dir);
+
+    // Write token XML
+    fs::write(&token_path, &result.token_xml).map_err(|e| JackError::io(&token_path, e))?;
+
+    // Write parse tree XML
+    fs::write(&parse_path, &result.parse_xml).map_err(|e| JackError::io(&parse_path, e))?;
+
+    Ok(())
+}
+
+/// The token and parse tree XML paths for `filename` in `output_dir`.
+fn output_paths(filename: &str, output_dir: &Path) -> (PathBuf, PathBuf) {
+    let stem = filename.strip_suffix(".jack").unwrap_or(filename);
+    (
+        output_dir.join(format!("{}T.xml", stem)),
+        output_dir.join(format!("{}.xml", stem)),
+    )
+}
+
+/// Stream the token and parse tree XML of `class` to its output files.
+fn write_xml_to(
+    tokens: &[SpannedToken],
+    class: &Class,
+    filename: &str,
+    output_dir: &Path,
+) -> Result<(), JackError> {
+    let (token_path, parse_path) = output_paths(filename, output_dir);
+    fs::File::create(&token_path)
+        .and_then(|out| tokens_to_xml_to(tokens, XmlStyle::Course, out))
+        .map_err(|e| JackError::io(&token_path, e))?;
+
+    fs::File::create(&parse_path)
+        .and_then(|out| XmlWriter::new().write_class_to(class, tokens, out))
+        .map_err(|e| JackError::io(&parse_path, e))?;
+
+    Ok(())
+}
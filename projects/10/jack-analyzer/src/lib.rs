@@ -7,6 +7,7 @@
 //! # Usage
 //!
 //! ```no_run
+//! # #[cfg(feature = "fs")] {
 //! use jack_analyzer::{analyze_file, analyze_directory};
 //! use std::path::Path;
 //!
@@ -15,22 +16,119 @@
 //!
 //! // Analyze a directory (parallel processing)
 //! let results = analyze_directory(Path::new("Square/"));
+//! # }
 //! ```
+//!
+//! Besides the XML, each [`AnalysisResult`] carries the parsed [`ast::Class`],
+//! so other tools can use the analyzer as a front end without re-parsing.
+//! Fragments can be parsed on their own with [`parse_expression_snippet`]
+//! and [`parse_statements_snippet`].
+//!
+//! # Precedence Mode
+//!
+//! Jack evaluates binary operators strictly left to right, so `2 + 3 * 4` is
+//! 20. [`AnalyzeOptions::precedence`] opts into conventional precedence
+//! instead; the grouping is made explicit in the AST and parse tree XML.
+//!
+//! # Features
+//!
+//! The file and directory functions need the `fs` feature, on by default.
+//! With `default-features = false` only the in-memory API
+//! ([`analyze_source`], the snippet parsers) is built, e.g. for WASM.
 
 pub mod ast;
+pub mod compare;
 pub mod error;
+#[cfg(feature = "fs")]
+mod files;
 pub mod parser;
+pub mod preprocess;
 pub mod token;
 pub mod tokenizer;
 pub mod xml;
 
+use ast::{Class, Expression, Statement};
 use error::JackError;
 use parser::Parser;
-use rayon::prelude::*;
-use std::fs;
 use std::path::Path;
+use std::str::FromStr;
 use tokenizer::JackTokenizer;
 
+#[cfg(feature = "fs")]
+pub use files::{
+    analyze_directory, analyze_directory_to, analyze_directory_with_options,
+    analyze_directory_with_progress, analyze_file, analyze_file_to, analyze_file_with_options,
+    par_map, write_results,
+};
+
+/// Analysis options.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AnalyzeOptions {
+    /// Group binary operators by conventional precedence instead of strict
+    /// left to right (default: false, as in the course).
+    pub precedence: bool,
+    /// Size of a dedicated thread pool for directory analysis (default:
+    /// `None`, which uses rayon's global pool).
+    pub threads: Option<usize>,
+    /// Order of the results of a directory analysis (default: alphabetical).
+    pub file_order: FileOrder,
+}
+
+/// Order in which the files of a directory are processed and reported.
+///
+/// Directory listings come back in a file-system dependent order; sorting
+/// keeps results (and anything concatenating them) reproducible.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FileOrder {
+    /// By file name.
+    #[default]
+    Alphabetical,
+    /// `Sys.jack`, then `Main.jack`, then the rest by file name.
+    SysMainFirst,
+}
+
+impl FileOrder {
+    /// Sort `paths` (files of one directory) into this order.
+    pub fn sort<P: AsRef<Path>>(self, paths: &mut [P]) {
+        let rank = |path: &Path| match self {
+            FileOrder::Alphabetical => 0,
+            FileOrder::SysMainFirst => match path.file_stem().and_then(|s| s.to_str()) {
+                Some("Sys") => 0,
+                Some("Main") => 1,
+                _ => 2,
+            },
+        };
+        paths.sort_by(|a, b| {
+            let (a, b) = (a.as_ref(), b.as_ref());
+            rank(a)
+                .cmp(&rank(b))
+                .then_with(|| a.file_name().cmp(&b.file_name()))
+        });
+    }
+
+    /// The command-line name, `alphabetical` or `sys-main-first`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FileOrder::Alphabetical => "alphabetical",
+            FileOrder::SysMainFirst => "sys-main-first",
+        }
+    }
+}
+
+impl FromStr for FileOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "alphabetical" => Ok(FileOrder::Alphabetical),
+            "sys-main-first" => Ok(FileOrder::SysMainFirst),
+            _ => Err(format!(
+                "unknown file order '{s}' (expected 'alphabetical' or 'sys-main-first')"
+            )),
+        }
+    }
+}
+
 /// Result of analyzing a single Jack file.
 #[derive(Debug)]
 pub struct AnalysisResult {
@@ -42,8 +140,13 @@ pub struct AnalysisResult {
     pub token_xml: String,
     /// Parse tree XML output (for *.xml file).
     pub parse_xml: String,
+    /// The parsed class, or `None` if tokenizing or parsing failed.
+    pub class: Option<Class>,
     /// Any errors encountered during analysis.
     pub errors: Vec<JackError>,
+    /// Origin of each line of `source` when it was preprocessed from
+    /// several files (see [`preprocess`]); `None` for in-memory source.
+    pub source_map: Option<preprocess::SourceMap>,
 }
 
 impl AnalysisResult {
@@ -53,133 +156,123 @@ impl AnalysisResult {
     }
 }
 
-/// Analyze a single Jack file.
+/// Analyze Jack source code directly.
 ///
-/// Returns an `AnalysisResult` containing the token XML, parse tree XML,
-/// and any errors encountered.
-pub fn analyze_file(path: &Path) -> AnalysisResult {
-    let filename = path
-        .file_name()
-        .and_then(|s| s.to_str())
-        .unwrap_or("unknown")
-        .to_string();
-
-    // Read the source file
-    let source = match fs::read_to_string(path) {
-        Ok(s) => s,
-        Err(e) => {
-            return AnalysisResult {
-                filename,
-                source: String::new(),
-                token_xml: String::new(),
-                parse_xml: String::new(),
-                errors: vec![JackError::io(path, e)],
-            };
-        }
-    };
+/// This is useful for testing or when the source is already in memory.
+pub fn analyze_source(source: &str, filename: &str) -> AnalysisResult {
+    analyze_source_with_options(source, filename, AnalyzeOptions::default())
+}
 
-    analyze_source(&source, &filename)
+/// Analyze Jack source code with custom options.
+pub fn analyze_source_with_options(
+    source: &str,
+    filename: &str,
+    options: AnalyzeOptions,
+) -> AnalysisResult {
+    analyze_source_with(source, filename, options, |tokens, class| {
+        let token_xml = xml::tokens_to_xml(tokens, xml::XmlStyle::Course);
+        let parse_xml = class
+            .map(|class| xml::XmlWriter::new().write_class(class, tokens))
+            .unwrap_or_default();
+        Ok((token_xml, parse_xml))
+    })
 }
 
-/// Analyze Jack source code directly.
+/// [`analyze_source_with_options`] with the XML left to `emit`.
 ///
-/// This is useful for testing or when the source is already in memory.
-pub fn analyze_source(source: &str, filename: &str) -> AnalysisResult {
+/// Once the source tokenizes, `emit` gets the tokens and the class (`None`
+/// if parsing failed) and returns the token XML and parse tree XML to keep
+/// in the result; an error it returns is added to the result's errors.
+pub(crate) fn analyze_source_with(
+    source: &str,
+    filename: &str,
+    options: AnalyzeOptions,
+    emit: impl FnOnce(&[token::SpannedToken], Option<&Class>) -> Result<(String, String), JackError>,
+) -> AnalysisResult {
+    let mut result = AnalysisResult {
+        filename: filename.to_string(),
+        source: source.to_string(),
+        token_xml: String::new(),
+        parse_xml: String::new(),
+        class: None,
+        errors: Vec::new(),
+        source_map: None,
+    };
+
     // Tokenize
-    let tokenizer = JackTokenizer::new(source);
-    let tokens = match tokenizer.tokenize() {
+    let tokens = match JackTokenizer::new(source).tokenize() {
         Ok(tokens) => tokens,
         Err(errors) => {
-            return AnalysisResult {
-                filename: filename.to_string(),
-                source: source.to_string(),
-                token_xml: String::new(),
-                parse_xml: String::new(),
-                errors,
-            };
+            result.errors = errors;
+            return result;
         }
     };
 
-    // Generate token XML
-    let token_xml = xml::tokens_to_xml(&tokens);
-
     // Parse
-    let parser = Parser::new(&tokens);
-    let class = match parser.parse() {
-        Ok(class) => class,
-        Err(errors) => {
-            return AnalysisResult {
-                filename: filename.to_string(),
-                source: source.to_string(),
-                token_xml,
-                parse_xml: String::new(),
-                errors,
-            };
-        }
-    };
-
-    // Generate parse tree XML
-    let parse_xml = xml::XmlWriter::new().write_class(&class, &tokens);
+    let parser = Parser::new(&tokens).with_precedence(options.precedence);
+    match parser.parse() {
+        Ok(class) => result.class = Some(class),
+        Err(errors) => result.errors = errors,
+    }
 
-    AnalysisResult {
-        filename: filename.to_string(),
-        source: source.to_string(),
-        token_xml,
-        parse_xml,
-        errors: Vec::new(),
+    // Generate the XML
+    match emit(&tokens, result.class.as_ref()) {
+        Ok((token_xml, parse_xml)) => {
+            result.token_xml = token_xml;
+            result.parse_xml = parse_xml;
+        }
+        Err(e) => result.errors.push(e),
     }
+    result
 }
 
-/// Analyze all Jack files in a directory.
+/// Parse a single expression, such as `1 + 2 * 3`.
 ///
-/// Uses parallel processing via Rayon to analyze multiple files concurrently.
-pub fn analyze_directory(dir: &Path) -> Vec<AnalysisResult> {
-    let jack_files: Vec<_> = match fs::read_dir(dir) {
-        Ok(entries) => entries
-            .filter_map(|e| e.ok())
-            .map(|e| e.path())
-            .filter(|p| p.extension().is_some_and(|ext| ext == "jack"))
-            .collect(),
-        Err(e) => {
-            return vec![AnalysisResult {
-                filename: dir.to_string_lossy().to_string(),
-                source: String::new(),
-                token_xml: String::new(),
-                parse_xml: String::new(),
-                errors: vec![JackError::io(dir, e)],
-            }];
-        }
-    };
-
-    if jack_files.is_empty() {
-        return Vec::new();
-    }
+/// Trailing tokens after the expression are an error.
+pub fn parse_expression_snippet(source: &str) -> Result<Expression, Vec<JackError>> {
+    parse_expression_snippet_with_options(source, AnalyzeOptions::default())
+}
 
-    // Parallel analysis
-    jack_files
-        .par_iter()
-        .map(|path| analyze_file(path))
-        .collect()
+/// Parse a single expression with custom options.
+pub fn parse_expression_snippet_with_options(
+    source: &str,
+    options: AnalyzeOptions,
+) -> Result<Expression, Vec<JackError>> {
+    let tokens = JackTokenizer::new(source).tokenize()?;
+    Parser::new(&tokens)
+        .with_precedence(options.precedence)
+        .parse_expression_snippet()
 }
 
-/// Write analysis results to output files.
+/// Parse a sequence of statements, such as `let x = 1; do f(x);`.
 ///
-/// Creates *T.xml (tokens) and *.xml (parse tree) files.
-pub fn write_results(result: &AnalysisResult, output_dir: &Path) -> Result<(), JackError> {
-    let stem = result
-        .filename
-        .strip_suffix(".jack")
-        .unwrap_or(&result.filename);
-
-    // Write token XML
-    let token_path = output_dir.join(format!("{}T.xml", stem));
-    fs::write(&token_path, &result.token_xml).map_err(|e| JackError::io(&token_path, e))?;
-
-    // Write parse tree XML
-    let parse_path = output_dir.join(format!("{}.xml", stem));
-    fs::write(&parse_path, &result.parse_xml).map_err(|e| JackError::io(&parse_path, e))?;
-
-    Ok(())
+/// Anything other than complete statements (e.g. a `var` declaration) is an
+/// error.
+pub fn parse_statements_snippet(source: &str) -> Result<Vec<Statement>, Vec<JackError>> {
+    parse_statements_snippet_with_options(source, AnalyzeOptions::default())
+}
+
+/// Parse a sequence of statements with custom options.
+pub fn parse_statements_snippet_with_options(
+    source: &str,
+    options: AnalyzeOptions,
+) -> Result<Vec<Statement>, Vec<JackError>> {
+    let tokens = JackTokenizer::new(source).tokenize()?;
+    Parser::new(&tokens)
+        .with_precedence(options.precedence)
+        .parse_statements_snippet()
+}
+
+/// Parse a single expression and render its parse tree XML.
+pub fn expression_snippet_xml(
+    source: &str,
+    options: AnalyzeOptions,
+) -> Result<String, Vec<JackError>> {
+    let tokens = JackTokenizer::new(source).tokenize()?;
+    let expr = Parser::new(&tokens)
+        .with_precedence(options.precedence)
+        .parse_expression_snippet()?;
+    Ok(xml::XmlWriter::new().write_expression_snippet(&expr, &tokens))
 }
 
 #[cfg(test)]
@@ -196,6 +289,10 @@ mod tests {
         assert!(!result.parse_xml.is_empty());
         assert!(result.token_xml.contains("<tokens>"));
         assert!(result.parse_xml.contains("<class>"));
+
+        let class = result.class.unwrap();
+        assert_eq!(class.name, "Main");
+        assert_eq!(class.subroutine_decs[0].name, "main");
     }
 
     #[test]
@@ -205,5 +302,111 @@ mod tests {
 
         assert!(!result.is_ok());
         assert!(!result.errors.is_empty());
+        assert!(result.class.is_none());
+    }
+
+    #[test]
+    fn test_snippets() {
+        let expr = parse_expression_snippet("1 + 2 * 3").unwrap();
+        assert_eq!(expr.ops.len(), 2);
+
+        let errors = parse_expression_snippet("1 + 2 3").unwrap_err();
+        assert!(errors[0].to_string().contains("after end of snippet"));
+
+        let statements = parse_statements_snippet("let x = 1; return x;").unwrap();
+        assert_eq!(statements.len(), 2);
+
+        // Tokenizer errors are reported too
+        assert!(parse_statements_snippet("let s = \"open;").is_err());
+    }
+
+    #[test]
+    fn test_expression_snippet_xml() {
+        let xml = expression_snippet_xml("1 + x", AnalyzeOptions::default()).unwrap();
+        assert_eq!(
+            xml,
+            "<expression>\n  <term>\n    <integerConstant> 1 </integerConstant>\n  </term>\n  \
+             <symbol> + </symbol>\n  <term>\n    <identifier> x </identifier>\n  </term>\n\
+             </expression>\n"
+        );
+
+        // Precedence mode groups without adding paren tokens
+        let options = AnalyzeOptions {
+            precedence: true,
+            ..Default::default()
+        };
+        let xml = expression_snippet_xml("1 + 2 * 3", options).unwrap();
+        assert_eq!(xml.matches("<expression>").count(), 2);
+        assert!(!xml.contains("<symbol> ( </symbol>"));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let source = "class Main { field Array a; method int f(int i) { \
+                      let a[i] = -i + Math.abs(i); return a[0]; } }";
+        let tokens = JackTokenizer::new(source).tokenize().unwrap();
+        let json = serde_json::to_string(&tokens).unwrap();
+        let restored: Vec<token::SpannedToken> = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, tokens);
+
+        let class = analyze_source(source, "Main.jack").class.unwrap();
+        let json = serde_json::to_string(&class).unwrap();
+        let restored: Class = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&restored).unwrap(), json);
+        // The restored tree renders the same parse tree XML
+        assert_eq!(
+            xml::XmlWriter::new().write_class(&restored, &tokens),
+            xml::XmlWriter::new().write_class(&class, &tokens)
+        );
+    }
+
+    #[test]
+    fn test_precedence_mode_xml_grouping() {
+        let source = "class Main { function int f() { return 2 + 3 * 4; } }";
+        let grouped = "<term>\n<expression>\n<term>\n<integerConstant> 3 </integerConstant>";
+
+        let flat = analyze_source(source, "Main.jack");
+        assert!(flat.is_ok());
+        assert!(!squash(&flat.parse_xml).contains(grouped));
+
+        let options = AnalyzeOptions {
+            precedence: true,
+            ..Default::default()
+        };
+        let result = analyze_source_with_options(source, "Main.jack", options);
+        assert!(result.is_ok());
+        assert!(squash(&result.parse_xml).contains(grouped));
+        // Only source tokens appear in the parse tree
+        assert_eq!(
+            result.parse_xml.matches("<symbol> ( </symbol>").count(),
+            flat.parse_xml.matches("<symbol> ( </symbol>").count()
+        );
+        assert_eq!(result.token_xml, flat.token_xml);
+    }
+
+    #[test]
+    fn test_file_order() {
+        let mut paths = ["Square.jack", "Main.jack", "Sys.jack", "Array.jack"];
+        FileOrder::Alphabetical.sort(&mut paths);
+        assert_eq!(
+            paths,
+            ["Array.jack", "Main.jack", "Square.jack", "Sys.jack"]
+        );
+        FileOrder::SysMainFirst.sort(&mut paths);
+        assert_eq!(
+            paths,
+            ["Sys.jack", "Main.jack", "Array.jack", "Square.jack"]
+        );
+
+        for order in [FileOrder::Alphabetical, FileOrder::SysMainFirst] {
+            assert_eq!(order.as_str().parse(), Ok(order));
+        }
+        assert!("random".parse::<FileOrder>().is_err());
+    }
+
+    /// Strip indentation so nesting can be matched across lines.
+    fn squash(xml: &str) -> String {
+        xml.lines().map(str::trim).collect::<Vec<_>>().join("\n")
     }
 }
@@ -1,9 +1,19 @@
 //! JackAnalyzer CLI - Syntax analyzer for the Jack programming language.
 
 use clap::Parser as ClapParser;
-use jack_analyzer::error::format_errors;
-use jack_analyzer::{analyze_directory, analyze_file, write_results};
-use std::path::PathBuf;
+use jack_analyzer::compare::{
+    DEFAULT_DIFF_LIMIT, diff_token_streams, parse_token_xml, token_pairs,
+};
+use jack_analyzer::error::{format_errors, format_errors_with_map};
+use jack_analyzer::tokenizer::JackTokenizer;
+use jack_analyzer::{
+    AnalysisResult, AnalyzeOptions, FileOrder, analyze_directory_to,
+    analyze_directory_with_options, analyze_file_to, analyze_file_with_options, ast,
+    expression_snippet_xml,
+};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
 
 #[derive(ClapParser, Debug)]
@@ -13,34 +23,106 @@ use std::process::ExitCode;
 #[command(about = "Syntax analyzer for the Jack programming language")]
 struct Args {
     /// Input file (.jack) or directory containing .jack files
-    #[arg(value_name = "INPUT")]
-    input: PathBuf,
+    #[arg(value_name = "INPUT", required_unless_present = "expr")]
+    input: Option<PathBuf>,
 
     /// Output directory (defaults to input directory)
     #[arg(short, long)]
     output: Option<PathBuf>,
+
+    /// Group operators by conventional precedence (* / over + - over < > =
+    /// over & |) instead of Jack's left-to-right order
+    #[arg(long)]
+    precedence: bool,
+
+    /// Parse a single expression and print its parse tree XML instead of
+    /// analyzing files
+    #[arg(long, value_name = "EXPRESSION", conflicts_with = "input")]
+    expr: Option<String>,
+
+    /// Analyze directory files on N threads instead of one per CPU
+    #[arg(short = 'j', long, value_name = "N")]
+    threads: Option<usize>,
+
+    /// Order of directory files in the output: alphabetical, or
+    /// sys-main-first (Sys.jack, Main.jack, then alphabetical)
+    #[arg(long, value_name = "ORDER", default_value = "alphabetical")]
+    file_order: FileOrder,
+
+    /// Tokenize INPUT (a .jack file) and compare the tokens with a student's
+    /// token file instead of writing output; exits 1 on any difference
+    #[arg(long, value_name = "T_XML", requires = "input")]
+    compare_tokens: Option<PathBuf>,
+
+    /// Write each class's AST as an indented tree, <stem>.ast.txt, instead
+    /// of the XML files; with `-o -` print it to stdout
+    #[arg(long)]
+    ast_text: bool,
+
+    /// End each --ast-text line with the node's line:column
+    #[arg(long, requires = "ast_text")]
+    spans: bool,
 }
 
 fn main() -> ExitCode {
     let args = Args::parse();
+    let options = AnalyzeOptions {
+        precedence: args.precedence,
+        threads: args.threads,
+        file_order: args.file_order,
+    };
 
-    let (results, output_dir) = if args.input.is_file() {
-        let result = analyze_file(&args.input);
+    if let Some(expr) = &args.expr {
+        return match expression_snippet_xml(expr, options) {
+            Ok(xml) => {
+                print!("{xml}");
+                ExitCode::SUCCESS
+            }
+            Err(errors) => {
+                eprint!("{}", format_errors(&errors, expr, "<expr>"));
+                ExitCode::from(1)
+            }
+        };
+    }
+    let Some(input) = args.input else {
+        // clap requires INPUT unless --expr is given
+        return ExitCode::from(2);
+    };
+    if let Some(student) = &args.compare_tokens {
+        return compare_tokens(&input, student);
+    }
+    if args.output.as_deref() == Some(Path::new("-")) && !args.ast_text {
+        eprintln!("Error: -o - (stdout) is only supported with --ast-text");
+        return ExitCode::from(2);
+    }
+
+    // The XML files are streamed out during analysis; --ast-text writes
+    // its own files afterwards
+    let (results, output_dir) = if input.is_file() {
         let output_dir = args
             .output
-            .unwrap_or_else(|| args.input.parent().unwrap_or(&args.input).to_path_buf());
+            .unwrap_or_else(|| input.parent().unwrap_or(&input).to_path_buf());
+        let result = if args.ast_text {
+            analyze_file_with_options(&input, options)
+        } else {
+            analyze_file_to(&input, &output_dir, options)
+        };
         (vec![result], output_dir)
-    } else if args.input.is_dir() {
-        let results = analyze_directory(&args.input);
-        let output_dir = args.output.unwrap_or_else(|| args.input.clone());
+    } else if input.is_dir() {
+        let output_dir = args.output.unwrap_or_else(|| input.clone());
+        let results = if args.ast_text {
+            analyze_directory_with_options(&input, options)
+        } else {
+            analyze_directory_to(&input, &output_dir, options)
+        };
         (results, output_dir)
     } else {
-        eprintln!("Error: Input path does not exist: {}", args.input.display());
+        eprintln!("Error: Input path does not exist: {}", input.display());
         return ExitCode::from(2);
     };
 
     if results.is_empty() {
-        eprintln!("Error: No .jack files found in {}", args.input.display());
+        eprintln!("Error: No .jack files found in {}", input.display());
         return ExitCode::from(2);
     }
 
@@ -51,9 +133,16 @@ fn main() -> ExitCode {
             has_errors = true;
             eprint!(
                 "{}",
-                format_errors(&result.errors, &result.source, &result.filename)
+                format_errors_with_map(
+                    &result.errors,
+                    &result.source,
+                    &result.filename,
+                    result.source_map.as_ref()
+                )
             );
-        } else if let Err(e) = write_results(result, &output_dir) {
+        } else if args.ast_text
+            && let Err(e) = write_ast_text(result, &output_dir, args.spans)
+        {
             eprintln!("Error writing output for {}: {}", result.filename, e);
             has_errors = true;
         }
@@ -65,3 +154,72 @@ fn main() -> ExitCode {
         ExitCode::SUCCESS
     }
 }
+
+/// Write the AST of a successful `result` to `<stem>.ast.txt` in
+/// `output_dir`, or to stdout if `output_dir` is `-`.
+fn write_ast_text(result: &AnalysisResult, output_dir: &Path, spans: bool) -> io::Result<()> {
+    let Some(class) = &result.class else {
+        return Ok(());
+    };
+    let text = if spans {
+        ast::pretty_with_spans(class)
+    } else {
+        ast::pretty(class)
+    };
+    if output_dir == Path::new("-") {
+        print!("{text}");
+        return Ok(());
+    }
+    let stem = result
+        .filename
+        .strip_suffix(".jack")
+        .unwrap_or(&result.filename);
+    fs::write(output_dir.join(format!("{stem}.ast.txt")), text)
+}
+
+/// Compare the tokens of `input` with the student token file `student`.
+fn compare_tokens(input: &Path, student: &Path) -> ExitCode {
+    let read = |path: &Path| {
+        fs::read_to_string(path).map_err(|e| eprintln!("Error reading {}: {}", path.display(), e))
+    };
+    let (Ok(source), Ok(xml)) = (read(input), read(student)) else {
+        return ExitCode::from(2);
+    };
+    let theirs = match parse_token_xml(&xml) {
+        Ok(tokens) => tokens,
+        Err(e) => {
+            eprintln!("Error: {}:{}", student.display(), e);
+            return ExitCode::from(2);
+        }
+    };
+    let ours = match JackTokenizer::new(&source).tokenize() {
+        Ok(tokens) => token_pairs(&tokens),
+        Err(errors) => {
+            eprint!(
+                "{}",
+                format_errors(&errors, &source, &input.display().to_string())
+            );
+            return ExitCode::from(2);
+        }
+    };
+
+    let diffs = diff_token_streams(&ours, &theirs, DEFAULT_DIFF_LIMIT);
+    if diffs.is_empty() {
+        println!("{}: {} tokens match", student.display(), ours.len());
+        return ExitCode::SUCCESS;
+    }
+    println!(
+        "{}: differs from {} ({} tokens expected, {} found)",
+        student.display(),
+        input.display(),
+        ours.len(),
+        theirs.len()
+    );
+    for diff in &diffs {
+        println!("  {diff}");
+    }
+    if diffs.len() == DEFAULT_DIFF_LIMIT {
+        println!("  (stopped after {DEFAULT_DIFF_LIMIT} differences)");
+    }
+    ExitCode::from(1)
+}
@@ -12,23 +12,66 @@ const MAX_DEPTH: usize = 25;
 
 /// Recursive descent parser for Jack language.
 pub struct Parser<'a> {
-    tokens: &'a [SpannedToken],
+    tokens: &'a [SpannedToken<'a>],
     pos: usize,
     errors: ErrorAccumulator,
     depth: usize,
+    /// Locals synthesized for the current subroutine body (compound assignment).
+    synthetic_locals: Vec<String>,
+    /// Statement to emit before the one just parsed (compound assignment index).
+    hoisted: Option<LetStatement>,
+    /// Group binary operators by precedence instead of left to right.
+    precedence: bool,
+    /// Accept non-standard syntax (`var` declarations after statements).
+    extensions: bool,
+    /// Accept `this` as a `let` target.
+    this_target: bool,
 }
 
 impl<'a> Parser<'a> {
     /// Create a new parser for the given tokens.
-    pub fn new(tokens: &'a [SpannedToken]) -> Self {
+    pub fn new(tokens: &'a [SpannedToken<'a>]) -> Self {
         Self {
             tokens,
             pos: 0,
             errors: ErrorAccumulator::new(),
             depth: 0,
+            synthetic_locals: Vec::new(),
+            hoisted: None,
+            precedence: false,
+            extensions: false,
+            this_target: false,
         }
     }
 
+    /// Parse expressions with conventional operator precedence (`*` `/` over
+    /// `+` `-` over `<` `>` `=` over `&` `|`) instead of Jack's strict left to
+    /// right order. The grouping is made explicit with synthesized
+    /// parenthesized terms; see [`Term::is_synthetic_group`].
+    pub fn with_precedence(mut self, enabled: bool) -> Self {
+        self.precedence = enabled;
+        self
+    }
+
+    /// Accept non-standard syntax: `var` declarations between the
+    /// statements of a subroutine body (not inside `if` or `while` blocks).
+    ///
+    /// They are hoisted into [`SubroutineBody::var_decs`], keeping their
+    /// spans, so a compiler can reject uses that precede the declaration.
+    /// Operator extensions such as `+=` are enabled on the tokenizer instead.
+    pub fn with_extensions(mut self, enabled: bool) -> Self {
+        self.extensions = enabled;
+        self
+    }
+
+    /// Accept `let this = ...;`, with `this` as the target's name, so a
+    /// compiler can report the assignment with a targeted error. Off by
+    /// default: `this` is not a variable, so it is a syntax error.
+    pub fn with_this_target(mut self, enabled: bool) -> Self {
+        self.this_target = enabled;
+        self
+    }
+
     /// Parse the tokens into a Class AST.
     pub fn parse(mut self) -> Result<Class, Vec<JackError>> {
         let class = self.parse_class();
@@ -40,15 +83,52 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Parse the tokens as a single expression.
+    ///
+    /// Every token must belong to the expression; trailing tokens are an error.
+    pub fn parse_expression_snippet(mut self) -> Result<Expression, Vec<JackError>> {
+        let expr = self.parse_expression();
+        self.finish_snippet(expr)
+    }
+
+    /// Parse the tokens as a sequence of statements.
+    ///
+    /// Every token must belong to a statement; trailing tokens are an error.
+    pub fn parse_statements_snippet(mut self) -> Result<Vec<Statement>, Vec<JackError>> {
+        let statements = self.parse_statements();
+        self.finish_snippet(Some(statements))
+    }
+
+    /// Reject leftover tokens after a snippet production and collect errors.
+    fn finish_snippet<T>(mut self, parsed: Option<T>) -> Result<T, Vec<JackError>> {
+        if !self.errors.has_errors()
+            && let Some(token) = self.current()
+        {
+            self.errors.push(JackError::syntax(
+                token.span.clone(),
+                format!("unexpected {} after end of snippet", token.token),
+            ));
+        }
+
+        match parsed {
+            Some(parsed) if !self.errors.has_errors() => Ok(parsed),
+            _ => Err(self.errors.into_errors()),
+        }
+    }
+
     // ========================================================================
     // Helper methods
+    //
+    // Tokens are borrowed for the parser's whole lifetime `'a`, not just for
+    // one call, so a peeked token can be held across `advance()` and its text
+    // is only copied where the AST stores it.
     // ========================================================================
 
     fn is_at_end(&self) -> bool {
         self.pos >= self.tokens.len()
     }
 
-    fn current(&self) -> Option<&SpannedToken> {
+    fn current(&self) -> Option<&'a SpannedToken<'a>> {
         self.tokens.get(self.pos)
     }
 
@@ -58,7 +138,7 @@ impl<'a> Parser<'a> {
             .unwrap_or_else(|| Span::new(0, 0, 1, 1))
     }
 
-    fn peek_token(&self) -> Option<&Token> {
+    fn peek_token(&self) -> Option<&'a Token<'a>> {
         self.current().map(|t| &t.token)
     }
 
@@ -76,7 +156,7 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn advance(&mut self) -> Option<&SpannedToken> {
+    fn advance(&mut self) -> Option<&'a SpannedToken<'a>> {
         if self.is_at_end() {
             None
         } else {
@@ -122,8 +202,8 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn expect_identifier(&mut self) -> Option<(String, Span)> {
-        if let Some(Token::Identifier(name)) = self.peek_token().cloned() {
+    fn expect_identifier(&mut self) -> Option<(&'a str, Span)> {
+        if let Some(Token::Identifier(name)) = self.peek_token() {
             let span = self.advance().unwrap().span.clone();
             Some((name, span))
         } else {
@@ -185,7 +265,10 @@ impl<'a> Parser<'a> {
         let start_span = self.current_span();
 
         self.expect_keyword(Keyword::Class);
-        let name = self.expect_identifier().map(|(n, _)| n).unwrap_or_default();
+        let name = self
+            .expect_identifier()
+            .map(|(n, _)| n.to_string())
+            .unwrap_or_default();
         self.expect_symbol('{');
 
         let mut class_var_decs = Vec::new();
@@ -242,13 +325,13 @@ impl<'a> Parser<'a> {
 
         let mut names = Vec::new();
         if let Some((name, _)) = self.expect_identifier() {
-            names.push(name);
+            names.push(name.to_string());
         }
 
         while self.peek_symbol() == Some(',') {
             self.advance();
             if let Some((name, _)) = self.expect_identifier() {
-                names.push(name);
+                names.push(name.to_string());
             }
         }
 
@@ -278,9 +361,8 @@ impl<'a> Parser<'a> {
                 Some(Type::Boolean)
             }
             Some(Token::Identifier(name)) => {
-                let name = name.clone();
                 self.advance();
-                Some(Type::ClassName(name))
+                Some(Type::ClassName(name.to_string()))
             }
             _ => {
                 let got = self
@@ -302,6 +384,9 @@ impl<'a> Parser<'a> {
     /// subroutineDec: ('constructor'|'function'|'method') ('void'|type) subroutineName '(' parameterList ')' subroutineBody
     fn parse_subroutine_dec(&mut self) -> Option<SubroutineDec> {
         let start_span = self.current_span();
+        // Nothing synthesized for a subroutine that bailed out carries over
+        self.synthetic_locals.clear();
+        self.hoisted = None;
 
         let kind = match self.peek_keyword() {
             Some(Keyword::Constructor) => {
@@ -333,7 +418,10 @@ impl<'a> Parser<'a> {
             ReturnType::Type(self.parse_type()?)
         };
 
-        let name = self.expect_identifier().map(|(n, _)| n).unwrap_or_default();
+        let name = self
+            .expect_identifier()
+            .map(|(n, _)| n.to_string())
+            .unwrap_or_default();
 
         self.expect_symbol('(');
         let parameters = self.parse_parameter_list();
@@ -362,7 +450,10 @@ impl<'a> Parser<'a> {
         if let Some(var_type) = self.parse_type()
             && let Some((name, _)) = self.expect_identifier()
         {
-            params.push(Parameter { var_type, name });
+            params.push(Parameter {
+                var_type,
+                name: name.to_string(),
+            });
         }
 
         while self.peek_symbol() == Some(',') {
@@ -370,7 +461,10 @@ impl<'a> Parser<'a> {
             if let Some(var_type) = self.parse_type()
                 && let Some((name, _)) = self.expect_identifier()
             {
-                params.push(Parameter { var_type, name });
+                params.push(Parameter {
+                    var_type,
+                    name: name.to_string(),
+                });
             }
         }
 
@@ -378,6 +472,8 @@ impl<'a> Parser<'a> {
     }
 
     /// subroutineBody: '{' varDec* statements '}'
+    ///
+    /// With extensions: '{' (varDec | statement)* '}'
     fn parse_subroutine_body(&mut self) -> SubroutineBody {
         let start_span = self.current_span();
 
@@ -390,7 +486,21 @@ impl<'a> Parser<'a> {
             }
         }
 
-        let statements = self.parse_statements();
+        let mut statements = self.parse_statements();
+        while self.extensions && self.peek_keyword() == Some(Keyword::Var) {
+            if let Some(dec) = self.parse_var_dec() {
+                var_decs.push(dec);
+            }
+            statements.extend(self.parse_statements());
+        }
+
+        if !self.synthetic_locals.is_empty() {
+            var_decs.push(VarDec {
+                var_type: Type::Int,
+                names: std::mem::take(&mut self.synthetic_locals),
+                span: start_span.clone(),
+            });
+        }
 
         self.expect_symbol('}');
 
@@ -410,13 +520,13 @@ impl<'a> Parser<'a> {
 
         let mut names = Vec::new();
         if let Some((name, _)) = self.expect_identifier() {
-            names.push(name);
+            names.push(name.to_string());
         }
 
         while self.peek_symbol() == Some(',') {
             self.advance();
             if let Some((name, _)) = self.expect_identifier() {
-                names.push(name);
+                names.push(name.to_string());
             }
         }
 
@@ -437,6 +547,9 @@ impl<'a> Parser<'a> {
             match self.peek_keyword() {
                 Some(Keyword::Let) => {
                     if let Some(stmt) = self.parse_let_statement() {
+                        if let Some(hoisted) = self.hoisted.take() {
+                            statements.push(Statement::Let(hoisted));
+                        }
                         statements.push(Statement::Let(stmt));
                     }
                 }
@@ -471,12 +584,21 @@ impl<'a> Parser<'a> {
         statements
     }
 
-    /// letStatement: 'let' varName ('[' expression ']')? '=' expression ';'
+    /// letStatement: 'let' varName ('[' expression ']')? ('=' | '+=' | '-=') expression ';'
+    ///
+    /// The compound forms only appear when the tokenizer runs with extensions.
     fn parse_let_statement(&mut self) -> Option<LetStatement> {
         let start_span = self.current_span();
 
         self.expect_keyword(Keyword::Let)?;
-        let (var_name, _) = self.expect_identifier()?;
+        let (var_name, name_span) =
+            if self.this_target && self.peek_keyword() == Some(Keyword::This) {
+                let span = self.advance().unwrap().span.clone();
+                ("this", span)
+            } else {
+                self.expect_identifier()?
+            };
+        let var_name = var_name.to_string();
 
         let index = if self.peek_symbol() == Some('[') {
             self.advance();
@@ -487,18 +609,78 @@ impl<'a> Parser<'a> {
             None
         };
 
-        self.expect_symbol('=');
+        let compound = match self.peek_token() {
+            Some(Token::CompoundAssign(c)) => {
+                let op = BinaryOp::from_char(*c);
+                self.advance();
+                op
+            }
+            _ => {
+                self.expect_symbol('=');
+                None
+            }
+        };
         let value = self.parse_expression()?;
         self.expect_symbol(';');
 
+        let Some(op) = compound else {
+            return Some(LetStatement {
+                var_name,
+                index,
+                value,
+                compound: None,
+                span: start_span,
+            });
+        };
+
+        // Desugar `let x op= e` into `let x = x op (e)`. For array targets the
+        // index is evaluated once into a synthesized local unless it is a constant.
+        let index = index.map(|index| match index.term {
+            Term::IntegerConstant(..) if index.ops.is_empty() => index,
+            _ => Box::new(self.hoist_index(*index, &start_span)),
+        });
+        let current = match &index {
+            Some(index) => Term::ArrayAccess(var_name.clone(), index.clone(), name_span.clone()),
+            None => Term::VarName(var_name.clone(), name_span.clone()),
+        };
+        let value_span = value.span.clone();
+        let value = Expression {
+            term: current,
+            ops: vec![(op, Term::Parenthesized(Box::new(value), value_span))],
+            span: name_span,
+        };
+
         Some(LetStatement {
             var_name,
             index,
             value,
+            compound: Some(op),
             span: start_span,
         })
     }
 
+    /// Store an index expression in a fresh synthesized local and return a
+    /// reference to it. The assignment is emitted before the current statement.
+    fn hoist_index(&mut self, index: Expression, span: &Span) -> Expression {
+        let name = format!("{}index{}", SYNTHETIC_PREFIX, self.synthetic_locals.len());
+        self.synthetic_locals.push(name.clone());
+
+        let index_span = index.span.clone();
+        self.hoisted = Some(LetStatement {
+            var_name: name.clone(),
+            index: None,
+            value: index,
+            compound: None,
+            span: span.clone(),
+        });
+
+        Expression {
+            term: Term::VarName(name, index_span.clone()),
+            ops: Vec::new(),
+            span: index_span,
+        }
+    }
+
     /// ifStatement: 'if' '(' expression ')' '{' statements '}' ('else' '{' statements '}')?
     fn parse_if_statement(&mut self) -> Option<IfStatement> {
         let start_span = self.current_span();
@@ -616,6 +798,12 @@ impl<'a> Parser<'a> {
             }
         }
 
+        let (term, ops) = if self.precedence {
+            group_by_precedence(term, ops)
+        } else {
+            (term, ops)
+        };
+
         Some(Expression {
             term,
             ops,
@@ -643,16 +831,16 @@ impl<'a> Parser<'a> {
     fn parse_term_inner(&mut self) -> Option<Term> {
         let start_span = self.current_span();
 
-        match self.peek_token().cloned() {
-            Some(Token::IntegerConstant(n)) => {
+        match self.peek_token() {
+            Some(&Token::IntegerConstant(n)) => {
                 self.advance();
                 Some(Term::IntegerConstant(n, start_span))
             }
             Some(Token::StringConstant(s)) => {
                 self.advance();
-                Some(Term::StringConstant(s, start_span))
+                Some(Term::StringConstant(s.to_string(), start_span))
             }
-            Some(Token::Keyword(k)) => {
+            Some(&Token::Keyword(k)) => {
                 if let Some(kc) = KeywordConstant::from_keyword(k) {
                     self.advance();
                     Some(Term::KeywordConstant(kc, start_span))
@@ -670,7 +858,7 @@ impl<'a> Parser<'a> {
                 self.expect_symbol(')');
                 Some(Term::Parenthesized(Box::new(expr), start_span))
             }
-            Some(Token::Symbol(c)) if c == '-' || c == '~' => {
+            Some(&Token::Symbol(c)) if c == '-' || c == '~' => {
                 self.advance();
                 let op = UnaryOp::from_char(c).unwrap();
                 let term = self.parse_term()?;
@@ -678,6 +866,7 @@ impl<'a> Parser<'a> {
             }
             Some(Token::Identifier(name)) => {
                 self.advance();
+                let name = name.to_string();
 
                 match self.peek_symbol() {
                     Some('[') => {
@@ -708,7 +897,7 @@ impl<'a> Parser<'a> {
                         self.expect_symbol(')');
                         Some(Term::SubroutineCall(SubroutineCall {
                             receiver: Some(name),
-                            name: method_name,
+                            name: method_name.to_string(),
                             arguments,
                             span: start_span,
                         }))
@@ -743,9 +932,9 @@ impl<'a> Parser<'a> {
         let (receiver, name) = if self.peek_symbol() == Some('.') {
             self.advance();
             let (method_name, _) = self.expect_identifier()?;
-            (Some(first_name), method_name)
+            (Some(first_name.to_string()), method_name.to_string())
         } else {
-            (None, first_name)
+            (None, first_name.to_string())
         };
 
         self.expect_symbol('(');
@@ -783,6 +972,53 @@ impl<'a> Parser<'a> {
     }
 }
 
+/// Regroup a flat `term (op term)*` chain by operator precedence.
+///
+/// The returned chain only contains the loosest-binding operator present;
+/// each run of tighter-binding operators between them is wrapped, recursively,
+/// in a synthesized parenthesized term. Operators of equal precedence stay
+/// left-associative because a flat chain is evaluated left to right.
+fn group_by_precedence(term: Term, ops: Vec<(BinaryOp, Term)>) -> (Term, Vec<(BinaryOp, Term)>) {
+    let Some(loosest) = ops.iter().map(|(op, _)| op.precedence()).min() else {
+        return (term, ops);
+    };
+    if ops.iter().all(|(op, _)| op.precedence() == loosest) {
+        return (term, ops);
+    }
+
+    let mut first = (term, Vec::new());
+    let mut rest: Vec<(BinaryOp, Run)> = Vec::new();
+    for (op, next) in ops {
+        if op.precedence() == loosest {
+            rest.push((op, (next, Vec::new())));
+        } else {
+            let run = rest.last_mut().map_or(&mut first, |(_, run)| run);
+            run.1.push((op, next));
+        }
+    }
+
+    let rest = rest.into_iter().map(|(op, run)| (op, group_run(run)));
+    (group_run(first), rest.collect())
+}
+
+/// A term followed by tighter-binding operators.
+type Run = (Term, Vec<(BinaryOp, Term)>);
+
+/// Wrap a run with operators in a synthesized parenthesized term.
+fn group_run((term, ops): Run) -> Term {
+    if ops.is_empty() {
+        return term;
+    }
+    let (term, ops) = group_by_precedence(term, ops);
+    let span = term.span().clone();
+    let expr = Expression {
+        term,
+        ops,
+        span: span.clone(),
+    };
+    Term::Parenthesized(Box::new(expr), span)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -827,6 +1063,131 @@ mod tests {
         assert!(matches!(stmts[0], Statement::Let(_)));
     }
 
+    #[test]
+    fn test_let_this_target() {
+        let source = "class Main { method void reset() { let this = null; return; } }";
+        assert!(parse(source).is_err());
+
+        let tokens = JackTokenizer::new(source).tokenize().unwrap();
+        let class = Parser::new(&tokens).with_this_target(true).parse().unwrap();
+        let Statement::Let(s) = &class.subroutine_decs[0].body.statements[0] else {
+            panic!("expected let statement");
+        };
+        assert_eq!(s.var_name, "this");
+    }
+
+    fn parse_extended(input: &str) -> Class {
+        let tokens = JackTokenizer::new(input)
+            .with_extensions(true)
+            .tokenize()
+            .unwrap();
+        Parser::new(&tokens).with_extensions(true).parse().unwrap()
+    }
+
+    #[test]
+    fn test_compound_assign_desugars() {
+        let class =
+            parse_extended("class Main { function void main() { let x -= y + 1; return; } }");
+        let Statement::Let(s) = &class.subroutine_decs[0].body.statements[0] else {
+            panic!("Expected let statement");
+        };
+        assert_eq!(s.compound, Some(BinaryOp::Sub));
+        assert!(s.index.is_none());
+        // x - (y + 1)
+        assert!(matches!(&s.value.term, Term::VarName(n, _) if n == "x"));
+        assert_eq!(s.value.ops.len(), 1);
+        assert_eq!(s.value.ops[0].0, BinaryOp::Sub);
+        assert!(matches!(&s.value.ops[0].1, Term::Parenthesized(e, _) if e.ops.len() == 1));
+    }
+
+    #[test]
+    fn test_compound_assign_array_hoists_index() {
+        let class = parse_extended(
+            "class Main { function void main() { var Array a; let a[i + 1] += 2; let a[3] += 4; return; } }",
+        );
+        let body = &class.subroutine_decs[0].body;
+
+        // One synthesized local for the non-constant index
+        assert_eq!(body.var_decs.len(), 2);
+        assert_eq!(body.var_decs[1].names, vec!["$index0"]);
+        assert!(is_synthetic_name(&body.var_decs[1].names[0]));
+
+        // let $index0 = i + 1; let a[$index0] = a[$index0] + (2);
+        assert_eq!(body.statements.len(), 4);
+        let Statement::Let(hoisted) = &body.statements[0] else {
+            panic!("Expected hoisted let");
+        };
+        assert_eq!(hoisted.var_name, "$index0");
+        assert_eq!(hoisted.value.ops.len(), 1);
+
+        let Statement::Let(s) = &body.statements[1] else {
+            panic!("Expected let statement");
+        };
+        assert_eq!(s.compound, Some(BinaryOp::Add));
+        let index = s.index.as_ref().unwrap();
+        assert!(matches!(&index.term, Term::VarName(n, _) if n == "$index0"));
+        assert!(matches!(&s.value.term, Term::ArrayAccess(n, i, _)
+            if n == "a" && matches!(&i.term, Term::VarName(t, _) if t == "$index0")));
+
+        // Constant index is reused directly
+        let Statement::Let(s) = &body.statements[2] else {
+            panic!("Expected let statement");
+        };
+        assert!(matches!(
+            s.index.as_ref().unwrap().term,
+            Term::IntegerConstant(3, _)
+        ));
+    }
+
+    #[test]
+    fn test_interleaved_var_decs() {
+        let source = "class Main { function void main() { var int a; let a = 1; var int b, c; let b = a; return; } }";
+        let body = &parse_extended(source).subroutine_decs[0].body;
+        let names: Vec<_> = body.var_decs.iter().flat_map(|d| &d.names).collect();
+        assert_eq!(names, ["a", "b", "c"]);
+        assert_eq!(body.statements.len(), 3);
+        // The late declaration keeps its position
+        assert_eq!(body.var_decs[1].span.column, 59);
+
+        assert!(parse(source).is_err());
+        // Only at the top level of the body
+        let nested = "class Main { function void main() { while (true) { var int x; } return; } }";
+        let tokens = JackTokenizer::new(nested).tokenize().unwrap();
+        assert!(Parser::new(&tokens).with_extensions(true).parse().is_err());
+    }
+
+    #[test]
+    fn test_synthetic_locals_reset_per_subroutine() {
+        let source = "class Main { function void main() { var Array a; let a[i] += 1; return; } }";
+        let tokens = JackTokenizer::new(source)
+            .with_extensions(true)
+            .tokenize()
+            .unwrap();
+        let stale = parse_extended("class Main { function void f() { let b[j] += 2; return; } }");
+        let Statement::Let(stale) = stale.subroutine_decs[0].body.statements[0].clone() else {
+            panic!("Expected hoisted let");
+        };
+
+        // As left behind by a subroutine that bailed out mid-statement
+        let mut parser = Parser::new(&tokens).with_extensions(true);
+        parser.synthetic_locals.push(stale.var_name.clone());
+        parser.hoisted = Some(stale);
+        let class = parser.parse().unwrap();
+        let body = &class.subroutine_decs[0].body;
+        assert_eq!(body.var_decs[1].names, vec!["$index0"]);
+        assert_eq!(body.statements.len(), 3);
+        let Statement::Let(hoisted) = &body.statements[0] else {
+            panic!("Expected hoisted let");
+        };
+        assert_eq!(hoisted.var_name, "$index0");
+        assert!(matches!(&hoisted.value.term, Term::VarName(name, _) if name == "i"));
+    }
+
+    #[test]
+    fn test_compound_assign_rejected_without_extensions() {
+        assert!(parse("class Main { function void main() { let x += 1; return; } }").is_err());
+    }
+
     #[test]
     fn test_expression() {
         let class =
@@ -838,4 +1199,103 @@ mod tests {
             panic!("Expected let statement");
         }
     }
+
+    /// Render an expression with every group made explicit.
+    fn show(expr: &Expression) -> String {
+        fn term(t: &Term) -> String {
+            match t {
+                Term::IntegerConstant(n, _) => n.to_string(),
+                Term::VarName(name, _) => name.clone(),
+                Term::Parenthesized(e, _) => format!("({})", show(e)),
+                _ => "?".to_string(),
+            }
+        }
+        let mut out = term(&expr.term);
+        for (op, t) in &expr.ops {
+            out.push_str(&format!(" {} {}", op.as_char(), term(t)));
+        }
+        out
+    }
+
+    fn return_value(input: &str, precedence: bool) -> Expression {
+        let source = format!("class Main {{ function int f() {{ return {input}; }} }}");
+        let tokens = JackTokenizer::new(&source).tokenize().unwrap();
+        let class = Parser::new(&tokens)
+            .with_precedence(precedence)
+            .parse()
+            .unwrap();
+        let Statement::Return(s) = &class.subroutine_decs[0].body.statements[0] else {
+            panic!("expected return statement");
+        };
+        s.value.clone().unwrap()
+    }
+
+    fn tokens(input: &str) -> Vec<SpannedToken<'_>> {
+        JackTokenizer::new(input).tokenize().unwrap()
+    }
+
+    #[test]
+    fn test_expression_snippet() {
+        let expr = Parser::new(&tokens("x + (y) * 2"))
+            .parse_expression_snippet()
+            .unwrap();
+        assert_eq!(show(&expr), "x + (y) * 2");
+
+        let errors = Parser::new(&tokens("x + 1 )"))
+            .parse_expression_snippet()
+            .unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "Syntax error at 1:7: unexpected symbol ')' after end of snippet"
+        );
+        assert!(Parser::new(&[]).parse_expression_snippet().is_err());
+    }
+
+    #[test]
+    fn test_statements_snippet() {
+        let statements = Parser::new(&tokens("let x = 1; do Output.printInt(x);"))
+            .parse_statements_snippet()
+            .unwrap();
+        assert!(matches!(
+            statements[..],
+            [Statement::Let(_), Statement::Do(_)]
+        ));
+        assert!(
+            Parser::new(&tokens(""))
+                .parse_statements_snippet()
+                .unwrap()
+                .is_empty()
+        );
+        assert!(
+            Parser::new(&tokens("let x = 1; var int y;"))
+                .parse_statements_snippet()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_flat_expression_by_default() {
+        assert_eq!(show(&return_value("2 + 3 * 4", false)), "2 + 3 * 4");
+    }
+
+    #[test]
+    fn test_precedence_grouping() {
+        let cases = [
+            ("2 + 3 * 4", "2 + (3 * 4)"),
+            ("2 * 3 + 4", "(2 * 3) + 4"),
+            ("1 - 2 - 3", "1 - 2 - 3"),
+            ("a < b + 1 & c = 2 * d", "(a < (b + 1)) & (c = (2 * d))"),
+            ("(2 + 3) * 4", "(2 + 3) * 4"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(show(&return_value(input, true)), expected, "{input}");
+        }
+    }
+
+    #[test]
+    fn test_synthetic_groups_are_marked() {
+        let expr = return_value("(1 + 2) + 3 * 4", true);
+        assert!(!expr.term.is_synthetic_group());
+        assert!(expr.ops[0].1.is_synthetic_group());
+    }
 }
@@ -0,0 +1,226 @@
+//! Include preprocessing for split teaching fixtures.
+//!
+//! A line consisting of `//!include <path>` is replaced by the contents of
+//! the named file, resolved relative to the including file. Since the
+//! directive is a comment, files using it remain valid Jack on their own.
+//! Includes nest up to [`MAX_INCLUDE_DEPTH`] levels; cycles are an error.
+//!
+//! The combined text is tokenized and parsed as one file, so error spans
+//! refer to its lines. A [`SourceMap`] translates them back to the file and
+//! line they came from; see [`format_errors_with_map`].
+//!
+//! [`format_errors_with_map`]: crate::error::format_errors_with_map
+
+#[cfg(feature = "fs")]
+use crate::error::JackError;
+#[cfg(feature = "fs")]
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The include directive, followed by whitespace and a file path.
+pub const INCLUDE_DIRECTIVE: &str = "//!include";
+
+/// Maximum nesting of includes below the top-level file.
+pub const MAX_INCLUDE_DEPTH: usize = 8;
+
+/// Origin of each line of preprocessed source.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    /// Files in order of first inclusion; the top-level file is first.
+    files: Vec<PathBuf>,
+    /// `(file index, original line)` for each combined line.
+    lines: Vec<(usize, usize)>,
+}
+
+impl SourceMap {
+    /// The file and original line of a (1-based) combined line.
+    pub fn locate(&self, line: usize) -> Option<(&Path, usize)> {
+        let &(file, original) = self.lines.get(line.checked_sub(1)?)?;
+        Some((&self.files[file], original))
+    }
+
+    /// All files that contributed to the source, top-level file first.
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+}
+
+/// Read `path`, splicing in `//!include` files.
+///
+/// Returns the combined source and its [`SourceMap`]. A file without
+/// includes is returned unchanged.
+#[cfg(feature = "fs")]
+pub fn preprocess_source(path: &Path) -> Result<(String, SourceMap), JackError> {
+    let mut preprocessor = Preprocessor::default();
+    let mut stack = Vec::new();
+    let source = preprocessor.include(path, &mut stack)?;
+    if preprocessor.map.files.len() == 1 {
+        return Ok((source, preprocessor.map));
+    }
+    Ok((preprocessor.text, preprocessor.map))
+}
+
+#[cfg(feature = "fs")]
+#[derive(Default)]
+struct Preprocessor {
+    text: String,
+    map: SourceMap,
+}
+
+#[cfg(feature = "fs")]
+impl Preprocessor {
+    /// Append `path` to the combined text, expanding its includes.
+    ///
+    /// `stack` holds the canonical paths of the files being included.
+    /// Returns the file's own text.
+    fn include(&mut self, path: &Path, stack: &mut Vec<PathBuf>) -> Result<String, JackError> {
+        let source = fs::read_to_string(path).map_err(|e| JackError::io(path, e))?;
+        stack.push(fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()));
+        let file = self.map.files.len();
+        self.map.files.push(path.to_path_buf());
+
+        for (i, line) in source.lines().enumerate() {
+            let Some(target) = include_target(line) else {
+                self.text.push_str(line);
+                self.text.push('\n');
+                self.map.lines.push((file, i + 1));
+                continue;
+            };
+            let error = |message: String| JackError::include(path, i + 1, message);
+
+            if target.is_empty() {
+                return Err(error("missing file name".to_string()));
+            }
+            if stack.len() > MAX_INCLUDE_DEPTH {
+                return Err(error(format!(
+                    "includes nested deeper than {MAX_INCLUDE_DEPTH} levels"
+                )));
+            }
+            let target = path.parent().unwrap_or(Path::new("")).join(target);
+            let canonical = fs::canonicalize(&target).unwrap_or_else(|_| target.clone());
+            if let Some(start) = stack.iter().position(|p| *p == canonical) {
+                let cycle: Vec<_> = stack[start..]
+                    .iter()
+                    .chain([&canonical])
+                    .map(|p| p.file_name().unwrap_or(p.as_os_str()).to_string_lossy())
+                    .collect();
+                return Err(error(format!("include cycle: {}", cycle.join(" -> "))));
+            }
+            self.include(&target, stack)?;
+        }
+
+        stack.pop();
+        Ok(source)
+    }
+}
+
+/// The path of an include directive line, if `line` is one.
+#[cfg(feature = "fs")]
+fn include_target(line: &str) -> Option<&str> {
+    let rest = line.trim().strip_prefix(INCLUDE_DIRECTIVE)?;
+    (rest.is_empty() || rest.starts_with(char::is_whitespace)).then(|| rest.trim())
+}
+
+#[cfg(all(test, feature = "fs"))]
+mod tests {
+    use super::*;
+    use crate::analyze_file;
+    use crate::error::format_errors_with_map;
+    use tempfile::TempDir;
+
+    fn write(dir: &TempDir, name: &str, text: &str) -> PathBuf {
+        let path = dir.path().join(name);
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_include_maps_errors_to_included_file() {
+        let dir = TempDir::new().unwrap();
+        let main = write(
+            &dir,
+            "Main.jack",
+            "class Main {\n    //!include Helpers.part\n    function void main() { return; }\n}\n",
+        );
+        write(
+            &dir,
+            "Helpers.part",
+            "function int one() {\n    return 1 +;\n}\n",
+        );
+
+        let (source, map) = preprocess_source(&main).unwrap();
+        assert_eq!(source.lines().count(), 6);
+        assert_eq!(map.files().len(), 2);
+        assert_eq!(map.locate(1), Some((main.as_path(), 1)));
+        assert_eq!(map.locate(3).map(|(_, line)| line), Some(2));
+        assert_eq!(map.locate(5), Some((main.as_path(), 3)));
+
+        let result = analyze_file(&main);
+        assert!(!result.is_ok());
+        let report = format_errors_with_map(
+            &result.errors,
+            &result.source,
+            &result.filename,
+            result.source_map.as_ref(),
+        );
+        assert!(report.contains("Helpers.part:2:"), "{report}");
+        assert!(report.contains("  2 |     return 1 +;"), "{report}");
+    }
+
+    #[test]
+    fn test_file_without_includes_is_unchanged() {
+        let dir = TempDir::new().unwrap();
+        let text = "class Main {\r\n}";
+        let main = write(&dir, "Main.jack", text);
+        let (source, map) = preprocess_source(&main).unwrap();
+        assert_eq!(source, text);
+        assert_eq!(map.locate(2), Some((main.as_path(), 2)));
+    }
+
+    #[test]
+    fn test_include_cycle() {
+        let dir = TempDir::new().unwrap();
+        let main = write(&dir, "Main.jack", "//!include A.part\n");
+        write(&dir, "A.part", "//!include B.part\n");
+        write(&dir, "B.part", "\n//!include A.part\n");
+
+        let err = preprocess_source(&main).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Include error at {}:2: include cycle: A.part -> B.part -> A.part",
+                dir.path().join("B.part").display()
+            )
+        );
+    }
+
+    #[test]
+    fn test_include_depth_limit() {
+        let dir = TempDir::new().unwrap();
+        let main = write(&dir, "Main.jack", "//!include P1.part\n");
+        for i in 1..=MAX_INCLUDE_DEPTH + 1 {
+            write(
+                &dir,
+                &format!("P{i}.part"),
+                &format!("//!include P{}.part\n", i + 1),
+            );
+        }
+        write(&dir, &format!("P{}.part", MAX_INCLUDE_DEPTH + 2), "\n");
+
+        let err = preprocess_source(&main).unwrap_err();
+        assert!(matches!(
+            &err,
+            JackError::Include { path, line: 1, message }
+                if path.ends_with(format!("P{MAX_INCLUDE_DEPTH}.part"))
+                    && message.contains("deeper than 8")
+        ));
+
+        // Exactly at the limit is fine
+        write(
+            &dir,
+            &format!("P{MAX_INCLUDE_DEPTH}.part"),
+            "class Main {}\n",
+        );
+        assert!(preprocess_source(&main).is_ok());
+    }
+}
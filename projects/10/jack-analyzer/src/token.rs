@@ -1,9 +1,15 @@
 //! Token types and source spans for the Jack tokenizer.
+//!
+//! Tokens borrow identifier and string text from the source they were read
+//! from (`Token<'a>`). [`Token::into_owned`] detaches them, for tokens that
+//! must outlive their source.
 
+use std::borrow::Cow;
 use std::fmt;
 
 /// Source location span for error reporting.
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Span {
     pub start: usize,
     pub end: usize,
@@ -30,28 +36,55 @@ impl fmt::Display for Span {
 
 /// A token with its source location.
 #[derive(Debug, Clone, PartialEq)]
-pub struct SpannedToken {
-    pub token: Token,
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SpannedToken<'a> {
+    pub token: Token<'a>,
     pub span: Span,
 }
 
-impl SpannedToken {
-    pub fn new(token: Token, span: Span) -> Self {
+impl<'a> SpannedToken<'a> {
+    pub fn new(token: Token<'a>, span: Span) -> Self {
         Self { token, span }
     }
+
+    /// A copy of this token that owns its text.
+    pub fn into_owned(self) -> SpannedToken<'static> {
+        SpannedToken {
+            token: self.token.into_owned(),
+            span: self.span,
+        }
+    }
 }
 
 /// Jack language token types.
+///
+/// Text is borrowed from the source, except for string constants containing
+/// escape sequences, whose decoded value is owned.
 #[derive(Debug, Clone, PartialEq)]
-pub enum Token {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Token<'a> {
     Keyword(Keyword),
     Symbol(char),
     IntegerConstant(u16),
-    StringConstant(String),
-    Identifier(String),
+    StringConstant(Cow<'a, str>),
+    Identifier(Cow<'a, str>),
+    /// Compound assignment `+=` / `-=` (extension); holds the operator char.
+    CompoundAssign(char),
 }
 
-impl Token {
+impl Token<'_> {
+    /// A copy of this token that owns its text.
+    pub fn into_owned(self) -> Token<'static> {
+        match self {
+            Token::Keyword(k) => Token::Keyword(k),
+            Token::Symbol(c) => Token::Symbol(c),
+            Token::IntegerConstant(n) => Token::IntegerConstant(n),
+            Token::StringConstant(s) => Token::StringConstant(Cow::Owned(s.into_owned())),
+            Token::Identifier(s) => Token::Identifier(Cow::Owned(s.into_owned())),
+            Token::CompoundAssign(c) => Token::CompoundAssign(c),
+        }
+    }
+
     /// Returns the XML tag name for this token type.
     pub fn xml_tag(&self) -> &'static str {
         match self {
@@ -60,6 +93,7 @@ impl Token {
             Token::IntegerConstant(_) => "integerConstant",
             Token::StringConstant(_) => "stringConstant",
             Token::Identifier(_) => "identifier",
+            Token::CompoundAssign(_) => "symbol",
         }
     }
 
@@ -71,11 +105,12 @@ impl Token {
             Token::IntegerConstant(n) => n.to_string(),
             Token::StringConstant(s) => xml_escape(s),
             Token::Identifier(s) => xml_escape(s),
+            Token::CompoundAssign(c) => format!("{}=", c),
         }
     }
 }
 
-impl fmt::Display for Token {
+impl fmt::Display for Token<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Token::Keyword(k) => write!(f, "keyword '{}'", k.as_str()),
@@ -83,6 +118,7 @@ impl fmt::Display for Token {
             Token::IntegerConstant(n) => write!(f, "integer {}", n),
             Token::StringConstant(s) => write!(f, "string \"{}\"", s),
             Token::Identifier(s) => write!(f, "identifier '{}'", s),
+            Token::CompoundAssign(c) => write!(f, "symbol '{}='", c),
         }
     }
 }
@@ -94,6 +130,8 @@ fn xml_escape_char(c: char) -> String {
         '>' => "&gt;".to_string(),
         '&' => "&amp;".to_string(),
         '"' => "&quot;".to_string(),
+        // Only reachable via the `\n` string escape extension; keeps one token per line
+        '\n' => "&#10;".to_string(),
         _ => c.to_string(),
     }
 }
@@ -105,6 +143,7 @@ fn xml_escape(s: &str) -> String {
 
 /// Jack language keywords.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Keyword {
     Class,
     Constructor,
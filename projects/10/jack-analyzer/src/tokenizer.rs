@@ -1,11 +1,12 @@
 //! Lexical analyzer (tokenizer) for the Jack language.
 
+use std::borrow::Cow;
+
 use crate::error::{ErrorAccumulator, JackError};
 use crate::token::{Keyword, Span, SpannedToken, Token, is_symbol};
 
 /// Jack language tokenizer.
 pub struct JackTokenizer<'a> {
-    #[allow(dead_code)]
     input: &'a str,
     chars: Vec<char>,
     pos: usize,
@@ -13,6 +14,7 @@ pub struct JackTokenizer<'a> {
     line: usize,
     column: usize,
     errors: ErrorAccumulator,
+    extensions: bool,
 }
 
 impl<'a> JackTokenizer<'a> {
@@ -26,11 +28,21 @@ impl<'a> JackTokenizer<'a> {
             line: 1,
             column: 1,
             errors: ErrorAccumulator::new(),
+            extensions: false,
         }
     }
 
+    /// Enable non-standard language extensions (e.g. `+=` / `-=`, and the
+    /// `\"`, `\\`, `\n` escapes in string constants).
+    pub fn with_extensions(mut self, enabled: bool) -> Self {
+        self.extensions = enabled;
+        self
+    }
+
     /// Tokenize the input and return tokens or errors.
-    pub fn tokenize(mut self) -> Result<Vec<SpannedToken>, Vec<JackError>> {
+    ///
+    /// Tokens borrow their text from the input.
+    pub fn tokenize(mut self) -> Result<Vec<SpannedToken<'a>>, Vec<JackError>> {
         let mut tokens = Vec::new();
 
         while !self.is_at_end() {
@@ -136,13 +148,21 @@ impl<'a> JackTokenizer<'a> {
     }
 
     /// Parse the next token.
-    fn next_token(&mut self) -> Option<SpannedToken> {
+    fn next_token(&mut self) -> Option<SpannedToken<'a>> {
         let start_pos = self.byte_offset;
         let start_line = self.line;
         let start_column = self.column;
 
         let c = self.peek()?;
 
+        // Compound assignment (extension): '+=' or '-='
+        if self.extensions && (c == '+' || c == '-') && self.peek_next() == Some('=') {
+            self.advance();
+            self.advance();
+            let span = Span::new(start_pos, self.byte_offset, start_line, start_column);
+            return Some(SpannedToken::new(Token::CompoundAssign(c), span));
+        }
+
         // Symbol
         if is_symbol(c) {
             self.advance();
@@ -181,7 +201,7 @@ impl<'a> JackTokenizer<'a> {
         start_pos: usize,
         start_line: usize,
         start_column: usize,
-    ) -> SpannedToken {
+    ) -> SpannedToken<'a> {
         let mut value: u32 = 0;
         let mut overflow = false;
 
@@ -201,9 +221,11 @@ impl<'a> JackTokenizer<'a> {
         let span = Span::new(start_pos, self.byte_offset, start_line, start_column);
 
         if overflow {
+            // The literal as written: `value` saturates for very long ones
+            let literal = &self.input[start_pos..self.byte_offset];
             self.errors.push(JackError::lexical(
                 span.clone(),
-                format!("integer constant {} exceeds maximum value 32767", value),
+                format!("integer constant {literal} is out of range (valid range is 0-32767)"),
             ));
         }
 
@@ -211,30 +233,59 @@ impl<'a> JackTokenizer<'a> {
     }
 
     /// Read a string constant.
+    ///
+    /// Strings end at the closing quote and may not span lines or contain
+    /// control characters. Backslash escapes are an extension (see
+    /// [`read_escape`](Self::read_escape)); without it a backslash is an error.
     fn read_string(
         &mut self,
         start_pos: usize,
         start_line: usize,
         start_column: usize,
-    ) -> Option<SpannedToken> {
+    ) -> Option<SpannedToken<'a>> {
         self.advance(); // Opening quote
 
-        let mut value = String::new();
+        // Borrowed from the input until an escape (or a skipped control
+        // character) makes the value differ from the source text
+        let content_start = self.byte_offset;
+        let mut owned: Option<String> = None;
+        let mut content_end = content_start;
         let mut terminated = false;
 
         while let Some(c) = self.peek() {
             if c == '"' {
+                content_end = self.byte_offset;
                 self.advance();
                 terminated = true;
                 break;
-            } else if c == '\n' {
+            } else if c == '\n' || c == '\r' {
                 // Newline in string - unterminated
                 break;
+            } else if c.is_control() {
+                let (pos, line, column) = (self.byte_offset, self.line, self.column);
+                owned.get_or_insert_with(|| self.input[content_start..pos].to_string());
+                self.advance();
+                let span = Span::new(pos, self.byte_offset, line, column);
+                self.errors.push(JackError::lexical(
+                    span,
+                    format!("control character U+{:04X} in string constant", c as u32),
+                ));
+            } else if c == '\\' {
+                let value = owned
+                    .get_or_insert_with(|| self.input[content_start..self.byte_offset].to_string());
+                if let Some(decoded) = self.read_escape() {
+                    value.push(decoded);
+                }
             } else {
-                value.push(c);
+                if let Some(value) = &mut owned {
+                    value.push(c);
+                }
                 self.advance();
             }
         }
+        if !terminated {
+            content_end = self.byte_offset;
+        }
 
         let span = Span::new(start_pos, self.byte_offset, start_line, start_column);
 
@@ -245,21 +296,55 @@ impl<'a> JackTokenizer<'a> {
             ));
         }
 
+        let value = match owned {
+            Some(value) => Cow::Owned(value),
+            None => Cow::Borrowed(&self.input[content_start..content_end]),
+        };
         Some(SpannedToken::new(Token::StringConstant(value), span))
     }
 
+    /// Read an escape sequence inside a string constant, starting at the
+    /// backslash. Escapes are an extension; without it this is an error.
+    fn read_escape(&mut self) -> Option<char> {
+        let (start_pos, start_line, start_column) = (self.byte_offset, self.line, self.column);
+        self.advance(); // Backslash
+
+        if !self.extensions {
+            let span = Span::new(start_pos, self.byte_offset, start_line, start_column);
+            self.errors.push(JackError::lexical(
+                span,
+                "backslash in string constant (escape sequences require extensions)",
+            ));
+            return None;
+        }
+
+        let decoded = match self.peek() {
+            Some('"') => '"',
+            Some('\\') => '\\',
+            Some('n') => '\n',
+            other => {
+                let span = Span::new(start_pos, self.byte_offset, start_line, start_column);
+                let message = match other {
+                    Some(c) if c != '\n' => format!("unknown escape sequence '\\{}'", c),
+                    _ => "incomplete escape sequence".to_string(),
+                };
+                self.errors.push(JackError::lexical(span, message));
+                return None;
+            }
+        };
+        self.advance();
+        Some(decoded)
+    }
+
     /// Read a keyword or identifier.
     fn read_identifier(
         &mut self,
         start_pos: usize,
         start_line: usize,
         start_column: usize,
-    ) -> SpannedToken {
-        let mut value = String::new();
-
+    ) -> SpannedToken<'a> {
         while let Some(c) = self.peek() {
             if c.is_alphanumeric() || c == '_' {
-                value.push(c);
                 self.advance();
             } else {
                 break;
@@ -267,11 +352,12 @@ impl<'a> JackTokenizer<'a> {
         }
 
         let span = Span::new(start_pos, self.byte_offset, start_line, start_column);
+        let value = &self.input[start_pos..self.byte_offset];
 
-        let token = if let Some(keyword) = Keyword::parse_keyword(&value) {
+        let token = if let Some(keyword) = Keyword::parse_keyword(value) {
             Token::Keyword(keyword)
         } else {
-            Token::Identifier(value)
+            Token::Identifier(Cow::Borrowed(value))
         };
 
         SpannedToken::new(token, span)
@@ -282,7 +368,7 @@ impl<'a> JackTokenizer<'a> {
 mod tests {
     use super::*;
 
-    fn tokenize(input: &str) -> Vec<Token> {
+    fn tokenize(input: &str) -> Vec<Token<'_>> {
         JackTokenizer::new(input)
             .tokenize()
             .unwrap()
@@ -326,31 +412,119 @@ mod tests {
         assert_eq!(tokenize("32767"), vec![Token::IntegerConstant(32767)]);
     }
 
+    #[test]
+    fn test_integer_out_of_range() {
+        let errors = JackTokenizer::new("let x = 32768;").tokenize().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].to_string(),
+            "Lexical error at 1:9: integer constant 32768 is out of range (valid range is 0-32767)"
+        );
+        let span = errors[0].span().unwrap();
+        assert_eq!((span.start, span.end), (8, 13));
+
+        // Too long for any integer type, still shown as written
+        let errors = JackTokenizer::new("123456789012345678901234567890")
+            .tokenize()
+            .unwrap_err();
+        assert!(
+            errors[0]
+                .to_string()
+                .contains("integer constant 123456789012345678901234567890 is out of range")
+        );
+    }
+
     #[test]
     fn test_strings() {
         assert_eq!(
             tokenize("\"hello\""),
-            vec![Token::StringConstant("hello".to_string())]
+            vec![Token::StringConstant("hello".into())]
         );
         assert_eq!(
             tokenize("\"hello world\""),
-            vec![Token::StringConstant("hello world".to_string())]
+            vec![Token::StringConstant("hello world".into())]
         );
     }
 
     #[test]
-    fn test_identifiers() {
-        assert_eq!(tokenize("foo"), vec![Token::Identifier("foo".to_string())]);
-        assert_eq!(
-            tokenize("_bar"),
-            vec![Token::Identifier("_bar".to_string())]
+    fn test_string_escapes_with_extensions() {
+        let tokens: Vec<Token> = JackTokenizer::new(r#""say \"hi\"\n\\""#)
+            .with_extensions(true)
+            .tokenize()
+            .unwrap()
+            .into_iter()
+            .map(|t| t.token)
+            .collect();
+        assert_eq!(tokens, vec![Token::StringConstant("say \"hi\"\n\\".into())]);
+    }
+
+    #[test]
+    fn test_string_backslash_requires_extensions() {
+        let errors = JackTokenizer::new(r#""a\nb""#).tokenize().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].to_string().contains("backslash"));
+        assert_eq!(errors[0].span().unwrap().column, 3);
+    }
+
+    #[test]
+    fn test_string_control_character() {
+        let errors = JackTokenizer::new("let s = \"a\tb\";")
+            .tokenize()
+            .unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].to_string().contains("U+0009"));
+        let span = errors[0].span().unwrap();
+        assert_eq!((span.line, span.column), (1, 11));
+        assert_eq!(span.end - span.start, 1);
+    }
+
+    #[test]
+    fn test_string_unterminated_on_line() {
+        let errors = JackTokenizer::new("x = \"abc\ny\";")
+            .tokenize()
+            .unwrap_err();
+        assert!(
+            errors[0]
+                .to_string()
+                .contains("unterminated string constant")
         );
-        assert_eq!(
-            tokenize("x123"),
-            vec![Token::Identifier("x123".to_string())]
+        let span = errors[0].span().unwrap();
+        assert_eq!((span.line, span.column), (1, 5));
+        assert_eq!(span.end, 8);
+    }
+
+    #[test]
+    fn test_string_with_escaped_backslash() {
+        let tokens: Vec<Token> = JackTokenizer::new(r#""C:\\dir""#)
+            .with_extensions(true)
+            .tokenize()
+            .unwrap()
+            .into_iter()
+            .map(|t| t.token)
+            .collect();
+        assert_eq!(tokens, vec![Token::StringConstant("C:\\dir".into())]);
+    }
+
+    #[test]
+    fn test_string_unknown_escape() {
+        let errors = JackTokenizer::new(r#""a\tb""#)
+            .with_extensions(true)
+            .tokenize()
+            .unwrap_err();
+        assert!(
+            errors[0]
+                .to_string()
+                .contains("unknown escape sequence '\\t'")
         );
     }
 
+    #[test]
+    fn test_identifiers() {
+        assert_eq!(tokenize("foo"), vec![Token::Identifier("foo".into())]);
+        assert_eq!(tokenize("_bar"), vec![Token::Identifier("_bar".into())]);
+        assert_eq!(tokenize("x123"), vec![Token::Identifier("x123".into())]);
+    }
+
     #[test]
     fn test_comments() {
         assert_eq!(
@@ -367,12 +541,57 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_compound_assign_requires_extensions() {
+        assert_eq!(tokenize("+="), vec![Token::Symbol('+'), Token::Symbol('=')]);
+
+        let tokens: Vec<Token> = JackTokenizer::new("x += 1; y -= 2; z + = 3")
+            .with_extensions(true)
+            .tokenize()
+            .unwrap()
+            .into_iter()
+            .map(|t| t.token)
+            .collect();
+        assert_eq!(tokens[1], Token::CompoundAssign('+'));
+        assert_eq!(tokens[5], Token::CompoundAssign('-'));
+        // Separated by whitespace: still two plain symbols
+        assert_eq!(tokens[9], Token::Symbol('+'));
+        assert_eq!(tokens[10], Token::Symbol('='));
+    }
+
     #[test]
     fn test_complex() {
         let input = "class Main { function void main() { return; } }";
         let tokens = tokenize(input);
         assert_eq!(tokens.len(), 13);
         assert_eq!(tokens[0], Token::Keyword(Keyword::Class));
-        assert_eq!(tokens[1], Token::Identifier("Main".to_string()));
+        assert_eq!(tokens[1], Token::Identifier("Main".into()));
+    }
+
+    #[test]
+    fn test_tokens_borrow_from_source() {
+        let tokens: Vec<Token> = JackTokenizer::new(r#"let name = "plain"; let s = "a\nb\"c";"#)
+            .with_extensions(true)
+            .tokenize()
+            .unwrap()
+            .into_iter()
+            .map(|t| t.token)
+            .collect();
+        assert!(matches!(
+            &tokens[1],
+            Token::Identifier(Cow::Borrowed("name"))
+        ));
+        assert!(matches!(
+            &tokens[3],
+            Token::StringConstant(Cow::Borrowed("plain"))
+        ));
+        // Escapes make the value differ from the source text
+        assert!(matches!(
+            &tokens[8],
+            Token::StringConstant(Cow::Owned(s)) if s == "a\nb\"c"
+        ));
+
+        let owned: Vec<Token<'static>> = tokens.into_iter().map(Token::into_owned).collect();
+        assert!(matches!(&owned[1], Token::Identifier(Cow::Owned(s)) if s == "name"));
     }
 }
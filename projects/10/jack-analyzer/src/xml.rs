@@ -4,9 +4,15 @@
 //! - Pre-sized String buffers based on token count estimates
 //! - Direct push_str() instead of format!() macros
 //! - Static string slices for tag names
+//!
+//! [`tokens_to_xml_to`] and [`XmlWriter::write_class_to`] stream the same
+//! output to an [`io::Write`] instead of building it in a `String`.
+
+use std::fmt;
+use std::io::{self, Write};
 
 use crate::ast::*;
-use crate::token::SpannedToken;
+use crate::token::{SpannedToken, Token};
 
 /// Estimated bytes per token in XML output (for buffer pre-allocation).
 const BYTES_PER_TOKEN: usize = 40;
@@ -14,31 +20,186 @@ const BYTES_PER_TOKEN: usize = 40;
 /// Estimated bytes per indent level.
 const BYTES_PER_INDENT: usize = 2;
 
+/// XML output formatting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum XmlStyle {
+    /// The course reference tool's format: two-space indentation in parse
+    /// trees, terminal values padded with one space (`<symbol> ; </symbol>`),
+    /// `<`, `>`, `&` and `"` escaped, LF line endings and a final newline.
+    #[default]
+    Course,
+    /// No indentation and no padding inside terminal tags.
+    Compact,
+}
+
+/// Destination of generated XML text.
+trait Sink {
+    fn push_str(&mut self, text: &str);
+
+    fn push(&mut self, c: char) {
+        self.push_str(c.encode_utf8(&mut [0; 4]));
+    }
+}
+
+impl Sink for String {
+    #[inline]
+    fn push_str(&mut self, text: &str) {
+        String::push_str(self, text);
+    }
+
+    #[inline]
+    fn push(&mut self, c: char) {
+        String::push(self, c);
+    }
+}
+
+/// A buffered writer keeping its first error, so that generation does not
+/// have to check every write.
+struct IoSink<W: Write> {
+    out: io::BufWriter<W>,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> IoSink<W> {
+    fn new(out: W) -> Self {
+        Self {
+            out: io::BufWriter::new(out),
+            error: None,
+        }
+    }
+
+    /// Flush the output, returning the first error of any write.
+    fn finish(mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(error) => Err(error),
+            None => self.out.flush(),
+        }
+    }
+}
+
+impl<W: Write> Sink for IoSink<W> {
+    #[inline]
+    fn push_str(&mut self, text: &str) {
+        if self.error.is_none()
+            && let Err(error) = self.out.write_all(text.as_bytes())
+        {
+            self.error = Some(error);
+        }
+    }
+}
+
+/// Write a terminal element in the given style (zero-allocation).
+#[inline]
+fn push_terminal(output: &mut impl Sink, tag: &str, value: &str, style: XmlStyle) {
+    let (open_pad, close_pad) = match style {
+        XmlStyle::Course => ("> ", " </"),
+        XmlStyle::Compact => (">", "</"),
+    };
+    output.push('<');
+    output.push_str(tag);
+    output.push_str(open_pad);
+    output.push_str(value);
+    output.push_str(close_pad);
+    output.push_str(tag);
+    output.push_str(">\n");
+}
+
 /// Generate token XML output (*T.xml format).
 ///
 /// Uses zero-allocation techniques with pre-sized buffer.
-pub fn tokens_to_xml(tokens: &[SpannedToken]) -> String {
+pub fn tokens_to_xml(tokens: &[SpannedToken], style: XmlStyle) -> String {
     // Pre-allocate: <tokens>\n + tokens + </tokens>\n
     let capacity = 10 + (tokens.len() * BYTES_PER_TOKEN) + 11;
     let mut output = String::with_capacity(capacity);
+    write_tokens(&mut output, tokens, style);
+    output
+}
+
+/// Stream token XML output (*T.xml format) to `out`.
+///
+/// Writes exactly what [`tokens_to_xml`] returns, through a buffer.
+pub fn tokens_to_xml_to<W: Write>(
+    tokens: &[SpannedToken],
+    style: XmlStyle,
+    out: W,
+) -> io::Result<()> {
+    let mut sink = IoSink::new(out);
+    write_tokens(&mut sink, tokens, style);
+    sink.finish()
+}
 
+fn write_tokens(output: &mut impl Sink, tokens: &[SpannedToken], style: XmlStyle) {
     output.push_str("<tokens>\n");
 
     for token in tokens {
-        let tag = token.token.xml_tag();
-        let value = token.token.xml_value();
-        // Write directly without format!()
-        output.push('<');
-        output.push_str(tag);
-        output.push_str("> ");
-        output.push_str(&value);
-        output.push_str(" </");
-        output.push_str(tag);
-        output.push_str(">\n");
+        push_terminal(
+            output,
+            token.token.xml_tag(),
+            &token.token.xml_value(),
+            style,
+        );
     }
 
     output.push_str("</tokens>\n");
-    output
+}
+
+/// First difference found by [`compare_xml_ignoring_whitespace`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    /// 1-based line in the left input, `None` if it ended first.
+    pub left_line: Option<usize>,
+    /// 1-based line in the right input, `None` if it ended first.
+    pub right_line: Option<usize>,
+    /// Trimmed text of the differing left line.
+    pub left: String,
+    /// Trimmed text of the differing right line.
+    pub right: String,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let side = |line: Option<usize>, text: &str| match line {
+            Some(n) => format!("line {}: {}", n, text),
+            None => "end of input".to_string(),
+        };
+        write!(
+            f,
+            "{} != {}",
+            side(self.left_line, &self.left),
+            side(self.right_line, &self.right)
+        )
+    }
+}
+
+/// Compare two XML documents element by element, ignoring all whitespace
+/// (indentation, tag padding, blank lines and line endings), the way the
+/// course's TextComparer does.
+///
+/// Returns the first differing line pair, or `None` if they match.
+pub fn compare_xml_ignoring_whitespace(a: &str, b: &str) -> Option<Mismatch> {
+    fn significant(xml: &str) -> impl Iterator<Item = (usize, &str, String)> {
+        xml.lines().enumerate().filter_map(|(i, line)| {
+            let key: String = line.chars().filter(|c| !c.is_whitespace()).collect();
+            (!key.is_empty()).then(|| (i + 1, line.trim(), key))
+        })
+    }
+
+    let mut left = significant(a);
+    let mut right = significant(b);
+    loop {
+        match (left.next(), right.next()) {
+            (None, None) => return None,
+            (Some((_, _, l)), Some((_, _, r))) if l == r => {}
+            (l, r) => {
+                return Some(Mismatch {
+                    left_line: l.as_ref().map(|(n, ..)| *n),
+                    right_line: r.as_ref().map(|(n, ..)| *n),
+                    left: l.map(|(_, text, _)| text.to_string()).unwrap_or_default(),
+                    right: r.map(|(_, text, _)| text.to_string()).unwrap_or_default(),
+                });
+            }
+        }
+    }
 }
 
 /// XML writer for AST nodes (*.xml format).
@@ -47,9 +208,10 @@ pub fn tokens_to_xml(tokens: &[SpannedToken]) -> String {
 /// - Pre-sized buffer based on token count
 /// - Direct string operations instead of format!()
 /// - Indent string reuse
-pub struct XmlWriter {
-    output: String,
+pub struct XmlWriter<S = String> {
+    output: S,
     indent: usize,
+    style: XmlStyle,
 }
 
 impl XmlWriter {
@@ -58,6 +220,7 @@ impl XmlWriter {
         Self {
             output: String::new(),
             indent: 0,
+            style: XmlStyle::default(),
         }
     }
 
@@ -68,9 +231,16 @@ impl XmlWriter {
         Self {
             output: String::with_capacity(capacity),
             indent: 0,
+            style: XmlStyle::default(),
         }
     }
 
+    /// Set the output style (default [`XmlStyle::Course`]).
+    pub fn with_style(mut self, style: XmlStyle) -> Self {
+        self.style = style;
+        self
+    }
+
     /// Write a class to XML.
     pub fn write_class(mut self, class: &Class, tokens: &[SpannedToken]) -> String {
         // Resize buffer based on actual token count
@@ -84,9 +254,51 @@ impl XmlWriter {
         self.output
     }
 
+    /// Stream a class's XML to `out`.
+    ///
+    /// Writes exactly what [`write_class`](Self::write_class) returns,
+    /// through a buffer, without holding the document in memory.
+    pub fn write_class_to<W: Write>(
+        self,
+        class: &Class,
+        tokens: &[SpannedToken],
+        out: W,
+    ) -> io::Result<()> {
+        let mut writer = XmlWriter {
+            output: IoSink::new(out),
+            indent: 0,
+            style: self.style,
+        };
+        let mut ctx = XmlContext::new(tokens);
+        writer.write_class_impl(class, &mut ctx);
+        writer.output.finish()
+    }
+
+    /// Write a standalone expression to XML.
+    ///
+    /// `tokens` must be exactly the expression's tokens, as accepted by
+    /// [`Parser::parse_expression_snippet`](crate::parser::Parser::parse_expression_snippet).
+    pub fn write_expression_snippet(
+        mut self,
+        expr: &Expression,
+        tokens: &[SpannedToken],
+    ) -> String {
+        let mut ctx = XmlContext::new(tokens);
+        self.write_expression(expr, &mut ctx);
+        self.output
+    }
+}
+
+// `Sink` is an implementation detail: only the `String` and streaming
+// writers above can be built
+#[allow(private_bounds)]
+impl<S: Sink> XmlWriter<S> {
     /// Write indentation directly (no allocation).
     #[inline]
     fn write_indent(&mut self) {
+        if self.style == XmlStyle::Compact {
+            return;
+        }
         for _ in 0..self.indent {
             self.output.push_str("  ");
         }
@@ -116,13 +328,7 @@ impl XmlWriter {
     #[inline]
     fn write_terminal(&mut self, tag: &str, value: &str) {
         self.write_indent();
-        self.output.push('<');
-        self.output.push_str(tag);
-        self.output.push_str("> ");
-        self.output.push_str(value);
-        self.output.push_str(" </");
-        self.output.push_str(tag);
-        self.output.push_str(">\n");
+        push_terminal(&mut self.output, tag, value, self.style);
     }
 
     /// Write a token from the context.
@@ -229,6 +435,10 @@ impl XmlWriter {
         self.write_token(ctx);
 
         for dec in &body.var_decs {
+            // Locals synthesized by the parser have no source tokens
+            if dec.names.iter().all(|n| is_synthetic_name(n)) {
+                continue;
+            }
             self.write_var_dec(dec, ctx);
         }
 
@@ -267,6 +477,7 @@ impl XmlWriter {
 
         for stmt in statements {
             match stmt {
+                Statement::Let(s) if is_synthetic_name(&s.var_name) => {}
                 Statement::Let(s) => self.write_let_statement(s, ctx),
                 Statement::If(s) => self.write_if_statement(s, ctx),
                 Statement::While(s) => self.write_while_statement(s, ctx),
@@ -281,6 +492,19 @@ impl XmlWriter {
     fn write_let_statement(&mut self, stmt: &LetStatement, ctx: &mut XmlContext) {
         self.open_tag("letStatement");
 
+        // Compound assignment is an extension with no reference grammar and a
+        // desugared AST, so its tokens are written flat up to the ';'.
+        if stmt.compound.is_some() {
+            while let Some(token) = ctx.advance() {
+                self.write_terminal(token.token.xml_tag(), &token.token.xml_value());
+                if token.token == Token::Symbol(';') {
+                    break;
+                }
+            }
+            self.close_tag("letStatement");
+            return;
+        }
+
         // 'let'
         self.write_token(ctx);
         // varName
@@ -421,6 +645,11 @@ impl XmlWriter {
             Term::SubroutineCall(call) => {
                 self.write_subroutine_call(call, ctx);
             }
+            // Precedence-mode grouping has no source tokens; the nesting
+            // alone shows the effective grouping.
+            Term::Parenthesized(expr, _) if term.is_synthetic_group() => {
+                self.write_expression(expr, ctx);
+            }
             Term::Parenthesized(expr, _) => {
                 // '('
                 self.write_token(ctx);
@@ -480,17 +709,17 @@ impl Default for XmlWriter {
 
 /// Context for tracking token position during XML generation.
 struct XmlContext<'a> {
-    tokens: &'a [SpannedToken],
+    tokens: &'a [SpannedToken<'a>],
     pos: usize,
 }
 
 impl<'a> XmlContext<'a> {
-    fn new(tokens: &'a [SpannedToken]) -> Self {
+    fn new(tokens: &'a [SpannedToken<'a>]) -> Self {
         Self { tokens, pos: 0 }
     }
 
     #[inline]
-    fn advance(&mut self) -> Option<&'a SpannedToken> {
+    fn advance(&mut self) -> Option<&'a SpannedToken<'a>> {
         if self.pos < self.tokens.len() {
             let token = &self.tokens[self.pos];
             self.pos += 1;
@@ -510,7 +739,7 @@ mod tests {
     #[test]
     fn test_tokens_to_xml() {
         let tokens = JackTokenizer::new("class Main { }").tokenize().unwrap();
-        let xml = tokens_to_xml(&tokens);
+        let xml = tokens_to_xml(&tokens, XmlStyle::Course);
         assert!(xml.contains("<keyword> class </keyword>"));
         assert!(xml.contains("<identifier> Main </identifier>"));
         assert!(xml.contains("<symbol> { </symbol>"));
@@ -527,6 +756,108 @@ mod tests {
         assert!(xml.contains("<keyword> class </keyword>"));
     }
 
+    #[test]
+    fn test_compound_assign_xml_stays_in_sync() {
+        let input = "class Main { function void main() { var Array a; let a[i] += 1; return; } }";
+        let tokens = JackTokenizer::new(input)
+            .with_extensions(true)
+            .tokenize()
+            .unwrap();
+        let class = Parser::new(&tokens).parse().unwrap();
+        let xml = XmlWriter::new().write_class(&class, &tokens);
+        assert!(xml.contains("<symbol> += </symbol>"));
+        assert!(!xml.contains("$index"));
+        assert!(xml.contains("<returnStatement>\n"));
+        assert_eq!(xml.matches("<letStatement>").count(), 1);
+        assert!(xml.trim_end().ends_with("</class>"));
+    }
+
+    #[test]
+    fn test_string_escape_xml() {
+        let tokens = JackTokenizer::new(r#""a\"b\nc""#)
+            .with_extensions(true)
+            .tokenize()
+            .unwrap();
+        let xml = tokens_to_xml(&tokens, XmlStyle::Course);
+        assert!(xml.contains("<stringConstant> a&quot;b&#10;c </stringConstant>"));
+    }
+
+    #[test]
+    fn test_compact_style() {
+        let source = "class Main { function void main() { return; } }";
+        let tokens = JackTokenizer::new(source).tokenize().unwrap();
+        let token_xml = tokens_to_xml(&tokens, XmlStyle::Compact);
+        assert!(token_xml.contains("<keyword>class</keyword>\n"));
+
+        let class = Parser::new(&tokens).parse().unwrap();
+        let xml = XmlWriter::new()
+            .with_style(XmlStyle::Compact)
+            .write_class(&class, &tokens);
+        assert!(xml.contains("<subroutineDec>\n<keyword>function</keyword>\n"));
+        assert!(!xml.contains("  "));
+
+        let course = XmlWriter::new().write_class(&class, &tokens);
+        assert_eq!(compare_xml_ignoring_whitespace(&xml, &course), None);
+    }
+
+    #[test]
+    fn test_streaming_matches_string_output() {
+        let source = "class Main { field int x; method int f(int a) { let x = a < 5; return x; } }";
+        let tokens = JackTokenizer::new(source).tokenize().unwrap();
+        let class = Parser::new(&tokens).parse().unwrap();
+
+        for style in [XmlStyle::Course, XmlStyle::Compact] {
+            let mut token_xml = Vec::new();
+            tokens_to_xml_to(&tokens, style, &mut token_xml).unwrap();
+            assert_eq!(token_xml, tokens_to_xml(&tokens, style).into_bytes());
+
+            let mut xml = Vec::new();
+            let writer = XmlWriter::new().with_style(style);
+            writer.write_class_to(&class, &tokens, &mut xml).unwrap();
+            let expected = XmlWriter::new()
+                .with_style(style)
+                .write_class(&class, &tokens);
+            assert_eq!(String::from_utf8(xml).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_streaming_reports_write_error() {
+        struct Failing;
+        impl Write for Failing {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::Error::other("disk full"))
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let tokens = JackTokenizer::new("class Main { }").tokenize().unwrap();
+        let class = Parser::new(&tokens).parse().unwrap();
+        let err = XmlWriter::new()
+            .write_class_to(&class, &tokens, Failing)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "disk full");
+        assert!(tokens_to_xml_to(&tokens, XmlStyle::Course, Failing).is_err());
+    }
+
+    #[test]
+    fn test_compare_xml_reports_first_mismatch() {
+        let a = "<tokens>\n<symbol> { </symbol>\n<symbol> } </symbol>\n</tokens>\n";
+        let b = "<tokens>\r\n\r\n  <symbol>{</symbol>\r\n  <symbol> ; </symbol>\r\n</tokens>\r\n";
+        let mismatch = compare_xml_ignoring_whitespace(a, b).unwrap();
+        assert_eq!(mismatch.left_line, Some(3));
+        assert_eq!(mismatch.right_line, Some(4));
+        assert_eq!(
+            mismatch.to_string(),
+            "line 3: <symbol> } </symbol> != line 4: <symbol> ; </symbol>"
+        );
+
+        let truncated = compare_xml_ignoring_whitespace(a, "<tokens>\n").unwrap();
+        assert_eq!(truncated.right_line, None);
+    }
+
     #[test]
     fn test_xml_escaping() {
         let tokens = JackTokenizer::new(
@@ -534,7 +865,7 @@ mod tests {
         )
         .tokenize()
         .unwrap();
-        let xml = tokens_to_xml(&tokens);
+        let xml = tokens_to_xml(&tokens, XmlStyle::Course);
         assert!(xml.contains("<symbol> &lt; </symbol>"));
     }
 
@@ -553,7 +884,7 @@ mod tests {
     fn test_pre_allocation() {
         // Verify that pre-allocation reduces reallocations
         let tokens = JackTokenizer::new("class Main { }").tokenize().unwrap();
-        let xml = tokens_to_xml(&tokens);
+        let xml = tokens_to_xml(&tokens, XmlStyle::Course);
         // Output should fit in pre-allocated buffer (no reallocation needed)
         assert!(xml.len() < tokens.len() * BYTES_PER_TOKEN + 21);
     }
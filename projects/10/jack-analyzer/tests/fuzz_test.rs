@@ -488,6 +488,9 @@ proptest! {
         let source = format!("class Test {{ function void main() {{ let x = {}; return; }} }}", n);
         let result = analyze_source(&source, "Test.jack");
         prop_assert!(!result.is_ok(), "Integer {} out of range should fail", n);
+        let message = result.errors[0].to_string();
+        prop_assert!(message.contains(&format!("integer constant {n} is out of range")), "{}", message);
+        prop_assert!(message.contains("0-32767"), "{}", message);
     }
 
     /// Test that empty class body works
@@ -0,0 +1,144 @@
+//! Compare Course-style XML output against the checked-in reference files.
+
+use jack_analyzer::xml::compare_xml_ignoring_whitespace;
+use jack_analyzer::{
+    AnalyzeOptions, FileOrder, analyze_directory, analyze_directory_to,
+    analyze_directory_with_options, analyze_directory_with_progress, analyze_file,
+};
+use std::fs;
+use std::path::Path;
+
+/// Check one generated document against its `.expected` reference.
+///
+/// The whitespace-insensitive comparison reports the first differing element;
+/// the exact comparison then pins the Course formatting byte for byte (some
+/// reference files were checked in with CRLF line endings).
+fn check_against_reference(actual: &str, reference: &Path) {
+    let expected = fs::read_to_string(reference)
+        .unwrap_or_else(|e| panic!("Failed to read {}: {}", reference.display(), e));
+
+    if let Some(mismatch) = compare_xml_ignoring_whitespace(actual, &expected) {
+        panic!("{}: {}", reference.display(), mismatch);
+    }
+    assert_eq!(
+        actual,
+        expected.replace("\r\n", "\n"),
+        "{}: formatting differs from reference",
+        reference.display()
+    );
+}
+
+/// Analyze every class in a fixture directory and compare the outputs.
+fn check_fixture(dir: &str, classes: &[&str], check_tokens: bool) {
+    let dir = Path::new("..").join(dir);
+    for class in classes {
+        let result = analyze_file(&dir.join(format!("{}.jack", class)));
+        assert!(result.is_ok(), "{}: {:?}", class, result.errors);
+
+        check_against_reference(
+            &result.parse_xml,
+            &dir.join(format!("{}.xml.expected", class)),
+        );
+        if check_tokens {
+            check_against_reference(
+                &result.token_xml,
+                &dir.join(format!("{}T.xml.expected", class)),
+            );
+        }
+    }
+}
+
+#[test]
+fn test_square_matches_reference() {
+    check_fixture("Square", &["Main", "Square", "SquareGame"], true);
+}
+
+#[test]
+fn test_expressionless_square_matches_reference() {
+    check_fixture(
+        "ExpressionLessSquare",
+        &["Main", "Square", "SquareGame"],
+        true,
+    );
+}
+
+#[test]
+fn test_array_test_matches_reference() {
+    // ArrayTest ships without a token reference
+    check_fixture("ArrayTest", &["Main"], false);
+}
+
+#[test]
+fn test_streamed_files_match_reference() {
+    let dir = Path::new("../Square");
+    let output = tempfile::tempdir().unwrap();
+    let results = analyze_directory_to(dir, output.path(), AnalyzeOptions::default());
+    assert_eq!(results.len(), 3);
+    for result in &results {
+        assert!(result.is_ok(), "{}: {:?}", result.filename, result.errors);
+        // Streamed, not kept
+        assert!(result.token_xml.is_empty() && result.parse_xml.is_empty());
+        let class = result.filename.strip_suffix(".jack").unwrap();
+        for name in [format!("{class}.xml"), format!("{class}T.xml")] {
+            let actual = fs::read_to_string(output.path().join(&name)).unwrap();
+            check_against_reference(&actual, &dir.join(format!("{name}.expected")));
+        }
+    }
+}
+
+#[test]
+fn test_directory_on_dedicated_pool_matches_global_pool() {
+    let dir = Path::new("../Square");
+    let expected = analyze_directory(dir);
+    for threads in [1, 2] {
+        let options = AnalyzeOptions {
+            threads: Some(threads),
+            ..Default::default()
+        };
+        let results = analyze_directory_with_options(dir, options);
+        assert_eq!(results.len(), expected.len());
+        for (result, expected) in results.iter().zip(&expected) {
+            assert_eq!(result.filename, expected.filename);
+            assert_eq!(result.parse_xml, expected.parse_xml);
+        }
+    }
+}
+
+#[test]
+fn test_directory_progress_reports_each_file() {
+    let seen = std::sync::Mutex::new(Vec::new());
+    let results = analyze_directory_with_progress(
+        Path::new("../Square"),
+        AnalyzeOptions::default(),
+        |filename| seen.lock().unwrap().push(filename.to_string()),
+    );
+
+    let mut seen = seen.into_inner().unwrap();
+    seen.sort();
+    let mut expected: Vec<_> = results.iter().map(|r| r.filename.clone()).collect();
+    expected.sort();
+    assert_eq!(seen, expected);
+    assert_eq!(seen, ["Main.jack", "Square.jack", "SquareGame.jack"]);
+}
+
+#[test]
+fn test_directory_results_follow_file_order() {
+    let filenames = |file_order| {
+        let options = AnalyzeOptions {
+            file_order,
+            ..Default::default()
+        };
+        analyze_directory_with_options(Path::new("../Square"), options)
+            .into_iter()
+            .map(|r| r.filename)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        filenames(FileOrder::Alphabetical),
+        ["Main.jack", "Square.jack", "SquareGame.jack"]
+    );
+    assert_eq!(
+        filenames(FileOrder::SysMainFirst),
+        ["Main.jack", "Square.jack", "SquareGame.jack"]
+    );
+}
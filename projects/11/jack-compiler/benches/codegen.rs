@@ -0,0 +1,123 @@
+//! Code generation benchmarks.
+//!
+//! Run with `cargo bench`; the allocations of one code generation pass over
+//! a large class are printed first (see [`alloc_counter`]), covering the
+//! code generator and symbol table together. The `symbol_table` group times
+//! defining and looking up a few hundred fields and locals on its own.
+
+use alloc_counter::CountingAllocator;
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use jack_analyzer::ast::{Class, Type};
+use jack_analyzer::token::Span;
+use jack_compiler::{CodeGenerator, CompileOptions, SymbolKind, SymbolTable};
+use std::hint::black_box;
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+const METHODS: usize = 200;
+
+/// One class with many methods using locals, fields, loops and calls.
+fn big_class() -> Class {
+    let mut source =
+        String::from("class Big {\n    field int count, total;\n    field Array data;\n");
+    for i in 0..METHODS {
+        source.push_str(&format!(
+            "    method int m{i}(int a, int b) {{
+        var int i, sum;
+        var Big other;
+        let i = 0;
+        let sum = total;
+        while (i < a) {{
+            if (i > b) {{
+                let sum = sum + data[i];
+            }} else {{
+                let count = count - sum;
+            }}
+            let sum = other.m{}(i, sum);
+            do Output.printInt(sum);
+            let i = i + 1;
+        }}
+        return sum;
+    }}
+",
+            (i + 1) % METHODS
+        ));
+    }
+    source.push_str("}\n");
+    jack_analyzer::analyze_source(&source, "Big")
+        .class
+        .expect("benchmark class parses")
+}
+
+fn compile(class: &Class) -> String {
+    CodeGenerator::compile_with_config(class, &CompileOptions::default())
+        .result
+        .unwrap()
+}
+
+fn bench_codegen(c: &mut Criterion) {
+    let class = big_class();
+
+    let (vm_code, allocations) = alloc_counter::count(|| compile(&class));
+    black_box(vm_code);
+    println!("codegen/{METHODS}_methods: {allocations} allocations per class");
+
+    let mut group = c.benchmark_group("codegen");
+    group.throughput(Throughput::Elements(METHODS as u64));
+    group.bench_function(format!("{METHODS}_methods"), |b| {
+        b.iter(|| compile(black_box(&class)))
+    });
+    group.finish();
+}
+
+const FIELDS: usize = 300;
+const SUBROUTINES: usize = 50;
+const LOCALS: usize = 20;
+
+/// Define `FIELDS` fields, then for each of `SUBROUTINES` subroutines define
+/// `LOCALS` locals (some shadowing fields) and look every name up twice.
+fn symbol_workload(table: &mut SymbolTable, fields: &[String], locals: &[String]) -> usize {
+    let span = Span::new(0, 0, 1, 1);
+    table.start_class("Big");
+    for name in fields {
+        let _ = table.define(name, Type::Int, SymbolKind::Field, span.clone());
+    }
+    let mut found = 0;
+    for _ in 0..SUBROUTINES {
+        table.start_subroutine();
+        for name in locals {
+            let _ = table.define(name, Type::Int, SymbolKind::Local, span.clone());
+        }
+        for name in locals.iter().chain(fields).chain(locals) {
+            found += table
+                .lookup(name)
+                .map_or(0, |symbol| usize::from(symbol.index));
+        }
+    }
+    found
+}
+
+fn bench_symbol_table(c: &mut Criterion) {
+    let fields: Vec<String> = (0..FIELDS).map(|i| format!("field{i}")).collect();
+    // Every other local shadows a field
+    let locals: Vec<String> = (0..LOCALS)
+        .map(|i| match i % 2 {
+            0 => format!("field{i}"),
+            _ => format!("local{i}"),
+        })
+        .collect();
+
+    let mut group = c.benchmark_group("symbol_table");
+    group.throughput(Throughput::Elements(
+        (FIELDS + SUBROUTINES * (LOCALS * 3 + FIELDS)) as u64,
+    ));
+    group.bench_function(format!("{FIELDS}_fields"), |b| {
+        let mut table = SymbolTable::new();
+        b.iter(|| symbol_workload(&mut table, black_box(&fields), black_box(&locals)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_codegen, bench_symbol_table);
+criterion_main!(benches);
@@ -0,0 +1,411 @@
+//! Assignment analyses for local variables and fields.
+//!
+//! [`unassigned_reads`] is a definite-assignment analysis.
+//! [`uninitialized_fields`] runs the same analysis over a constructor's fields.
+//! [`constant_array_lengths`] finds arrays whose length is known statically.
+//!
+//! Jack does not initialize locals, so reading one before any `let` on the
+//! path to the read yields whatever the previous call frame left behind. The
+//! analysis is deliberately simple: `if` branches are joined by intersection
+//! (a local assigned in only one branch is "maybe unassigned"), `while`
+//! bodies may not run, and nothing is assigned by a loop for the code after
+//! it. Arguments and class variables are always considered assigned.
+
+use std::collections::{HashMap, HashSet};
+
+use jack_analyzer::ast::{Expression, Statement, SubroutineCall, SubroutineDec, Term};
+use jack_analyzer::token::Span;
+
+use crate::optimizer::ConstantFolder;
+
+/// Find reads of locals that are not assigned on every path to the read.
+///
+/// Each local is reported at most once, at its first offending read.
+pub fn unassigned_reads(sub: &SubroutineDec) -> Vec<(String, Span)> {
+    let mut checker = Checker::new(
+        sub.body
+            .var_decs
+            .iter()
+            .flat_map(|dec| dec.names.iter().map(String::as_str))
+            .collect(),
+    );
+    checker.statements(&sub.body.statements, Some(HashSet::new()));
+    checker.reads
+}
+
+/// Find the `fields` a constructor does not assign on every path to its end.
+///
+/// Fields hidden by a parameter or local of the same name are not tracked.
+/// Returns `None` when the constructor calls a method of its own class (a
+/// call without receiver): the method may initialize fields, which this
+/// analysis cannot see.
+pub fn uninitialized_fields<'a>(
+    sub: &'a SubroutineDec,
+    fields: &[&'a str],
+) -> Option<Vec<&'a str>> {
+    let shadowed: HashSet<&str> = sub
+        .parameters
+        .iter()
+        .map(|param| param.name.as_str())
+        .chain(
+            sub.body
+                .var_decs
+                .iter()
+                .flat_map(|dec| dec.names.iter().map(String::as_str)),
+        )
+        .collect();
+    let mut checker = Checker::new(
+        fields
+            .iter()
+            .copied()
+            .filter(|field| !shadowed.contains(field))
+            .collect(),
+    );
+    let end = checker.statements(&sub.body.statements, Some(HashSet::new()));
+    if checker.calls_own_method {
+        return None;
+    }
+
+    // A path that never returns (an endless loop) initializes nothing it exits
+    let exits: Vec<_> = checker.exits.into_iter().chain(end).collect();
+    let missing = fields
+        .iter()
+        .copied()
+        .filter(|field| checker.tracked.contains(field))
+        .filter(|field| exits.iter().any(|assigned| !assigned.contains(field)))
+        .collect();
+    Some(missing)
+}
+
+/// Find locals assigned exactly once, from `Array.new(k)` with a constant `k`.
+///
+/// Returns the length `k` of each such array. This deliberately narrow
+/// pattern is the only case where bounds checks know the array length.
+pub fn constant_array_lengths(sub: &SubroutineDec) -> HashMap<String, u16> {
+    let locals: HashSet<&str> = sub
+        .body
+        .var_decs
+        .iter()
+        .flat_map(|dec| dec.names.iter().map(String::as_str))
+        .collect();
+
+    // local -> constant length of each plain assignment (`None` if not one)
+    let mut assignments: HashMap<&str, Vec<Option<u16>>> = HashMap::new();
+    let mut pending: Vec<&Statement> = sub.body.statements.iter().collect();
+    while let Some(stmt) = pending.pop() {
+        match stmt {
+            Statement::Let(s) if s.index.is_none() && locals.contains(s.var_name.as_str()) => {
+                assignments
+                    .entry(&s.var_name)
+                    .or_default()
+                    .push(array_new_length(&s.value));
+            }
+            Statement::If(s) => {
+                pending.extend(&s.then_statements);
+                pending.extend(s.else_statements.iter().flatten());
+            }
+            Statement::While(s) => pending.extend(&s.statements),
+            _ => {}
+        }
+    }
+
+    assignments
+        .into_iter()
+        .filter_map(|(name, lengths)| match lengths[..] {
+            [Some(length)] => Some((name.to_string(), length)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The constant `k` of an `Array.new(k)` expression.
+fn array_new_length(expr: &Expression) -> Option<u16> {
+    let Term::SubroutineCall(call) = &expr.term else {
+        return None;
+    };
+    if !expr.ops.is_empty()
+        || call.receiver.as_deref() != Some("Array")
+        || call.name != "new"
+        || call.arguments.len() != 1
+    {
+        return None;
+    }
+    let length = ConstantFolder::fold_expression(&call.arguments[0])?;
+    u16::try_from(length).ok()
+}
+
+/// Locals definitely assigned at a program point; `None` when unreachable.
+type Assigned<'a> = Option<HashSet<&'a str>>;
+
+struct Checker<'a> {
+    /// Names whose assignment is tracked: locals, or a constructor's fields.
+    tracked: HashSet<&'a str>,
+    reported: HashSet<&'a str>,
+    reads: Vec<(String, Span)>,
+    /// Assigned names at each `return`.
+    exits: Vec<HashSet<&'a str>>,
+    /// Whether a method of the current class is called (no receiver).
+    calls_own_method: bool,
+}
+
+impl<'a> Checker<'a> {
+    fn new(tracked: HashSet<&'a str>) -> Self {
+        Self {
+            tracked,
+            reported: HashSet::new(),
+            reads: Vec::new(),
+            exits: Vec::new(),
+            calls_own_method: false,
+        }
+    }
+
+    fn statements(&mut self, stmts: &'a [Statement], mut state: Assigned<'a>) -> Assigned<'a> {
+        for stmt in stmts {
+            state = self.statement(stmt, state);
+        }
+        state
+    }
+
+    fn statement(&mut self, stmt: &'a Statement, state: Assigned<'a>) -> Assigned<'a> {
+        let Some(mut assigned) = state else {
+            // Unreachable code reads nothing.
+            return None;
+        };
+        match stmt {
+            Statement::Let(s) => {
+                if let Some(index) = &s.index {
+                    self.read(&s.var_name, &s.span, &assigned);
+                    self.expression(index, &assigned);
+                }
+                self.expression(&s.value, &assigned);
+                if s.index.is_none() && self.tracked.contains(s.var_name.as_str()) {
+                    assigned.insert(&s.var_name);
+                }
+                Some(assigned)
+            }
+            Statement::If(s) => {
+                self.expression(&s.condition, &assigned);
+                let then = self.statements(&s.then_statements, Some(assigned.clone()));
+                let otherwise = match &s.else_statements {
+                    Some(stmts) => self.statements(stmts, Some(assigned)),
+                    None => Some(assigned),
+                };
+                join(then, otherwise)
+            }
+            Statement::While(s) => {
+                self.expression(&s.condition, &assigned);
+                self.statements(&s.statements, Some(assigned.clone()));
+                Some(assigned)
+            }
+            Statement::Do(s) => {
+                self.call(&s.call, &assigned);
+                Some(assigned)
+            }
+            Statement::Return(s) => {
+                if let Some(value) = &s.value {
+                    self.expression(value, &assigned);
+                }
+                self.exits.push(assigned);
+                None
+            }
+        }
+    }
+
+    fn expression(&mut self, expr: &'a Expression, assigned: &HashSet<&'a str>) {
+        self.term(&expr.term, assigned);
+        for (_, term) in &expr.ops {
+            self.term(term, assigned);
+        }
+    }
+
+    fn term(&mut self, term: &'a Term, assigned: &HashSet<&'a str>) {
+        match term {
+            Term::VarName(name, span) => self.read(name, span, assigned),
+            Term::ArrayAccess(name, index, span) => {
+                self.read(name, span, assigned);
+                self.expression(index, assigned);
+            }
+            Term::SubroutineCall(call) => self.call(call, assigned),
+            Term::Parenthesized(inner, _) => self.expression(inner, assigned),
+            Term::UnaryOp(_, inner, _) => self.term(inner, assigned),
+            Term::IntegerConstant(..) | Term::StringConstant(..) | Term::KeywordConstant(..) => {}
+        }
+    }
+
+    fn call(&mut self, call: &'a SubroutineCall, assigned: &HashSet<&'a str>) {
+        match &call.receiver {
+            Some(receiver) => self.read(receiver, &call.span, assigned),
+            None => self.calls_own_method = true,
+        }
+        for arg in &call.arguments {
+            self.expression(arg, assigned);
+        }
+    }
+
+    fn read(&mut self, name: &'a str, span: &Span, assigned: &HashSet<&'a str>) {
+        if self.tracked.contains(name) && !assigned.contains(name) && self.reported.insert(name) {
+            self.reads.push((name.to_string(), span.clone()));
+        }
+    }
+}
+
+/// Merge the states at the end of two branches.
+fn join<'a>(a: Assigned<'a>, b: Assigned<'a>) -> Assigned<'a> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.intersection(&b).copied().collect()),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jack_analyzer::parser::Parser;
+    use jack_analyzer::tokenizer::JackTokenizer;
+
+    fn unassigned(body: &str) -> Vec<String> {
+        let source = format!("class Main {{ function int f(int a) {{ {body} }} }}");
+        let tokens = JackTokenizer::new(&source).tokenize().unwrap();
+        let class = Parser::new(&tokens).parse().unwrap();
+        unassigned_reads(&class.subroutine_decs[0])
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    fn uninitialized(constructor: &str) -> Option<Vec<String>> {
+        let source = format!(
+            "class Point {{ field int x, y; {constructor} method void init() {{ return; }} }}"
+        );
+        let tokens = JackTokenizer::new(&source).tokenize().unwrap();
+        let class = Parser::new(&tokens).parse().unwrap();
+        let missing = uninitialized_fields(&class.subroutine_decs[0], &["x", "y"])?;
+        Some(missing.into_iter().map(String::from).collect())
+    }
+
+    fn lengths(body: &str) -> Vec<(String, u16)> {
+        let source = format!("class Main {{ function int f(int a) {{ {body} }} }}");
+        let tokens = JackTokenizer::new(&source).tokenize().unwrap();
+        let class = Parser::new(&tokens).parse().unwrap();
+        let mut lengths: Vec<_> = constant_array_lengths(&class.subroutine_decs[0])
+            .into_iter()
+            .collect();
+        lengths.sort();
+        lengths
+    }
+
+    #[test]
+    fn test_constant_array_lengths() {
+        assert_eq!(
+            lengths(
+                "var Array v, w; let v = Array.new(2 * 5); if (a) { let w = Array.new(3); } return 0;"
+            ),
+            [("v".to_string(), 10), ("w".to_string(), 3)]
+        );
+        // Reassigned, non-constant, or not a local
+        assert!(
+            lengths("var Array v; let v = Array.new(2); let v = Array.new(2); return 0;")
+                .is_empty()
+        );
+        assert!(lengths("var Array v; let v = Array.new(a); return 0;").is_empty());
+        assert!(lengths("var Array v; let v = Array.new(2) + 1; return 0;").is_empty());
+        assert!(lengths("let a = Array.new(2); return 0;").is_empty());
+    }
+
+    #[test]
+    fn test_uninitialized_return() {
+        assert_eq!(unassigned("var int x; return x;"), ["x"]);
+    }
+
+    #[test]
+    fn test_assigned_before_read() {
+        assert!(unassigned("var int x; let x = a; return x + a;").is_empty());
+        assert!(
+            unassigned("var Array v; let v = Array.new(2); let v[0] = 1; return v[0];").is_empty()
+        );
+    }
+
+    #[test]
+    fn test_self_referencing_assignment() {
+        assert_eq!(unassigned("var int x; let x = x + 1; return x;"), ["x"]);
+    }
+
+    #[test]
+    fn test_branches_join_conservatively() {
+        assert_eq!(
+            unassigned("var int x; if (a) { let x = 1; } return x;"),
+            ["x"]
+        );
+        assert!(
+            unassigned("var int x; if (a) { let x = 1; } else { let x = 2; } return x;").is_empty()
+        );
+        assert!(
+            unassigned("var int x; if (a) { return 0; } else { let x = 2; } return x;").is_empty()
+        );
+    }
+
+    #[test]
+    fn test_loop_body_may_not_run() {
+        assert_eq!(
+            unassigned("var int x; while (a) { let x = 1; } return x;"),
+            ["x"]
+        );
+    }
+
+    #[test]
+    fn test_receiver_and_index_are_reads() {
+        assert_eq!(
+            unassigned("var Array v; var int i; let v[i] = 1; return 0;"),
+            ["v", "i"]
+        );
+    }
+
+    #[test]
+    fn test_constructor_initializing_all_fields() {
+        assert_eq!(
+            uninitialized(
+                "constructor Point new(int a) { let x = a; if (a) { let y = 1; } else { let y = 2; } return this; }"
+            ),
+            Some(vec![])
+        );
+    }
+
+    #[test]
+    fn test_constructor_missing_fields() {
+        assert_eq!(
+            uninitialized("constructor Point new(int a) { let x = a; return this; }"),
+            Some(vec!["y".to_string()])
+        );
+        // Assigned on one branch only, or only on one of two returns
+        assert_eq!(
+            uninitialized(
+                "constructor Point new(int a) { if (a) { let x = 1; } let y = 2; return this; }"
+            ),
+            Some(vec!["x".to_string()])
+        );
+        assert_eq!(
+            uninitialized(
+                "constructor Point new(int a) { if (a) { return this; } let x = 1; let y = 2; return this; }"
+            ),
+            Some(vec!["x".to_string(), "y".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_constructor_shadowed_field_not_tracked() {
+        // `let x = x` assigns the parameter, not the field
+        assert_eq!(
+            uninitialized("constructor Point new(int x) { let x = x; let y = 0; return this; }"),
+            Some(vec![])
+        );
+    }
+
+    #[test]
+    fn test_constructor_delegating_to_method() {
+        assert_eq!(
+            uninitialized("constructor Point new() { do init(); return this; }"),
+            None
+        );
+    }
+}
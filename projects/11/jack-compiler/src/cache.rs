@@ -0,0 +1,145 @@
+//! Incremental directory compilation.
+//!
+//! Each Jack file compiles independently of the others, so a file's result
+//! depends only on its content and the options. A watch-mode loop can keep
+//! results in a [`CompileCache`] and recompile only the files that changed.
+
+use crate::error::CompileError;
+use crate::files::{directory_files, total_budget_result};
+use crate::{CompileOptions, CompileResult, compile_checked};
+use jack_analyzer::par_map;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+/// Results of earlier [`compile_directory_cached`] calls.
+#[derive(Debug, Default)]
+pub struct CompileCache {
+    /// Path -> hash of the content compiled (`None` if it could not be
+    /// read) and the result.
+    entries: HashMap<PathBuf, (Option<u64>, CompileResult)>,
+    /// Options the cached results were compiled with, less `threads`.
+    options: Option<CompileOptions>,
+    /// Code size budget result of the last call.
+    total: Option<CompileResult>,
+    /// Files compiled by the last call, in result order.
+    recompiled: Vec<String>,
+}
+
+impl CompileCache {
+    /// Create an empty cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of cached files.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether no file is cached.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// [`CompileResult::filename`]s of the files the last
+    /// [`compile_directory_cached`] call had to compile; the others were
+    /// unchanged.
+    pub fn recompiled(&self) -> &[String] {
+        &self.recompiled
+    }
+
+    /// Forget all cached results.
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Compile all Jack files in a directory, reusing the cached result of each
+/// file whose content is unchanged since the last call.
+///
+/// Files no longer in the directory are dropped from the cache, new files
+/// are compiled, and changing `options` recompiles everything. Results are
+/// in [`CompileOptions::file_order`], followed by the
+/// [`TOTAL_BUDGET_FILENAME`](crate::TOTAL_BUDGET_FILENAME) result if the
+/// files exceed [`CompileOptions::max_vm_lines_total`]. Fails only if the
+/// directory cannot be read. A file that cannot be read gets a result with
+/// the I/O error and is retried on the next call.
+///
+/// ```no_run
+/// use jack_compiler::{CompileCache, CompileOptions, compile_directory_cached};
+/// use std::path::Path;
+///
+/// let mut cache = CompileCache::new();
+/// let dir = Path::new("Square/");
+/// compile_directory_cached(dir, CompileOptions::default(), &mut cache).unwrap();
+/// // ... after an edit to Square.jack:
+/// compile_directory_cached(dir, CompileOptions::default(), &mut cache).unwrap();
+/// assert_eq!(cache.recompiled(), ["Square"]);
+/// ```
+pub fn compile_directory_cached<'c>(
+    dir: &Path,
+    options: CompileOptions,
+    cache: &'c mut CompileCache,
+) -> Result<Vec<&'c CompileResult>, CompileError> {
+    let jack_files = directory_files(dir, options)?;
+    // The pool size does not change the output
+    let key = CompileOptions {
+        threads: None,
+        ..options
+    };
+    if cache.options != Some(key) {
+        cache.clear();
+        cache.options = Some(key);
+    }
+
+    let present: HashSet<&PathBuf> = jack_files.iter().collect();
+    cache.entries.retain(|path, _| present.contains(path));
+
+    // Read and hash every file; compile the changed ones in parallel
+    let entries = &cache.entries;
+    let compiled = par_map(&jack_files, options.threads, |path| {
+        let source = fs::read_to_string(path);
+        let hash = source.as_deref().ok().map(content_hash);
+        if hash.is_some() && entries.get(path).is_some_and(|(h, _)| *h == hash) {
+            return None;
+        }
+        let filename = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("unknown");
+        let result = match source {
+            Ok(source) => compile_checked(&source, filename, options, true),
+            Err(e) => CompileResult {
+                filename: filename.to_string(),
+                vm_code: String::new(),
+                errors: vec![CompileError::io(path, e)],
+                warnings: Vec::new(),
+            },
+        };
+        Some((hash, result))
+    });
+
+    cache.recompiled.clear();
+    for (path, entry) in jack_files.iter().zip(compiled) {
+        if let Some(entry) = entry {
+            cache.recompiled.push(entry.1.filename.clone());
+            cache.entries.insert(path.clone(), entry);
+        }
+    }
+
+    cache.total = total_budget_result(jack_files.iter().map(|p| &cache.entries[p].1), options);
+
+    let cache = &*cache;
+    let mut results: Vec<_> = jack_files.iter().map(|p| &cache.entries[p].1).collect();
+    results.extend(cache.total.as_ref());
+    Ok(results)
+}
+
+/// Hash of a file's content.
+fn content_hash(source: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+    hasher.finish()
+}
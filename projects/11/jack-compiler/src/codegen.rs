@@ -2,11 +2,39 @@
 //!
 //! Traverses the AST and emits VM code using the VMWriter.
 
-use crate::error::CompileError;
-use crate::optimizer::{ConstantFolder, StrengthReduction};
-use crate::symbol_table::{SymbolKind, SymbolTable};
-use crate::vm_writer::VMWriter;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+
+use crate::assignment::{constant_array_lengths, unassigned_reads, uninitialized_fields};
+use crate::error::{CompileError, CompileWarning};
+use crate::leak::undisposed_locals;
+use crate::optimizer::{ChainStep, ConstantFolder, ConstantPropagator, Inliner, StrengthReduction};
+use crate::registry::{ClassRegistry, os_signature};
+use crate::slot_verify::{argument_count, verify_slots_with};
+use crate::symbol_table::{DefineOutcome, Symbol, SymbolKind, SymbolTable};
+use crate::vm_writer::{Segment, VMWriter};
+use crate::{CompileOptions, OptLevel};
 use jack_analyzer::ast::*;
+use jack_analyzer::token::Span;
+
+/// Newline character code in the Hack OS character set.
+const HACK_NEWLINE: u16 = 128;
+
+/// Character code of `ch` in a string constant.
+///
+/// A `\n` escape (extension) decodes to ASCII 10, which the Hack screen
+/// cannot render, so it maps to the OS newline character 128.
+#[inline]
+fn hack_char_code(ch: char) -> u16 {
+    if ch == '\n' { HACK_NEWLINE } else { ch as u16 }
+}
+
+/// `Sys.error` code for a negative array index (bounds checks).
+pub const NEGATIVE_INDEX_ERROR: u16 = 100;
+
+/// `Sys.error` code for an index at or past a known array length.
+pub const INDEX_TOO_LARGE_ERROR: u16 = 101;
 
 /// Write a u32 value to a string buffer without allocation.
 #[inline]
@@ -50,22 +78,96 @@ fn write_u16(n: u16, buf: &mut String) {
     }
 }
 
+/// Check that `name` is a Jack identifier (letters, digits and `_`, not
+/// starting with a digit), and so contains neither the `.` nor the `$` that
+/// separate the parts of generated labels.
+fn is_label_safe(name: &str) -> bool {
+    name.chars().next().is_some_and(|c| !c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Check if an expression always evaluates to true (-1) or false (0): a
+/// comparison, a boolean literal, or `~` of either.
+fn is_boolean(expr: &Expression) -> bool {
+    fn term_is_boolean(term: &Term) -> bool {
+        match term {
+            Term::KeywordConstant(KeywordConstant::True | KeywordConstant::False, _) => true,
+            Term::UnaryOp(UnaryOp::Not, inner, _) => term_is_boolean(inner),
+            Term::Parenthesized(inner, _) => is_boolean(inner),
+            _ => false,
+        }
+    }
+    match expr.ops.last() {
+        Some((op, _)) => matches!(op, BinaryOp::Lt | BinaryOp::Gt | BinaryOp::Eq),
+        None => term_is_boolean(&expr.term),
+    }
+}
+
+/// Output of a code generation run: VM code or errors, plus warnings.
+///
+/// `T` is `()` when the code was streamed to a writer
+/// ([`CodeGenerator::compile_to_writer`]).
+#[derive(Debug)]
+pub struct CodegenOutput<T = String> {
+    /// The generated VM code, or the errors that prevented it.
+    pub result: Result<T, Vec<CompileError>>,
+    /// Non-fatal diagnostics (reported even when compilation fails).
+    pub warnings: Vec<CompileWarning>,
+}
+
 /// Code generator that compiles Jack AST to VM code.
 pub struct CodeGenerator {
     /// Symbol table for variable lookup.
     symbols: SymbolTable,
+    /// Known subroutine signatures (for call-kind checks).
+    registry: ClassRegistry,
     /// VM code emitter.
     vm: VMWriter,
     /// Counter for generating unique labels.
     label_counter: u32,
-    /// Current class name.
-    class_name: String,
+    /// Current class name (interned).
+    class_name: Arc<str>,
+    /// Current subroutine name (scopes generated labels).
+    subroutine_name: String,
     /// Current subroutine kind (for `this` handling).
     current_subroutine_kind: Option<SubroutineKind>,
     /// Collected compilation errors.
     errors: Vec<CompileError>,
-    /// Whether to apply constant folding optimization.
-    optimize: bool,
+    /// Collected warnings (symbol table warnings are merged in at the end).
+    warnings: Vec<CompileWarning>,
+    /// Inlinable subroutines of the current class (`O2` only).
+    inline_candidates: HashMap<String, (SubroutineKind, Expression)>,
+    /// Undefined names already reported in the current subroutine.
+    reported_undefined: HashSet<String>,
+    /// Class variables read anywhere in the class.
+    class_vars_read: HashSet<Arc<str>>,
+    /// Class variables assigned anywhere in the class.
+    class_vars_written: HashSet<Arc<str>>,
+    /// Optimization level (gates constant folding and strength reduction).
+    opt_level: OptLevel,
+    /// Warn when a local or parameter shadows a field/static.
+    warn_shadowing: bool,
+    /// Warn when a local is read before it is assigned.
+    warn_unassigned: bool,
+    /// Check argument counts of calls into the standard OS.
+    check_os_calls: bool,
+    /// Guard array indexes with `Sys.error` calls.
+    bounds_checks: bool,
+    /// Warn about constructed objects that are never disposed.
+    lint_leaks: bool,
+    /// Print string literals with `Output.printChar` (when optimizing).
+    fuse_print_string: bool,
+    /// Check the slot indexes of each subroutine's code.
+    verify_slots: bool,
+    /// Declare this many locals fewer than used, to exercise `verify_slots`.
+    #[cfg(test)]
+    understate_locals: u16,
+    /// Claim this many arguments more than pushed in each call, to exercise
+    /// `verify_stack`.
+    #[cfg(test)]
+    pub(crate) overstate_call_args: u16,
+    /// Statically known lengths of local arrays (bounds checks only).
+    array_lengths: HashMap<String, u16>,
 }
 
 impl CodeGenerator {
@@ -75,15 +177,41 @@ impl CodeGenerator {
     }
 
     /// Create a new code generator with specified optimization setting.
+    ///
+    /// `true` maps to [`OptLevel::O1`], `false` to [`OptLevel::O0`].
     pub fn with_options(optimize: bool) -> Self {
+        Self::with_opt_level(optimize.into())
+    }
+
+    /// Create a new code generator with the given optimization level.
+    pub fn with_opt_level(opt_level: OptLevel) -> Self {
         Self {
             symbols: SymbolTable::new(),
+            registry: ClassRegistry::new(),
             vm: VMWriter::new(),
             label_counter: 0,
-            class_name: String::new(),
+            class_name: Arc::from(""),
+            subroutine_name: String::new(),
             current_subroutine_kind: None,
             errors: Vec::new(),
-            optimize,
+            warnings: Vec::new(),
+            inline_candidates: HashMap::new(),
+            reported_undefined: HashSet::new(),
+            class_vars_read: HashSet::new(),
+            class_vars_written: HashSet::new(),
+            opt_level,
+            warn_shadowing: true,
+            warn_unassigned: true,
+            check_os_calls: false,
+            bounds_checks: false,
+            lint_leaks: false,
+            fuse_print_string: false,
+            verify_slots: cfg!(debug_assertions),
+            #[cfg(test)]
+            understate_locals: 0,
+            #[cfg(test)]
+            overstate_call_args: 0,
+            array_lengths: HashMap::new(),
         }
     }
 
@@ -101,21 +229,93 @@ impl CodeGenerator {
         class: &Class,
         optimize: bool,
     ) -> Result<String, Vec<CompileError>> {
-        let mut compiler = CodeGenerator::with_options(optimize);
-        compiler.compile_class(class);
+        let options = CompileOptions {
+            optimize,
+            ..CompileOptions::default()
+        };
+        Self::compile_with_config(class, &options).result
+    }
 
-        if compiler.errors.is_empty() {
-            Ok(compiler.vm.into_output())
-        } else {
-            Err(compiler.errors)
+    /// Compile a class to VM code using the full set of compile options.
+    ///
+    /// Returns the generated VM code (or errors) together with any warnings.
+    pub fn compile_with_config(class: &Class, options: &CompileOptions) -> CodegenOutput {
+        let mut vm_code = String::new();
+        let CodegenOutput { result, warnings } =
+            Self::compile_to_writer(class, options, &mut vm_code)
+                .expect("writing to a String cannot fail");
+        CodegenOutput {
+            result: result.map(|()| vm_code),
+            warnings,
         }
     }
 
-    /// Generate a unique label with the given prefix.
+    /// Compile a class, writing the VM code to `out` one subroutine at a
+    /// time.
+    ///
+    /// Produces the same code as [`compile_with_config`](Self::compile_with_config)
+    /// without holding all of it in memory. If the result is an error, `out`
+    /// has received code for the whole class anyway; discard it. Fails only
+    /// if `out` does.
+    pub fn compile_to_writer<W: fmt::Write>(
+        class: &Class,
+        options: &CompileOptions,
+        out: &mut W,
+    ) -> Result<CodegenOutput<()>, fmt::Error> {
+        Self::configured(options).compile_output_to(class, out)
+    }
+
+    /// A code generator set up for `options`.
+    pub(crate) fn configured(options: &CompileOptions) -> Self {
+        let mut compiler = CodeGenerator::with_opt_level(options.effective_opt_level());
+        compiler.warn_shadowing = options.warn_shadowing;
+        compiler.warn_unassigned = options.warn_unassigned;
+        compiler.check_os_calls = options.check_os_calls;
+        compiler.bounds_checks = options.bounds_checks;
+        compiler.lint_leaks = options.lint_leaks;
+        compiler.fuse_print_string = options.fuse_print_string;
+        compiler.verify_slots |= options.verify_slots;
+        compiler
+    }
+
+    /// Compile `class` to `out`, collecting this generator's errors and
+    /// warnings into the output.
+    pub(crate) fn compile_output_to<W: fmt::Write>(
+        mut self,
+        class: &Class,
+        out: &mut W,
+    ) -> Result<CodegenOutput<()>, fmt::Error> {
+        self.compile_class(class, out)?;
+
+        let warnings = self.warnings;
+        self.errors.append(&mut self.vm.take_errors());
+        let result = if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(self.errors)
+        };
+        Ok(CodegenOutput { result, warnings })
+    }
+
+    /// Generate a unique label with the given prefix, scoped by class and
+    /// subroutine: `Main.foo$IF_FALSE_3`.
+    ///
+    /// Jack identifiers cannot contain `.` or `$`, so the class and
+    /// subroutine are recovered unambiguously from any label, and no two
+    /// subroutines share one even when a class is named `IF_FALSE_0` or a
+    /// subroutine `ret`. A label also never equals a function name (which
+    /// has no `$`), nor the VM translator's `Main.foo$ret.0` return
+    /// addresses, which it scopes separately from labels.
     /// Uses pre-allocated capacity to reduce allocations.
     #[inline]
     fn unique_label(&mut self, prefix: &str) -> String {
-        let mut label = String::with_capacity(prefix.len() + 11); // prefix + '_' + max 10 digits
+        // class + '.' + subroutine + '$' + prefix + '_' + max 10 digits
+        let capacity = self.class_name.len() + self.subroutine_name.len() + prefix.len() + 13;
+        let mut label = String::with_capacity(capacity);
+        label.push_str(&self.class_name);
+        label.push('.');
+        label.push_str(&self.subroutine_name);
+        label.push('$');
         label.push_str(prefix);
         label.push('_');
         write_u32(self.label_counter, &mut label);
@@ -132,18 +332,117 @@ impl CodeGenerator {
     // Class Compilation
     // ========================================================================
 
-    fn compile_class(&mut self, class: &Class) {
-        self.class_name = class.name.clone();
+    /// Compile `class`, moving each subroutine's code to `out` once it is
+    /// complete.
+    fn compile_class(&mut self, class: &Class, out: &mut dyn fmt::Write) -> fmt::Result {
+        // The tokenizer guarantees this; labels rely on it (see `unique_label`)
+        debug_assert!(
+            is_label_safe(&class.name),
+            "class name '{}' cannot appear in VM labels",
+            class.name
+        );
+        for sub in &class.subroutine_decs {
+            debug_assert!(
+                is_label_safe(&sub.name),
+                "subroutine name '{}.{}' cannot appear in VM labels",
+                class.name,
+                sub.name
+            );
+        }
+        self.class_name = self.symbols.intern(&class.name);
         self.symbols.start_class(&class.name);
+        self.registry.register_class(class);
 
         // Define class-level variables
         for var_dec in &class.class_var_decs {
             self.compile_class_var_dec(var_dec);
         }
 
+        if self.opt_level.is_aggressive() {
+            for sub in &class.subroutine_decs {
+                if sub.kind != SubroutineKind::Constructor
+                    && let Some(expr) = Inliner::candidate(sub)
+                {
+                    self.inline_candidates
+                        .insert(sub.name.clone(), (sub.kind, expr));
+                }
+            }
+        }
+
         // Compile subroutines
         for sub in &class.subroutine_decs {
+            let errors = self.errors.len();
             self.compile_subroutine(sub);
+            // Code of a subroutine with errors is discarded anyway
+            if self.verify_slots && self.errors.len() == errors {
+                let arguments = argument_count(sub);
+                let mut violations = verify_slots_with(self.vm.as_str(), |_| Some(arguments));
+                self.errors.append(&mut violations);
+            }
+            self.vm.flush_to(out)?;
+        }
+
+        self.check_class_var_usage(class);
+        self.check_constructor_fields(class);
+        Ok(())
+    }
+
+    /// Warn about fields a constructor does not assign on every path.
+    fn check_constructor_fields(&mut self, class: &Class) {
+        let fields: Vec<&str> = class
+            .class_var_decs
+            .iter()
+            .filter(|dec| dec.kind == ClassVarKind::Field)
+            .flat_map(|dec| dec.names.iter().map(String::as_str))
+            .collect();
+        if fields.is_empty() {
+            return;
+        }
+        for sub in &class.subroutine_decs {
+            if sub.kind != SubroutineKind::Constructor {
+                continue;
+            }
+            if let Some(missing) = uninitialized_fields(sub, &fields)
+                && !missing.is_empty()
+            {
+                self.warnings.push(CompileWarning::uninitialized_fields(
+                    &sub.name,
+                    missing,
+                    sub.span.clone(),
+                ));
+            }
+        }
+    }
+
+    /// Warn about fields and statics that are never used, or only assigned.
+    fn check_class_var_usage(&mut self, class: &Class) {
+        for dec in &class.class_var_decs {
+            for name in &dec.names {
+                if self.class_vars_read.contains(name.as_str()) {
+                    continue;
+                }
+                let warning = if self.class_vars_written.contains(name.as_str()) {
+                    CompileWarning::unread_class_variable(name, dec.kind, dec.span.clone())
+                } else {
+                    CompileWarning::unused_class_variable(name, dec.kind, dec.span.clone())
+                };
+                self.warnings.push(warning);
+            }
+        }
+    }
+
+    /// Record a read or write of a symbol for unused class variable checks.
+    fn note_use(&mut self, symbol: &Symbol, write: bool) {
+        if !symbol.kind.is_class_level() {
+            return;
+        }
+        let used = if write {
+            &mut self.class_vars_written
+        } else {
+            &mut self.class_vars_read
+        };
+        if !used.contains(&symbol.name) {
+            used.insert(Arc::clone(&symbol.name));
         }
     }
 
@@ -154,12 +453,26 @@ impl CodeGenerator {
         };
 
         for name in &dec.names {
-            if let Err(e) = self
-                .symbols
-                .define(name, dec.var_type.clone(), kind, dec.span.clone())
-            {
-                self.error(e);
+            self.define_symbol(name, dec.var_type.clone(), kind, &dec.span);
+        }
+    }
+
+    /// Define a symbol, reporting duplicates as errors and shadowing as warnings.
+    fn define_symbol(&mut self, name: &str, symbol_type: Type, kind: SymbolKind, span: &Span) {
+        match self.symbols.define(name, symbol_type, kind, span.clone()) {
+            Ok(DefineOutcome::Fresh) => {}
+            Ok(DefineOutcome::Shadows(shadowed)) => {
+                if self.warn_shadowing {
+                    let warning = CompileWarning::shadowed_class_variable(
+                        name,
+                        kind,
+                        span.clone(),
+                        &shadowed,
+                    );
+                    self.warnings.push(warning);
+                }
             }
+            Err(e) => self.error(e),
         }
     }
 
@@ -169,48 +482,51 @@ impl CodeGenerator {
 
     fn compile_subroutine(&mut self, sub: &SubroutineDec) {
         self.symbols.start_subroutine();
+        self.subroutine_name.clone_from(&sub.name);
         self.current_subroutine_kind = Some(sub.kind);
+        self.reported_undefined.clear();
 
         // For methods, `this` is argument 0
-        if sub.kind == SubroutineKind::Method
-            && let Err(e) = self.symbols.define(
-                "this",
-                Type::ClassName(self.class_name.clone()),
-                SymbolKind::Argument,
-                sub.span.clone(),
-            )
-        {
-            self.error(e);
+        if sub.kind == SubroutineKind::Method {
+            let this_type = Type::ClassName(self.class_name.to_string());
+            self.define_symbol("this", this_type, SymbolKind::Argument, &sub.span);
         }
 
-        // Define parameters
+        // Define parameters (parameters carry no span of their own)
         for param in &sub.parameters {
-            if let Err(e) = self.symbols.define(
-                &param.name,
-                param.var_type.clone(),
-                SymbolKind::Argument,
-                sub.span.clone(),
-            ) {
-                self.error(e);
-            }
+            let param_type = param.var_type.clone();
+            self.define_symbol(&param.name, param_type, SymbolKind::Argument, &sub.span);
         }
 
         // Define local variables
         for var_dec in &sub.body.var_decs {
             for name in &var_dec.names {
-                if let Err(e) = self.symbols.define(
-                    name,
-                    var_dec.var_type.clone(),
-                    SymbolKind::Local,
-                    var_dec.span.clone(),
-                ) {
-                    self.error(e);
-                }
+                let var_type = var_dec.var_type.clone();
+                self.define_symbol(name, var_type, SymbolKind::Local, &var_dec.span);
+            }
+        }
+
+        if self.warn_unassigned {
+            for (name, span) in unassigned_reads(sub) {
+                self.warnings
+                    .push(CompileWarning::used_before_assigned(name, span));
+            }
+        }
+        if self.lint_leaks {
+            let leaks = undisposed_locals(sub, |name| self.symbols.lookup(name).is_some());
+            for (name, class, span) in leaks {
+                self.warnings
+                    .push(CompileWarning::undisposed_object(name, class, span));
             }
         }
+        if self.bounds_checks {
+            self.array_lengths = constant_array_lengths(sub);
+        }
 
         // Emit function declaration (zero-allocation)
         let num_locals = self.symbols.var_count(SymbolKind::Local);
+        #[cfg(test)]
+        let num_locals = num_locals.saturating_sub(self.understate_locals);
         {
             let buf = self.vm.output_mut();
             buf.push_str("function ");
@@ -227,22 +543,26 @@ impl CodeGenerator {
             SubroutineKind::Constructor => {
                 // Allocate memory for object fields
                 let field_count = self.symbols.field_count();
-                self.vm.write_push("constant", field_count);
+                self.vm.write_push(Segment::Constant, field_count);
                 self.vm.write_call("Memory.alloc", 1);
-                self.vm.write_pop("pointer", 0);
+                self.vm.write_pop(Segment::Pointer, 0);
             }
             SubroutineKind::Method => {
                 // Set `this` to argument 0
-                self.vm.write_push("argument", 0);
-                self.vm.write_pop("pointer", 0);
+                self.vm.write_push(Segment::Argument, 0);
+                self.vm.write_pop(Segment::Pointer, 0);
             }
             SubroutineKind::Function => {
                 // No special setup needed
             }
         }
 
-        // Compile statements
-        self.compile_statements(&sub.body.statements);
+        // Compile statements, with constant locals substituted at O2
+        if self.opt_level.is_aggressive() {
+            self.compile_statements(&ConstantPropagator::propagate(sub));
+        } else {
+            self.compile_statements(&sub.body.statements);
+        }
     }
 
     // ========================================================================
@@ -268,31 +588,39 @@ impl CodeGenerator {
     }
 
     fn compile_let(&mut self, stmt: &LetStatement) {
-        let symbol = match self.symbols.lookup(&stmt.var_name) {
-            Some(s) => s.clone(),
-            None => {
-                self.error(CompileError::undefined_variable(
-                    &stmt.var_name,
-                    stmt.span.clone(),
-                ));
-                return;
+        // In a method `this` resolves to argument 0, which holds the object pointer
+        if stmt.var_name == "this" {
+            self.error(CompileError::assignment_to_this(stmt.span.clone()));
+            return;
+        }
+
+        let Some(symbol) = self.resolve_symbol_for_use(&stmt.var_name, &stmt.span) else {
+            // Still check the right-hand side, discarding what it pushes
+            if let Some(index_expr) = &stmt.index {
+                self.compile_expression(index_expr);
+                self.vm.write_pop(Segment::Temp, 0);
             }
+            self.compile_expression(&stmt.value);
+            self.vm.write_pop(Segment::Temp, 0);
+            return;
         };
+        // `let a[i] = ...` reads the array pointer rather than assigning it
+        self.note_use(&symbol, stmt.index.is_none());
 
         if let Some(index_expr) = &stmt.index {
             // Array assignment: let arr[i] = expr
             // Push base address
             self.vm.write_push(symbol.segment(), symbol.index);
             // Compile and add index
-            self.compile_expression(index_expr);
+            self.compile_index(&stmt.var_name, index_expr);
             self.vm.write_arithmetic("add");
             // Compile value
             self.compile_expression(&stmt.value);
             // Store via THAT
-            self.vm.write_pop("temp", 0);
-            self.vm.write_pop("pointer", 1);
-            self.vm.write_push("temp", 0);
-            self.vm.write_pop("that", 0);
+            self.vm.write_pop(Segment::Temp, 0);
+            self.vm.write_pop(Segment::Pointer, 1);
+            self.vm.write_push(Segment::Temp, 0);
+            self.vm.write_pop(Segment::That, 0);
         } else {
             // Simple assignment
             self.compile_expression(&stmt.value);
@@ -300,7 +628,128 @@ impl CodeGenerator {
         }
     }
 
+    /// Compile an array index, guarded by bounds checks if enabled.
+    ///
+    /// The index is kept in `temp 1` while it is compared against 0 and,
+    /// for arrays in `array_lengths`, the array length. A failed check calls
+    /// `Sys.error` with [`NEGATIVE_INDEX_ERROR`] or [`INDEX_TOO_LARGE_ERROR`].
+    /// Checks that a constant index is known to pass are left out.
+    fn compile_index(&mut self, array: &str, index: &Expression) {
+        self.compile_expression(index);
+        if !self.bounds_checks {
+            return;
+        }
+
+        let constant = ConstantFolder::fold_expression(index);
+        let length = self.array_lengths.get(array).copied();
+        let check_negative = constant.is_none_or(|i| i < 0);
+        let check_length = length.is_some_and(|len| constant.is_none_or(|i| i >= len as i32));
+        if !check_negative && !check_length {
+            return;
+        }
+
+        self.vm.write_pop(Segment::Temp, 1);
+        if check_negative {
+            // if (~(i < 0)) skip the error
+            self.vm.write_push(Segment::Temp, 1);
+            self.vm.write_push(Segment::Constant, 0);
+            self.vm.write_arithmetic("lt");
+            self.vm.write_arithmetic("not");
+            self.write_bounds_error(NEGATIVE_INDEX_ERROR);
+        }
+        if let Some(length) = length.filter(|_| check_length) {
+            // if (i < length) skip the error
+            self.vm.write_push(Segment::Temp, 1);
+            self.vm.write_push(Segment::Constant, length);
+            self.vm.write_arithmetic("lt");
+            self.write_bounds_error(INDEX_TOO_LARGE_ERROR);
+        }
+        self.vm.write_push(Segment::Temp, 1);
+    }
+
+    /// Call `Sys.error(code)` unless the condition on the stack is true.
+    fn write_bounds_error(&mut self, code: u16) {
+        let ok = self.unique_label("BOUNDS_OK");
+        self.vm.write_if_goto(&ok);
+        self.vm.write_push(Segment::Constant, code);
+        self.vm.write_call("Sys.error", 1);
+        self.vm.write_pop(Segment::Temp, 0);
+        self.vm.write_label(&ok);
+    }
+
+    /// Look up a variable at a use site, reporting undefined names, locals
+    /// used before a late `var` declaration (extensions) and fields
+    /// referenced from a function.
+    ///
+    /// Each undefined name is reported once per subroutine, at its first use.
+    fn resolve_symbol_for_use(&mut self, name: &str, span: &Span) -> Option<Arc<Symbol>> {
+        let Some(symbol) = self.symbols.lookup_shared(name).cloned() else {
+            if !self.reported_undefined.contains(name) {
+                self.reported_undefined.insert(name.to_string());
+                self.error(CompileError::undefined_variable(name, span.clone()));
+            }
+            return None;
+        };
+        if symbol.kind == SymbolKind::Local && symbol.span.start > span.start {
+            if !self.reported_undefined.contains(name) {
+                self.reported_undefined.insert(name.to_string());
+                self.error(CompileError::used_before_declaration(
+                    name,
+                    symbol.span.clone(),
+                    span.clone(),
+                ));
+            }
+            return None;
+        }
+        if symbol.kind == SymbolKind::Field
+            && self.current_subroutine_kind == Some(SubroutineKind::Function)
+        {
+            self.error(CompileError::field_in_static_context(name, span.clone()));
+            return None;
+        }
+        Some(symbol)
+    }
+
     fn compile_if(&mut self, stmt: &IfStatement) {
+        // Dead-branch elimination: emit only the taken branch
+        if let Some(taken) = self.constant_condition(&stmt.condition) {
+            let else_stmts = stmt.else_statements.as_deref().unwrap_or_default();
+            let (live, dead) = if taken {
+                (&stmt.then_statements[..], else_stmts)
+            } else {
+                (else_stmts, &stmt.then_statements[..])
+            };
+            self.compile_statements(live);
+            self.compile_discarded(dead);
+            return;
+        }
+
+        if stmt.then_statements.is_empty() && self.opt_level.is_enabled() {
+            let else_stmts = stmt.else_statements.as_deref().unwrap_or_default();
+            if else_stmts.is_empty() {
+                // Nothing to branch over: evaluate the condition only if it
+                // may have side effects (calls, or bounds-check failures)
+                let len = self.vm.len();
+                self.compile_expression(&stmt.condition);
+                if self.bounds_checks || expression_has_calls(&stmt.condition) {
+                    self.vm.write_pop(Segment::Temp, 0);
+                } else {
+                    self.vm.output_mut().truncate(len);
+                }
+                return;
+            }
+            // A true (-1) or false (0) condition can jump over the else
+            // branch directly; other values must still take it
+            if is_boolean(&stmt.condition) {
+                let end_label = self.unique_label("IF_END");
+                self.compile_expression(&stmt.condition);
+                self.vm.write_if_goto(&end_label);
+                self.compile_statements(else_stmts);
+                self.vm.write_label(&end_label);
+                return;
+            }
+        }
+
         let false_label = self.unique_label("IF_FALSE");
         let end_label = self.unique_label("IF_END");
 
@@ -322,7 +771,25 @@ impl CodeGenerator {
         self.vm.write_label(&end_label);
     }
 
+    /// An empty body is still compiled as a loop: `while (~Keyboard.keyPressed()) {}`
+    /// is a busy-wait.
     fn compile_while(&mut self, stmt: &WhileStatement) {
+        match self.constant_condition(&stmt.condition) {
+            Some(false) => {
+                self.compile_discarded(&stmt.statements);
+                return;
+            }
+            Some(true) => {
+                // Infinite loop: no condition test and no exit label
+                let exp_label = self.unique_label("WHILE_EXP");
+                self.vm.write_label(&exp_label);
+                self.compile_statements(&stmt.statements);
+                self.vm.write_goto(&exp_label);
+                return;
+            }
+            None => {}
+        }
+
         let exp_label = self.unique_label("WHILE_EXP");
         let end_label = self.unique_label("WHILE_END");
 
@@ -340,10 +807,72 @@ impl CodeGenerator {
         self.vm.write_label(&end_label);
     }
 
+    /// Evaluate a condition that folds to `true` (-1) or `false` (0) at `O2`.
+    ///
+    /// Other constants are left to the runtime test: `not` + `if-goto`
+    /// treats every value except -1 as false.
+    fn constant_condition(&self, condition: &Expression) -> Option<bool> {
+        if !self.opt_level.is_aggressive() {
+            return None;
+        }
+        match ConstantFolder::fold_expression(condition)? {
+            -1 => Some(true),
+            0 => Some(false),
+            _ => None,
+        }
+    }
+
+    /// Compile statements for their diagnostics only, dropping the VM code.
+    fn compile_discarded(&mut self, statements: &[Statement]) {
+        let len = self.vm.len();
+        self.compile_statements(statements);
+        self.vm.output_mut().truncate(len);
+    }
+
     fn compile_do(&mut self, stmt: &DoStatement) {
+        if let Some(text) = self.fusible_print_string(&stmt.call) {
+            self.compile_print_chars(text);
+            return;
+        }
         self.compile_subroutine_call(&stmt.call);
         // Discard return value
-        self.vm.write_pop("temp", 0);
+        self.vm.write_pop(Segment::Temp, 0);
+    }
+
+    /// The literal of a `do Output.printString("...")` that can be printed
+    /// character by character instead (see `CompileOptions::fuse_print_string`).
+    fn fusible_print_string<'c>(&self, call: &'c SubroutineCall) -> Option<&'c str> {
+        if !self.fuse_print_string
+            || !self.opt_level.is_enabled()
+            || call.receiver.as_deref() != Some("Output")
+            || call.name != "printString"
+        {
+            return None;
+        }
+        // `Output` must be the OS class, not a variable or a replacement class
+        if self.symbols.lookup("Output").is_some() || self.registry.has_class("Output") {
+            return None;
+        }
+        match call.arguments.as_slice() {
+            [
+                Expression {
+                    term: Term::StringConstant(text, _),
+                    ops,
+                    ..
+                },
+            ] if ops.is_empty() => Some(text),
+            _ => None,
+        }
+    }
+
+    /// Print a string literal with one `Output.printChar` call per character,
+    /// never building (and leaking) a `String` object.
+    fn compile_print_chars(&mut self, text: &str) {
+        for ch in text.chars() {
+            self.vm.write_push(Segment::Constant, hack_char_code(ch));
+            self.vm.write_call("Output.printChar", 1);
+            self.vm.write_pop(Segment::Temp, 0);
+        }
     }
 
     fn compile_return(&mut self, stmt: &ReturnStatement) {
@@ -351,7 +880,7 @@ impl CodeGenerator {
             self.compile_expression(expr);
         } else {
             // Void return - push 0
-            self.vm.write_push("constant", 0);
+            self.vm.write_push(Segment::Constant, 0);
         }
         self.vm.write_return();
     }
@@ -363,35 +892,35 @@ impl CodeGenerator {
     #[inline]
     fn compile_expression(&mut self, expr: &Expression) {
         // Try constant folding first (only if optimization is enabled)
-        if self.optimize
+        if self.opt_level.is_enabled()
             && let Some(value) = ConstantFolder::fold_expression(expr)
         {
             if (0..=32767).contains(&value) {
-                self.vm.write_push("constant", value as u16);
+                self.vm.write_push(Segment::Constant, value as u16);
                 return;
             } else if (-32768..0).contains(&value) {
                 // Handle negative constants: push |value| then negate
-                self.vm.write_push("constant", (-value) as u16);
+                self.vm.write_push(Segment::Constant, (-value) as u16);
                 self.vm.write_arithmetic("neg");
                 return;
             }
         }
 
-        // Strength reduction: const_pow2 * expr (left-side constant)
-        // Pattern: first term is IntegerConstant(pow2), first op is Mul
-        if self.optimize
+        // Strength reduction: const * expr (left-side constant)
+        // Pattern: first term is a reducible IntegerConstant, first op is Mul
+        if self.opt_level.is_enabled()
             && !expr.ops.is_empty()
             && let (BinaryOp::Mul, ref right_term) = expr.ops[0]
             && let Term::IntegerConstant(n, _) = &expr.term
-            && let Some(shifts) = StrengthReduction::optimize_multiply(*n)
+            && Self::reduces_multiply(*n)
         {
-            // Compile the right term first, then shift left
+            // Compile the right term first, then multiply in place
             self.compile_term(right_term);
-            self.emit_shift_left(shifts);
+            self.emit_constant_multiply(*n);
             // Continue with remaining ops (if any)
             for (op, term) in expr.ops.iter().skip(1) {
                 self.compile_term(term);
-                self.compile_binary_op(*op);
+                self.vm.write_arithmetic_op(*op);
             }
             return;
         }
@@ -400,18 +929,53 @@ impl CodeGenerator {
         self.compile_term(&expr.term);
 
         for (op, term) in &expr.ops {
-            // Strength reduction: expr * const_pow2 (right-side constant)
-            if self.optimize
+            // Strength reduction: expr * const (right-side constant)
+            if self.opt_level.is_enabled()
                 && *op == BinaryOp::Mul
                 && let Term::IntegerConstant(n, _) = term
-                && let Some(shifts) = StrengthReduction::optimize_multiply(*n)
+                && Self::reduces_multiply(*n)
             {
-                // Value is already on stack; emit shift-left instead of Math.multiply
-                self.emit_shift_left(shifts);
+                // Value is already on stack; multiply in place instead of Math.multiply
+                self.emit_constant_multiply(*n);
                 continue;
             }
             self.compile_term(term);
-            self.compile_binary_op(*op);
+            self.vm.write_arithmetic_op(*op);
+        }
+    }
+
+    /// Whether multiplying by `n` can avoid the `Math.multiply` call.
+    fn reduces_multiply(n: u16) -> bool {
+        StrengthReduction::optimize_multiply(n).is_some()
+            || StrengthReduction::addition_chain(n).is_some()
+    }
+
+    /// Multiply the value on top of the stack by `n` (see [`Self::reduces_multiply`]).
+    ///
+    /// Powers of two shift; other small constants use an addition chain.
+    fn emit_constant_multiply(&mut self, n: u16) {
+        if let Some(shifts) = StrengthReduction::optimize_multiply(n) {
+            self.emit_shift_left(shifts);
+            return;
+        }
+        for step in StrengthReduction::addition_chain(n).unwrap_or_default() {
+            match step {
+                ChainStep::Save => {
+                    self.vm.write_pop(Segment::Temp, 0);
+                    self.vm.write_push(Segment::Temp, 0);
+                }
+                ChainStep::Double => {
+                    // temp 0 may hold the saved value
+                    self.vm.write_pop(Segment::Temp, 1);
+                    self.vm.write_push(Segment::Temp, 1);
+                    self.vm.write_push(Segment::Temp, 1);
+                    self.vm.write_arithmetic("add");
+                }
+                ChainStep::AddSaved => {
+                    self.vm.write_push(Segment::Temp, 0);
+                    self.vm.write_arithmetic("add");
+                }
+            }
         }
     }
 
@@ -424,9 +988,9 @@ impl CodeGenerator {
     fn emit_shift_left(&mut self, shifts: u32) {
         for _ in 0..shifts {
             // Duplicate top of stack and add (x + x = x * 2)
-            self.vm.write_pop("temp", 0);
-            self.vm.write_push("temp", 0);
-            self.vm.write_push("temp", 0);
+            self.vm.write_pop(Segment::Temp, 0);
+            self.vm.write_push(Segment::Temp, 0);
+            self.vm.write_push(Segment::Temp, 0);
             self.vm.write_arithmetic("add");
         }
     }
@@ -435,41 +999,42 @@ impl CodeGenerator {
     fn compile_term(&mut self, term: &Term) {
         match term {
             Term::IntegerConstant(value, _) => {
-                self.vm.write_push("constant", *value);
+                self.vm.write_push(Segment::Constant, *value);
             }
 
             Term::StringConstant(s, _) => {
                 self.compile_string_constant(s);
             }
 
-            Term::KeywordConstant(kw, _) => {
-                self.compile_keyword_constant(*kw);
+            Term::KeywordConstant(kw, span) => {
+                self.compile_keyword_constant(*kw, span);
             }
 
-            Term::VarName(name, span) => match self.symbols.lookup(name) {
-                Some(symbol) => {
+            Term::VarName(name, span) => {
+                if let Some(symbol) = self.resolve_symbol_for_use(name, span) {
+                    self.note_use(&symbol, false);
                     self.vm.write_push(symbol.segment(), symbol.index);
+                } else {
+                    // Placeholder keeps the (discarded) output stack-balanced
+                    self.vm.write_push(Segment::Constant, 0);
                 }
-                None => {
-                    self.error(CompileError::undefined_variable(name, span.clone()));
-                }
-            },
+            }
 
             Term::ArrayAccess(name, index_expr, span) => {
-                match self.symbols.lookup(name) {
-                    Some(symbol) => {
-                        // Push base address
-                        self.vm.write_push(symbol.segment(), symbol.index);
-                        // Compile and add index
-                        self.compile_expression(index_expr);
-                        self.vm.write_arithmetic("add");
-                        // Access via THAT
-                        self.vm.write_pop("pointer", 1);
-                        self.vm.write_push("that", 0);
-                    }
-                    None => {
-                        self.error(CompileError::undefined_variable(name, span.clone()));
-                    }
+                if let Some(symbol) = self.resolve_symbol_for_use(name, span) {
+                    self.note_use(&symbol, false);
+                    // Push base address
+                    self.vm.write_push(symbol.segment(), symbol.index);
+                    // Compile and add index
+                    self.compile_index(name, index_expr);
+                    self.vm.write_arithmetic("add");
+                    // Access via THAT
+                    self.vm.write_pop(Segment::Pointer, 1);
+                    self.vm.write_push(Segment::That, 0);
+                } else {
+                    self.compile_expression(index_expr);
+                    self.vm.write_pop(Segment::Temp, 0);
+                    self.vm.write_push(Segment::Constant, 0);
                 }
             }
 
@@ -483,88 +1048,190 @@ impl CodeGenerator {
 
             Term::UnaryOp(op, inner, _) => {
                 self.compile_term(inner);
-                match op {
-                    UnaryOp::Neg => self.vm.write_arithmetic("neg"),
-                    UnaryOp::Not => self.vm.write_arithmetic("not"),
-                }
+                self.vm.write_unary_op(*op);
             }
         }
     }
 
+    /// Compile a string constant into `String.new` / `String.appendChar` calls.
     #[inline]
     fn compile_string_constant(&mut self, s: &str) {
         // Create string object
-        let len = s.len() as u16;
-        self.vm.write_push("constant", len);
+        let len = s.chars().count() as u16;
+        self.vm.write_push(Segment::Constant, len);
         self.vm.write_call("String.new", 1);
 
         // Append each character
         for ch in s.chars() {
-            self.vm.write_push("constant", ch as u16);
+            self.vm.write_push(Segment::Constant, hack_char_code(ch));
             self.vm.write_call("String.appendChar", 2);
         }
     }
 
     #[inline]
-    fn compile_keyword_constant(&mut self, kw: KeywordConstant) {
+    fn compile_keyword_constant(&mut self, kw: KeywordConstant, span: &Span) {
         match kw {
             KeywordConstant::True => {
                 // true = -1 = ~0
-                self.vm.write_push("constant", 0);
+                self.vm.write_push(Segment::Constant, 0);
                 self.vm.write_arithmetic("not");
             }
             KeywordConstant::False | KeywordConstant::Null => {
-                self.vm.write_push("constant", 0);
+                self.vm.write_push(Segment::Constant, 0);
             }
             KeywordConstant::This => {
-                self.vm.write_push("pointer", 0);
+                if self.current_subroutine_kind == Some(SubroutineKind::Function) {
+                    self.error(CompileError::this_in_function(span.clone()));
+                }
+                self.vm.write_push(Segment::Pointer, 0);
             }
         }
     }
 
-    #[inline]
-    fn compile_binary_op(&mut self, op: BinaryOp) {
-        match op {
-            BinaryOp::Add => self.vm.write_arithmetic("add"),
-            BinaryOp::Sub => self.vm.write_arithmetic("sub"),
-            BinaryOp::And => self.vm.write_arithmetic("and"),
-            BinaryOp::Or => self.vm.write_arithmetic("or"),
-            BinaryOp::Lt => self.vm.write_arithmetic("lt"),
-            BinaryOp::Gt => self.vm.write_arithmetic("gt"),
-            BinaryOp::Eq => self.vm.write_arithmetic("eq"),
-            BinaryOp::Mul => self.vm.write_call("Math.multiply", 2),
-            BinaryOp::Div => self.vm.write_call("Math.divide", 2),
+    /// The body to compile in place of `call`, if the callee can be inlined.
+    ///
+    /// Methods are inlined only on the implicit receiver (`this` is already
+    /// the object), functions only when called as `ThisClass.f()`. Every
+    /// variable the body reads must still resolve to the class-level symbol,
+    /// not a local or parameter of the caller shadowing it.
+    fn inline_body(&self, call: &SubroutineCall) -> Option<Expression> {
+        if !call.arguments.is_empty() {
+            return None;
         }
+        let (kind, expr) = self.inline_candidates.get(&call.name)?;
+        let eligible = match (&call.receiver, kind) {
+            (None, SubroutineKind::Method) => {
+                self.current_subroutine_kind != Some(SubroutineKind::Function)
+            }
+            (Some(receiver), SubroutineKind::Function) => {
+                **receiver == *self.class_name && self.symbols.lookup(receiver).is_none()
+            }
+            _ => false,
+        };
+        let allowed = |symbol_kind: SymbolKind| match kind {
+            SubroutineKind::Function => symbol_kind == SymbolKind::Static,
+            _ => symbol_kind.is_class_level(),
+        };
+        let resolves = Inliner::variables(expr)
+            .into_iter()
+            .all(|name| self.symbols.lookup(name).is_some_and(|s| allowed(s.kind)));
+        (eligible && resolves).then(|| expr.clone())
     }
 
     fn compile_subroutine_call(&mut self, call: &SubroutineCall) {
+        if let Some(body) = self.inline_body(call) {
+            self.compile_expression(&body);
+            return;
+        }
+
         // Determine class name for the call and push receiver if method
-        // We need to clone the class name to avoid borrow issues
+        // (interned, so holding it across the borrows below is cheap)
         let (class_name_owned, num_args) = if let Some(receiver) = &call.receiver {
             // Either ClassName.function() or varName.method()
-            if let Some(symbol) = self.symbols.lookup(receiver) {
+            if self.symbols.lookup(receiver).is_some() {
                 // Method call on object variable - push receiver
+                let Some(symbol) = self.resolve_symbol_for_use(receiver, &call.span) else {
+                    // Stand in for the call's return value
+                    self.vm.write_push(Segment::Constant, 0);
+                    return;
+                };
+                self.note_use(&symbol, false);
                 self.vm.write_push(symbol.segment(), symbol.index);
                 let cn = match &symbol.symbol_type {
-                    Type::ClassName(name) => name.clone(),
-                    _ => receiver.clone(), // Fallback
+                    Type::ClassName(name) => self.symbols.intern(name),
+                    _ => self.symbols.intern(receiver), // Fallback
                 };
+                if let Some(sig) = self.registry.lookup(&cn, &call.name)
+                    && sig.kind != SubroutineKind::Method
+                {
+                    self.error(CompileError::non_method_call_on_object(
+                        &*cn,
+                        &call.name,
+                        sig.kind,
+                        call.span.clone(),
+                    ));
+                }
                 (cn, call.arguments.len() as u16 + 1)
             } else {
                 // Function or constructor call: ClassName.func()
-                (receiver.clone(), call.arguments.len() as u16)
+                if let Some(sig) = self.registry.lookup(receiver, &call.name)
+                    && sig.kind == SubroutineKind::Method
+                {
+                    self.error(CompileError::method_call_without_object(
+                        receiver,
+                        &call.name,
+                        call.span.clone(),
+                    ));
+                }
+                (self.symbols.intern(receiver), call.arguments.len() as u16)
             }
         } else {
-            // Method call on `this`: method()
-            self.vm.write_push("pointer", 0);
-            (self.class_name.clone(), call.arguments.len() as u16 + 1)
+            // Receiver-less call: a subroutine of this class
+            let found = call.arguments.len() as u16;
+            let sig = self.registry.lookup(&self.class_name, &call.name);
+            match sig {
+                None => self.error(CompileError::unknown_subroutine(
+                    &*self.class_name,
+                    &call.name,
+                    call.span.clone(),
+                )),
+                Some(sig) if sig.kind != SubroutineKind::Method => {
+                    self.warnings.push(CompileWarning::implicit_function_call(
+                        &*self.class_name,
+                        &call.name,
+                        sig.kind,
+                        call.span.clone(),
+                    ));
+                }
+                Some(_) if self.current_subroutine_kind == Some(SubroutineKind::Function) => {
+                    self.error(CompileError::method_call_without_object(
+                        &*self.class_name,
+                        &call.name,
+                        call.span.clone(),
+                    ));
+                }
+                Some(_) => {}
+            }
+            if let Some(sig) = sig
+                && sig.param_count != found
+            {
+                self.error(CompileError::argument_count_mismatch(
+                    &self.class_name,
+                    &call.name,
+                    sig.param_count,
+                    found,
+                    call.span.clone(),
+                ));
+            }
+            // Method call on `this`: method(), whatever `sig` says
+            self.vm.write_push(Segment::Pointer, 0);
+            (Arc::clone(&self.class_name), found + 1)
         };
 
+        // A class compiled alongside this one replaces the OS class
+        let found = call.arguments.len() as u16;
+        if self.check_os_calls
+            && !self.registry.has_class(&class_name_owned)
+            && let Some(sig) = os_signature(&class_name_owned, &call.name)
+            && sig.param_count != found
+        {
+            self.error(CompileError::argument_count_mismatch(
+                &class_name_owned,
+                &call.name,
+                sig.param_count,
+                found,
+                call.span.clone(),
+            ));
+        }
+
         // Compile arguments
         for arg in &call.arguments {
             self.compile_expression(arg);
         }
 
+        #[cfg(test)]
+        let num_args = num_args + self.overstate_call_args;
+
         // Write call command (zero-allocation for the write itself)
         {
             let buf = self.vm.output_mut();
@@ -588,6 +1255,7 @@ impl Default for CodeGenerator {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::optimizer::PeepholeOptimizer;
     use jack_analyzer::parser::Parser;
     use jack_analyzer::tokenizer::JackTokenizer;
 
@@ -597,9 +1265,31 @@ mod tests {
         let tokens = tokenizer.tokenize().expect("tokenization failed");
         let parser = Parser::new(&tokens);
         let class = parser.parse().expect("parsing failed");
+        check_streaming(&class, &CompileOptions::default());
         CodeGenerator::compile(&class)
     }
 
+    /// Check that streaming a class through the peephole writer gives the
+    /// code of the whole-class path, which peephole-optimizes the complete
+    /// output at once.
+    fn check_streaming(class: &Class, options: &CompileOptions) {
+        let whole = CodeGenerator::compile_with_config(class, options).result;
+        let mut streamed = String::new();
+        let output = crate::compile_class_to_writer(class, *options, &mut streamed).unwrap();
+        match whole {
+            Ok(vm_code) => {
+                assert!(output.result.is_ok());
+                let expected = if options.effective_opt_level().is_enabled() {
+                    PeepholeOptimizer::optimize(&vm_code)
+                } else {
+                    vm_code
+                };
+                assert_eq!(streamed, expected);
+            }
+            Err(errors) => assert_eq!(output.result.unwrap_err().len(), errors.len()),
+        }
+    }
+
     #[test]
     fn test_empty_function() {
         let source = r#"
@@ -707,23 +1397,47 @@ class Main {
 
     #[test]
     fn test_multiplication_with_variable_non_power_of_two() {
-        // Test that multiplication by non-power-of-2 still calls Math.multiply
+        // Test that multiplication by a larger non-power-of-2 still calls Math.multiply
         let source = r#"
 class Main {
     function int mul() {
         var int x;
         let x = 3;
-        return x * 5;
+        return x * 7;
     }
 }
 "#;
         let vm = compile_source(source).unwrap();
         assert!(
             vm.contains("call Math.multiply 2"),
-            "Should use Math.multiply for non-power-of-2"
+            "Should use Math.multiply for x * 7"
         );
     }
 
+    #[test]
+    fn test_multiplication_addition_chain() {
+        let multiply = |expr: &str| {
+            let source = format!("class Main {{ function int f(int x) {{ return {expr}; }} }}");
+            compile_source(&source).unwrap()
+        };
+        for (n, expected) in [(3, 8), (5, 12), (6, 12)] {
+            for vm in [multiply(&format!("x * {n}")), multiply(&format!("{n} * x"))] {
+                assert!(!vm.contains("call Math.multiply"), "x * {n}:\n{vm}");
+                // function, push argument 0, chain, return
+                let chain = vm.lines().count() - 3;
+                assert_eq!(chain, expected, "x * {n}:\n{vm}");
+                assert!(chain < StrengthReduction::MULTIPLY_CALL_COST);
+            }
+        }
+        assert!(multiply("x * 5").starts_with(
+            "function Main.f 0\npush argument 0\npop temp 0\npush temp 0\n\
+             pop temp 1\npush temp 1\npush temp 1\nadd\n\
+             pop temp 1\npush temp 1\npush temp 1\nadd\n\
+             push temp 0\nadd\n"
+        ));
+        assert!(multiply("x * 7").contains("call Math.multiply 2"));
+    }
+
     #[test]
     fn test_strength_reduction_left_constant() {
         // Test strength reduction when power-of-2 is on the left: 2 * x
@@ -873,10 +1587,10 @@ class Main {
 }
 "#;
         let vm = compile_source(source).unwrap();
-        assert!(vm.contains("if-goto IF_FALSE_"));
-        assert!(vm.contains("goto IF_END_"));
-        assert!(vm.contains("label IF_FALSE_"));
-        assert!(vm.contains("label IF_END_"));
+        assert!(vm.contains("if-goto Main.test$IF_FALSE_"));
+        assert!(vm.contains("goto Main.test$IF_END_"));
+        assert!(vm.contains("label Main.test$IF_FALSE_"));
+        assert!(vm.contains("label Main.test$IF_END_"));
     }
 
     #[test]
@@ -895,9 +1609,9 @@ class Main {
 }
 "#;
         let vm = compile_source(source).unwrap();
-        assert!(vm.contains("if-goto IF_FALSE_"));
-        assert!(vm.contains("goto IF_END_"));
-        assert!(vm.contains("label IF_FALSE_"));
+        assert!(vm.contains("if-goto Main.test$IF_FALSE_"));
+        assert!(vm.contains("goto Main.test$IF_END_"));
+        assert!(vm.contains("label Main.test$IF_FALSE_"));
         assert!(vm.contains("push constant 2"));
     }
 
@@ -916,10 +1630,10 @@ class Main {
 }
 "#;
         let vm = compile_source(source).unwrap();
-        assert!(vm.contains("label WHILE_EXP_"));
-        assert!(vm.contains("if-goto WHILE_END_"));
-        assert!(vm.contains("goto WHILE_EXP_"));
-        assert!(vm.contains("label WHILE_END_"));
+        assert!(vm.contains("label Main.test$WHILE_EXP_"));
+        assert!(vm.contains("if-goto Main.test$WHILE_END_"));
+        assert!(vm.contains("goto Main.test$WHILE_EXP_"));
+        assert!(vm.contains("label Main.test$WHILE_END_"));
     }
 
     #[test]
@@ -1075,22 +1789,1360 @@ class Main {
     }
 
     #[test]
-    fn test_undefined_variable_error() {
+    fn test_unused_class_variables_warn() {
         let source = r#"
-class Main {
-    function void test() {
-        let x = 5;
-        return;
+class Counter {
+    field int count, spare;
+    field int written;
+    static int unused;
+
+    constructor Counter new() {
+        let written = 1;
+        return this;
+    }
+
+    method int get() {
+        var int spare;
+        let spare = 0;
+        return count + spare;
     }
 }
 "#;
-        let result = compile_source(source);
-        assert!(result.is_err());
-        let errors = result.unwrap_err();
+        let tokens = JackTokenizer::new(source).tokenize().unwrap();
+        let class = Parser::new(&tokens).parse().unwrap();
+        let options = CompileOptions {
+            warn_shadowing: false,
+            ..CompileOptions::default()
+        };
+        let output = CodeGenerator::compile_with_config(&class, &options);
+        assert!(output.result.is_ok());
+
+        let messages: Vec<String> = output.warnings.iter().map(|w| w.to_string()).collect();
+        assert_eq!(messages.len(), 4, "{messages:?}");
+        // `count` is read; the local `spare` does not count as a use of the field
+        assert!(matches!(
+            &output.warnings[0],
+            CompileWarning::UnusedClassVariable { name, .. } if name == "spare"
+        ));
+        assert!(matches!(
+            &output.warnings[1],
+            CompileWarning::UnreadClassVariable { name, .. } if name == "written"
+        ));
+        assert!(messages[2].starts_with("static 'unused' declared at"));
+        assert!(matches!(
+            &output.warnings[3],
+            CompileWarning::UninitializedFields { fields, .. } if fields == &["count", "spare"]
+        ));
+    }
+
+    #[test]
+    fn test_lint_leaks() {
+        let source = "class Main { field Ball ball;
+            method void play() { var Ball b; let b = Ball.new(); do b.move(); return; }
+            method void keep() { var Ball b; let b = Ball.new(); let ball = b; return; }
+        }";
+        let tokens = JackTokenizer::new(source).tokenize().unwrap();
+        let class = Parser::new(&tokens).parse().unwrap();
+        let leaks = |lint_leaks| {
+            let options = CompileOptions {
+                lint_leaks,
+                ..CompileOptions::default()
+            };
+            CodeGenerator::compile_with_config(&class, &options)
+                .warnings
+                .into_iter()
+                .filter(|w| matches!(w, CompileWarning::UndisposedObject { .. }))
+                .collect::<Vec<_>>()
+        };
+
+        let warnings = leaks(true);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].code(), "JW0008");
+        assert_eq!(warnings[0].span().map(|span| span.line), Some(2));
         assert!(
-            errors
-                .iter()
-                .any(|e| matches!(e, CompileError::UndefinedVariable { .. }))
+            warnings[0]
+                .to_string()
+                .starts_with("local 'b' holds a new 'Ball' at 2:"),
+            "{}",
+            warnings[0]
+        );
+        assert!(leaks(false).is_empty());
+    }
+
+    #[test]
+    fn test_constructor_uninitialized_fields_warn() {
+        let warnings = |constructor: &str| {
+            let source = format!(
+                "class Point {{ field int x, y; {constructor} method void init() {{ let x = 0; let y = 0; return; }} }}"
+            );
+            let tokens = JackTokenizer::new(&source).tokenize().unwrap();
+            let class = Parser::new(&tokens).parse().unwrap();
+            let output = CodeGenerator::compile_with_config(&class, &CompileOptions::default());
+            assert!(output.result.is_ok());
+            output
+                .warnings
+                .into_iter()
+                .filter(|w| matches!(w, CompileWarning::UninitializedFields { .. }))
+                .collect::<Vec<_>>()
+        };
+
+        assert!(
+            warnings("constructor Point new() { let x = 1; let y = 2; return this; }").is_empty()
         );
+        assert!(warnings("constructor Point new() { do init(); return this; }").is_empty());
+
+        let missing = warnings("constructor Point new() { let x = 1; return this; }");
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[0].code(), "JW0006");
+        assert_eq!(missing[0].span().map(|span| span.line), Some(1));
+        assert!(
+            missing[0]
+                .to_string()
+                .ends_with("may not initialize field 'y'"),
+            "{}",
+            missing[0]
+        );
+    }
+
+    fn shadow_warnings(source: &str, options: &CompileOptions) -> Vec<CompileWarning> {
+        let tokens = JackTokenizer::new(source).tokenize().unwrap();
+        let class = Parser::new(&tokens).parse().unwrap();
+        let output = CodeGenerator::compile_with_config(&class, options);
+        assert!(output.result.is_ok(), "shadowing is legal");
+        output
+            .warnings
+            .into_iter()
+            .filter(|w| matches!(w, CompileWarning::ShadowedClassVariable { .. }))
+            .collect()
+    }
+
+    #[test]
+    fn test_local_shadowing_field_warns() {
+        let source = r#"
+class Counter {
+    field int count;
+
+    method int bump() {
+        var int count;
+        let count = 1;
+        return count;
+    }
+}
+"#;
+        let warnings = shadow_warnings(source, &CompileOptions::default());
+        assert_eq!(warnings.len(), 1);
+        match &warnings[0] {
+            CompileWarning::ShadowedClassVariable {
+                name,
+                kind,
+                span,
+                shadowed_kind,
+                shadowed_type,
+                shadowed_span,
+            } => {
+                assert_eq!(name, "count");
+                assert_eq!(*kind, SymbolKind::Local);
+                assert_eq!(span.line, 6);
+                assert_eq!(*shadowed_kind, SymbolKind::Field);
+                assert_eq!(*shadowed_type, Type::Int);
+                assert_eq!(shadowed_span.line, 3);
+            }
+            other => panic!("unexpected warning: {other:?}"),
+        }
+        assert!(warnings[0].to_string().contains("local 'count'"));
+        assert!(warnings[0].to_string().contains("field 'count' (int)"));
+
+        // Opt-out
+        let options = CompileOptions {
+            warn_shadowing: false,
+            ..CompileOptions::default()
+        };
+        assert!(shadow_warnings(source, &options).is_empty());
+    }
+
+    #[test]
+    fn test_shadowed_field_counts_as_unused() {
+        let unused = |source: &str| {
+            let tokens = JackTokenizer::new(source).tokenize().unwrap();
+            let class = Parser::new(&tokens).parse().unwrap();
+            let output = CodeGenerator::compile_with_config(&class, &CompileOptions::default());
+            assert!(output.result.is_ok());
+            output
+                .warnings
+                .into_iter()
+                .filter(|w| matches!(w, CompileWarning::UnusedClassVariable { .. }))
+                .count()
+        };
+        // Only the local `count` is used
+        let source = r#"
+class Counter {
+    field int count;
+
+    method int bump() {
+        var int count;
+        let count = 1;
+        return count;
+    }
+}
+"#;
+        assert_eq!(unused(source), 1);
+
+        let source = r#"
+class Counter {
+    field int count;
+
+    method int bump() {
+        var int count;
+        let count = 1;
+        return count;
+    }
+
+    method int get() {
+        return count;
+    }
+}
+"#;
+        assert_eq!(unused(source), 0);
+    }
+
+    #[test]
+    fn test_parameter_shadowing_field_warns() {
+        let source = r#"
+class Point {
+    field Point next;
+    static int origin;
+
+    method void link(Point next, int origin) {
+        return;
+    }
+}
+"#;
+        let warnings = shadow_warnings(source, &CompileOptions::default());
+        assert_eq!(warnings.len(), 2);
+        assert!(matches!(
+            &warnings[0],
+            CompileWarning::ShadowedClassVariable {
+                kind: SymbolKind::Argument,
+                shadowed_kind: SymbolKind::Field,
+                shadowed_type: Type::ClassName(class),
+                ..
+            } if class == "Point"
+        ));
+        assert!(matches!(
+            &warnings[1],
+            CompileWarning::ShadowedClassVariable {
+                shadowed_kind: SymbolKind::Static,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_same_local_in_two_subroutines_does_not_warn() {
+        let source = r#"
+class Main {
+    function int f() {
+        var int i;
+        let i = 1;
+        return i;
+    }
+
+    function int g() {
+        var int i;
+        let i = 2;
+        return i;
+    }
+}
+"#;
+        assert!(shadow_warnings(source, &CompileOptions::default()).is_empty());
+    }
+
+    #[test]
+    fn test_string_escape_extension() {
+        let source = r#"
+class Main {
+    function void main() {
+        do Output.printString("a\"\n");
+        return;
+    }
+}
+"#;
+        let tokens = JackTokenizer::new(source)
+            .with_extensions(true)
+            .tokenize()
+            .unwrap();
+        let class = Parser::new(&tokens).parse().unwrap();
+        let vm = CodeGenerator::compile_with_options(&class, false).unwrap();
+
+        assert!(vm.contains("push constant 3\ncall String.new 1\n"));
+        assert!(vm.contains("push constant 34\ncall String.appendChar 2\n"));
+        // `\n` maps to the Hack newline, not ASCII 10
+        assert!(vm.contains("push constant 128\ncall String.appendChar 2\n"));
+        assert!(!vm.contains("push constant 10\n"));
+    }
+
+    #[test]
+    fn test_print_string_literal_fusion() {
+        let source = r#"
+class Main {
+    function void main() {
+        do Output.printString("Hi");
+        do Output.printString("");
+        return;
+    }
+}
+"#;
+        let fused = compile_fused(source);
+        assert_eq!(
+            fused,
+            "function Main.main 0\n\
+             push constant 72\ncall Output.printChar 1\npop temp 0\n\
+             push constant 105\ncall Output.printChar 1\npop temp 0\n\
+             push constant 0\nreturn\n"
+        );
+
+        // Off by default, and unoptimized: the String is built as usual
+        let unfused = compile_source(source).unwrap();
+        assert_eq!(unfused, compile_at(source, OptLevel::O0));
+        assert!(unfused.contains(
+            "push constant 2\ncall String.new 1\n\
+             push constant 72\ncall String.appendChar 2\n\
+             push constant 105\ncall String.appendChar 2\n\
+             call Output.printString 1\npop temp 0\n"
+        ));
+        assert!(!unfused.contains("printChar"));
+    }
+
+    #[test]
+    fn test_print_string_fusion_needs_literal_argument() {
+        for call in [
+            "do Output.printString(s);",
+            "do Output.printString(Main.name());",
+            "do Output.printInt(\"x\");",
+            "do Main.printString(\"x\");",
+        ] {
+            let source = format!(
+                "class Main {{ function String name() {{ return \"n\"; }} \
+                 function void main() {{ var String s; let s = \"s\"; {call} return; }} }}"
+            );
+            let vm = compile_fused(&source);
+            assert!(!vm.contains("printChar"), "{call}: {vm}");
+        }
+
+        // A replacement Output class may print differently
+        let vm = compile_fused(
+            "class Output { function void printString(String s) { return; } \
+             function void main() { do Output.printString(\"x\"); return; } }",
+        );
+        assert!(vm.contains("call Output.printString 1"));
+    }
+
+    /// Compile `source` with `printString` fusion on.
+    fn compile_fused(source: &str) -> String {
+        let tokens = JackTokenizer::new(source).tokenize().unwrap();
+        let class = Parser::new(&tokens).parse().unwrap();
+        let options = CompileOptions {
+            fuse_print_string: true,
+            ..CompileOptions::default()
+        };
+        CodeGenerator::compile_with_config(&class, &options)
+            .result
+            .unwrap()
+    }
+
+    #[test]
+    fn test_compound_assignment_extension() {
+        let source = r#"
+class Main {
+    function void main() {
+        var int x;
+        var Array a;
+        let x += 3;
+        let a[Main.next()] -= x;
+        return;
+    }
+}
+"#;
+        let tokens = JackTokenizer::new(source)
+            .with_extensions(true)
+            .tokenize()
+            .unwrap();
+        let class = Parser::new(&tokens).parse().unwrap();
+        let vm = CodeGenerator::compile_with_options(&class, false).unwrap();
+
+        // Two declared locals plus one synthesized index temp
+        assert!(vm.contains("function Main.main 3"));
+        // let x = x + (3)
+        assert!(vm.contains("push local 0\npush constant 3\nadd\npop local 0\n"));
+        // Index call evaluated exactly once, stored in the temp local
+        assert_eq!(vm.matches("call Main.next 0").count(), 1);
+        assert!(vm.contains("call Main.next 0\npop local 2\n"));
+        // a[t] = a[t] - (x)
+        assert!(vm.contains(
+            "push local 1\npush local 2\nadd\n\
+             push local 1\npush local 2\nadd\npop pointer 1\npush that 0\n\
+             push local 0\nsub\n"
+        ));
+    }
+
+    #[test]
+    fn test_this_in_function_errors() {
+        let source = r#"
+class Main {
+    function Main make() {
+        return this;
+    }
+
+    function void run() {
+        do missing();
+        return;
+    }
+
+    constructor Main new() {
+        return this;
+    }
+
+    method Main self() {
+        return this;
+    }
+}
+"#;
+        let errors = compile_source(source).unwrap_err();
+        assert_eq!(errors.len(), 2, "{errors:?}");
+        assert!(matches!(&errors[0], CompileError::ThisInFunction { span } if span.line == 4));
+        assert!(matches!(
+            &errors[1],
+            CompileError::UnknownSubroutine { name, span, .. } if name == "missing" && span.line == 8
+        ));
+    }
+
+    #[test]
+    fn test_implicit_call_checks() {
+        let source = r#"
+class Shape {
+    field int x;
+
+    method void draw(int dx, int dy) {
+        let x = x + dx + dy;
+        return;
+    }
+
+    function int area() {
+        return 1;
+    }
+
+    method void update() {
+        do draw(1, 2);
+        do draw(1);
+        do erase();
+        let x = area();
+        return;
+    }
+}
+"#;
+        let tokens = JackTokenizer::new(source).tokenize().unwrap();
+        let class = Parser::new(&tokens).parse().unwrap();
+        let output = CodeGenerator::compile_with_config(&class, &CompileOptions::default());
+        let errors = output.result.unwrap_err();
+        assert_eq!(errors.len(), 2, "{errors:?}");
+        assert!(matches!(
+            &errors[0],
+            CompileError::ArgumentCountMismatch { name, expected: 2, found: 1, span }
+                if name == "Shape.draw" && span.line == 16
+        ));
+        assert!(matches!(
+            &errors[1],
+            CompileError::UnknownSubroutine { class, name, span }
+                if class == "Shape" && name == "erase" && span.line == 17
+        ));
+        assert_eq!(errors[1].code(), "JC0014");
+
+        let [warning] = &output.warnings[..] else {
+            panic!("expected one warning: {:?}", output.warnings);
+        };
+        assert_eq!(warning.code(), "JW0007");
+        assert!(
+            warning
+                .to_string()
+                .starts_with("function 'area' called like a method at 18:")
+        );
+        assert!(warning.to_string().ends_with("write 'Shape.area(...)'"));
+    }
+
+    #[test]
+    fn test_correct_implicit_call_is_clean() {
+        let source = r#"
+class Shape {
+    field int x;
+
+    method void draw(int dx, int dy) {
+        let x = x + dx + dy;
+        return;
+    }
+
+    method void update() {
+        do draw(1, 2);
+        return;
+    }
+}
+"#;
+        let tokens = JackTokenizer::new(source).tokenize().unwrap();
+        let class = Parser::new(&tokens).parse().unwrap();
+        let output = CodeGenerator::compile_with_config(&class, &CompileOptions::default());
+        assert!(output.warnings.is_empty(), "{:?}", output.warnings);
+        let vm = output.result.unwrap();
+        assert!(vm.contains(
+            "push pointer 0\npush constant 1\npush constant 2\ncall Shape.draw 3\npop temp 0\n"
+        ));
+    }
+
+    #[test]
+    fn test_os_call_argument_counts_opt_in() {
+        let source = r#"
+class Main {
+    function void main() {
+        var String s;
+        let s = String.new(4);
+        do s.appendChar(65);
+        do Output.printInt();
+        do Output.printString(s);
+        do Math.multiply(1, 2, 3);
+        return;
+    }
+}
+"#;
+        let tokens = JackTokenizer::new(source).tokenize().unwrap();
+        let class = Parser::new(&tokens).parse().unwrap();
+        assert!(compile_source(source).is_ok(), "Unchecked by default");
+
+        let options = CompileOptions {
+            check_os_calls: true,
+            ..CompileOptions::default()
+        };
+        let errors = CodeGenerator::compile_with_config(&class, &options)
+            .result
+            .unwrap_err();
+        assert_eq!(errors.len(), 2, "{errors:?}");
+        assert!(matches!(
+            &errors[0],
+            CompileError::ArgumentCountMismatch { name, expected: 1, found: 0, span }
+                if name == "Output.printInt" && span.line == 7
+        ));
+        assert!(matches!(
+            &errors[1],
+            CompileError::ArgumentCountMismatch { name, expected: 2, found: 3, .. }
+                if name == "Math.multiply"
+        ));
+        assert!(
+            errors[1]
+                .to_string()
+                .starts_with("'Math.multiply' expects 2 argument(s), found 3")
+        );
+    }
+
+    fn compile_bounds_checked(source: &str, bounds_checks: bool) -> String {
+        let tokens = JackTokenizer::new(source).tokenize().unwrap();
+        let class = Parser::new(&tokens).parse().unwrap();
+        let options = CompileOptions {
+            bounds_checks,
+            ..CompileOptions::default()
+        };
+        CodeGenerator::compile_with_config(&class, &options)
+            .result
+            .unwrap()
+    }
+
+    #[test]
+    fn test_bounds_check_negative_constant_index() {
+        let source = r#"
+class Main {
+    function void f(Array a) {
+        let a[-1] = 5;
+        let a[2] = a[3];
+        return;
+    }
+}
+"#;
+        let vm = compile_bounds_checked(source, true);
+        assert_eq!(vm.matches("call Sys.error 1").count(), 1, "{vm}");
+        assert!(vm.contains(
+            "pop temp 1\npush temp 1\npush constant 0\nlt\nnot\nif-goto Main.f$BOUNDS_OK_0\n\
+             push constant 100\ncall Sys.error 1\npop temp 0\nlabel Main.f$BOUNDS_OK_0\npush temp 1\nadd"
+        ));
+    }
+
+    #[test]
+    fn test_bounds_check_constant_length() {
+        let source = r#"
+class Main {
+    function int f(int i) {
+        var Array v;
+        let v = Array.new(4);
+        let v[3] = 1;
+        return v[i];
+    }
+}
+"#;
+        let vm = compile_bounds_checked(source, true);
+        // v[3] is known to be in bounds; v[i] gets both checks
+        assert_eq!(vm.matches("call Sys.error 1").count(), 2, "{vm}");
+        assert!(vm.contains("push constant 100\ncall Sys.error 1"));
+        assert!(vm.contains("push temp 1\npush constant 4\nlt\nif-goto"));
+        assert!(vm.contains("push constant 101\ncall Sys.error 1"));
+    }
+
+    #[test]
+    fn test_bounds_checks_disabled_by_default() {
+        let source = r#"
+class Main {
+    function int f(int i) {
+        var Array v;
+        let v = Array.new(4);
+        let v[i] = v[-1];
+        return v[i];
+    }
+}
+"#;
+        let vm = compile_bounds_checked(source, false);
+        assert_eq!(vm, compile_source(source).unwrap());
+        assert!(!vm.contains("Sys.error"));
+        assert!(!vm.contains("temp 1"));
+    }
+
+    #[test]
+    fn test_method_called_from_function_errors() {
+        let source = r#"
+class Main {
+    function void main() {
+        do helper();
+        return;
+    }
+
+    method void helper() {
+        return;
+    }
+}
+"#;
+        let errors = compile_source(source).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            &errors[0],
+            CompileError::MethodCallWithoutObject { class, name, .. }
+                if class == "Main" && name == "helper"
+        ));
+    }
+
+    #[test]
+    fn test_method_called_from_function_in_expression_errors() {
+        // `two` is small enough to inline, which must not skip the check
+        let source = r#"
+class Main {
+    method int two() {
+        return 2;
+    }
+
+    function int f() {
+        return two() + 1;
+    }
+
+    function void g() {
+        var int x;
+        let x = two();
+        return;
+    }
+
+    constructor Main new() {
+        do two();
+        return this;
+    }
+}
+"#;
+        let tokens = JackTokenizer::new(source).tokenize().unwrap();
+        let class = Parser::new(&tokens).parse().unwrap();
+        for level in [OptLevel::O0, OptLevel::O1, OptLevel::O2] {
+            let errors =
+                CodeGenerator::compile_with_config(&class, &CompileOptions::with_opt_level(level))
+                    .result
+                    .unwrap_err();
+            let lines: Vec<usize> = errors
+                .iter()
+                .map(|e| match e {
+                    CompileError::MethodCallWithoutObject { name, span, .. } if name == "two" => {
+                        span.line
+                    }
+                    other => panic!("{level:?}: unexpected error {other}"),
+                })
+                .collect();
+            // Not the constructor's call at line 19
+            assert_eq!(lines, [8, 13], "{level:?}");
+        }
+    }
+
+    #[test]
+    fn test_assignment_to_this_errors() {
+        let source = r#"
+class Main {
+    method void reset() {
+        let this = null;
+        return;
+    }
+}
+"#;
+        let result = crate::compile_source(source, "Main");
+        assert_eq!(result.errors.len(), 1);
+        assert!(matches!(
+            &result.errors[0],
+            CompileError::AssignmentToThis { .. }
+        ));
+    }
+
+    #[test]
+    fn test_field_read_in_function_errors() {
+        let source = r#"
+class Main {
+    field int x;
+
+    function int get() {
+        return x;
+    }
+}
+"#;
+        let errors = compile_source(source).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            &errors[0],
+            CompileError::FieldInStaticContext { name, .. } if name == "x"
+        ));
+    }
+
+    #[test]
+    fn test_field_write_in_function_errors() {
+        let source = r#"
+class Main {
+    field Array a;
+
+    function void set() {
+        let a[0] = 1;
+        return;
+    }
+}
+"#;
+        let errors = compile_source(source).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            &errors[0],
+            CompileError::FieldInStaticContext { name, .. } if name == "a"
+        ));
+    }
+
+    #[test]
+    fn test_field_access_in_method_compiles() {
+        let source = r#"
+class Main {
+    field Array a;
+    field int x;
+
+    method int get() {
+        let a[0] = x;
+        return x;
+    }
+}
+"#;
+        let vm = compile_source(source).unwrap();
+        assert!(vm.contains("push this 1\n"));
+        assert!(vm.contains("push this 0\n"));
+    }
+
+    #[test]
+    fn test_method_called_on_class_name_errors() {
+        let source = r#"
+class Main {
+    method void helper() {
+        return;
+    }
+
+    method void run() {
+        do Main.helper();
+        return;
+    }
+}
+"#;
+        let errors = compile_source(source).unwrap_err();
+        assert!(matches!(
+            &errors[0],
+            CompileError::MethodCallWithoutObject { name, .. } if name == "helper"
+        ));
+    }
+
+    #[test]
+    fn test_function_called_on_object_errors() {
+        let source = r#"
+class Main {
+    function void util() {
+        return;
+    }
+
+    function void main() {
+        var Main m;
+        do m.util();
+        return;
+    }
+}
+"#;
+        let errors = compile_source(source).unwrap_err();
+        assert!(matches!(
+            &errors[0],
+            CompileError::NonMethodCallOnObject { name, kind, .. }
+                if name == "util" && *kind == SubroutineKind::Function
+        ));
+        assert!(errors[0].to_string().contains("is a function"));
+    }
+
+    #[test]
+    fn test_valid_call_kinds_compile() {
+        let source = r#"
+class Main {
+    field int x;
+
+    constructor Main new() {
+        do helper();
+        return this;
+    }
+
+    method void helper() {
+        do Main.util();
+        return;
+    }
+
+    function void util() {
+        var Main m;
+        let m = Main.new();
+        do m.helper();
+        do Other.anything();
+        return;
+    }
+}
+"#;
+        assert!(compile_source(source).is_ok());
+    }
+
+    #[test]
+    fn test_undefined_variable_error() {
+        let source = r#"
+class Main {
+    function void test() {
+        let x = 5;
+        return;
+    }
+}
+"#;
+        let result = compile_source(source);
+        assert!(result.is_err());
+        let errors = result.unwrap_err();
+        assert!(
+            errors
+                .iter()
+                .any(|e| matches!(e, CompileError::UndefinedVariable { .. }))
+        );
+    }
+
+    #[test]
+    fn test_undefined_variable_reported_once() {
+        let source = r#"
+class Main {
+    function int f() {
+        var int y;
+        let x = 1;
+        let y = x + x;
+        let y = x[2];
+        do Output.printInt(x);
+        return x;
+    }
+
+    function void g() {
+        let x = 2;
+        return;
+    }
+}
+"#;
+        let tokens = JackTokenizer::new(source).tokenize().unwrap();
+        let class = Parser::new(&tokens).parse().unwrap();
+        let mut compiler = CodeGenerator::new();
+        let mut vm_code = String::new();
+        compiler.compile_class(&class, &mut vm_code).unwrap();
+
+        // One report per subroutine, at the first use
+        let undefined: Vec<_> = compiler
+            .errors
+            .iter()
+            .filter_map(|e| match e {
+                CompileError::UndefinedVariable { name, span } => Some((name.as_str(), span.line)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(undefined, vec![("x", 5), ("x", 13)]);
+
+        // The discarded output stays well-formed and stack-balanced
+        let mut depth: i32 = 0;
+        for line in vm_code.lines() {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            match parts.as_slice() {
+                ["push", _, index] => {
+                    index.parse::<u16>().unwrap();
+                    depth += 1;
+                }
+                ["pop", _, index] => {
+                    index.parse::<u16>().unwrap();
+                    depth -= 1;
+                }
+                ["add" | "sub" | "and" | "or" | "eq" | "gt" | "lt"] => depth -= 1,
+                ["neg" | "not"] => {}
+                ["call", _, args] => depth -= args.parse::<i32>().unwrap() - 1,
+                ["function", _, _] => depth = 0,
+                ["return"] => assert_eq!(depth, 1, "unbalanced before return"),
+                ["label" | "goto" | "if-goto", _] => {}
+                other => panic!("malformed VM line: {other:?}"),
+            }
+            assert!(depth >= 0, "stack underflow at '{line}'");
+        }
+    }
+
+    #[test]
+    fn test_verify_slots_catches_understated_locals() {
+        let source = "class Main {
+            method int f(int x) { var int a, b; let b = x; return b; }
+            function int g() { return 0; }
+        }";
+        let tokens = JackTokenizer::new(source).tokenize().unwrap();
+        let class = Parser::new(&tokens).parse().unwrap();
+        let vm_code = compile_at(source, OptLevel::O0);
+        assert!(crate::verify_slots(&vm_code, &class).is_empty());
+
+        let mut compiler = CodeGenerator::with_opt_level(OptLevel::O0);
+        compiler.verify_slots = true;
+        compiler.understate_locals = 1;
+        let mut vm_code = String::new();
+        compiler.compile_class(&class, &mut vm_code).unwrap();
+        assert!(vm_code.starts_with("function Main.f 1\n"));
+        let messages: Vec<String> = compiler.errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            ["internal error: 'Main.f' declares 1 local slot(s) but its code uses 'local 1'"]
+        );
+        assert_eq!(crate::verify_slots(&vm_code, &class).len(), 1);
+
+        // One argument past the parameters and the object
+        let corrupted = compile_at(source, OptLevel::O0).replace("argument 1", "argument 2");
+        let errors = crate::verify_slots(&corrupted, &class);
+        assert!(matches!(
+            errors.as_slice(),
+            [CompileError::SlotOutOfRange { subroutine, segment: Segment::Argument, index: 2, declared: 2 }]
+                if subroutine == "Main.f"
+        ));
+    }
+
+    #[test]
+    fn test_names_like_generated_labels() {
+        // A class and subroutines named after label prefixes and the VM
+        // translator's return-address suffix
+        let source = "class IF_FALSE_0 {
+            function int ret(int x) {
+                if (x < 0) { let x = -x; } else { let x = x + 1; }
+                while (x > 9) { let x = x - 9; }
+                return x;
+            }
+            function int IF_END_0(int x) {
+                if (x) { return IF_FALSE_0.ret(x); }
+                while (x < 0) { let x = IF_FALSE_0.WHILE_EXP_0(x); }
+                return x;
+            }
+            function int WHILE_EXP_0(int x) {
+                while (x) { let x = IF_FALSE_0.ret(x); }
+                return x;
+            }
+        }";
+        for level in [OptLevel::O0, OptLevel::O1, OptLevel::O2] {
+            let vm = compile_at(source, level);
+            let labels: Vec<&str> = vm
+                .lines()
+                .filter_map(|line| line.strip_prefix("label "))
+                .collect();
+            let unique: HashSet<&str> = labels.iter().copied().collect();
+            assert_eq!(unique.len(), labels.len(), "{level:?}: {vm}");
+            assert!(labels.contains(&"IF_FALSE_0.ret$IF_FALSE_0"), "{vm}");
+
+            let functions: HashSet<&str> = vm
+                .lines()
+                .filter_map(|line| line.strip_prefix("function "))
+                .filter_map(|line| line.split(' ').next())
+                .collect();
+            assert!(functions.is_disjoint(&unique));
+            // Every jump names a label of its own subroutine
+            let mut function = "";
+            for line in vm.lines() {
+                if let Some(rest) = line.strip_prefix("function ") {
+                    function = rest.split(' ').next().unwrap();
+                } else if let Some(label) = line
+                    .strip_prefix("goto ")
+                    .or_else(|| line.strip_prefix("if-goto "))
+                {
+                    assert!(label.starts_with(&format!("{function}$")), "{line}");
+                    assert!(unique.contains(label), "{line}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_is_label_safe() {
+        assert!(is_label_safe("Main"));
+        assert!(is_label_safe("_IF_FALSE_0"));
+        assert!(!is_label_safe(""));
+        assert!(!is_label_safe("0main"));
+        assert!(!is_label_safe("Main.main"));
+        assert!(!is_label_safe("f$ret"));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "subroutine name 'Main.f$ret' cannot appear in VM labels")]
+    fn test_label_unsafe_name_asserts() {
+        let tokens = JackTokenizer::new("class Main { function void f() { return; } }")
+            .tokenize()
+            .unwrap();
+        let mut class = Parser::new(&tokens).parse().unwrap();
+        class.subroutine_decs[0].name = "f$ret".to_string();
+        let _ = CodeGenerator::compile(&class);
+    }
+
+    fn compile_at(source: &str, opt_level: OptLevel) -> String {
+        let tokens = JackTokenizer::new(source).tokenize().unwrap();
+        let class = Parser::new(&tokens).parse().unwrap();
+        check_streaming(&class, &CompileOptions::with_opt_level(opt_level));
+        CodeGenerator::compile_with_config(&class, &CompileOptions::with_opt_level(opt_level))
+            .result
+            .unwrap()
+    }
+
+    #[test]
+    fn test_constant_propagation() {
+        let source = "class Main { function int f() { var int x; let x = 5; return x + 1; } }";
+        let o2 = compile_at(source, OptLevel::O2);
+        assert!(o2.contains("push constant 5\npop local 0\npush constant 6\nreturn"));
+        let o1 = compile_at(source, OptLevel::O1);
+        assert!(o1.contains("push local 0\npush constant 1\nadd"), "{o1}");
+
+        // Reassignment from a non-constant ends it; a new constant restarts it
+        let source = "class Main { function int f(int a) { var int x, y;
+            let x = 2; let y = x * 3; let x = a; let y = y + x; let x = -4; return x - y; } }";
+        let vm = compile_at(source, OptLevel::O2);
+        assert!(vm.contains("push constant 6\npop local 1\n"));
+        assert!(vm.contains("push constant 6\npush local 0\nadd\npop local 1"));
+        assert!(
+            vm.contains("push constant 4\nneg\npush local 1\nsub"),
+            "{vm}"
+        );
+    }
+
+    #[test]
+    fn test_constant_propagation_control_flow() {
+        // Branches agreeing on a value keep it; a loop body's assignments
+        // are forgotten from the condition on
+        let source = "class Main { function int f(int a) { var int x, y, i;
+            if (a) { let x = 1; let y = 2; } else { let x = 1; let y = 3; }
+            let i = 0;
+            while (i < 10) { let i = i + x; }
+            return y + i; } }";
+        let vm = compile_at(source, OptLevel::O2);
+        assert!(vm.contains("push local 2\npush constant 10\nlt"), "{vm}");
+        assert!(
+            vm.contains("push local 2\npush constant 1\nadd\npop local 2"),
+            "{vm}"
+        );
+        assert!(
+            vm.contains("push local 1\npush local 2\nadd\nreturn"),
+            "{vm}"
+        );
+
+        // Constant conditions fold, and dead-branch elimination follows
+        let source = "class Main { function void f() { var boolean debug;
+            let debug = false;
+            if (debug) { do Output.printInt(1); }
+            return; } }";
+        assert!(!compile_at(source, OptLevel::O2).contains("Output.printInt"));
+    }
+
+    #[test]
+    fn test_constant_propagation_skips_arrays() {
+        // `a` is an array pointer: never substituted, even when constant
+        let source = "class Main { function int f() { var Array a; var int i;
+            let a = 2048; let i = 1; let a[i] = 7; return a[i] + a; } }";
+        let vm = compile_at(source, OptLevel::O2);
+        assert!(
+            vm.contains("push local 0\npush constant 1\nadd\npush constant 7"),
+            "{vm}"
+        );
+        assert!(
+            vm.contains("push that 0\npush local 0\nadd\nreturn"),
+            "{vm}"
+        );
+    }
+
+    #[test]
+    fn test_labels_scoped_by_subroutine() {
+        let source = r#"
+class Main {
+    function void a() {
+        while (false) {}
+        return;
+    }
+
+    function void b(int x) {
+        if (x) { let x = 0; }
+        return;
+    }
+}
+"#;
+        let vm = compile_source(source).unwrap();
+        assert!(vm.contains("label Main.a$WHILE_EXP_0\n"));
+        assert!(vm.contains("if-goto Main.a$WHILE_END_1\n"));
+        assert!(vm.contains("if-goto Main.b$IF_FALSE_2\n"));
+        assert!(vm.contains("label Main.b$IF_END_3\n"));
+    }
+
+    #[test]
+    fn test_accessor_inlined_at_o2() {
+        let source = r#"
+class Point {
+    field int x;
+    static int count;
+
+    method int getX() { return x; }
+    function int total() { return count + 1; }
+
+    method int twice() {
+        return getX() + Point.total();
+    }
+}
+"#;
+        let vm = compile_at(source, OptLevel::O2);
+        let twice = &vm[vm.find("function Point.twice").unwrap()..];
+        assert!(!twice.contains("call"), "calls not inlined:\n{twice}");
+        assert!(twice.contains("push this 0\npush static 0\npush constant 1\nadd\nadd\n"));
+        // The callees are still emitted for other classes
+        assert!(vm.contains("function Point.getX 0"));
+
+        let vm = compile_at(source, OptLevel::O1);
+        assert!(vm.contains("call Point.getX 1"));
+        assert!(vm.contains("call Point.total 0"));
+    }
+
+    #[test]
+    fn test_inlining_restrictions() {
+        let source = r#"
+class Main {
+    field int x;
+
+    function int loop() { return Main.loop(); }
+    function int one() { return 1; }
+    function int big() { return 1 + 2 + 3 + 4 + 5 + 6 + 7 + 8 + 9; }
+    method int getX() { return x; }
+    method int withArg(int a) { return a; }
+
+    method int shadowed(Main other) {
+        var int x;
+        return getX() + other.getX() + loop() + Main.big() + withArg(1);
+    }
+}
+"#;
+        let vm = compile_at(source, OptLevel::O2);
+        let loop_fn = &vm[vm.find("function Main.loop").unwrap()..];
+        assert!(loop_fn.starts_with("function Main.loop 0\ncall Main.loop 0\n"));
+
+        let caller = &vm[vm.find("function Main.shadowed").unwrap()..];
+        for call in [
+            "call Main.getX 1",      // shadowed by the local `x`
+            "call Main.getX 1\nadd", // explicit receiver
+            "call Main.big 0",       // too large
+            "call Main.withArg 2",
+        ] {
+            assert!(caller.contains(call), "expected '{call}' in:\n{caller}");
+        }
+        // Unqualified call to a function is never inlined
+        assert!(caller.contains("call Main.loop 1"));
+    }
+
+    #[test]
+    fn test_empty_if_without_side_effects() {
+        let source = "class Main { function void main(int x) { if (x < 3) { } if (x) { } else { } return; } }";
+        let vm = compile_at(source, OptLevel::O1);
+        assert_eq!(vm, "function Main.main 0\npush constant 0\nreturn\n");
+        // Unoptimized code keeps the reference shape
+        let o0 = compile_at(source, OptLevel::O0);
+        assert_eq!(o0.matches("label ").count(), 4);
+    }
+
+    #[test]
+    fn test_empty_if_keeps_calls_in_condition() {
+        let source =
+            "class Main { function void main() { if (Keyboard.keyPressed() = 0) { } return; } }";
+        let vm = compile_at(source, OptLevel::O1);
+        assert!(
+            vm.contains("call Keyboard.keyPressed 0\npush constant 0\neq\npop temp 0\n"),
+            "{vm}"
+        );
+        assert!(!vm.contains("label"));
+    }
+
+    #[test]
+    fn test_empty_then_with_else() {
+        let source = "class Main { function void main(int x) { if (x > 3) { } else { do Output.printInt(x); } return; } }";
+        let vm = compile_at(source, OptLevel::O1);
+        assert!(
+            vm.contains("gt\nif-goto Main.main$IF_END_0\npush argument 0\ncall Output.printInt 1\npop temp 0\nlabel Main.main$IF_END_0\n"),
+            "{vm}"
+        );
+        assert_eq!(vm.matches("label ").count(), 1);
+        assert!(!vm.contains("not"));
+
+        // A non-boolean condition takes the else branch for every value but
+        // -1, which a single `if-goto` cannot express
+        let source = "class Main { function void main(int x) { if (x) { } else { do Output.printInt(x); } return; } }";
+        let vm = compile_at(source, OptLevel::O1);
+        assert!(
+            vm.contains("push argument 0\nnot\nif-goto Main.main$IF_FALSE_0\n"),
+            "{vm}"
+        );
+    }
+
+    #[test]
+    fn test_empty_while_body_kept() {
+        // A busy-wait: the loop is the point
+        let source =
+            "class Main { function void main() { while (~Keyboard.keyPressed()) { } return; } }";
+        let vm = compile_at(source, OptLevel::O1);
+        assert!(vm.contains("label Main.main$WHILE_EXP_0\n"), "{vm}");
+        assert!(vm.contains("goto Main.main$WHILE_EXP_0\n"), "{vm}");
+    }
+
+    #[test]
+    fn test_dead_branch_elimination() {
+        let source = r#"
+class Main {
+    function void main() {
+        if (true) { do Output.printInt(1); } else { do Output.printInt(2); }
+        if (~(1 = 1)) { do Output.printInt(3); } else { do Output.printInt(4); }
+        if (false) { do Output.printInt(5); }
+        while (1 > 2) { do Output.printInt(6); }
+        return;
+    }
+}
+"#;
+        let vm = compile_at(source, OptLevel::O2);
+        assert!(vm.contains("push constant 1\ncall Output.printInt 1"));
+        assert!(vm.contains("push constant 4\ncall Output.printInt 1"));
+        for dead in [
+            "push constant 2",
+            "push constant 3",
+            "push constant 5",
+            "push constant 6",
+        ] {
+            assert!(!vm.contains(dead), "untaken branch emitted '{dead}':\n{vm}");
+        }
+        assert!(
+            !vm.contains("label"),
+            "no label scaffolding expected:\n{vm}"
+        );
+        assert!(!vm.contains("if-goto"));
+
+        // O1 keeps every branch
+        let vm = compile_at(source, OptLevel::O1);
+        assert!(vm.contains("push constant 2"));
+        assert!(vm.contains("push constant 6"));
+        assert!(vm.contains("if-goto"));
+    }
+
+    #[test]
+    fn test_dead_branch_keeps_calls_in_condition() {
+        // A call is never folded, even next to a constant that decides the
+        // result, so the call and its branches survive
+        let source = r#"
+class Main {
+    function void main() {
+        if (false & Keyboard.keyPressed()) { do Output.printInt(7); } else { do Output.printInt(8); }
+        while (Keyboard.keyPressed() & false) { do Output.printInt(9); }
+        return;
+    }
+}
+"#;
+        let main = compile_at(source, OptLevel::O2);
+        assert_eq!(
+            main.matches("call Keyboard.keyPressed 0").count(),
+            2,
+            "{main}"
+        );
+        for literal in ["push constant 7", "push constant 8", "push constant 9"] {
+            assert!(main.contains(literal), "missing '{literal}':\n{main}");
+        }
+        // IF_FALSE, IF_END, WHILE_EXP, WHILE_END
+        assert_eq!(main.matches("label ").count(), 4, "{main}");
+        assert_eq!(main.matches("if-goto ").count(), 2, "{main}");
+    }
+
+    #[test]
+    fn test_dead_branch_only_for_boolean_constants() {
+        // `not` + `if-goto` takes the else branch for every value but -1,
+        // so `if (5)` is left to the runtime test rather than folded
+        let source = r#"
+class Main {
+    function void main() {
+        if (5) { do Output.printInt(1); } else { do Output.printInt(2); }
+        return;
+    }
+}
+"#;
+        let vm = compile_at(source, OptLevel::O2);
+        assert!(vm.contains("push constant 5\nnot\nif-goto"), "{vm}");
+        assert!(vm.contains("push constant 1") && vm.contains("push constant 2"));
+        assert_eq!(vm.matches("label ").count(), 2, "{vm}");
+    }
+
+    #[test]
+    fn test_while_true_drops_condition() {
+        let source = r#"
+class Main {
+    function void main() {
+        var int i;
+        while (true) { let i = i + 1; }
+        return;
+    }
+}
+"#;
+        let vm = compile_at(source, OptLevel::O2);
+        assert!(vm.contains("label Main.main$WHILE_EXP_0"));
+        assert!(vm.contains("goto Main.main$WHILE_EXP_0"));
+        assert!(!vm.contains("if-goto"));
+        assert!(!vm.contains("WHILE_END"));
+    }
+
+    #[test]
+    fn test_dead_branch_still_diagnosed() {
+        let source = r#"
+class Main {
+    function void main() {
+        if (false) { let x = 1; }
+        return;
+    }
+}
+"#;
+        let tokens = JackTokenizer::new(source).tokenize().unwrap();
+        let class = Parser::new(&tokens).parse().unwrap();
+        let errors = CodeGenerator::compile_with_config(
+            &class,
+            &CompileOptions::with_opt_level(OptLevel::O2),
+        )
+        .result
+        .unwrap_err();
+        assert!(matches!(&errors[0], CompileError::UndefinedVariable { name, .. } if name == "x"));
     }
 }
@@ -1,6 +1,9 @@
 //! Error types for the Jack compiler.
 
-use jack_analyzer::error::JackError;
+use crate::symbol_table::{Symbol, SymbolKind};
+use crate::vm_writer::Segment;
+use jack_analyzer::ast::{ClassVarKind, SubroutineKind, Type};
+use jack_analyzer::error::{JackError, Severity, json_diagnostic};
 use jack_analyzer::token::Span;
 use std::path::PathBuf;
 use thiserror::Error;
@@ -12,10 +15,112 @@ pub enum CompileError {
     #[error("Undefined variable '{name}' at {span}")]
     UndefinedVariable { name: String, span: Span },
 
+    /// Local variable used before its (late, extension) `var` declaration.
+    #[error("Variable '{name}' used at {span} before its declaration at {declared}")]
+    UsedBeforeDeclaration {
+        name: String,
+        declared: Span,
+        span: Span,
+    },
+
     /// Variable declared twice in the same scope.
     #[error("Duplicate definition of '{name}' at {span}")]
     DuplicateDefinition { name: String, span: Span },
 
+    /// Assignment to `this`, which would corrupt the object pointer.
+    #[error("Cannot assign to 'this' at {span}")]
+    AssignmentToThis { span: Span },
+
+    /// `this`, or an implicit `this` receiver, used in a function.
+    #[error(
+        "'this' used in a function at {span}; only constructors and methods have a current object"
+    )]
+    ThisInFunction { span: Span },
+
+    /// Field referenced from a function, where there is no `this`.
+    #[error(
+        "Field '{name}' used in a function at {span}; make the subroutine a method or pass the object explicitly"
+    )]
+    FieldInStaticContext { name: String, span: Span },
+
+    /// Method called with no object: from a function, or as `Class.method()`.
+    #[error(
+        "Method '{class}.{name}' called without an object at {span}; call it on an instance or from a method"
+    )]
+    MethodCallWithoutObject {
+        class: String,
+        name: String,
+        span: Span,
+    },
+
+    /// Function or constructor called through an object variable.
+    #[error(
+        "'{class}.{name}' is a {}, not a method, at {span}; call it as '{class}.{name}(...)'",
+        .kind.as_str()
+    )]
+    NonMethodCallOnObject {
+        class: String,
+        name: String,
+        kind: SubroutineKind,
+        span: Span,
+    },
+
+    /// Receiver-less call to a subroutine the current class does not declare.
+    #[error("Unknown subroutine '{class}.{name}' at {span}; '{class}' declares no such subroutine")]
+    UnknownSubroutine {
+        class: String,
+        name: String,
+        span: Span,
+    },
+
+    /// Call to a known subroutine with the wrong number of arguments.
+    #[error("'{name}' expects {expected} argument(s), found {found} at {span}")]
+    ArgumentCountMismatch {
+        name: String,
+        expected: u16,
+        found: u16,
+        span: Span,
+    },
+
+    /// `Foo.jack` declares a class other than `Foo`.
+    #[error(
+        "class '{found}' declared in '{expected}.jack'; the class name must match the file name"
+    )]
+    ClassNameMismatch { expected: String, found: String },
+
+    /// Push/pop operand outside its segment (or a pop into `constant`).
+    #[error("Invalid VM operand '{command} {segment} {index}'")]
+    InvalidVmOperand {
+        command: &'static str,
+        segment: Segment,
+        index: u16,
+    },
+
+    /// Generated code over the `max_vm_lines_*` budget.
+    #[error("generated {lines} VM instructions, over the budget of {limit}")]
+    CodeSizeBudgetExceeded { lines: usize, limit: usize },
+
+    /// Generated code with an inconsistent stack depth (internal error,
+    /// reported by the `verify_stack` option).
+    #[error("internal error: stack effect violation at VM line {line} '{instruction}': {message}")]
+    StackEffectViolation {
+        line: usize,
+        instruction: String,
+        message: String,
+    },
+
+    /// Generated code using a local or argument slot its function does not
+    /// declare (internal error, see [`slot_verify`](crate::slot_verify)).
+    #[error(
+        "internal error: '{subroutine}' declares {declared} {segment} slot(s) but its code uses '{segment} {index}'"
+    )]
+    SlotOutOfRange {
+        subroutine: String,
+        segment: Segment,
+        index: u16,
+        declared: u16,
+    },
+
     /// Lexical or syntax error from parser.
     #[error("Parse error: {0}")]
     Parse(#[from] JackError),
@@ -30,6 +135,64 @@ pub enum CompileError {
 }
 
 impl CompileError {
+    /// Source location of the error, if it has one.
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            Self::UndefinedVariable { span, .. }
+            | Self::UsedBeforeDeclaration { span, .. }
+            | Self::DuplicateDefinition { span, .. }
+            | Self::AssignmentToThis { span }
+            | Self::ThisInFunction { span }
+            | Self::FieldInStaticContext { span, .. }
+            | Self::MethodCallWithoutObject { span, .. }
+            | Self::NonMethodCallOnObject { span, .. }
+            | Self::UnknownSubroutine { span, .. }
+            | Self::ArgumentCountMismatch { span, .. } => Some(span),
+            Self::Parse(error) => error.span(),
+            Self::ClassNameMismatch { .. }
+            | Self::InvalidVmOperand { .. }
+            | Self::CodeSizeBudgetExceeded { .. }
+            | Self::StackEffectViolation { .. }
+            | Self::SlotOutOfRange { .. }
+            | Self::Io { .. } => None,
+        }
+    }
+
+    /// Stable diagnostic code (`JC0001`...). Parse errors keep the
+    /// analyzer's `JA` code.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::UndefinedVariable { .. } => "JC0001",
+            Self::DuplicateDefinition { .. } => "JC0002",
+            Self::AssignmentToThis { .. } => "JC0003",
+            Self::ThisInFunction { .. } => "JC0004",
+            Self::FieldInStaticContext { .. } => "JC0005",
+            Self::MethodCallWithoutObject { .. } => "JC0006",
+            Self::NonMethodCallOnObject { .. } => "JC0007",
+            Self::ArgumentCountMismatch { .. } => "JC0008",
+            Self::ClassNameMismatch { .. } => "JC0009",
+            Self::InvalidVmOperand { .. } => "JC0010",
+            Self::Io { .. } => "JC0011",
+            Self::CodeSizeBudgetExceeded { .. } => "JC0012",
+            Self::UsedBeforeDeclaration { .. } => "JC0013",
+            Self::UnknownSubroutine { .. } => "JC0014",
+            Self::StackEffectViolation { .. } => "JC0015",
+            Self::SlotOutOfRange { .. } => "JC0016",
+            Self::Parse(error) => error.code(),
+        }
+    }
+
+    /// This error as a one-line JSON diagnostic.
+    pub fn to_json(&self, file: &str) -> String {
+        json_diagnostic(
+            file,
+            self.span(),
+            self.code(),
+            &self.to_string(),
+            Severity::Error,
+        )
+    }
+
     /// Create an IO error.
     pub fn io(path: impl Into<PathBuf>, source: std::io::Error) -> Self {
         Self::Io {
@@ -46,6 +209,15 @@ impl CompileError {
         }
     }
 
+    /// Create a use-before-declaration error.
+    pub fn used_before_declaration(name: impl Into<String>, declared: Span, span: Span) -> Self {
+        Self::UsedBeforeDeclaration {
+            name: name.into(),
+            declared,
+            span,
+        }
+    }
+
     /// Create a duplicate definition error.
     pub fn duplicate_definition(name: impl Into<String>, span: Span) -> Self {
         Self::DuplicateDefinition {
@@ -53,6 +225,358 @@ impl CompileError {
             span,
         }
     }
+
+    /// Create an assignment-to-this error.
+    pub fn assignment_to_this(span: Span) -> Self {
+        Self::AssignmentToThis { span }
+    }
+
+    /// Create a class name mismatch error.
+    pub fn class_name_mismatch(expected: impl Into<String>, found: impl Into<String>) -> Self {
+        Self::ClassNameMismatch {
+            expected: expected.into(),
+            found: found.into(),
+        }
+    }
+
+    /// Create an invalid VM operand error.
+    pub fn invalid_vm_operand(command: &'static str, segment: Segment, index: u16) -> Self {
+        Self::InvalidVmOperand {
+            command,
+            segment,
+            index,
+        }
+    }
+
+    /// Create a code size budget error.
+    pub fn code_size_budget_exceeded(lines: usize, limit: usize) -> Self {
+        Self::CodeSizeBudgetExceeded { lines, limit }
+    }
+
+    /// Create a stack effect violation at 1-based VM `line`.
+    pub fn stack_effect_violation(
+        line: usize,
+        instruction: impl Into<String>,
+        message: impl Into<String>,
+    ) -> Self {
+        Self::StackEffectViolation {
+            line,
+            instruction: instruction.into(),
+            message: message.into(),
+        }
+    }
+
+    /// Create a slot-out-of-range error for function `subroutine`.
+    pub fn slot_out_of_range(
+        subroutine: impl Into<String>,
+        segment: Segment,
+        index: u16,
+        declared: u16,
+    ) -> Self {
+        Self::SlotOutOfRange {
+            subroutine: subroutine.into(),
+            segment,
+            index,
+            declared,
+        }
+    }
+
+    /// Create a this-in-function error.
+    pub fn this_in_function(span: Span) -> Self {
+        Self::ThisInFunction { span }
+    }
+
+    /// Create a field-in-static-context error.
+    pub fn field_in_static_context(name: impl Into<String>, span: Span) -> Self {
+        Self::FieldInStaticContext {
+            name: name.into(),
+            span,
+        }
+    }
+
+    /// Create a method-called-without-object error.
+    pub fn method_call_without_object(
+        class: impl Into<String>,
+        name: impl Into<String>,
+        span: Span,
+    ) -> Self {
+        Self::MethodCallWithoutObject {
+            class: class.into(),
+            name: name.into(),
+            span,
+        }
+    }
+
+    /// Create an unknown subroutine error for `class.name`.
+    pub fn unknown_subroutine(
+        class: impl Into<String>,
+        name: impl Into<String>,
+        span: Span,
+    ) -> Self {
+        Self::UnknownSubroutine {
+            class: class.into(),
+            name: name.into(),
+            span,
+        }
+    }
+
+    /// Create an argument count mismatch error for `class.name`.
+    pub fn argument_count_mismatch(
+        class: &str,
+        name: &str,
+        expected: u16,
+        found: u16,
+        span: Span,
+    ) -> Self {
+        Self::ArgumentCountMismatch {
+            name: format!("{class}.{name}"),
+            expected,
+            found,
+            span,
+        }
+    }
+
+    /// Create a function-or-constructor-called-on-object error.
+    pub fn non_method_call_on_object(
+        class: impl Into<String>,
+        name: impl Into<String>,
+        kind: SubroutineKind,
+        span: Span,
+    ) -> Self {
+        Self::NonMethodCallOnObject {
+            class: class.into(),
+            name: name.into(),
+            kind,
+            span,
+        }
+    }
+}
+
+/// Non-fatal diagnostics reported alongside the generated code.
+///
+/// Warnings never cause a compilation to fail; they are collected on
+/// [`CompileResult::warnings`](crate::CompileResult::warnings).
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum CompileWarning {
+    /// Local or parameter shadows a class-level variable.
+    #[error(
+        "{} '{name}' at {span} shadows {} '{name}' ({}) declared at {shadowed_span}",
+        .kind.as_str(),
+        .shadowed_kind.as_str(),
+        .shadowed_type.as_str()
+    )]
+    ShadowedClassVariable {
+        name: String,
+        kind: SymbolKind,
+        span: Span,
+        shadowed_kind: SymbolKind,
+        shadowed_type: Type,
+        shadowed_span: Span,
+    },
+
+    /// `Foo.jack` declares a class other than `Foo` (lenient mode).
+    #[error(
+        "class '{found}' declared in '{expected}.jack'; the class name must match the file name"
+    )]
+    ClassNameMismatch { expected: String, found: String },
+
+    /// Field or static never read or written.
+    #[error("{} '{name}' declared at {span} is never used", .kind.as_str())]
+    UnusedClassVariable {
+        name: String,
+        kind: ClassVarKind,
+        span: Span,
+    },
+
+    /// Local read before it is assigned on every path to the read.
+    #[error("local '{name}' may be used before it is assigned at {span}")]
+    UsedBeforeAssigned { name: String, span: Span },
+
+    /// Field or static assigned but never read.
+    #[error("{} '{name}' declared at {span} is assigned but never read", .kind.as_str())]
+    UnreadClassVariable {
+        name: String,
+        kind: ClassVarKind,
+        span: Span,
+    },
+
+    /// Constructor that leaves fields unassigned on some path.
+    #[error(
+        "constructor '{constructor}' at {span} may not initialize {}",
+        quoted_list(.fields)
+    )]
+    UninitializedFields {
+        constructor: String,
+        fields: Vec<String>,
+        span: Span,
+    },
+
+    /// Function or constructor of the current class called without its
+    /// class name, which compiles as a method call on `this`.
+    #[error(
+        "{} '{name}' called like a method at {span}; write '{class}.{name}(...)'",
+        .kind.as_str()
+    )]
+    ImplicitFunctionCall {
+        class: String,
+        name: String,
+        kind: SubroutineKind,
+        span: Span,
+    },
+
+    /// Object constructed into a local that is never disposed, passed on,
+    /// returned or stored (leak lint).
+    #[error(
+        "local '{name}' holds a new '{class}' at {span} that is never disposed, passed on, returned or stored; call {name}.dispose() when done"
+    )]
+    UndisposedObject {
+        name: String,
+        class: String,
+        span: Span,
+    },
+}
+
+impl CompileWarning {
+    /// Source location of the warning, if it has one.
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            Self::ShadowedClassVariable { span, .. }
+            | Self::UnusedClassVariable { span, .. }
+            | Self::UsedBeforeAssigned { span, .. }
+            | Self::UnreadClassVariable { span, .. }
+            | Self::UninitializedFields { span, .. }
+            | Self::ImplicitFunctionCall { span, .. }
+            | Self::UndisposedObject { span, .. } => Some(span),
+            Self::ClassNameMismatch { .. } => None,
+        }
+    }
+
+    /// Stable diagnostic code (`JW0001`...).
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::ShadowedClassVariable { .. } => "JW0001",
+            Self::ClassNameMismatch { .. } => "JW0002",
+            Self::UnusedClassVariable { .. } => "JW0003",
+            Self::UsedBeforeAssigned { .. } => "JW0004",
+            Self::UnreadClassVariable { .. } => "JW0005",
+            Self::UninitializedFields { .. } => "JW0006",
+            Self::ImplicitFunctionCall { .. } => "JW0007",
+            Self::UndisposedObject { .. } => "JW0008",
+        }
+    }
+
+    /// This warning as a one-line JSON diagnostic.
+    pub fn to_json(&self, file: &str) -> String {
+        json_diagnostic(
+            file,
+            self.span(),
+            self.code(),
+            &self.to_string(),
+            Severity::Warning,
+        )
+    }
+
+    /// Create a shadowed class variable warning.
+    pub fn shadowed_class_variable(
+        name: impl Into<String>,
+        kind: SymbolKind,
+        span: Span,
+        shadowed: &Symbol,
+    ) -> Self {
+        Self::ShadowedClassVariable {
+            name: name.into(),
+            kind,
+            span,
+            shadowed_kind: shadowed.kind,
+            shadowed_type: shadowed.symbol_type.clone(),
+            shadowed_span: shadowed.span.clone(),
+        }
+    }
+
+    /// Create a class name mismatch warning.
+    pub fn class_name_mismatch(expected: impl Into<String>, found: impl Into<String>) -> Self {
+        Self::ClassNameMismatch {
+            expected: expected.into(),
+            found: found.into(),
+        }
+    }
+
+    /// Create a used-before-assigned warning.
+    pub fn used_before_assigned(name: impl Into<String>, span: Span) -> Self {
+        Self::UsedBeforeAssigned {
+            name: name.into(),
+            span,
+        }
+    }
+
+    /// Create an unused class variable warning.
+    pub fn unused_class_variable(name: impl Into<String>, kind: ClassVarKind, span: Span) -> Self {
+        Self::UnusedClassVariable {
+            name: name.into(),
+            kind,
+            span,
+        }
+    }
+
+    /// Create an unread class variable warning.
+    pub fn unread_class_variable(name: impl Into<String>, kind: ClassVarKind, span: Span) -> Self {
+        Self::UnreadClassVariable {
+            name: name.into(),
+            kind,
+            span,
+        }
+    }
+
+    /// Create an uninitialized fields warning.
+    pub fn uninitialized_fields<S: Into<String>>(
+        constructor: impl Into<String>,
+        fields: impl IntoIterator<Item = S>,
+        span: Span,
+    ) -> Self {
+        Self::UninitializedFields {
+            constructor: constructor.into(),
+            fields: fields.into_iter().map(Into::into).collect(),
+            span,
+        }
+    }
+
+    /// Create an implicit function call warning.
+    pub fn implicit_function_call(
+        class: impl Into<String>,
+        name: impl Into<String>,
+        kind: SubroutineKind,
+        span: Span,
+    ) -> Self {
+        Self::ImplicitFunctionCall {
+            class: class.into(),
+            name: name.into(),
+            kind,
+            span,
+        }
+    }
+
+    /// Create an undisposed object warning.
+    pub fn undisposed_object(
+        name: impl Into<String>,
+        class: impl Into<String>,
+        span: Span,
+    ) -> Self {
+        Self::UndisposedObject {
+            name: name.into(),
+            class: class.into(),
+            span,
+        }
+    }
+}
+
+/// `'a'`, `'a' and 'b'`, `'a', 'b' and 'c'`, prefixed by `field` / `fields`.
+fn quoted_list(names: &[String]) -> String {
+    let quoted: Vec<String> = names.iter().map(|name| format!("'{name}'")).collect();
+    match quoted.split_last() {
+        Some((last, [])) => format!("field {last}"),
+        Some((last, rest)) => format!("fields {} and {last}", rest.join(", ")),
+        None => "fields".to_string(),
+    }
 }
 
 #[cfg(test)]
@@ -66,4 +590,20 @@ mod tests {
         assert!(err.to_string().contains("foo"));
         assert!(err.to_string().contains("Undefined"));
     }
+
+    #[test]
+    fn test_codes_and_spans() {
+        let span = Span::new(0, 5, 4, 9);
+        let err = CompileError::undefined_variable("foo", span.clone());
+        assert_eq!(err.code(), "JC0001");
+        assert_eq!(err.span(), Some(&span));
+
+        let err = CompileError::from(JackError::syntax(span.clone(), "oops"));
+        assert_eq!(err.code(), "JA0002");
+        assert_eq!(err.span(), Some(&span));
+
+        let warning = CompileWarning::class_name_mismatch("Main", "Foo");
+        assert_eq!(warning.code(), "JW0002");
+        assert_eq!(warning.span(), None);
+    }
 }
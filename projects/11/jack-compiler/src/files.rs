@@ -0,0 +1,130 @@
+//! File-system entry points: single files, directories and output files.
+//!
+//! Compiled only with the `fs` feature (on by default).
+
+use crate::error::CompileError;
+use crate::{CompileOptions, CompileResult, compile_checked, count_vm_lines};
+use jack_analyzer::par_map;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Compile a single Jack file.
+pub fn compile_file(path: &Path) -> CompileResult {
+    compile_file_with_options(path, CompileOptions::default())
+}
+
+/// Compile a single Jack file with custom options.
+pub fn compile_file_with_options(path: &Path, options: CompileOptions) -> CompileResult {
+    let filename = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("unknown")
+        .to_string();
+
+    let source = match fs::read_to_string(path) {
+        Ok(s) => s,
+        Err(e) => {
+            return CompileResult {
+                filename,
+                vm_code: String::new(),
+                errors: vec![CompileError::io(path, e)],
+                warnings: Vec::new(),
+            };
+        }
+    };
+
+    compile_checked(&source, &filename, options, true)
+}
+
+/// Compile all Jack files in a directory.
+///
+/// Results are in [`CompileOptions::file_order`], alphabetical by default.
+pub fn compile_directory(dir: &Path) -> Vec<CompileResult> {
+    compile_directory_with_options(dir, CompileOptions::default())
+}
+
+/// Compile all Jack files in a directory with custom options.
+///
+/// If the files together exceed [`CompileOptions::max_vm_lines_total`], an
+/// extra result named [`TOTAL_BUDGET_FILENAME`] carries the error.
+pub fn compile_directory_with_options(dir: &Path, options: CompileOptions) -> Vec<CompileResult> {
+    compile_directory_with_progress(dir, options, |_| {})
+}
+
+/// Compile all Jack files in a directory, calling `on_file` with each
+/// file's [`CompileResult::filename`] as soon as it is compiled.
+///
+/// Files are compiled in parallel, so `on_file` may be called from several
+/// threads and in any order. The returned results are the same as from
+/// [`compile_directory_with_options`].
+pub fn compile_directory_with_progress(
+    dir: &Path,
+    options: CompileOptions,
+    on_file: impl Fn(&str) + Sync,
+) -> Vec<CompileResult> {
+    let jack_files = match directory_files(dir, options) {
+        Ok(files) => files,
+        Err(e) => {
+            return vec![CompileResult {
+                filename: dir.to_string_lossy().to_string(),
+                vm_code: String::new(),
+                errors: vec![e],
+                warnings: Vec::new(),
+            }];
+        }
+    };
+
+    // Parallel compilation
+    let mut results = par_map(&jack_files, options.threads, |path| {
+        let result = compile_file_with_options(path, options);
+        on_file(&result.filename);
+        result
+    });
+
+    results.extend(total_budget_result(&results, options));
+    results
+}
+
+/// Name of the extra result reporting an exceeded
+/// [`CompileOptions::max_vm_lines_total`].
+pub const TOTAL_BUDGET_FILENAME: &str = "<total>";
+
+/// The `.jack` files of a directory in [`CompileOptions::file_order`].
+pub(crate) fn directory_files(
+    dir: &Path,
+    options: CompileOptions,
+) -> Result<Vec<PathBuf>, CompileError> {
+    let mut jack_files: Vec<_> = fs::read_dir(dir)
+        .map_err(|e| CompileError::io(dir, e))?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|ext| ext == "jack"))
+        .collect();
+    options.file_order.sort(&mut jack_files);
+    Ok(jack_files)
+}
+
+/// The error result for files exceeding [`CompileOptions::max_vm_lines_total`]
+/// together, if they do.
+pub(crate) fn total_budget_result<'a>(
+    results: impl IntoIterator<Item = &'a CompileResult>,
+    options: CompileOptions,
+) -> Option<CompileResult> {
+    let limit = options.max_vm_lines_total?;
+    let lines = results
+        .into_iter()
+        .map(|r| count_vm_lines(&r.vm_code))
+        .sum();
+    (lines > limit).then(|| CompileResult {
+        filename: TOTAL_BUDGET_FILENAME.to_string(),
+        vm_code: String::new(),
+        errors: vec![CompileError::code_size_budget_exceeded(lines, limit)],
+        warnings: Vec::new(),
+    })
+}
+
+/// Write a compile result to an output file.
+pub fn write_result(result: &CompileResult, output_dir: &Path) -> Result<(), CompileError> {
+    let vm_path = output_dir.join(format!("{}.vm", result.filename));
+    fs::write(&vm_path, &result.vm_code).map_err(|e| CompileError::io(&vm_path, e))
+}
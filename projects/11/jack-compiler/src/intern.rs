@@ -0,0 +1,59 @@
+//! String interning for names the code generator handles repeatedly.
+//!
+//! Variable, class and subroutine names recur throughout a class: every use
+//! of a variable looks up its symbol, every call names its target class.
+//! Interning stores each distinct name once as an `Arc<str>`; later requests
+//! for the same name only bump a reference count instead of allocating.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// A deduplicating store of names.
+#[derive(Debug, Default)]
+pub struct Interner {
+    names: HashSet<Arc<str>>,
+}
+
+impl Interner {
+    /// Create an empty interner.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The shared copy of `name`, allocated on first use only.
+    pub fn intern(&mut self, name: &str) -> Arc<str> {
+        if let Some(interned) = self.names.get(name) {
+            return Arc::clone(interned);
+        }
+        let interned: Arc<str> = Arc::from(name);
+        self.names.insert(Arc::clone(&interned));
+        interned
+    }
+
+    /// Number of distinct names interned.
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Whether no name has been interned yet.
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intern_shares_one_copy() {
+        let mut interner = Interner::new();
+        let a = interner.intern("count");
+        let b = interner.intern("count");
+        let c = interner.intern("total");
+        assert!(Arc::ptr_eq(&a, &b));
+        assert!(!Arc::ptr_eq(&a, &c));
+        assert_eq!(&*a, "count");
+        assert_eq!(interner.len(), 2);
+    }
+}
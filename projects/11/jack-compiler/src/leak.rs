@@ -0,0 +1,224 @@
+//! Heuristic leak analysis for objects constructed into locals.
+//!
+//! Jack has no garbage collector: an object whose last reference is a local
+//! is lost heap space once the subroutine returns, unless it is disposed.
+//! [`undisposed_locals`] finds locals assigned `ClassName.new(...)` that are
+//! never disposed and never leave the subroutine.
+//!
+//! The analysis is syntactic and errs towards silence. A local is kept alive
+//! (and not reported) if it appears anywhere as a call argument, in a
+//! returned value, or in the value or index of any `let`, or if `.dispose()`
+//! is called on it. Paths are not considered: a `dispose` in one `if` branch
+//! clears the local for the whole subroutine.
+
+use std::collections::HashSet;
+
+use jack_analyzer::ast::{Expression, Statement, SubroutineCall, SubroutineDec, Term};
+use jack_analyzer::token::Span;
+
+use crate::registry::is_os_class;
+
+/// Find locals holding a newly constructed object that is never disposed,
+/// passed on, returned or stored.
+///
+/// Returns `(local, class, span)` for each such local, at the first `let`
+/// constructing into it. `ClassName.new(...)` counts as a constructor call
+/// unless `ClassName` is an OS class, or `is_variable` says it names a
+/// variable (then the call is a method call on it).
+pub fn undisposed_locals(
+    sub: &SubroutineDec,
+    is_variable: impl Fn(&str) -> bool,
+) -> Vec<(String, String, Span)> {
+    let mut finder = LeakFinder {
+        locals: sub
+            .body
+            .var_decs
+            .iter()
+            .flat_map(|dec| dec.names.iter().map(String::as_str))
+            .collect(),
+        is_variable,
+        constructed: Vec::new(),
+        kept: HashSet::new(),
+    };
+    finder.statements(&sub.body.statements);
+
+    let LeakFinder {
+        constructed, kept, ..
+    } = finder;
+    constructed
+        .into_iter()
+        .filter(|(name, _, _)| !kept.contains(name))
+        .map(|(name, class, span)| (name.to_string(), class.to_string(), span.clone()))
+        .collect()
+}
+
+struct LeakFinder<'a, F> {
+    locals: HashSet<&'a str>,
+    is_variable: F,
+    /// First construction into each local, in source order.
+    constructed: Vec<(&'a str, &'a str, &'a Span)>,
+    /// Locals that escape or are disposed somewhere.
+    kept: HashSet<&'a str>,
+}
+
+impl<'a, F: Fn(&str) -> bool> LeakFinder<'a, F> {
+    fn statements(&mut self, stmts: &'a [Statement]) {
+        for stmt in stmts {
+            self.statement(stmt);
+        }
+    }
+
+    fn statement(&mut self, stmt: &'a Statement) {
+        match stmt {
+            Statement::Let(s) => {
+                if let Some(index) = &s.index {
+                    self.expression(index, true);
+                }
+                match self.constructor_call(&s.value) {
+                    Some(call)
+                        if s.index.is_none() && self.locals.contains(s.var_name.as_str()) =>
+                    {
+                        let name = s.var_name.as_str();
+                        if !self.constructed.iter().any(|(local, _, _)| *local == name) {
+                            let class = call.receiver.as_deref().unwrap_or_default();
+                            self.constructed.push((name, class, &s.span));
+                        }
+                        self.call(call);
+                    }
+                    _ => self.expression(&s.value, true),
+                }
+            }
+            Statement::If(s) => {
+                self.expression(&s.condition, false);
+                self.statements(&s.then_statements);
+                if let Some(stmts) = &s.else_statements {
+                    self.statements(stmts);
+                }
+            }
+            Statement::While(s) => {
+                self.expression(&s.condition, false);
+                self.statements(&s.statements);
+            }
+            Statement::Do(s) => self.call(&s.call),
+            Statement::Return(s) => {
+                if let Some(value) = &s.value {
+                    self.expression(value, true);
+                }
+            }
+        }
+    }
+
+    /// The call of an expression that is just `ClassName.new(...)`.
+    fn constructor_call(&self, expr: &'a Expression) -> Option<&'a SubroutineCall> {
+        let Term::SubroutineCall(call) = &expr.term else {
+            return None;
+        };
+        let class = call.receiver.as_deref()?;
+        let constructor = expr.ops.is_empty()
+            && call.name == "new"
+            && !self.locals.contains(class)
+            && !(self.is_variable)(class)
+            && !is_os_class(class);
+        constructor.then_some(call)
+    }
+
+    /// Walk an expression; with `escapes`, a bare local in it is kept.
+    fn expression(&mut self, expr: &'a Expression, escapes: bool) {
+        self.term(&expr.term, escapes);
+        for (_, term) in &expr.ops {
+            self.term(term, escapes);
+        }
+    }
+
+    fn term(&mut self, term: &'a Term, escapes: bool) {
+        match term {
+            Term::VarName(name, _) => {
+                if escapes {
+                    self.kept.insert(name);
+                }
+            }
+            Term::ArrayAccess(_, index, _) => self.expression(index, escapes),
+            Term::SubroutineCall(call) => self.call(call),
+            Term::Parenthesized(inner, _) => self.expression(inner, escapes),
+            Term::UnaryOp(_, inner, _) => self.term(inner, escapes),
+            Term::IntegerConstant(..) | Term::StringConstant(..) | Term::KeywordConstant(..) => {}
+        }
+    }
+
+    /// Arguments escape into the callee; `local.dispose()` disposes it.
+    fn call(&mut self, call: &'a SubroutineCall) {
+        if call.name == "dispose"
+            && let Some(receiver) = &call.receiver
+        {
+            self.kept.insert(receiver);
+        }
+        for arg in &call.arguments {
+            self.expression(arg, true);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jack_analyzer::parser::Parser;
+    use jack_analyzer::tokenizer::JackTokenizer;
+
+    fn leaks(body: &str) -> Vec<(String, String)> {
+        let source = format!(
+            "class Main {{ field Ball kept; method Ball f(Ball arg) {{ var Ball b, c; var int n; {body} }} }}"
+        );
+        let tokens = JackTokenizer::new(&source).tokenize().unwrap();
+        let class = Parser::new(&tokens).parse().unwrap();
+        undisposed_locals(&class.subroutine_decs[0], |name| {
+            ["kept", "arg"].contains(&name)
+        })
+        .into_iter()
+        .map(|(name, class, _)| (name, class))
+        .collect()
+    }
+
+    #[test]
+    fn test_leaked_local() {
+        assert_eq!(
+            leaks("let b = Ball.new(1, 2); do b.draw(); let n = b.size(); return null;"),
+            [("b".to_string(), "Ball".to_string())]
+        );
+        // Reported once, even if constructed twice
+        assert_eq!(
+            leaks("let b = Ball.new(1, 2); let b = Ball.new(3, 4); return null;").len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_disposed_local() {
+        assert!(leaks("let b = Ball.new(1, 2); do b.dispose(); return null;").is_empty());
+        // Any path counts
+        assert!(
+            leaks("let b = Ball.new(1, 2); if (n) { do b.dispose(); } return null;").is_empty()
+        );
+    }
+
+    #[test]
+    fn test_escaping_local() {
+        assert!(leaks("let b = Ball.new(1, 2); return b;").is_empty());
+        assert!(leaks("let b = Ball.new(1, 2); let kept = b; return null;").is_empty());
+        assert!(leaks("let b = Ball.new(1, 2); do Main.keep(b); return null;").is_empty());
+        assert!(leaks("let b = Ball.new(1, 2); let c = Ball.new(b, 0); return c;").is_empty());
+        // Reading through it is not an escape, comparing it is not either
+        assert_eq!(
+            leaks("let b = Ball.new(1, 2); if (b = null) { return null; } return kept;").len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_not_a_constructor_call() {
+        // OS classes, method calls on variables, non-`new` functions
+        assert!(leaks("let b = String.new(4); let c = Array.new(2); return null;").is_empty());
+        assert!(leaks("let b = kept.new(); let c = arg.new(); return null;").is_empty());
+        assert!(leaks("let b = Ball.create(1, 2); return null;").is_empty());
+        assert!(leaks("let b = Ball.new(1, 2) + 1; return null;").is_empty());
+    }
+}
@@ -5,14 +5,22 @@
 //!
 //! - Complete Jack language compilation
 //! - Constant folding optimization
-//! - Strength reduction (power-of-2 multiplications use shift instead of Math.multiply)
+//! - Strength reduction (multiplications by powers of 2 and small constants use
+//!   `add` sequences instead of Math.multiply)
+//! - Constant propagation through locals, dead-branch elimination and
+//!   small-subroutine inlining (`O2`)
 //! - Peephole optimization of generated VM code
 //! - Parallel file processing
 //!
+//! The file and directory functions need the `fs` feature, on by default.
+//! With `default-features = false` only the in-memory API
+//! ([`compile_source`] and friends) is built, e.g. for WASM.
+//!
 //! # Usage
 //!
 //! ```no_run
-//! use jack_compiler::{compile_file, compile_directory, compile_file_with_options, CompileOptions};
+//! # #[cfg(feature = "fs")] {
+//! use jack_compiler::{compile_file, compile_directory, compile_file_with_options, CompileOptions, OptLevel};
 //! use std::path::Path;
 //!
 //! // Compile a single file
@@ -22,26 +30,59 @@
 //! let results = compile_directory(Path::new("Square/"));
 //!
 //! // Compile without optimization
-//! let options = CompileOptions { optimize: false };
+//! let options = CompileOptions { optimize: false, ..Default::default() };
+//! let result = compile_file_with_options(Path::new("Main.jack"), options);
+//!
+//! // Compile with the aggressive optimization level
+//! let options = CompileOptions::with_opt_level(OptLevel::O2);
 //! let result = compile_file_with_options(Path::new("Main.jack"), options);
+//! # }
 //! ```
 
+pub mod assignment;
+#[cfg(feature = "fs")]
+mod cache;
 pub mod codegen;
 pub mod error;
+#[cfg(feature = "fs")]
+mod files;
+pub mod intern;
+pub mod leak;
 pub mod optimizer;
+pub mod registry;
+pub mod slot_verify;
+pub mod stack_verify;
 pub mod symbol_table;
 pub mod vm_writer;
 
-use rayon::prelude::*;
-use std::fs;
-use std::path::Path;
+use jack_analyzer::ast::Class;
+use jack_analyzer::token::Span;
+use stack_verify::VerifyingWriter;
+use std::fmt;
 
 // Re-export key types
-pub use codegen::CodeGenerator;
-pub use error::CompileError;
-pub use optimizer::{ConstantFolder, PeepholeOptimizer, StrengthReduction};
-pub use symbol_table::{Symbol, SymbolKind, SymbolTable};
-pub use vm_writer::VMWriter;
+pub use codegen::{CodeGenerator, CodegenOutput};
+pub use error::{CompileError, CompileWarning};
+pub use intern::Interner;
+pub use jack_analyzer::FileOrder;
+pub use jack_analyzer::ast::{BinaryOp, UnaryOp};
+pub use optimizer::{
+    ChainStep, ConstantFolder, ConstantPropagator, PeepholeOptimizer, PeepholeWriter,
+    StrengthReduction,
+};
+pub use registry::{ClassRegistry, Signature};
+pub use slot_verify::verify_slots;
+pub use stack_verify::verify_stack;
+pub use symbol_table::{DefineOutcome, Symbol, SymbolKind, SymbolTable};
+pub use vm_writer::{Segment, VMWriter};
+
+#[cfg(feature = "fs")]
+pub use cache::{CompileCache, compile_directory_cached};
+#[cfg(feature = "fs")]
+pub use files::{
+    TOTAL_BUDGET_FILENAME, compile_directory, compile_directory_with_options,
+    compile_directory_with_progress, compile_file, compile_file_with_options, write_result,
+};
 
 /// Result of compiling a single Jack file.
 #[derive(Debug)]
@@ -52,53 +93,169 @@ pub struct CompileResult {
     pub vm_code: String,
     /// Any errors encountered during compilation.
     pub errors: Vec<CompileError>,
+    /// Non-fatal diagnostics (e.g. shadowed or unused class variables).
+    /// Warnings are reported even when `errors` is non-empty.
+    pub warnings: Vec<CompileWarning>,
 }
 
 impl CompileResult {
     /// Check if the compilation was successful (no errors).
+    ///
+    /// Warnings do not affect the result.
     pub fn is_ok(&self) -> bool {
         self.errors.is_empty()
     }
+
+    /// Check if any warnings were reported.
+    pub fn has_warnings(&self) -> bool {
+        !self.warnings.is_empty()
+    }
+}
+
+/// Optimization level.
+///
+/// | Level | Passes |
+/// |-------|--------|
+/// | `O0`  | None: a direct AST-to-VM translation |
+/// | `O1`  | Constant folding, strength reduction (multiply by powers of 2 and small constants), empty `if` bodies without labels, peephole |
+/// | `O2`  | Everything in `O1`, plus constant propagation through locals, dead-branch elimination (`if`/`while` conditions folding to `true` or `false`) and inlining of tiny same-class accessors. Dead-store elimination and common-subexpression elimination will join as they land. |
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum OptLevel {
+    /// No optimization.
+    O0,
+    /// Cheap, local optimizations (the historical `optimize: true` behavior).
+    #[default]
+    O1,
+    /// `O1` plus whole-statement and data-flow passes.
+    O2,
+}
+
+impl OptLevel {
+    /// Check if the `O1` passes (folding, strength reduction, peephole) run.
+    #[inline]
+    pub fn is_enabled(self) -> bool {
+        self >= OptLevel::O1
+    }
+
+    /// Check if the heavier `O2` passes run.
+    #[inline]
+    pub fn is_aggressive(self) -> bool {
+        self >= OptLevel::O2
+    }
+}
+
+impl From<bool> for OptLevel {
+    /// Map the legacy `optimize` flag: `true` is `O1`, `false` is `O0`.
+    fn from(optimize: bool) -> Self {
+        if optimize { OptLevel::O1 } else { OptLevel::O0 }
+    }
 }
 
 /// Compilation options.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct CompileOptions {
-    /// Enable peephole optimization (default: true).
+    /// Master optimization switch (default: true). `false` forces `O0`
+    /// regardless of `opt_level`.
     pub optimize: bool,
+    /// Optimization level used when `optimize` is set (default: `O1`).
+    pub opt_level: OptLevel,
+    /// Warn when a local or parameter shadows a field/static (default: true).
+    pub warn_shadowing: bool,
+    /// Warn when a local is read before it is assigned on every path to the
+    /// read (default: true).
+    pub warn_unassigned: bool,
+    /// Accept non-standard language extensions such as `+=`, or `var`
+    /// declarations between the statements of a subroutine body (default:
+    /// false).
+    pub extensions: bool,
+    /// Report a class name that differs from the file name as a warning
+    /// instead of an error (default: false).
+    pub lenient_class_names: bool,
+    /// Parse with conventional operator precedence instead of Jack's strict
+    /// left-to-right order (default: false).
+    pub precedence: bool,
+    /// Check the argument counts of calls into the standard OS classes
+    /// (default: false, so replacement OS implementations are not flagged).
+    pub check_os_calls: bool,
+    /// Guard array indexes with `Sys.error` calls (default: false).
+    ///
+    /// Every index is checked for being negative. The upper bound is only
+    /// checked for a local assigned exactly once, from `Array.new(k)` with a
+    /// constant `k`; other arrays have no known length.
+    pub bounds_checks: bool,
+    /// Warn about objects constructed into a local that are never disposed
+    /// and never leave the subroutine (default: false). See [`leak`].
+    pub lint_leaks: bool,
+    /// Check the stack effects of the generated VM code of each file, and
+    /// fail it with internal errors if they are inconsistent (default:
+    /// false). A debugging aid for the compiler; see [`stack_verify`].
+    pub verify_stack: bool,
+    /// Check that the code of each subroutine only uses the local and
+    /// argument slots it declares, failing the file with internal errors
+    /// otherwise (default: false; always on in debug builds). See
+    /// [`slot_verify`].
+    pub verify_slots: bool,
+    /// When optimizing, compile `do Output.printString("...")` with a string
+    /// literal argument to one `Output.printChar` call per character, instead
+    /// of building a `String` that is never disposed (default: false, so the
+    /// OS call pattern of unoptimized code is kept).
+    pub fuse_print_string: bool,
+    /// Fail a file whose optimized VM code has more instructions than this
+    /// (default: `None`, no limit). See [`count_vm_lines`].
+    pub max_vm_lines_per_file: Option<usize>,
+    /// Fail directory compilation whose files together have more VM
+    /// instructions than this (default: `None`, no limit).
+    pub max_vm_lines_total: Option<usize>,
+    /// Size of a dedicated thread pool for directory compilation (default:
+    /// `None`, which uses rayon's global pool).
+    pub threads: Option<usize>,
+    /// Order of the results of a directory compilation (default:
+    /// alphabetical).
+    pub file_order: FileOrder,
 }
 
 impl Default for CompileOptions {
     fn default() -> Self {
-        Self { optimize: true }
+        Self {
+            optimize: true,
+            opt_level: OptLevel::O1,
+            warn_shadowing: true,
+            warn_unassigned: true,
+            extensions: false,
+            lenient_class_names: false,
+            precedence: false,
+            check_os_calls: false,
+            bounds_checks: false,
+            lint_leaks: false,
+            verify_stack: false,
+            verify_slots: false,
+            fuse_print_string: false,
+            max_vm_lines_per_file: None,
+            max_vm_lines_total: None,
+            threads: None,
+            file_order: FileOrder::Alphabetical,
+        }
     }
 }
 
-/// Compile a single Jack file.
-pub fn compile_file(path: &Path) -> CompileResult {
-    compile_file_with_options(path, CompileOptions::default())
-}
-
-/// Compile a single Jack file with custom options.
-pub fn compile_file_with_options(path: &Path, options: CompileOptions) -> CompileResult {
-    let filename = path
-        .file_stem()
-        .and_then(|s| s.to_str())
-        .unwrap_or("unknown")
-        .to_string();
-
-    let source = match fs::read_to_string(path) {
-        Ok(s) => s,
-        Err(e) => {
-            return CompileResult {
-                filename,
-                vm_code: String::new(),
-                errors: vec![CompileError::io(path, e)],
-            };
+impl CompileOptions {
+    /// Options running the given optimization level.
+    pub fn with_opt_level(opt_level: OptLevel) -> Self {
+        Self {
+            optimize: opt_level.is_enabled(),
+            opt_level,
+            ..Self::default()
         }
-    };
+    }
 
-    compile_source_with_options(&source, &filename, options)
+    /// The optimization level actually in effect.
+    pub fn effective_opt_level(&self) -> OptLevel {
+        if self.optimize {
+            self.opt_level
+        } else {
+            OptLevel::O0
+        }
+    }
 }
 
 /// Compile Jack source code directly.
@@ -111,9 +268,117 @@ pub fn compile_source_with_options(
     source: &str,
     filename: &str,
     options: CompileOptions,
+) -> CompileResult {
+    compile_checked(source, filename, options, false)
+}
+
+/// Compile a parsed class, writing the VM code to `out` one subroutine at a
+/// time.
+///
+/// The code is the same as [`compile_source_with_options`] produces, peephole
+/// pass included, but never held in memory as a whole, which matters for
+/// huge generated classes. With [`CompileOptions::verify_stack`] the code is
+/// checked line by line as it is written. The per-file code size budget and
+/// the class name check are left to the caller. If the result is an error,
+/// `out` has received code for the whole class anyway; discard it. Fails
+/// only if `out` does.
+///
+/// ```
+/// use jack_analyzer::parser::Parser;
+/// use jack_analyzer::tokenizer::JackTokenizer;
+/// use jack_compiler::{CompileOptions, compile_class_to_writer, compile_source};
+///
+/// let source = "class Main { function int two() { return 1 + 1; } }";
+/// let tokens = JackTokenizer::new(source).tokenize().unwrap();
+/// let class = Parser::new(&tokens).parse().unwrap();
+///
+/// let mut vm_code = String::new();
+/// let output = compile_class_to_writer(&class, CompileOptions::default(), &mut vm_code).unwrap();
+/// assert!(output.result.is_ok());
+/// assert_eq!(vm_code, compile_source(source, "Main").vm_code);
+/// ```
+pub fn compile_class_to_writer<W: fmt::Write>(
+    class: &Class,
+    options: CompileOptions,
+    out: &mut W,
+) -> Result<CodegenOutput<()>, fmt::Error> {
+    compile_class_with(CodeGenerator::configured(&options), class, options, out)
+}
+
+/// [`compile_class_to_writer`] with `compiler`, a code generator set up for
+/// `options`.
+fn compile_class_with<W: fmt::Write>(
+    compiler: CodeGenerator,
+    class: &Class,
+    options: CompileOptions,
+    out: &mut W,
+) -> Result<CodegenOutput<()>, fmt::Error> {
+    if !options.verify_stack {
+        return optimize_class_to(compiler, class, options, out);
+    }
+    let mut verifier = VerifyingWriter::new(out);
+    let mut output = optimize_class_to(compiler, class, options, &mut verifier)?;
+    let errors = verifier.finish();
+    if output.result.is_ok() && !errors.is_empty() {
+        output.result = Err(errors);
+    }
+    Ok(output)
+}
+
+/// Compile `class` with `compiler`, peephole optimizing each subroutine into
+/// `out` if `options` enable it.
+fn optimize_class_to<W: fmt::Write>(
+    compiler: CodeGenerator,
+    class: &Class,
+    options: CompileOptions,
+    out: &mut W,
+) -> Result<CodegenOutput<()>, fmt::Error> {
+    if !options.effective_opt_level().is_enabled() {
+        return compiler.compile_output_to(class, out);
+    }
+    let mut optimizer = PeepholeWriter::new(out);
+    let output = compiler.compile_output_to(class, &mut optimizer)?;
+    optimizer.finish()?;
+    Ok(output)
+}
+
+/// Compile Jack source, optionally requiring the class to be named `filename`.
+///
+/// Errors and warnings are sorted by (line, column, message); diagnostics
+/// without a location come first.
+fn compile_checked(
+    source: &str,
+    filename: &str,
+    options: CompileOptions,
+    check_class_name: bool,
+) -> CompileResult {
+    let mut result = compile_unsorted(source, filename, options, check_class_name);
+    result
+        .errors
+        .sort_by_cached_key(|e| diagnostic_key(e.span(), e.to_string()));
+    result
+        .warnings
+        .sort_by_cached_key(|w| diagnostic_key(w.span(), w.to_string()));
+    result
+}
+
+/// Sort key placing diagnostics in source order.
+fn diagnostic_key(span: Option<&Span>, message: String) -> (usize, usize, String) {
+    match span {
+        Some(span) => (span.line, span.column, message),
+        None => (0, 0, message),
+    }
+}
+
+fn compile_unsorted(
+    source: &str,
+    filename: &str,
+    options: CompileOptions,
+    check_class_name: bool,
 ) -> CompileResult {
     // Tokenize
-    let tokenizer = jack_analyzer::tokenizer::JackTokenizer::new(source);
+    let tokenizer =
+        jack_analyzer::tokenizer::JackTokenizer::new(source).with_extensions(options.extensions);
     let tokens = match tokenizer.tokenize() {
         Ok(tokens) => tokens,
         Err(errors) => {
@@ -121,12 +386,16 @@ pub fn compile_source_with_options(
                 filename: filename.to_string(),
                 vm_code: String::new(),
                 errors: errors.into_iter().map(CompileError::from).collect(),
+                warnings: Vec::new(),
             };
         }
     };
 
     // Parse
-    let parser = jack_analyzer::parser::Parser::new(&tokens);
+    let parser = jack_analyzer::parser::Parser::new(&tokens)
+        .with_precedence(options.precedence)
+        .with_extensions(options.extensions)
+        .with_this_target(true);
     let class = match parser.parse() {
         Ok(class) => class,
         Err(errors) => {
@@ -134,76 +403,107 @@ pub fn compile_source_with_options(
                 filename: filename.to_string(),
                 vm_code: String::new(),
                 errors: errors.into_iter().map(CompileError::from).collect(),
+                warnings: Vec::new(),
             };
         }
     };
+    // The AST owns its names; free the tokens before generating code
+    drop(tokens);
+
+    // Compile to VM code (the opt level gates constant folding), peephole
+    // optimizing each subroutine as it is generated
+    let mut vm_code = String::new();
+    let CodegenOutput {
+        result,
+        mut warnings,
+    } = compile_class_to_writer(&class, options, &mut vm_code)
+        .expect("writing to a String cannot fail");
+    let mut result = result.map(|()| vm_code);
+
+    // `Foo.jack` must declare `class Foo`, or calls will not link
+    if check_class_name && class.name != filename {
+        if options.lenient_class_names {
+            warnings.insert(
+                0,
+                CompileWarning::class_name_mismatch(filename, &class.name),
+            );
+        } else {
+            let error = CompileError::class_name_mismatch(filename, &class.name);
+            match &mut result {
+                Ok(_) => result = Err(vec![error]),
+                Err(errors) => errors.insert(0, error),
+            }
+        }
+    }
 
-    // Compile to VM code (pass optimize flag for constant folding)
-    match CodeGenerator::compile_with_options(&class, options.optimize) {
+    match result {
         Ok(vm_code) => {
-            // Apply peephole optimization if enabled
-            let vm_code = if options.optimize {
-                PeepholeOptimizer::optimize(&vm_code)
-            } else {
-                vm_code
-            };
+            let lines = count_vm_lines(&vm_code);
+            if let Some(limit) = options.max_vm_lines_per_file
+                && lines > limit
+            {
+                return CompileResult {
+                    filename: filename.to_string(),
+                    vm_code: String::new(),
+                    errors: vec![CompileError::code_size_budget_exceeded(lines, limit)],
+                    warnings,
+                };
+            }
 
             CompileResult {
                 filename: filename.to_string(),
                 vm_code,
                 errors: Vec::new(),
+                warnings,
             }
         }
         Err(errors) => CompileResult {
             filename: filename.to_string(),
             vm_code: String::new(),
             errors,
+            warnings,
         },
     }
 }
 
-/// Compile all Jack files in a directory.
-pub fn compile_directory(dir: &Path) -> Vec<CompileResult> {
-    compile_directory_with_options(dir, CompileOptions::default())
+/// Count VM instructions: lines other than blank lines and `//` comments.
+pub fn count_vm_lines(vm_code: &str) -> usize {
+    vm_code
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with("//"))
+        .count()
 }
 
-/// Compile all Jack files in a directory with custom options.
-pub fn compile_directory_with_options(dir: &Path, options: CompileOptions) -> Vec<CompileResult> {
-    let jack_files: Vec<_> = match fs::read_dir(dir) {
-        Ok(entries) => entries
-            .filter_map(|e| e.ok())
-            .map(|e| e.path())
-            .filter(|p| p.extension().is_some_and(|ext| ext == "jack"))
-            .collect(),
-        Err(e) => {
-            return vec![CompileResult {
-                filename: dir.to_string_lossy().to_string(),
-                vm_code: String::new(),
-                errors: vec![CompileError::io(dir, e)],
-            }];
-        }
-    };
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    if jack_files.is_empty() {
-        return Vec::new();
+    #[test]
+    fn test_count_vm_lines_skips_blank_and_comments() {
+        let vm = "// Main.main\nfunction Main.main 0\n\n  push constant 0\n  // done\nreturn\n";
+        assert_eq!(count_vm_lines(vm), 3);
     }
 
-    // Parallel compilation
-    jack_files
-        .par_iter()
-        .map(|path| compile_file_with_options(path, options))
-        .collect()
-}
+    #[test]
+    fn test_per_file_budget() {
+        let source = "class Main { function int main() { return 1 + 2; } }";
+        let budget = |limit| CompileOptions {
+            optimize: false,
+            max_vm_lines_per_file: Some(limit),
+            ..Default::default()
+        };
 
-/// Write a compile result to an output file.
-pub fn write_result(result: &CompileResult, output_dir: &Path) -> Result<(), CompileError> {
-    let vm_path = output_dir.join(format!("{}.vm", result.filename));
-    fs::write(&vm_path, &result.vm_code).map_err(|e| CompileError::io(&vm_path, e))
-}
+        // function, push, push, add, return
+        let result = compile_source_with_options(source, "Main", budget(4));
+        assert!(result.vm_code.is_empty());
+        assert!(matches!(
+            result.errors[..],
+            [CompileError::CodeSizeBudgetExceeded { lines: 5, limit: 4 }]
+        ));
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        assert!(compile_source_with_options(source, "Main", budget(5)).is_ok());
+    }
 
     #[test]
     fn test_compile_source_simple() {
@@ -234,6 +534,52 @@ class Main {
         assert!(!result.is_ok());
     }
 
+    #[test]
+    fn test_warnings_do_not_fail_compilation() {
+        let source = "class Main { field int unused; function void main() { return; } }";
+        let result = compile_source(source, "Main");
+        assert!(result.is_ok());
+        assert!(result.has_warnings());
+        assert!(result.vm_code.contains("function Main.main 0"));
+        assert!(matches!(
+            &result.warnings[0],
+            CompileWarning::UnusedClassVariable { name, .. } if name == "unused"
+        ));
+
+        // Warnings are still reported when the class fails to compile
+        let source = "class Main { field int unused; function void main() { let x = 1; return; } }";
+        let result = compile_source(source, "Main");
+        assert!(!result.is_ok());
+        assert_eq!(result.warnings.len(), 1);
+    }
+
+    #[test]
+    fn test_uninitialized_local_return_warns() {
+        let source = "class Main { function int f() { var int x; return x; } }";
+        let result = compile_source(source, "Main");
+        assert!(result.is_ok());
+        assert_eq!(result.warnings.len(), 1);
+        assert!(matches!(
+            &result.warnings[0],
+            CompileWarning::UsedBeforeAssigned { name, .. } if name == "x"
+        ));
+        assert!(
+            result.warnings[0]
+                .to_string()
+                .contains("local 'x' may be used before it is assigned")
+        );
+        assert_eq!(result.warnings[0].code(), "JW0004");
+
+        // Opt-out
+        let options = CompileOptions {
+            warn_unassigned: false,
+            ..Default::default()
+        };
+        let result = compile_source_with_options(source, "Main", options);
+        assert!(result.is_ok());
+        assert!(result.warnings.is_empty());
+    }
+
     #[test]
     fn test_compile_with_optimization() {
         let source = r#"
@@ -245,7 +591,14 @@ class Main {
     }
 }
 "#;
-        let result = compile_source_with_options(source, "Main", CompileOptions { optimize: true });
+        let result = compile_source_with_options(
+            source,
+            "Main",
+            CompileOptions {
+                optimize: true,
+                ..Default::default()
+            },
+        );
         assert!(result.is_ok());
 
         // Double not should be optimized away
@@ -253,6 +606,49 @@ class Main {
         assert_eq!(not_count, 0, "Double not should be eliminated");
     }
 
+    #[test]
+    fn test_precedence_mode_folding() {
+        let source = "class Main { function int f() { return 2 + 3 * 4; } }";
+        let result = compile_source(source, "Main");
+        assert!(result.is_ok());
+        assert!(result.vm_code.contains("push constant 20"));
+
+        let options = CompileOptions {
+            precedence: true,
+            ..Default::default()
+        };
+        let result = compile_source_with_options(source, "Main", options);
+        assert!(result.is_ok());
+        assert!(result.vm_code.contains("push constant 14"));
+    }
+
+    #[test]
+    fn test_interleaved_var_decs() {
+        let extensions = CompileOptions {
+            extensions: true,
+            ..Default::default()
+        };
+        let source = "class Main { function int f() { var int a; let a = 1; var int b, c; let b = a; let c = b; return c; } }";
+        let result = compile_source_with_options(source, "Main", extensions);
+        assert!(result.is_ok(), "{:?}", result.errors);
+        assert!(result.vm_code.starts_with("function Main.f 3\n"));
+        assert!(result.vm_code.contains("pop local 2"));
+
+        // Off by default: a syntax error
+        let result = compile_source(source, "Main");
+        assert!(matches!(result.errors[..], [CompileError::Parse(_), ..]));
+
+        let source = "class Main { function int f() { let a = 1; var int a; return a; } }";
+        let result = compile_source_with_options(source, "Main", extensions);
+        assert_eq!(result.errors.len(), 1, "{:?}", result.errors);
+        let err = &result.errors[0];
+        assert_eq!(err.code(), "JC0013");
+        assert_eq!(
+            err.to_string(),
+            "Variable 'a' used at 1:33 before its declaration at 1:44"
+        );
+    }
+
     #[test]
     fn test_compile_without_optimization() {
         let source = r#"
@@ -264,8 +660,14 @@ class Main {
     }
 }
 "#;
-        let result =
-            compile_source_with_options(source, "Main", CompileOptions { optimize: false });
+        let result = compile_source_with_options(
+            source,
+            "Main",
+            CompileOptions {
+                optimize: false,
+                ..Default::default()
+            },
+        );
         assert!(result.is_ok());
 
         // Without optimization, double not should remain
@@ -276,9 +678,133 @@ class Main {
         );
     }
 
+    #[test]
+    fn test_opt_levels() {
+        assert_eq!(OptLevel::from(true), OptLevel::O1);
+        assert_eq!(OptLevel::from(false), OptLevel::O0);
+        assert_eq!(
+            CompileOptions::default().effective_opt_level(),
+            OptLevel::O1
+        );
+
+        // The legacy switch overrides the level
+        let options = CompileOptions {
+            optimize: false,
+            opt_level: OptLevel::O2,
+            ..Default::default()
+        };
+        assert_eq!(options.effective_opt_level(), OptLevel::O0);
+
+        let source = "class Main { function int f() { var int x; return ~~x; } }";
+        let o0 = compile_source_with_options(
+            source,
+            "Main",
+            CompileOptions::with_opt_level(OptLevel::O0),
+        );
+        let o1 = compile_source_with_options(
+            source,
+            "Main",
+            CompileOptions::with_opt_level(OptLevel::O1),
+        );
+        let o2 = compile_source_with_options(
+            source,
+            "Main",
+            CompileOptions::with_opt_level(OptLevel::O2),
+        );
+        assert_eq!(o0.vm_code.matches("not\n").count(), 2);
+        assert_eq!(o1.vm_code.matches("not\n").count(), 0);
+        assert_eq!(o2.vm_code.matches("not\n").count(), 0);
+    }
+
+    #[test]
+    fn test_referenced_labels_kept_at_o2() {
+        // The inner `if` vanishes, leaving the outer then-branch as just
+        // `goto IF_END`; both labels are still jumped to
+        let source = "class Main { function void f(boolean b) {
+            if (b) { if (false) { do Output.printInt(1); } }
+            return;
+        } }";
+        let vm = compile_source_with_options(
+            source,
+            "Main",
+            CompileOptions::with_opt_level(OptLevel::O2),
+        )
+        .vm_code;
+        for label in ["IF_FALSE_0", "IF_END_1"] {
+            assert_eq!(vm.matches(&format!("goto Main.f${label}\n")).count(), 1);
+            assert!(vm.contains(&format!("label Main.f${label}\n")), "{vm}");
+        }
+    }
+
+    #[test]
+    fn test_verify_stack_accepts_generated_code() {
+        let source = "class Main {
+            field Array cells;
+            method int f(int x, Array a) {
+                var int i;
+                let a[i] = Math.max(Main.g(x, a[i + 1]), -x);
+                let cells[a[i]] = a[cells[i]];
+                while (i < x) { if (a[i]) { return f(i, a); } let i = i + 1; }
+                return Main.g(Main.g(1, 2), Math.min(x, 3));
+            }
+            function int g(int a, int b) { return a * b; }
+        }";
+        for level in [OptLevel::O0, OptLevel::O1, OptLevel::O2] {
+            for bounds_checks in [false, true] {
+                let options = CompileOptions {
+                    verify_stack: true,
+                    bounds_checks,
+                    ..CompileOptions::with_opt_level(level)
+                };
+                let result = compile_source_with_options(source, "Main", options);
+                assert!(result.is_ok(), "{level:?}: {:?}", result.errors);
+            }
+        }
+    }
+
+    #[test]
+    fn test_verify_stack_catches_corrupted_code() {
+        let source = "class Main {
+            function int f(int x) { let x = Main.g(x, 1); return x; }
+            function int g(int a, int b) { return a; }
+        }";
+        let tokens = jack_analyzer::tokenizer::JackTokenizer::new(source)
+            .tokenize()
+            .unwrap();
+        let class = jack_analyzer::parser::Parser::new(&tokens).parse().unwrap();
+        for level in [OptLevel::O0, OptLevel::O1] {
+            let options = CompileOptions {
+                verify_stack: true,
+                ..CompileOptions::with_opt_level(level)
+            };
+            let mut compiler = CodeGenerator::configured(&options);
+            compiler.overstate_call_args = 1;
+            let mut vm_code = String::new();
+            let output = compile_class_with(compiler, &class, options, &mut vm_code).unwrap();
+
+            // The call claims an argument that was never pushed
+            let errors = output.result.unwrap_err();
+            assert_eq!(errors.len(), 1, "{level:?}: {errors:?}");
+            assert_eq!(errors[0].code(), "JC0015");
+            let line = vm_code.lines().position(|l| l == "call Main.g 3").unwrap() + 1;
+            assert!(matches!(
+                &errors[0],
+                CompileError::StackEffectViolation { line: l, instruction, .. }
+                    if *l == line && instruction == "call Main.g 3"
+            ));
+        }
+    }
+
     #[test]
     fn test_default_options() {
         let options = CompileOptions::default();
         assert!(options.optimize);
+        assert_eq!(options.opt_level, OptLevel::O1);
+        assert!(options.warn_shadowing);
+        assert!(options.warn_unassigned);
+        assert!(!options.extensions);
+        assert!(!options.lenient_class_names);
+        assert!(!options.verify_stack);
+        assert!(!options.verify_slots);
     }
 }
@@ -3,10 +3,12 @@
 //! Usage:
 //!     JackCompiler <file.jack | directory>
 //!     JackCompiler --no-optimize <file.jack | directory>
+//!     JackCompiler -O2 <file.jack | directory>
 
 use clap::Parser as ClapParser;
 use jack_compiler::{
-    CompileOptions, compile_directory_with_options, compile_file_with_options, write_result,
+    CompileOptions, CompileResult, FileOrder, OptLevel, TOTAL_BUDGET_FILENAME,
+    compile_directory_with_options, compile_file_with_options, count_vm_lines, write_result,
 };
 use std::path::PathBuf;
 use std::process::ExitCode;
@@ -25,18 +27,128 @@ struct Args {
     #[arg(short, long)]
     output: Option<PathBuf>,
 
-    /// Disable peephole optimization
+    /// Disable all optimization (same as -O0)
     #[arg(long = "no-optimize")]
     no_optimize: bool,
+
+    /// Optimization level: 0 (none), 1 (fold, strength-reduce, peephole), 2 (aggressive)
+    #[arg(short = 'O', long = "opt-level", default_value_t = 1,
+          value_parser = clap::value_parser!(u8).range(0..=2))]
+    opt_level: u8,
+
+    /// Do not warn when a local or parameter shadows a field or static
+    #[arg(long = "no-shadow-warnings")]
+    no_shadow_warnings: bool,
+
+    /// Do not warn when a local may be read before it is assigned
+    #[arg(long = "no-unassigned-warnings")]
+    no_unassigned_warnings: bool,
+
+    /// Warn instead of failing when a class name differs from its file name
+    #[arg(long = "lenient-class-names")]
+    lenient_class_names: bool,
+
+    /// Group operators by conventional precedence instead of left to right
+    #[arg(long)]
+    precedence: bool,
+
+    /// Check argument counts of calls to the standard OS (Math, Output, ...)
+    #[arg(long = "check-os-calls")]
+    check_os_calls: bool,
+
+    /// Guard array indexes with Sys.error calls (negative index, and index
+    /// past the end for locals set once from Array.new(constant))
+    #[arg(long = "bounds-checks")]
+    bounds_checks: bool,
+
+    /// Warn about objects constructed into a local that are never disposed,
+    /// passed on, returned or stored
+    #[arg(long = "lint-leaks")]
+    lint_leaks: bool,
+
+    /// Check the stack depth of the generated VM code and report
+    /// inconsistencies as internal errors (for debugging the compiler)
+    #[arg(long = "verify-stack")]
+    verify_stack: bool,
+
+    /// Check that the generated code only uses the local and argument slots
+    /// each subroutine declares (always on in debug builds)
+    #[arg(long = "verify-slots")]
+    verify_slots: bool,
+
+    /// Print string literals passed to `do Output.printString("...")` with
+    /// Output.printChar instead, when optimizing
+    #[arg(long = "fuse-print-string")]
+    fuse_print_string: bool,
+
+    /// Enable language extensions (compound assignment `+=` / `-=`, string
+    /// escapes, `var` declarations after statements)
+    #[arg(long)]
+    extensions: bool,
+
+    /// Compile directory files on N threads instead of one per CPU
+    #[arg(short = 'j', long, value_name = "N")]
+    threads: Option<usize>,
+
+    /// Order of directory files in the output: alphabetical, or
+    /// sys-main-first (Sys.jack, Main.jack, then alphabetical)
+    #[arg(long, value_name = "ORDER", default_value = "alphabetical")]
+    file_order: FileOrder,
+
+    /// Print errors and warnings to stdout as one JSON object per line
+    /// (file, line, col, code, message, severity)
+    #[arg(long = "diagnostics-json")]
+    diagnostics_json: bool,
+
+    /// Fail any file whose VM code has more than N instructions
+    #[arg(long = "max-lines", value_name = "N")]
+    max_lines: Option<usize>,
+
+    /// Fail a directory whose VM code has more than N instructions in total
+    #[arg(long = "max-lines-total", value_name = "N")]
+    max_lines_total: Option<usize>,
+
+    /// Print a table of VM instruction counts per file
+    #[arg(short, long, conflicts_with = "diagnostics_json")]
+    verbose: bool,
 }
 
 fn main() -> ExitCode {
     let args = Args::parse();
+    let opt_level = match args.opt_level {
+        0 => OptLevel::O0,
+        1 => OptLevel::O1,
+        _ => OptLevel::O2,
+    };
     let options = CompileOptions {
         optimize: !args.no_optimize,
+        opt_level,
+        warn_shadowing: !args.no_shadow_warnings,
+        warn_unassigned: !args.no_unassigned_warnings,
+        extensions: args.extensions,
+        lenient_class_names: args.lenient_class_names,
+        precedence: args.precedence,
+        check_os_calls: args.check_os_calls,
+        bounds_checks: args.bounds_checks,
+        lint_leaks: args.lint_leaks,
+        verify_stack: args.verify_stack,
+        verify_slots: args.verify_slots,
+        fuse_print_string: args.fuse_print_string,
+        max_vm_lines_per_file: args.max_lines,
+        max_vm_lines_total: args.max_lines_total,
+        threads: args.threads,
+        file_order: args.file_order,
     };
 
     let (results, output_dir) = if args.input.is_file() {
+        // One file is the whole program: the total budget applies to it
+        let options = CompileOptions {
+            max_vm_lines_per_file: match (args.max_lines, args.max_lines_total) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            },
+            ..options
+        };
         let result = compile_file_with_options(&args.input, options);
         let output_dir = args.output.unwrap_or_else(|| {
             args.input
@@ -59,11 +171,26 @@ fn main() -> ExitCode {
         return ExitCode::from(2);
     }
 
-    let mut has_errors = false;
+    // A failed total budget fails the whole program: write none of it
+    let over_budget = results
+        .iter()
+        .any(|result| result.filename == TOTAL_BUDGET_FILENAME);
+    let mut has_errors = over_budget;
 
     for result in &results {
+        for warning in &result.warnings {
+            if args.diagnostics_json {
+                println!("{}", warning.to_json(&result.filename));
+            } else {
+                eprintln!("{}: warning: {}", result.filename, warning);
+            }
+        }
+        if result.is_ok() && over_budget {
+            continue;
+        }
         if result.is_ok() {
             match write_result(result, &output_dir) {
+                Ok(()) if args.diagnostics_json => {}
                 Ok(()) => {
                     println!(
                         "Compiled {}.jack -> {}.vm",
@@ -78,14 +205,45 @@ fn main() -> ExitCode {
         } else {
             has_errors = true;
             for err in &result.errors {
-                eprintln!("{}: {}", result.filename, err);
+                if args.diagnostics_json {
+                    println!("{}", err.to_json(&result.filename));
+                } else {
+                    eprintln!("{}: {}", result.filename, err);
+                }
             }
         }
     }
 
+    if args.verbose {
+        print_line_counts(&results);
+    }
+
     if has_errors {
         ExitCode::from(1)
     } else {
         ExitCode::SUCCESS
     }
 }
+
+/// Print the VM instruction count of each compiled file and the total.
+fn print_line_counts(results: &[CompileResult]) {
+    let counts: Vec<_> = results
+        .iter()
+        .filter(|r| r.is_ok() && r.filename != TOTAL_BUDGET_FILENAME)
+        .map(|r| (r.filename.as_str(), count_vm_lines(&r.vm_code)))
+        .collect();
+    let width = counts
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or(0)
+        .max("Total".len());
+
+    println!();
+    println!("{:<width$}  {:>8}", "File", "VM lines");
+    for (name, lines) in &counts {
+        println!("{:<width$}  {:>8}", name, lines);
+    }
+    let total: usize = counts.iter().map(|(_, lines)| lines).sum();
+    println!("{:<width$}  {:>8}", "Total", total);
+}
@@ -2,10 +2,19 @@
 //!
 //! Includes:
 //! - Constant folding (AST-level)
+//! - Constant propagation through locals (AST-level)
+//! - Small-subroutine inlining (AST-level)
 //! - Peephole optimization (VM-level)
 //! - Strength reduction (codegen-level)
 
-use jack_analyzer::ast::{BinaryOp, Expression, Term, UnaryOp};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use jack_analyzer::ast::{
+    BinaryOp, Expression, KeywordConstant, Statement, SubroutineDec, Term, UnaryOp,
+};
+use jack_analyzer::token::Span;
 
 /// Constant folder for compile-time expression evaluation.
 pub struct ConstantFolder;
@@ -79,17 +88,53 @@ pub struct PeepholeOptimizer;
 impl PeepholeOptimizer {
     /// Optimize VM code using peephole patterns.
     pub fn optimize(vm_code: &str) -> String {
+        let mut optimized = String::with_capacity(vm_code.len());
+        Self::optimize_to(vm_code, &mut optimized).expect("writing to a String cannot fail");
+        optimized
+    }
+
+    /// [`optimize`](Self::optimize), writing the result to `out` instead of
+    /// building a second copy of the code.
+    pub fn optimize_to<W: fmt::Write + ?Sized>(vm_code: &str, out: &mut W) -> fmt::Result {
         let lines: Vec<&str> = vm_code.lines().collect();
-        let mut optimized = Vec::with_capacity(lines.len());
+        let mut optimized: Vec<Cow<'_, str>> = Vec::with_capacity(lines.len());
         let mut i = 0;
 
         while i < lines.len() {
+            // Pattern: push constant n / (neg | not)+ → folded push constant
+            if let Some(consumed) = Self::fold_unary_chain(&lines[i..], &mut optimized) {
+                i += consumed;
+                continue;
+            }
+
             // Pattern: push X / pop X (same non-constant location) → remove both
             if i + 1 < lines.len() && Self::is_redundant_push_pop(lines[i], lines[i + 1]) {
                 i += 2;
                 continue;
             }
 
+            // Pattern: pop temp 0 / push temp 0 → remove both when temp 0 is
+            // not read again; the pair only copies the top of the stack
+            if i + 1 < lines.len()
+                && lines[i] == "pop temp 0"
+                && lines[i + 1] == "push temp 0"
+                && Self::temp0_is_dead(&lines[i + 2..])
+            {
+                i += 2;
+                continue;
+            }
+
+            // Pattern: push constant 0 / pop temp 0 → remove both when temp 0
+            // is not read again (a discarded void result before `return`)
+            if i + 1 < lines.len()
+                && lines[i] == "push constant 0"
+                && lines[i + 1] == "pop temp 0"
+                && Self::temp0_is_dead(&lines[i + 2..])
+            {
+                i += 2;
+                continue;
+            }
+
             // Pattern: push constant 0 / add → remove both (identity)
             if i + 1 < lines.len() && lines[i] == "push constant 0" && lines[i + 1] == "add" {
                 i += 2;
@@ -114,34 +159,518 @@ impl PeepholeOptimizer {
                 continue;
             }
 
+            // Pattern: goto L / label L → fall through to the label
+            if i + 1 < lines.len() && Self::is_jump_to_next(lines[i], lines[i + 1]) {
+                i += 1;
+                continue;
+            }
+
             // Pattern: push constant 0 / not → push constant -1 (true)
             if i + 1 < lines.len() && lines[i] == "push constant 0" && lines[i + 1] == "not" {
-                optimized.push("push constant 0");
-                optimized.push("not");
+                optimized.push(Cow::Borrowed("push constant 0"));
+                optimized.push(Cow::Borrowed("not"));
                 i += 2;
                 continue;
             }
 
-            optimized.push(lines[i]);
+            optimized.push(Cow::Borrowed(lines[i]));
             i += 1;
         }
 
-        if optimized.is_empty() {
-            String::new()
-        } else {
-            optimized.join("\n") + "\n"
+        // Must run last: the jump patterns above can orphan labels
+        Self::remove_orphaned_labels(&lines, &mut optimized);
+
+        for line in &optimized {
+            out.write_str(line)?;
+            out.write_char('\n')?;
+        }
+        Ok(())
+    }
+
+    /// Fold `push constant n` followed by a run of `neg`/`not` into the
+    /// resulting constant.
+    ///
+    /// Negative results are emitted as `push constant |m|` / `neg`, matching
+    /// codegen. The fold only applies when it shortens the code, so the
+    /// canonical `push constant 0` / `not` for `true` is left alone. Returns
+    /// the number of input lines consumed.
+    fn fold_unary_chain<'a>(lines: &[&'a str], out: &mut Vec<Cow<'a, str>>) -> Option<usize> {
+        let n: i16 = lines
+            .first()?
+            .strip_prefix("push constant ")?
+            .parse()
+            .ok()?;
+        let ops = &lines[1..];
+        let chain = ops
+            .iter()
+            .take_while(|l| matches!(**l, "neg" | "not"))
+            .count();
+        if chain == 0 {
+            return None;
+        }
+
+        let value = ops[..chain]
+            .iter()
+            .fold(n, |v, op| if *op == "neg" { v.wrapping_neg() } else { !v });
+        let (magnitude, unary) = match value {
+            0.. => (value, None),
+            i16::MIN => (i16::MAX, Some("not")),
+            _ => (-value, Some("neg")),
+        };
+        // Folded form is one push plus at most one unary op
+        if usize::from(unary.is_some()) >= chain {
+            return None;
+        }
+
+        out.push(Cow::Owned(format!("push constant {}", magnitude)));
+        if let Some(op) = unary {
+            out.push(Cow::Borrowed(op));
+        }
+        Some(1 + chain)
+    }
+
+    /// Check if `goto L` is immediately followed by `label L`.
+    ///
+    /// `if-goto` is never removed: it pops its condition either way.
+    fn is_jump_to_next(line1: &str, line2: &str) -> bool {
+        matches!(
+            (line1.strip_prefix("goto "), line2.strip_prefix("label ")),
+            (Some(target), Some(label)) if target == label
+        )
+    }
+
+    /// Check whether the value in temp 0 is never read by the code that
+    /// follows.
+    ///
+    /// Only straight-line code is followed: temp 0 is dead if it is
+    /// overwritten or the function returns before any `push temp 0`. A
+    /// label or jump ends the scan with `false`, since another path may
+    /// read the value, as does the end of the input.
+    fn temp0_is_dead(rest: &[&str]) -> bool {
+        for line in rest {
+            match *line {
+                "push temp 0" => return false,
+                "pop temp 0" | "return" => return true,
+                _ if line.starts_with("label ")
+                    || line.starts_with("goto ")
+                    || line.starts_with("if-goto ")
+                    || line.starts_with("function ") =>
+                {
+                    return false;
+                }
+                _ => {}
+            }
         }
+        false
+    }
+
+    /// Remove `label L` lines that `input` jumps to but `optimized` no
+    /// longer does: every `goto L` and `if-goto L` was optimized away.
+    ///
+    /// Labels the input never referred to are left alone. Generated labels
+    /// are unique per class, so one scan finds every reference.
+    fn remove_orphaned_labels(input: &[&str], optimized: &mut Vec<Cow<'_, str>>) {
+        fn targets<'a>(lines: impl Iterator<Item = &'a str>) -> HashSet<&'a str> {
+            lines
+                .filter_map(|l| {
+                    l.strip_prefix("goto ")
+                        .or_else(|| l.strip_prefix("if-goto "))
+                })
+                .collect()
+        }
+        let before = targets(input.iter().copied());
+        let after: HashSet<String> = targets(optimized.iter().map(|l| l.as_ref()))
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        optimized.retain(|l| {
+            l.strip_prefix("label ")
+                .is_none_or(|label| !before.contains(label) || after.contains(label))
+        });
     }
 
     /// Check if push/pop pair is redundant (same location, not constant).
+    ///
+    /// `push S i` followed by `pop S i` writes back the value it just read,
+    /// so the pair is a no-op whenever segment and index both match.
     fn is_redundant_push_pop(line1: &str, line2: &str) -> bool {
-        if let (Some(push_rest), Some(pop_rest)) =
-            (line1.strip_prefix("push "), line2.strip_prefix("pop "))
-        {
-            // Same location and not a constant (constants have side effects on stack)
-            push_rest == pop_rest && !push_rest.starts_with("constant")
+        match (Self::operand(line1, "push"), Self::operand(line2, "pop")) {
+            // Constants have no backing storage to write back to
+            (Some(push), Some(pop)) => push == pop && push.0 != "constant",
+            _ => false,
+        }
+    }
+
+    /// Split `command segment index` into `(segment, index)`.
+    fn operand<'a>(line: &'a str, command: &str) -> Option<(&'a str, &'a str)> {
+        let mut parts = line.split_whitespace();
+        if parts.next()? != command {
+            return None;
+        }
+        let segment = parts.next()?;
+        let index = parts.next()?;
+        parts.next().is_none().then_some((segment, index))
+    }
+}
+
+/// A writer that peephole-optimizes VM code one function at a time on its
+/// way to `out`.
+///
+/// No pattern spans a `function` line and labels are local to their
+/// function, so the output equals [`PeepholeOptimizer::optimize`] of the
+/// whole text, while only the function being written is buffered. Call
+/// [`finish`](Self::finish) to flush the last function.
+#[derive(Debug)]
+pub struct PeepholeWriter<W> {
+    out: W,
+    /// Text after the start of the last `function` line seen.
+    pending: String,
+}
+
+impl<W: fmt::Write> PeepholeWriter<W> {
+    const FUNCTION_START: &str = "\nfunction ";
+
+    /// Create a writer optimizing into `out`.
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            pending: String::new(),
+        }
+    }
+
+    /// Optimize and write the buffered code, returning the inner writer.
+    pub fn finish(mut self) -> Result<W, fmt::Error> {
+        PeepholeOptimizer::optimize_to(&self.pending, &mut self.out)?;
+        Ok(self.out)
+    }
+}
+
+impl<W: fmt::Write> fmt::Write for PeepholeWriter<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        // A new function start can only end inside the new text
+        let mut from = self
+            .pending
+            .len()
+            .saturating_sub(Self::FUNCTION_START.len() - 1);
+        while !self.pending.is_char_boundary(from) {
+            from -= 1;
+        }
+        self.pending.push_str(s);
+
+        if let Some(pos) = self.pending[from..].rfind(Self::FUNCTION_START) {
+            // Everything up to the newline is complete functions
+            let split = from + pos + 1;
+            PeepholeOptimizer::optimize_to(&self.pending[..split], &mut self.out)?;
+            self.pending.drain(..split);
+        }
+        Ok(())
+    }
+}
+
+/// Selection of subroutines small enough to inline at their call sites.
+///
+/// Only parameterless subroutines without locals whose body is a single
+/// `return` are candidates, so there are no arguments to remap: the returned
+/// expression is compiled in place of the call. Bodies containing calls are
+/// rejected, which also rules out (mutual) recursion.
+pub struct Inliner;
+
+impl Inliner {
+    /// Maximum number of terms in an inlined return expression.
+    pub const MAX_TERMS: usize = 8;
+
+    /// The expression to substitute for calls to `sub`, if it can be inlined.
+    ///
+    /// `return;` yields `0`, the value a void subroutine returns.
+    pub fn candidate(sub: &SubroutineDec) -> Option<Expression> {
+        if !sub.parameters.is_empty() || !sub.body.var_decs.is_empty() {
+            return None;
+        }
+        let [Statement::Return(ret)] = sub.body.statements.as_slice() else {
+            return None;
+        };
+        let Some(expr) = &ret.value else {
+            return Some(Self::zero(ret.span.clone()));
+        };
+        let terms = Self::count_terms(expr)?;
+        (terms <= Self::MAX_TERMS).then(|| expr.clone())
+    }
+
+    /// Names of the variables an inlinable expression reads.
+    pub fn variables(expr: &Expression) -> Vec<&str> {
+        let mut names = Vec::new();
+        Self::collect_variables(&expr.term, &mut names);
+        for (_, term) in &expr.ops {
+            Self::collect_variables(term, &mut names);
+        }
+        names
+    }
+
+    fn zero(span: Span) -> Expression {
+        Expression {
+            term: Term::KeywordConstant(KeywordConstant::False, span.clone()),
+            ops: Vec::new(),
+            span,
+        }
+    }
+
+    /// Count the terms in an expression, or `None` if it contains a call.
+    fn count_terms(expr: &Expression) -> Option<usize> {
+        let mut count = Self::count_term(&expr.term)?;
+        for (_, term) in &expr.ops {
+            count += Self::count_term(term)?;
+        }
+        Some(count)
+    }
+
+    fn count_term(term: &Term) -> Option<usize> {
+        match term {
+            Term::SubroutineCall(_) => None,
+            Term::ArrayAccess(_, index, _) => Some(1 + Self::count_terms(index)?),
+            Term::Parenthesized(inner, _) => Self::count_terms(inner),
+            Term::UnaryOp(_, inner, _) => Some(1 + Self::count_term(inner)?),
+            _ => Some(1),
+        }
+    }
+
+    fn collect_variables<'a>(term: &'a Term, names: &mut Vec<&'a str>) {
+        match term {
+            Term::VarName(name, _) => names.push(name),
+            Term::ArrayAccess(name, index, _) => {
+                names.push(name);
+                names.extend(Self::variables(index));
+            }
+            Term::Parenthesized(inner, _) => names.extend(Self::variables(inner)),
+            Term::UnaryOp(_, inner, _) => Self::collect_variables(inner, names),
+            _ => {}
+        }
+    }
+}
+
+/// Constant propagation through the locals of a subroutine.
+///
+/// After `let x = 5;`, reads of `x` are replaced by `5` until `x` is
+/// reassigned, so the expressions reading it can fold further. At the end of
+/// an `if`, only locals both branches leave with the same value stay known;
+/// a `while` forgets every local its body assigns, before its condition.
+/// Locals used as arrays (`a[i]`) are never tracked. Calls need no care: a
+/// callee cannot change the caller's locals. Reads before a late `var`
+/// declaration (extensions) are left alone, so they are still reported.
+pub struct ConstantPropagator<'a> {
+    /// Tracked locals and the offset of their declaration.
+    locals: HashMap<&'a str, usize>,
+}
+
+/// Values of the tracked locals known at a program point.
+type Known<'a> = HashMap<&'a str, i32>;
+
+impl<'a> ConstantPropagator<'a> {
+    /// The statements of `sub` with constant locals substituted.
+    pub fn propagate(sub: &'a SubroutineDec) -> Vec<Statement> {
+        let mut arrays = HashSet::new();
+        Self::collect_arrays(&sub.body.statements, &mut arrays);
+        let locals = sub
+            .body
+            .var_decs
+            .iter()
+            .flat_map(|dec| {
+                dec.names
+                    .iter()
+                    .map(move |name| (name.as_str(), dec.span.start))
+            })
+            .filter(|(name, _)| !arrays.contains(name))
+            .filter(|(name, _)| !sub.parameters.iter().any(|param| param.name == *name))
+            .collect();
+
+        let propagator = Self { locals };
+        let mut statements = sub.body.statements.clone();
+        propagator.statements(&mut statements, &mut Known::new());
+        statements
+    }
+
+    fn statements(&self, stmts: &mut [Statement], known: &mut Known<'a>) {
+        for stmt in stmts {
+            self.statement(stmt, known);
+        }
+    }
+
+    fn statement(&self, stmt: &mut Statement, known: &mut Known<'a>) {
+        match stmt {
+            Statement::Let(s) => {
+                if let Some(index) = &mut s.index {
+                    self.expression(index, known);
+                }
+                self.expression(&mut s.value, known);
+                if s.index.is_none()
+                    && let Some((&name, _)) = self.locals.get_key_value(s.var_name.as_str())
+                {
+                    // -32768 has no `push constant` form, see `compile_expression`
+                    match ConstantFolder::fold_expression(&s.value)
+                        .filter(|value| (-32767..=32767).contains(value))
+                    {
+                        Some(value) => known.insert(name, value),
+                        None => known.remove(name),
+                    };
+                }
+            }
+            Statement::If(s) => {
+                self.expression(&mut s.condition, known);
+                let mut otherwise = known.clone();
+                self.statements(&mut s.then_statements, known);
+                if let Some(stmts) = &mut s.else_statements {
+                    self.statements(stmts, &mut otherwise);
+                }
+                known.retain(|name, value| otherwise.get(name) == Some(value));
+            }
+            Statement::While(s) => {
+                let mut assigned = HashSet::new();
+                Self::collect_assigned(&s.statements, &mut assigned);
+                known.retain(|name, _| !assigned.contains(name));
+                self.expression(&mut s.condition, known);
+                self.statements(&mut s.statements, &mut known.clone());
+            }
+            Statement::Do(s) => {
+                for arg in &mut s.call.arguments {
+                    self.expression(arg, known);
+                }
+            }
+            Statement::Return(s) => {
+                if let Some(value) = &mut s.value {
+                    self.expression(value, known);
+                }
+            }
+        }
+    }
+
+    fn expression(&self, expr: &mut Expression, known: &Known<'a>) {
+        self.term(&mut expr.term, known);
+        for (_, term) in &mut expr.ops {
+            self.term(term, known);
+        }
+    }
+
+    fn term(&self, term: &mut Term, known: &Known<'a>) {
+        match term {
+            Term::VarName(name, span) => {
+                let declared = self.locals.get(name.as_str());
+                if let Some(&value) = known.get(name.as_str())
+                    && declared.is_some_and(|&start| start < span.start)
+                {
+                    *term = Self::constant(value, span.clone());
+                }
+            }
+            Term::ArrayAccess(_, index, _) => self.expression(index, known),
+            Term::SubroutineCall(call) => {
+                for arg in &mut call.arguments {
+                    self.expression(arg, known);
+                }
+            }
+            Term::Parenthesized(inner, _) => self.expression(inner, known),
+            Term::UnaryOp(_, inner, _) => self.term(inner, known),
+            Term::IntegerConstant(..) | Term::StringConstant(..) | Term::KeywordConstant(..) => {}
+        }
+    }
+
+    /// A term evaluating to `value` (in -32767..=32767).
+    fn constant(value: i32, span: Span) -> Term {
+        let magnitude = Term::IntegerConstant(value.unsigned_abs() as u16, span.clone());
+        if value < 0 {
+            Term::UnaryOp(UnaryOp::Neg, Box::new(magnitude), span)
         } else {
-            false
+            magnitude
+        }
+    }
+
+    /// Names assigned by `let` anywhere in `stmts`.
+    fn collect_assigned<'s>(stmts: &'s [Statement], names: &mut HashSet<&'s str>) {
+        for stmt in stmts {
+            match stmt {
+                Statement::Let(s) if s.index.is_none() => {
+                    names.insert(&s.var_name);
+                }
+                Statement::If(s) => {
+                    Self::collect_assigned(&s.then_statements, names);
+                    Self::collect_assigned(s.else_statements.as_deref().unwrap_or_default(), names);
+                }
+                Statement::While(s) => Self::collect_assigned(&s.statements, names),
+                _ => {}
+            }
+        }
+    }
+
+    /// Names used as arrays (`a[i]`, `let a[i] = ...`) anywhere in `stmts`.
+    fn collect_arrays<'s>(stmts: &'s [Statement], names: &mut HashSet<&'s str>) {
+        fn expression<'s>(expr: &'s Expression, names: &mut HashSet<&'s str>) {
+            term(&expr.term, names);
+            for (_, t) in &expr.ops {
+                term(t, names);
+            }
+        }
+        fn term<'s>(t: &'s Term, names: &mut HashSet<&'s str>) {
+            match t {
+                Term::ArrayAccess(name, index, _) => {
+                    names.insert(name);
+                    expression(index, names);
+                }
+                Term::SubroutineCall(call) => {
+                    call.arguments.iter().for_each(|arg| expression(arg, names));
+                }
+                Term::Parenthesized(inner, _) => expression(inner, names),
+                Term::UnaryOp(_, inner, _) => term(inner, names),
+                _ => {}
+            }
+        }
+
+        for stmt in stmts {
+            match stmt {
+                Statement::Let(s) => {
+                    if let Some(index) = &s.index {
+                        names.insert(&s.var_name);
+                        expression(index, names);
+                    }
+                    expression(&s.value, names);
+                }
+                Statement::If(s) => {
+                    expression(&s.condition, names);
+                    Self::collect_arrays(&s.then_statements, names);
+                    Self::collect_arrays(s.else_statements.as_deref().unwrap_or_default(), names);
+                }
+                Statement::While(s) => {
+                    expression(&s.condition, names);
+                    Self::collect_arrays(&s.statements, names);
+                }
+                Statement::Do(s) => s
+                    .call
+                    .arguments
+                    .iter()
+                    .for_each(|arg| expression(arg, names)),
+                Statement::Return(s) => {
+                    if let Some(value) = &s.value {
+                        expression(value, names);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// One step of an addition chain, applied to the value on top of the stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChainStep {
+    /// Copy the value to temp 0 (`pop temp 0` / `push temp 0`).
+    Save,
+    /// Double the value, using temp 1 as scratch.
+    Double,
+    /// Add the value saved in temp 0 (`push temp 0` / `add`).
+    AddSaved,
+}
+
+impl ChainStep {
+    /// Number of VM commands the step compiles to.
+    pub fn cost(self) -> usize {
+        match self {
+            ChainStep::Save | ChainStep::AddSaved => 2,
+            ChainStep::Double => 4,
         }
     }
 }
@@ -150,6 +679,13 @@ impl PeepholeOptimizer {
 pub struct StrengthReduction;
 
 impl StrengthReduction {
+    /// Approximate VM command cost of a `Math.multiply` call, which an
+    /// addition chain must undercut.
+    pub const MULTIPLY_CALL_COST: usize = 15;
+
+    /// Largest constant multiplied by an addition chain.
+    pub const MAX_CHAIN_FACTOR: u16 = 6;
+
     /// Check if a number is a power of 2 (for multiplication optimization).
     pub fn is_power_of_two(n: u16) -> bool {
         n > 0 && (n & (n - 1)) == 0
@@ -173,37 +709,42 @@ impl StrengthReduction {
             None
         }
     }
+
+    /// Addition chain multiplying the top of the stack by a small constant.
+    ///
+    /// Built from the binary digits of `n` (double, then add the original for
+    /// each set bit), so `x * 5` is `((x + x) + (x + x)) + x`. Returns `None`
+    /// outside `2..=MAX_CHAIN_FACTOR` or when the chain is not cheaper than
+    /// the call.
+    pub fn addition_chain(n: u16) -> Option<Vec<ChainStep>> {
+        if !(2..=Self::MAX_CHAIN_FACTOR).contains(&n) {
+            return None;
+        }
+        let mut steps = Vec::new();
+        if !Self::is_power_of_two(n) {
+            steps.push(ChainStep::Save);
+        }
+        for bit in (0..n.ilog2()).rev() {
+            steps.push(ChainStep::Double);
+            if n & (1 << bit) != 0 {
+                steps.push(ChainStep::AddSaved);
+            }
+        }
+        let cost: usize = steps.iter().map(|step| step.cost()).sum();
+        (cost < Self::MULTIPLY_CALL_COST).then_some(steps)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use jack_analyzer::parser::Parser;
-    use jack_analyzer::tokenizer::JackTokenizer;
 
     // ========================================================================
     // Constant Folding Tests
     // ========================================================================
 
     fn parse_expr(source: &str) -> Expression {
-        // Wrap in a minimal class to parse
-        let full_source = format!(
-            "class T {{ function void f() {{ var int x; let x = {}; return; }} }}",
-            source
-        );
-        let tokenizer = JackTokenizer::new(&full_source);
-        let tokens = tokenizer.tokenize().unwrap();
-        let parser = Parser::new(&tokens);
-        let class = parser.parse().unwrap();
-
-        // Extract expression from let statement
-        if let jack_analyzer::ast::Statement::Let(let_stmt) =
-            &class.subroutine_decs[0].body.statements[0]
-        {
-            let_stmt.value.clone()
-        } else {
-            panic!("Expected let statement");
-        }
+        jack_analyzer::parse_expression_snippet(source).unwrap()
     }
 
     #[test]
@@ -328,6 +869,81 @@ mod tests {
         assert_eq!(optimized, "push constant 5\n");
     }
 
+    #[test]
+    fn test_peephole_goto_next_label_removed() {
+        // The label is then unreferenced and removed as well
+        let input = "goto END\nlabel END\nreturn\n";
+        assert_eq!(PeepholeOptimizer::optimize(input), "return\n");
+    }
+
+    #[test]
+    fn test_peephole_goto_other_label_kept() {
+        let input = "goto END\nlabel ELSE\nlabel END\nreturn\n";
+        assert_eq!(PeepholeOptimizer::optimize(input), input);
+        let input = "if-goto END\nlabel END\nreturn\n";
+        assert_eq!(PeepholeOptimizer::optimize(input), input);
+        let input = "goto END_2\nlabel END\n";
+        assert_eq!(PeepholeOptimizer::optimize(input), input);
+    }
+
+    #[test]
+    fn test_peephole_orphaned_labels_removed() {
+        // The jump to the next line falls through, orphaning END, while
+        // ELSE is still a target and UNUSED never was one
+        let input = "\
+if-goto ELSE
+goto END
+label END
+label ELSE
+label UNUSED
+push constant 1
+if-goto ELSE
+";
+        assert_eq!(
+            PeepholeOptimizer::optimize(input),
+            "if-goto ELSE\nlabel ELSE\nlabel UNUSED\npush constant 1\nif-goto ELSE\n"
+        );
+    }
+
+    #[test]
+    fn test_peephole_writer_matches_whole_text() {
+        use std::fmt::Write;
+
+        let input = "\
+function Main.a 0
+push constant 1
+neg
+neg
+goto Main.a$END_0
+label Main.a$END_0
+return
+// Main.b
+function Main.b 1
+pop temp 0
+push temp 0
+label Main.b$UNUSED_1
+push local 0
+push constant 0
+add
+return
+function Main.c 0
+push constant 0
+return
+";
+        let expected = PeepholeOptimizer::optimize(input);
+        // Chunks that split lines and the `function` keyword
+        for size in [1, 3, 10, input.len()] {
+            let mut writer = PeepholeWriter::new(String::new());
+            for chunk in input.as_bytes().chunks(size) {
+                writer
+                    .write_str(std::str::from_utf8(chunk).unwrap())
+                    .unwrap();
+            }
+            assert_eq!(writer.finish().unwrap(), expected, "chunk size {size}");
+        }
+        assert_eq!(PeepholeWriter::new(String::new()).finish().unwrap(), "");
+    }
+
     #[test]
     fn test_peephole_push_pop_different_locations() {
         let input = "push local 0\npop local 1\n";
@@ -335,6 +951,23 @@ mod tests {
         assert_eq!(optimized, "push local 0\npop local 1\n");
     }
 
+    #[test]
+    fn test_peephole_push_pop_different_segments() {
+        let input = "push local 0\npop argument 0\npush this 2\npop that 2\n";
+        let optimized = PeepholeOptimizer::optimize(input);
+        assert_eq!(optimized, input);
+    }
+
+    #[test]
+    fn test_peephole_push_pop_all_segments() {
+        for segment in [
+            "local", "argument", "this", "that", "static", "temp", "pointer",
+        ] {
+            let input = format!("push {segment} 1\npop {segment} 1\nadd\n");
+            assert_eq!(PeepholeOptimizer::optimize(&input), "add\n", "{segment}");
+        }
+    }
+
     #[test]
     fn test_peephole_constant_not_eliminated() {
         // push constant / pop should not be eliminated (side effect on stack)
@@ -357,6 +990,49 @@ mod tests {
         assert_eq!(optimized, "push local 0\n");
     }
 
+    #[test]
+    fn test_peephole_fold_neg_zero() {
+        let input = "push constant 0\nneg\nadd\n";
+        assert_eq!(PeepholeOptimizer::optimize(input), "push constant 0\nadd\n");
+    }
+
+    #[test]
+    fn test_peephole_fold_not_chain() {
+        // !(-5) = 4
+        let input = "push constant 5\nneg\nnot\n";
+        assert_eq!(PeepholeOptimizer::optimize(input), "push constant 4\n");
+    }
+
+    #[test]
+    fn test_peephole_fold_reemits_neg() {
+        // -(!5) = 6, then !6 = -7
+        let input = "push constant 5\nnot\nneg\nnot\n";
+        assert_eq!(PeepholeOptimizer::optimize(input), "push constant 7\nneg\n");
+    }
+
+    #[test]
+    fn test_peephole_fold_min_value() {
+        // !32767 = -32768, which has no positive magnitude to negate
+        let input = "push constant 32767\nneg\nneg\nnot\n";
+        assert_eq!(
+            PeepholeOptimizer::optimize(input),
+            "push constant 32767\nnot\n"
+        );
+    }
+
+    #[test]
+    fn test_peephole_fold_keeps_single_op() {
+        for input in ["push constant 5\nneg\n", "push constant 0\nnot\n"] {
+            assert_eq!(PeepholeOptimizer::optimize(input), input);
+        }
+    }
+
+    #[test]
+    fn test_peephole_fold_ignores_non_constant() {
+        let input = "push local 0\nneg\nnot\n";
+        assert_eq!(PeepholeOptimizer::optimize(input), input);
+    }
+
     #[test]
     fn test_peephole_identity_add() {
         let input = "push local 0\npush constant 0\nadd\n";
@@ -378,6 +1054,48 @@ mod tests {
         assert_eq!(optimized, "push constant 5\n");
     }
 
+    #[test]
+    fn test_peephole_temp_copy_removed() {
+        // The copy is overwritten before it is read
+        let input =
+            "call Main.f 0\npop temp 0\npush temp 0\npop local 0\npush constant 1\npop temp 0\n";
+        assert_eq!(
+            PeepholeOptimizer::optimize(input),
+            "call Main.f 0\npop local 0\npush constant 1\npop temp 0\n"
+        );
+        let input = "pop temp 0\npush temp 0\nreturn\n";
+        assert_eq!(PeepholeOptimizer::optimize(input), "return\n");
+    }
+
+    #[test]
+    fn test_peephole_temp_copy_kept_when_read() {
+        // Doubling reads the copy right after the pair
+        let input = "pop temp 0\npush temp 0\npush temp 0\nadd\nreturn\n";
+        assert_eq!(PeepholeOptimizer::optimize(input), input);
+        // An addition chain reads it later
+        let input = "pop temp 0\npush temp 0\npush constant 2\nadd\npush temp 0\nadd\nreturn\n";
+        assert_eq!(PeepholeOptimizer::optimize(input), input);
+        // The label may be reached from code that reads it
+        let input = "pop temp 0\npush temp 0\nlabel L\npop temp 0\ngoto L\n";
+        assert_eq!(PeepholeOptimizer::optimize(input), input);
+        // Nothing overwrites it before the end of the input
+        let input = "pop temp 0\npush temp 0\nadd\n";
+        assert_eq!(PeepholeOptimizer::optimize(input), input);
+    }
+
+    #[test]
+    fn test_peephole_dead_temp_store_before_return() {
+        let input = "function Main.f 0\npush constant 0\npop temp 0\npush constant 0\nreturn\n";
+        assert_eq!(
+            PeepholeOptimizer::optimize(input),
+            "function Main.f 0\npush constant 0\nreturn\n"
+        );
+        let input = "push constant 0\npop temp 0\nlabel L\ngoto L\nreturn\n";
+        assert_eq!(PeepholeOptimizer::optimize(input), input);
+        let input = "push constant 0\npop temp 0\npush constant 1\npush temp 0\nadd\nreturn\n";
+        assert_eq!(PeepholeOptimizer::optimize(input), input);
+    }
+
     #[test]
     fn test_peephole_empty_input() {
         let input = "";
@@ -431,4 +1149,37 @@ mod tests {
         // Non-power-of-2
         assert_eq!(StrengthReduction::optimize_multiply(3), None);
     }
+
+    /// Apply a chain the way the emitted VM code does.
+    fn run_chain(x: i16, steps: &[ChainStep]) -> i16 {
+        let (mut top, mut saved) = (x, 0i16);
+        for step in steps {
+            match step {
+                ChainStep::Save => saved = top,
+                ChainStep::Double => top = top.wrapping_add(top),
+                ChainStep::AddSaved => top = top.wrapping_add(saved),
+            }
+        }
+        top
+    }
+
+    #[test]
+    fn test_addition_chain() {
+        use ChainStep::*;
+        assert_eq!(
+            StrengthReduction::addition_chain(5),
+            Some(vec![Save, Double, Double, AddSaved])
+        );
+        for n in 2..=StrengthReduction::MAX_CHAIN_FACTOR {
+            let steps = StrengthReduction::addition_chain(n).unwrap();
+            let cost: usize = steps.iter().map(|step| step.cost()).sum();
+            assert!(cost < StrengthReduction::MULTIPLY_CALL_COST, "x * {n}");
+            for x in [0, 1, 7, -3, 1000, i16::MAX] {
+                assert_eq!(run_chain(x, &steps), x.wrapping_mul(n as i16), "{x} * {n}");
+            }
+        }
+        assert_eq!(StrengthReduction::addition_chain(0), None);
+        assert_eq!(StrengthReduction::addition_chain(1), None);
+        assert_eq!(StrengthReduction::addition_chain(7), None);
+    }
 }
@@ -0,0 +1,178 @@
+//! Subroutine signature registry for call checking.
+//!
+//! Records the kind and parameter count of every subroutine in the classes
+//! the compiler knows about, so call sites can be checked against them.
+//! The standard OS API is described by a fixed table, [`os_signature`].
+
+use jack_analyzer::ast::{Class, SubroutineKind};
+use std::collections::HashMap;
+
+/// The callable shape of a subroutine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Signature {
+    /// Constructor, function, or method.
+    pub kind: SubroutineKind,
+    /// Number of declared parameters (excluding the implicit `this`).
+    pub param_count: u16,
+}
+
+/// Registry of subroutine signatures, keyed by class then subroutine name.
+#[derive(Debug, Default, Clone)]
+pub struct ClassRegistry {
+    classes: HashMap<String, HashMap<String, Signature>>,
+}
+
+impl ClassRegistry {
+    /// Create an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register all subroutines declared by a class.
+    pub fn register_class(&mut self, class: &Class) {
+        let subs = self.classes.entry(class.name.clone()).or_default();
+        for sub in &class.subroutine_decs {
+            subs.insert(
+                sub.name.clone(),
+                Signature {
+                    kind: sub.kind,
+                    param_count: sub.parameters.len() as u16,
+                },
+            );
+        }
+    }
+
+    /// Check if a class is known to the registry.
+    pub fn has_class(&self, class_name: &str) -> bool {
+        self.classes.contains_key(class_name)
+    }
+
+    /// Look up the signature of `class_name.sub_name`.
+    pub fn lookup(&self, class_name: &str, sub_name: &str) -> Option<Signature> {
+        self.classes.get(class_name)?.get(sub_name).copied()
+    }
+}
+
+/// Signatures of the standard Jack OS subroutines, as documented in the
+/// course's OS API: (class, subroutine, kind, parameter count).
+const OS_SUBROUTINES: &[(&str, &str, SubroutineKind, u16)] = {
+    use SubroutineKind::{Constructor as C, Function as F, Method as M};
+    &[
+        ("Math", "init", F, 0),
+        ("Math", "abs", F, 1),
+        ("Math", "multiply", F, 2),
+        ("Math", "divide", F, 2),
+        ("Math", "min", F, 2),
+        ("Math", "max", F, 2),
+        ("Math", "sqrt", F, 1),
+        ("String", "new", C, 1),
+        ("String", "dispose", M, 0),
+        ("String", "length", M, 0),
+        ("String", "charAt", M, 1),
+        ("String", "setCharAt", M, 2),
+        ("String", "appendChar", M, 1),
+        ("String", "eraseLastChar", M, 0),
+        ("String", "intValue", M, 0),
+        ("String", "setInt", M, 1),
+        ("String", "backSpace", F, 0),
+        ("String", "doubleQuote", F, 0),
+        ("String", "newLine", F, 0),
+        ("Array", "new", F, 1),
+        ("Array", "dispose", M, 0),
+        ("Output", "init", F, 0),
+        ("Output", "moveCursor", F, 2),
+        ("Output", "printChar", F, 1),
+        ("Output", "printString", F, 1),
+        ("Output", "printInt", F, 1),
+        ("Output", "println", F, 0),
+        ("Output", "backSpace", F, 0),
+        ("Screen", "init", F, 0),
+        ("Screen", "clearScreen", F, 0),
+        ("Screen", "setColor", F, 1),
+        ("Screen", "drawPixel", F, 2),
+        ("Screen", "drawLine", F, 4),
+        ("Screen", "drawRectangle", F, 4),
+        ("Screen", "drawCircle", F, 3),
+        ("Keyboard", "init", F, 0),
+        ("Keyboard", "keyPressed", F, 0),
+        ("Keyboard", "readChar", F, 0),
+        ("Keyboard", "readLine", F, 1),
+        ("Keyboard", "readInt", F, 1),
+        ("Memory", "init", F, 0),
+        ("Memory", "peek", F, 1),
+        ("Memory", "poke", F, 2),
+        ("Memory", "alloc", F, 1),
+        ("Memory", "deAlloc", F, 1),
+        ("Sys", "init", F, 0),
+        ("Sys", "halt", F, 0),
+        ("Sys", "error", F, 1),
+        ("Sys", "wait", F, 1),
+    ]
+};
+
+/// Check if `class_name` is one of the standard OS classes.
+pub fn is_os_class(class_name: &str) -> bool {
+    OS_SUBROUTINES
+        .iter()
+        .any(|(class, _, _, _)| *class == class_name)
+}
+
+/// Look up the signature of a standard OS subroutine.
+pub fn os_signature(class_name: &str, sub_name: &str) -> Option<Signature> {
+    OS_SUBROUTINES
+        .iter()
+        .find(|(class, sub, _, _)| *class == class_name && *sub == sub_name)
+        .map(|&(_, _, kind, param_count)| Signature { kind, param_count })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jack_analyzer::parser::Parser;
+    use jack_analyzer::tokenizer::JackTokenizer;
+
+    #[test]
+    fn test_register_and_lookup() {
+        let source = "class Point { constructor Point new(int x, int y) { return this; } \
+                      method int getX() { return 0; } function int origin() { return 0; } }";
+        let tokens = JackTokenizer::new(source).tokenize().unwrap();
+        let class = Parser::new(&tokens).parse().unwrap();
+
+        let mut registry = ClassRegistry::new();
+        registry.register_class(&class);
+
+        assert!(registry.has_class("Point"));
+        assert!(!registry.has_class("Main"));
+        assert_eq!(
+            registry.lookup("Point", "new"),
+            Some(Signature {
+                kind: SubroutineKind::Constructor,
+                param_count: 2
+            })
+        );
+        assert_eq!(
+            registry.lookup("Point", "getX").map(|s| s.kind),
+            Some(SubroutineKind::Method)
+        );
+        assert_eq!(registry.lookup("Point", "missing"), None);
+    }
+
+    #[test]
+    fn test_os_signatures() {
+        assert_eq!(
+            os_signature("Output", "printInt"),
+            Some(Signature {
+                kind: SubroutineKind::Function,
+                param_count: 1
+            })
+        );
+        assert_eq!(
+            os_signature("String", "appendChar").map(|s| s.kind),
+            Some(SubroutineKind::Method)
+        );
+        assert_eq!(os_signature("Output", "printLine"), None);
+        assert_eq!(os_signature("Main", "main"), None);
+        assert!(is_os_class("Memory"));
+        assert!(!is_os_class("Main"));
+    }
+}
@@ -0,0 +1,132 @@
+//! Local and argument slot verification of generated VM code.
+//!
+//! Like [`stack_verify`](crate::stack_verify), a consistency check on the
+//! compiler itself: [`verify_slots`] checks that each `function F k` only
+//! touches `local 0` to `local k-1`, and only the arguments its subroutine
+//! declares (plus `argument 0`, the object, for a method). A slot past
+//! either bound reads or overwrites another frame's memory at run time.
+//!
+//! Declaring more slots than the code uses is wasteful but harmless, and is
+//! not reported: the optimizer legitimately removes uses of locals.
+
+use jack_analyzer::ast::{Class, SubroutineDec, SubroutineKind};
+
+use crate::error::CompileError;
+use crate::vm_writer::Segment;
+
+/// Check the slot indexes of VM code generated for `class`, returning the
+/// first violation in each function as [`CompileError::SlotOutOfRange`].
+///
+/// Functions that `class` does not declare get the local check only.
+pub fn verify_slots(vm_code: &str, class: &Class) -> Vec<CompileError> {
+    verify_slots_with(vm_code, |function| {
+        let name = function
+            .strip_prefix(class.name.as_str())?
+            .strip_prefix('.')?;
+        let sub = class.subroutine_decs.iter().find(|sub| sub.name == name)?;
+        Some(argument_count(sub))
+    })
+}
+
+/// The argument slots of `sub`: its parameters, plus the object of a method.
+pub(crate) fn argument_count(sub: &SubroutineDec) -> u16 {
+    let object = u16::from(sub.kind == SubroutineKind::Method);
+    sub.parameters.len() as u16 + object
+}
+
+/// [`verify_slots`] with the argument count of each function looked up by
+/// its qualified name (`None` skips the argument check).
+pub(crate) fn verify_slots_with(
+    vm_code: &str,
+    arguments: impl Fn(&str) -> Option<u16>,
+) -> Vec<CompileError> {
+    let mut errors = Vec::new();
+    // (name, declared locals, declared arguments), `None` once reported
+    let mut function: Option<(&str, u16, Option<u16>)> = None;
+    for line in vm_code.lines() {
+        let mut words = line.split_whitespace();
+        let (Some(command), Some(arg1), Some(arg2)) = (words.next(), words.next(), words.next())
+        else {
+            continue;
+        };
+        let Ok(index) = arg2.parse::<u16>() else {
+            continue;
+        };
+        if command == "function" {
+            function = Some((arg1, index, arguments(arg1)));
+            continue;
+        }
+        let Some((name, locals, args)) = function else {
+            continue;
+        };
+        if command != "push" && command != "pop" {
+            continue;
+        }
+        let (segment, declared) = match arg1 {
+            "local" => (Segment::Local, locals),
+            "argument" => match args {
+                Some(args) => (Segment::Argument, args),
+                None => continue,
+            },
+            _ => continue,
+        };
+        if index >= declared {
+            errors.push(CompileError::slot_out_of_range(
+                name, segment, index, declared,
+            ));
+            function = None;
+        }
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn violations(vm_code: &str, arguments: Option<u16>) -> Vec<String> {
+        verify_slots_with(vm_code, |_| arguments)
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn test_slots_in_range() {
+        let vm = "function Main.f 2\npush argument 1\npop local 1\npush local 0\n\
+                  push constant 5\npush temp 7\nreturn\n";
+        assert_eq!(violations(vm, Some(2)), Vec::<String>::new());
+        // Unused declared slots are fine
+        assert_eq!(
+            violations("function Main.f 3\npush constant 0\nreturn\n", Some(4)),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn test_local_out_of_range() {
+        let vm = "function Main.f 2\npush local 2\npush local 3\nreturn\n\
+                  function Main.g 0\npop local 0\n";
+        assert_eq!(
+            violations(vm, None),
+            [
+                "internal error: 'Main.f' declares 2 local slot(s) but its code uses 'local 2'",
+                "internal error: 'Main.g' declares 0 local slot(s) but its code uses 'local 0'",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_argument_out_of_range() {
+        let vm = "function Main.f 0\npush argument 1\nreturn\n";
+        assert_eq!(violations(vm, None), Vec::<String>::new());
+        let errors = verify_slots_with(vm, |_| Some(1));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code(), "JC0016");
+        assert!(matches!(
+            &errors[0],
+            CompileError::SlotOutOfRange { subroutine, segment: Segment::Argument, index: 1, declared: 1 }
+                if subroutine == "Main.f"
+        ));
+    }
+}
@@ -0,0 +1,307 @@
+//! Stack-effect verification of generated VM code.
+//!
+//! A consistency check on the compiler itself, not on the Jack program:
+//! [`verify_stack`] evaluates the stack depth of each function symbolically
+//! and reports code that could not have come from a correct code generator.
+//! Each function starts with an empty stack and must:
+//!
+//! - never pop more values than it pushed;
+//! - have at least `n` values on the stack at each `call F n`;
+//! - hold exactly one value (the return value) at each `return`;
+//! - reach each label with the same depth from every jump and fall-through.
+//!
+//! Code after `goto` or `return` has an unknown depth until the next label
+//! that some jump targets, and is not checked.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::error::CompileError;
+
+/// Check the stack effects of VM code, returning the first violation in each
+/// function as [`CompileError::StackEffectViolation`].
+pub fn verify_stack(vm_code: &str) -> Vec<CompileError> {
+    let mut verifier = StackVerifier::default();
+    for line in vm_code.lines() {
+        verifier.line(line);
+    }
+    verifier.errors
+}
+
+/// [`verify_stack`] one line at a time.
+#[derive(Default)]
+struct StackVerifier {
+    /// Lines seen so far.
+    lines: usize,
+    function: FunctionState,
+    errors: Vec<CompileError>,
+}
+
+impl StackVerifier {
+    fn line(&mut self, line: &str) {
+        self.lines += 1;
+        let instruction = line.trim();
+        if instruction.is_empty() || instruction.starts_with("//") {
+            return;
+        }
+        let mut words = instruction.split_whitespace();
+        let command = words.next().unwrap_or_default();
+        if command == "function" {
+            self.function = FunctionState {
+                depth: Some(0),
+                ..FunctionState::default()
+            };
+            return;
+        }
+        if self.function.failed {
+            return;
+        }
+        if let Err(message) = self.function.step(command, words.next(), words.next()) {
+            self.function.failed = true;
+            self.errors.push(CompileError::stack_effect_violation(
+                self.lines,
+                instruction,
+                message,
+            ));
+        }
+    }
+}
+
+/// Forwards VM code to `out`, checking it as [`verify_stack`] would check the
+/// whole text while holding at most one incomplete line.
+pub(crate) struct VerifyingWriter<W> {
+    out: W,
+    verifier: StackVerifier,
+    /// Text after the last newline written.
+    partial: String,
+}
+
+impl<W: fmt::Write> VerifyingWriter<W> {
+    pub(crate) fn new(out: W) -> Self {
+        Self {
+            out,
+            verifier: StackVerifier::default(),
+            partial: String::new(),
+        }
+    }
+
+    /// Check the last line, returning the violations found.
+    pub(crate) fn finish(mut self) -> Vec<CompileError> {
+        if !self.partial.is_empty() {
+            self.verifier.line(&self.partial);
+        }
+        self.verifier.errors
+    }
+}
+
+impl<W: fmt::Write> fmt::Write for VerifyingWriter<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.out.write_str(s)?;
+        let mut rest = s;
+        while let Some(end) = rest.find('\n') {
+            if self.partial.is_empty() {
+                self.verifier.line(&rest[..end]);
+            } else {
+                self.partial.push_str(&rest[..end]);
+                self.verifier.line(&self.partial);
+                self.partial.clear();
+            }
+            rest = &rest[end + 1..];
+        }
+        self.partial.push_str(rest);
+        Ok(())
+    }
+}
+
+/// Symbolic state while walking one function.
+#[derive(Default)]
+struct FunctionState {
+    /// Current depth, `None` after an unconditional jump.
+    depth: Option<usize>,
+    /// Depth recorded by the first jump to each label.
+    labels: HashMap<String, usize>,
+    /// A violation was reported; skip to the next function.
+    failed: bool,
+}
+
+impl FunctionState {
+    fn step(
+        &mut self,
+        command: &str,
+        arg1: Option<&str>,
+        arg2: Option<&str>,
+    ) -> Result<(), String> {
+        match command {
+            "push" => self.apply(0, 1),
+            "pop" => self.apply(1, 0),
+            "add" | "sub" | "and" | "or" | "eq" | "gt" | "lt" => self.apply(2, 1),
+            "neg" | "not" => self.apply(1, 1),
+            "call" => {
+                let args = arg2.and_then(|n| n.parse().ok()).unwrap_or(0);
+                self.apply(args, 1)
+            }
+            "label" => self.label(arg1.unwrap_or_default()),
+            "goto" => {
+                self.jump(arg1.unwrap_or_default())?;
+                self.depth = None;
+                Ok(())
+            }
+            "if-goto" => {
+                self.apply(1, 0)?;
+                self.jump(arg1.unwrap_or_default())
+            }
+            "return" => {
+                match self.depth {
+                    Some(1) | None => {}
+                    Some(depth) => {
+                        return Err(format!(
+                            "returns with {depth} value(s) on the stack, expected 1"
+                        ));
+                    }
+                }
+                self.depth = None;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Pop `pops` values, then push `pushes`.
+    fn apply(&mut self, pops: usize, pushes: usize) -> Result<(), String> {
+        if let Some(depth) = self.depth {
+            if depth < pops {
+                return Err(format!("needs {pops} value(s) but the stack holds {depth}"));
+            }
+            self.depth = Some(depth - pops + pushes);
+        }
+        Ok(())
+    }
+
+    /// Record or check the depth of a jump to `label`.
+    fn jump(&mut self, label: &str) -> Result<(), String> {
+        let Some(depth) = self.depth else {
+            return Ok(());
+        };
+        match self.labels.get(label) {
+            Some(&expected) if expected != depth => Err(format!(
+                "jumps to '{label}' with {depth} value(s) on the stack, but another jump has {expected}"
+            )),
+            Some(_) => Ok(()),
+            None => {
+                self.labels.insert(label.to_string(), depth);
+                Ok(())
+            }
+        }
+    }
+
+    fn label(&mut self, label: &str) -> Result<(), String> {
+        let recorded = self.labels.get(label).copied();
+        match (self.depth, recorded) {
+            (Some(depth), Some(expected)) if depth != expected => Err(format!(
+                "reaches '{label}' with {depth} value(s) on the stack, but a jump to it has {expected}"
+            )),
+            (None, _) => {
+                self.depth = recorded;
+                Ok(())
+            }
+            (Some(depth), None) => {
+                self.labels.insert(label.to_string(), depth);
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm_writer::{Segment, VMWriter};
+
+    fn violations(vm_code: &str) -> Vec<(usize, String)> {
+        verify_stack(vm_code)
+            .into_iter()
+            .map(|e| match e {
+                CompileError::StackEffectViolation {
+                    line, instruction, ..
+                } => (line, instruction),
+                other => panic!("unexpected error {other}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_balanced_function() {
+        let vm = "// f\nfunction Main.f 1\npush argument 0\npush constant 1\ncall Main.g 2\npop temp 0\n\
+                  label LOOP\npush local 0\nnot\nif-goto END\ngoto LOOP\nlabel END\n\
+                  push constant 0\nreturn\n";
+        assert_eq!(violations(vm), []);
+    }
+
+    #[test]
+    fn test_underflow() {
+        let mut vm = VMWriter::new();
+        vm.write_function("Main.f", 0);
+        vm.write_push(Segment::Constant, 1);
+        vm.write_arithmetic("add");
+        vm.write_return();
+        assert_eq!(violations(vm.as_str()), [(3, "add".to_string())]);
+    }
+
+    #[test]
+    fn test_call_needs_its_arguments() {
+        let vm = "function Main.f 0\npush constant 1\ncall Math.multiply 2\nreturn\n";
+        assert_eq!(violations(vm), [(3, "call Math.multiply 2".to_string())]);
+    }
+
+    #[test]
+    fn test_return_depth() {
+        let vm = "function Main.f 0\npush constant 1\npush constant 2\nreturn\n\
+                  function Main.g 0\nreturn\n";
+        assert_eq!(
+            violations(vm),
+            [(4, "return".to_string()), (6, "return".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_label_depth_mismatch() {
+        let vm = "function Main.f 0\npush constant 1\npush constant 0\nif-goto END\n\
+                  push constant 2\nlabel END\npop temp 0\npush constant 0\nreturn\n";
+        assert_eq!(violations(vm), [(6, "label END".to_string())]);
+    }
+
+    #[test]
+    fn test_unreachable_code_after_return() {
+        let vm = "function Main.f 0\npush constant 0\nreturn\ngoto END\nlabel END\n\
+                  push constant 0\nreturn\n";
+        assert_eq!(violations(vm), []);
+    }
+
+    #[test]
+    fn test_writer_matches_whole_text() {
+        use std::fmt::Write;
+
+        let vm = "function Main.f 0\npush constant 1\nreturn\nfunction Main.g 0\n\
+                  push constant 1\ncall Math.multiply 2\nreturn";
+        for chunk in [1, 3, 7, vm.len()] {
+            let mut out = String::new();
+            let mut writer = VerifyingWriter::new(&mut out);
+            for piece in vm.as_bytes().chunks(chunk) {
+                writer
+                    .write_str(std::str::from_utf8(piece).unwrap())
+                    .unwrap();
+            }
+            let errors = writer.finish();
+            assert_eq!(out, vm);
+            assert_eq!(
+                errors.iter().map(ToString::to_string).collect::<Vec<_>>(),
+                verify_stack(vm)
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+            );
+            assert_eq!(errors.len(), 1);
+        }
+    }
+}
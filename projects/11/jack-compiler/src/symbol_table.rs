@@ -5,11 +5,24 @@
 //! - **Subroutine scope**: `argument` and `local` variables, reset per subroutine
 //!
 //! Lookup is subroutine-first, allowing local variables to shadow class-level ones.
+//!
+//! Both scopes live in one map from name to [`Binding`], so a lookup or a
+//! define hashes the name once. Subroutine-scope entries are tagged with a
+//! generation instead of being removed: starting a subroutine bumps the
+//! generation, which hides every older entry without touching the map.
+//!
+//! Names are interned (see [`Interner`]), so the same local name in every
+//! subroutine of a class shares one allocation, and symbols are stored behind
+//! `Arc` so callers can hold on to one without copying it. `Arc` rather than
+//! `Rc` keeps the table `Send`, so classes can be compiled on worker threads.
 
 use crate::error::CompileError;
+use crate::intern::Interner;
+use crate::vm_writer::Segment;
 use jack_analyzer::ast::Type;
 use jack_analyzer::token::Span;
 use std::collections::HashMap;
+use std::sync::Arc;
 
 /// The kind of symbol, determining its VM segment.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -27,10 +40,21 @@ pub enum SymbolKind {
 impl SymbolKind {
     /// Convert to VM segment name.
     #[inline]
-    pub fn to_segment(self) -> &'static str {
+    pub fn to_segment(self) -> Segment {
+        match self {
+            SymbolKind::Static => Segment::Static,
+            SymbolKind::Field => Segment::This,
+            SymbolKind::Argument => Segment::Argument,
+            SymbolKind::Local => Segment::Local,
+        }
+    }
+
+    /// The declaration keyword for this kind (`field`, `argument`, ...).
+    #[inline]
+    pub fn as_str(self) -> &'static str {
         match self {
             SymbolKind::Static => "static",
-            SymbolKind::Field => "this",
+            SymbolKind::Field => "field",
             SymbolKind::Argument => "argument",
             SymbolKind::Local => "local",
         }
@@ -46,57 +70,169 @@ impl SymbolKind {
 /// A symbol entry in the symbol table.
 #[derive(Debug, Clone)]
 pub struct Symbol {
-    /// The symbol name.
-    pub name: String,
+    /// The symbol name (interned).
+    pub name: Arc<str>,
     /// The symbol's type (int, char, boolean, or class name).
     pub symbol_type: Type,
     /// The kind of symbol (determines VM segment).
     pub kind: SymbolKind,
     /// The index within its segment.
     pub index: u16,
+    /// Where the symbol was declared.
+    pub span: Span,
 }
 
 impl Symbol {
     /// Get the VM segment for this symbol.
     #[inline]
-    pub fn segment(&self) -> &'static str {
+    pub fn segment(&self) -> Segment {
         self.kind.to_segment()
     }
 }
 
+/// Result of a successful [`SymbolTable::define`].
+#[derive(Debug, Clone)]
+pub enum DefineOutcome {
+    /// The name was not visible before.
+    Fresh,
+    /// A subroutine-scope symbol now hides this class-scope one.
+    Shadows(Arc<Symbol>),
+}
+
+/// The symbols one name is bound to in each scope.
+#[derive(Debug)]
+struct Binding {
+    /// The interned name, shared by both symbols.
+    name: Arc<str>,
+    /// Class-scope symbol (static or field).
+    class: Option<Arc<Symbol>>,
+    /// Subroutine-scope symbol (argument or local), visible only while
+    /// `generation` is the table's current one.
+    subroutine: Option<Arc<Symbol>>,
+    generation: u32,
+}
+
+impl Binding {
+    fn new(name: Arc<str>) -> Self {
+        Self {
+            name,
+            class: None,
+            subroutine: None,
+            generation: 0,
+        }
+    }
+
+    /// The subroutine-scope symbol, if it belongs to the current subroutine.
+    fn subroutine(&self, generation: u32) -> Option<&Arc<Symbol>> {
+        self.subroutine
+            .as_ref()
+            .filter(|_| self.generation == generation)
+    }
+
+    /// Bind a new symbol in the scope of `kind`.
+    fn define(
+        &mut self,
+        counts: &mut Counts,
+        generation: u32,
+        symbol_type: Type,
+        kind: SymbolKind,
+        span: Span,
+    ) -> Result<DefineOutcome, CompileError> {
+        let taken = if kind.is_class_level() {
+            self.class.is_some()
+        } else {
+            self.subroutine(generation).is_some()
+        };
+        if taken {
+            return Err(CompileError::duplicate_definition(&*self.name, span));
+        }
+
+        // Shadowing a field/static is legal, but often a student bug
+        let outcome = match &self.class {
+            Some(shadowed) if !kind.is_class_level() => {
+                DefineOutcome::Shadows(Arc::clone(shadowed))
+            }
+            _ => DefineOutcome::Fresh,
+        };
+
+        let symbol = Arc::new(Symbol {
+            name: Arc::clone(&self.name),
+            symbol_type,
+            kind,
+            index: counts.next(kind),
+            span,
+        });
+        if kind.is_class_level() {
+            self.class = Some(symbol);
+        } else {
+            self.subroutine = Some(symbol);
+            self.generation = generation;
+        }
+
+        Ok(outcome)
+    }
+}
+
+/// Number of symbols defined so far, per kind.
+#[derive(Debug, Default)]
+struct Counts {
+    statics: u16,
+    fields: u16,
+    arguments: u16,
+    locals: u16,
+}
+
+impl Counts {
+    fn get(&self, kind: SymbolKind) -> u16 {
+        match kind {
+            SymbolKind::Static => self.statics,
+            SymbolKind::Field => self.fields,
+            SymbolKind::Argument => self.arguments,
+            SymbolKind::Local => self.locals,
+        }
+    }
+
+    /// The next index for `kind`, counting it.
+    fn next(&mut self, kind: SymbolKind) -> u16 {
+        let count = match kind {
+            SymbolKind::Static => &mut self.statics,
+            SymbolKind::Field => &mut self.fields,
+            SymbolKind::Argument => &mut self.arguments,
+            SymbolKind::Local => &mut self.locals,
+        };
+        let index = *count;
+        *count += 1;
+        index
+    }
+}
+
 /// Two-level symbol table for Jack compilation.
 ///
 /// Manages class-scope (static, field) and subroutine-scope (argument, local) symbols
 /// with proper index counting per kind.
 #[derive(Debug)]
 pub struct SymbolTable {
-    /// Class-level symbols (static and field).
-    class_scope: HashMap<String, Symbol>,
-    /// Subroutine-level symbols (argument and local).
-    subroutine_scope: HashMap<String, Symbol>,
-    /// Count of static variables.
-    static_count: u16,
-    /// Count of field variables.
-    field_count: u16,
-    /// Count of argument variables.
-    argument_count: u16,
-    /// Count of local variables.
-    local_count: u16,
+    /// Symbols of both scopes, by name.
+    names: HashMap<Arc<str>, Binding>,
+    /// Current subroutine; older subroutine-scope bindings are out of scope.
+    generation: u32,
+    /// Symbols defined per kind.
+    counts: Counts,
     /// Current class name.
-    class_name: String,
+    class_name: Arc<str>,
+    /// Symbol and class names seen so far; kept across classes.
+    interner: Interner,
 }
 
 impl SymbolTable {
     /// Create a new empty symbol table.
     pub fn new() -> Self {
         Self {
-            class_scope: HashMap::new(),
-            subroutine_scope: HashMap::new(),
-            static_count: 0,
-            field_count: 0,
-            argument_count: 0,
-            local_count: 0,
-            class_name: String::new(),
+            names: HashMap::new(),
+            generation: 0,
+            counts: Counts::default(),
+            class_name: Arc::from(""),
+            interner: Interner::new(),
         }
     }
 
@@ -104,13 +240,9 @@ impl SymbolTable {
     ///
     /// Clears class-level symbols and resets static/field counters.
     pub fn start_class(&mut self, name: &str) {
-        self.class_scope.clear();
-        self.subroutine_scope.clear();
-        self.static_count = 0;
-        self.field_count = 0;
-        self.argument_count = 0;
-        self.local_count = 0;
-        self.class_name = name.to_string();
+        self.names.clear();
+        self.counts = Counts::default();
+        self.class_name = self.interner.intern(name);
     }
 
     /// Start compiling a new subroutine.
@@ -118,96 +250,71 @@ impl SymbolTable {
     /// Clears subroutine-level symbols and resets argument/local counters.
     /// Class-level symbols remain accessible.
     pub fn start_subroutine(&mut self) {
-        self.subroutine_scope.clear();
-        self.argument_count = 0;
-        self.local_count = 0;
+        self.generation = self.generation.wrapping_add(1);
+        if self.generation == 0 {
+            // Wrapped: entries from 2^32 subroutines ago would look current
+            for binding in self.names.values_mut() {
+                binding.subroutine = None;
+            }
+        }
+        self.counts.arguments = 0;
+        self.counts.locals = 0;
     }
 
     /// Define a new symbol in the appropriate scope.
     ///
-    /// Returns an error if the symbol is already defined in the same scope.
+    /// Returns an error if the symbol is already defined in the same scope,
+    /// and reports whether a subroutine-scope symbol shadows a class-scope one.
     pub fn define(
         &mut self,
         name: &str,
         symbol_type: Type,
         kind: SymbolKind,
         span: Span,
-    ) -> Result<(), CompileError> {
-        // Check for duplicates in the appropriate scope
-        let scope = if kind.is_class_level() {
-            &self.class_scope
-        } else {
-            &self.subroutine_scope
-        };
-
-        if scope.contains_key(name) {
-            return Err(CompileError::duplicate_definition(name, span));
-        }
-
-        // Get and increment the appropriate counter
-        let index = match kind {
-            SymbolKind::Static => {
-                let idx = self.static_count;
-                self.static_count += 1;
-                idx
-            }
-            SymbolKind::Field => {
-                let idx = self.field_count;
-                self.field_count += 1;
-                idx
-            }
-            SymbolKind::Argument => {
-                let idx = self.argument_count;
-                self.argument_count += 1;
-                idx
-            }
-            SymbolKind::Local => {
-                let idx = self.local_count;
-                self.local_count += 1;
-                idx
-            }
-        };
-
-        let symbol = Symbol {
-            name: name.to_string(),
-            symbol_type,
-            kind,
-            index,
-        };
-
-        // Insert into appropriate scope
-        if kind.is_class_level() {
-            self.class_scope.insert(name.to_string(), symbol);
-        } else {
-            self.subroutine_scope.insert(name.to_string(), symbol);
+    ) -> Result<DefineOutcome, CompileError> {
+        let generation = self.generation;
+        if let Some(binding) = self.names.get_mut(name) {
+            return binding.define(&mut self.counts, generation, symbol_type, kind, span);
         }
 
-        Ok(())
+        // First use of the name in this class
+        let name = self.interner.intern(name);
+        let mut binding = Binding::new(Arc::clone(&name));
+        let outcome = binding.define(&mut self.counts, generation, symbol_type, kind, span);
+        self.names.insert(name, binding);
+        outcome
     }
 
     /// Look up a symbol by name.
     ///
     /// Searches subroutine scope first, then class scope (allowing shadowing).
     pub fn lookup(&self, name: &str) -> Option<&Symbol> {
-        self.subroutine_scope
-            .get(name)
-            .or_else(|| self.class_scope.get(name))
+        self.lookup_shared(name).map(|symbol| &**symbol)
+    }
+
+    /// Look up a symbol by name, returning a handle that outlives the borrow
+    /// of the table without copying the symbol.
+    pub fn lookup_shared(&self, name: &str) -> Option<&Arc<Symbol>> {
+        let binding = self.names.get(name)?;
+        binding
+            .subroutine(self.generation)
+            .or(binding.class.as_ref())
+    }
+
+    /// The shared copy of a class or symbol name.
+    pub fn intern(&mut self, name: &str) -> Arc<str> {
+        self.interner.intern(name)
     }
 
     /// Get the count of symbols of a given kind.
     pub fn var_count(&self, kind: SymbolKind) -> u16 {
-        match kind {
-            SymbolKind::Static => self.static_count,
-            SymbolKind::Field => self.field_count,
-            SymbolKind::Argument => self.argument_count,
-            SymbolKind::Local => self.local_count,
-        }
+        self.counts.get(kind)
     }
 
     /// Get the number of field variables (needed for Memory.alloc in constructors).
     #[inline]
     pub fn field_count(&self) -> u16 {
-        self.field_count
+        self.counts.fields
     }
 
     /// Get the current class name.
@@ -263,10 +370,10 @@ mod tests {
         assert_eq!(table.var_count(SymbolKind::Static), 2);
 
         let a = table.lookup("a").unwrap();
-        assert_eq!(a.name, "a");
+        assert_eq!(&*a.name, "a");
         assert_eq!(a.kind, SymbolKind::Static);
         assert_eq!(a.index, 0);
-        assert_eq!(a.segment(), "static");
+        assert_eq!(a.segment(), Segment::Static);
 
         let b = table.lookup("b").unwrap();
         assert_eq!(b.index, 1);
@@ -290,7 +397,7 @@ mod tests {
         let x = table.lookup("x").unwrap();
         assert_eq!(x.kind, SymbolKind::Field);
         assert_eq!(x.index, 0);
-        assert_eq!(x.segment(), "this");
+        assert_eq!(x.segment(), Segment::This);
 
         let y = table.lookup("y").unwrap();
         assert_eq!(y.index, 1);
@@ -334,11 +441,11 @@ mod tests {
 
         let x = table.lookup("x").unwrap();
         assert_eq!(x.kind, SymbolKind::Argument);
-        assert_eq!(x.segment(), "argument");
+        assert_eq!(x.segment(), Segment::Argument);
 
         let y = table.lookup("y").unwrap();
         assert_eq!(y.kind, SymbolKind::Local);
-        assert_eq!(y.segment(), "local");
+        assert_eq!(y.segment(), Segment::Local);
     }
 
     #[test]
@@ -394,6 +501,101 @@ mod tests {
         assert_eq!(sym.symbol_type, Type::Boolean);
     }
 
+    #[test]
+    fn test_define_reports_shadowing() {
+        let mut table = SymbolTable::new();
+        table.start_class("Test");
+        assert!(matches!(
+            table
+                .define("x", Type::Int, SymbolKind::Field, test_span())
+                .unwrap(),
+            DefineOutcome::Fresh
+        ));
+        assert!(matches!(
+            table
+                .define("s", Type::Int, SymbolKind::Static, test_span())
+                .unwrap(),
+            DefineOutcome::Fresh
+        ));
+
+        table.start_subroutine();
+        assert!(matches!(
+            table
+                .define("x", Type::Int, SymbolKind::Local, test_span())
+                .unwrap(),
+            DefineOutcome::Shadows(shadowed) if shadowed.kind == SymbolKind::Field
+        ));
+        assert!(matches!(
+            table
+                .define("s", Type::Int, SymbolKind::Argument, test_span())
+                .unwrap(),
+            DefineOutcome::Shadows(shadowed) if shadowed.kind == SymbolKind::Static
+        ));
+        assert!(matches!(
+            table
+                .define("y", Type::Int, SymbolKind::Local, test_span())
+                .unwrap(),
+            DefineOutcome::Fresh
+        ));
+
+        assert_eq!(table.lookup("x").unwrap().kind, SymbolKind::Local);
+    }
+
+    #[test]
+    fn test_redefine_in_next_subroutine() {
+        let mut table = SymbolTable::new();
+        table.start_class("Test");
+        table
+            .define("x", Type::Int, SymbolKind::Field, test_span())
+            .unwrap();
+
+        table.start_subroutine();
+        table
+            .define("x", Type::Int, SymbolKind::Local, test_span())
+            .unwrap();
+        table
+            .define("y", Type::Int, SymbolKind::Local, test_span())
+            .unwrap();
+
+        // The previous subroutine's `x` and `y` are gone, not duplicates
+        table.start_subroutine();
+        assert_eq!(table.lookup("x").unwrap().kind, SymbolKind::Field);
+        assert!(table.lookup("y").is_none());
+        assert!(matches!(
+            table
+                .define("y", Type::Boolean, SymbolKind::Argument, test_span())
+                .unwrap(),
+            DefineOutcome::Fresh
+        ));
+        assert!(matches!(
+            table
+                .define("x", Type::Int, SymbolKind::Argument, test_span())
+                .unwrap(),
+            DefineOutcome::Shadows(shadowed) if shadowed.kind == SymbolKind::Field
+        ));
+        assert_eq!(table.lookup("y").unwrap().index, 0);
+        assert_eq!(table.lookup("x").unwrap().index, 1);
+        assert!(
+            table
+                .define("x", Type::Int, SymbolKind::Local, test_span())
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_subroutine_generation_wraps() {
+        let mut table = SymbolTable::new();
+        table.start_class("Test");
+        table
+            .define("x", Type::Int, SymbolKind::Local, test_span())
+            .unwrap();
+        // 2^32 subroutines later the generation is the same again
+        table.generation = u32::MAX;
+        table.start_subroutine();
+        assert_eq!(table.generation, 0);
+        assert!(table.lookup("x").is_none());
+    }
+
     #[test]
     fn test_duplicate_definition_error_same_scope() {
         let mut table = SymbolTable::new();
@@ -456,10 +658,10 @@ mod tests {
 
     #[test]
     fn test_kind_to_segment() {
-        assert_eq!(SymbolKind::Static.to_segment(), "static");
-        assert_eq!(SymbolKind::Field.to_segment(), "this");
-        assert_eq!(SymbolKind::Argument.to_segment(), "argument");
-        assert_eq!(SymbolKind::Local.to_segment(), "local");
+        assert_eq!(SymbolKind::Static.to_segment(), Segment::Static);
+        assert_eq!(SymbolKind::Field.to_segment(), Segment::This);
+        assert_eq!(SymbolKind::Argument.to_segment(), Segment::Argument);
+        assert_eq!(SymbolKind::Local.to_segment(), Segment::Local);
     }
 
     #[test]
@@ -496,4 +698,29 @@ mod tests {
         assert_eq!(table.lookup("y").unwrap().index, 2);
         assert_eq!(table.var_count(SymbolKind::Argument), 3);
     }
+
+    #[test]
+    fn test_names_are_interned_across_subroutines() {
+        let mut table = SymbolTable::new();
+        table.start_class("Test");
+        table.start_subroutine();
+        table
+            .define("i", Type::Int, SymbolKind::Local, test_span())
+            .unwrap();
+        let first = Arc::clone(&table.lookup("i").unwrap().name);
+
+        table.start_subroutine();
+        table
+            .define("i", Type::Int, SymbolKind::Local, test_span())
+            .unwrap();
+        assert!(Arc::ptr_eq(&first, &table.lookup("i").unwrap().name));
+        assert!(Arc::ptr_eq(&first, &table.intern("i")));
+    }
+
+    #[test]
+    fn test_symbol_table_is_send() {
+        fn assert_send<T: Send>() {}
+        assert_send::<Symbol>();
+        assert_send::<SymbolTable>();
+    }
 }
@@ -2,13 +2,112 @@
 //!
 //! Generates VM commands as text with zero allocation during writes
 //! by using pre-sized string buffers and manual digit conversion.
+//!
+//! [`VMWriter`] does not depend on the rest of the compiler, so other code
+//! generators can use it to emit VM code:
+//!
+//! ```
+//! use jack_compiler::{BinaryOp, Segment, VMWriter};
+//!
+//! let mut vm = VMWriter::new();
+//! vm.write_comment("int Main.double(int x)");
+//! vm.write_function("Main.double", 0);
+//! vm.write_push(Segment::Argument, 0);
+//! vm.write_push(Segment::Constant, 2);
+//! vm.write_arithmetic_op(BinaryOp::Mul);
+//! vm.write_return();
+//! assert!(vm.errors().is_empty());
+//! assert_eq!(
+//!     vm.into_output(),
+//!     "// int Main.double(int x)\n\
+//!      function Main.double 0\n\
+//!      push argument 0\n\
+//!      push constant 2\n\
+//!      call Math.multiply 2\n\
+//!      return\n"
+//! );
+//! ```
+
+use std::fmt;
+
+use jack_analyzer::ast::{BinaryOp, UnaryOp};
+
+use crate::error::CompileError;
+
+/// VM memory segments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Segment {
+    Constant,
+    Argument,
+    Local,
+    Static,
+    This,
+    That,
+    Pointer,
+    Temp,
+}
+
+impl Segment {
+    /// Every segment, in VM specification order.
+    pub const ALL: [Segment; 8] = [
+        Segment::Argument,
+        Segment::Local,
+        Segment::Static,
+        Segment::Constant,
+        Segment::This,
+        Segment::That,
+        Segment::Pointer,
+        Segment::Temp,
+    ];
+
+    /// The segment name as written in VM code.
+    #[inline]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Segment::Constant => "constant",
+            Segment::Argument => "argument",
+            Segment::Local => "local",
+            Segment::Static => "static",
+            Segment::This => "this",
+            Segment::That => "that",
+            Segment::Pointer => "pointer",
+            Segment::Temp => "temp",
+        }
+    }
+
+    /// Parse a segment name as written in VM code.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|segment| segment.as_str() == name)
+    }
+
+    /// Largest valid index: `constant` 32767, `pointer` 1, `temp` 7.
+    #[inline]
+    pub fn max_index(self) -> u16 {
+        match self {
+            Segment::Constant => 32767,
+            Segment::Pointer => 1,
+            Segment::Temp => 7,
+            _ => u16::MAX,
+        }
+    }
+}
+
+impl fmt::Display for Segment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
 
 /// VM command writer with pre-allocated output buffer.
 ///
 /// Uses direct string manipulation for minimal allocation overhead.
+/// Invalid push/pop operands are recorded as errors instead of being emitted.
 #[derive(Debug)]
 pub struct VMWriter {
     output: String,
+    errors: Vec<CompileError>,
 }
 
 /// Write a u16 value to a string buffer without allocation.
@@ -38,45 +137,139 @@ impl VMWriter {
 
     /// Create a new VM writer with default capacity.
     pub fn new() -> Self {
-        Self {
-            output: String::with_capacity(Self::DEFAULT_CAPACITY),
-        }
+        Self::with_capacity(Self::DEFAULT_CAPACITY)
     }
 
     /// Create a new VM writer with specified capacity.
     pub fn with_capacity(capacity: usize) -> Self {
         Self {
             output: String::with_capacity(capacity),
+            errors: Vec::new(),
         }
     }
 
     /// Write a push command.
+    ///
+    /// An index past [`Segment::max_index`] is recorded as an error.
     #[inline]
-    pub fn write_push(&mut self, segment: &str, index: u16) {
-        self.output.push_str("push ");
-        self.output.push_str(segment);
-        self.output.push(' ');
-        write_u16(index, &mut self.output);
-        self.output.push('\n');
+    pub fn write_push(&mut self, segment: Segment, index: u16) {
+        if index > segment.max_index() {
+            self.errors
+                .push(CompileError::invalid_vm_operand("push", segment, index));
+            return;
+        }
+        self.write_memory_access("push ", segment.as_str(), index);
     }
 
     /// Write a pop command.
+    ///
+    /// Popping into `constant`, or an index past [`Segment::max_index`],
+    /// is recorded as an error.
+    #[inline]
+    pub fn write_pop(&mut self, segment: Segment, index: u16) {
+        if segment == Segment::Constant || index > segment.max_index() {
+            self.errors
+                .push(CompileError::invalid_vm_operand("pop", segment, index));
+            return;
+        }
+        self.write_memory_access("pop ", segment.as_str(), index);
+    }
+
+    /// Write a push command from a segment name.
+    ///
+    /// Panics in debug builds if the name is not a VM segment.
+    #[deprecated(note = "use `write_push` with a `Segment`")]
+    pub fn write_push_str(&mut self, segment: &str, index: u16) {
+        match Segment::from_name(segment) {
+            Some(segment) => self.write_push(segment, index),
+            None => {
+                debug_assert!(false, "unknown VM segment '{segment}'");
+                self.write_memory_access("push ", segment, index);
+            }
+        }
+    }
+
+    /// Write a pop command from a segment name.
+    ///
+    /// Panics in debug builds if the name is not a VM segment.
+    #[deprecated(note = "use `write_pop` with a `Segment`")]
+    pub fn write_pop_str(&mut self, segment: &str, index: u16) {
+        match Segment::from_name(segment) {
+            Some(segment) => self.write_pop(segment, index),
+            None => {
+                debug_assert!(false, "unknown VM segment '{segment}'");
+                self.write_memory_access("pop ", segment, index);
+            }
+        }
+    }
+
     #[inline]
-    pub fn write_pop(&mut self, segment: &str, index: u16) {
-        self.output.push_str("pop ");
+    fn write_memory_access(&mut self, command: &str, segment: &str, index: u16) {
+        self.output.push_str(command);
         self.output.push_str(segment);
         self.output.push(' ');
         write_u16(index, &mut self.output);
         self.output.push('\n');
     }
 
-    /// Write an arithmetic/logical command.
+    /// Errors recorded for invalid operands so far.
+    pub fn errors(&self) -> &[CompileError] {
+        &self.errors
+    }
+
+    /// Take the recorded errors, leaving the list empty.
+    pub fn take_errors(&mut self) -> Vec<CompileError> {
+        std::mem::take(&mut self.errors)
+    }
+
+    /// Write an arithmetic/logical command (`add`, `not`, ...) verbatim.
     #[inline]
     pub fn write_arithmetic(&mut self, cmd: &str) {
         self.output.push_str(cmd);
         self.output.push('\n');
     }
 
+    /// Write a Jack binary operator: a VM command, or a call to
+    /// `Math.multiply` / `Math.divide` for `*` and `/`.
+    #[inline]
+    pub fn write_arithmetic_op(&mut self, op: BinaryOp) {
+        match op {
+            BinaryOp::Add => self.write_arithmetic("add"),
+            BinaryOp::Sub => self.write_arithmetic("sub"),
+            BinaryOp::And => self.write_arithmetic("and"),
+            BinaryOp::Or => self.write_arithmetic("or"),
+            BinaryOp::Lt => self.write_arithmetic("lt"),
+            BinaryOp::Gt => self.write_arithmetic("gt"),
+            BinaryOp::Eq => self.write_arithmetic("eq"),
+            BinaryOp::Mul => self.write_call("Math.multiply", 2),
+            BinaryOp::Div => self.write_call("Math.divide", 2),
+        }
+    }
+
+    /// Write a Jack unary operator: `neg` or `not`.
+    #[inline]
+    pub fn write_unary_op(&mut self, op: UnaryOp) {
+        match op {
+            UnaryOp::Neg => self.write_arithmetic("neg"),
+            UnaryOp::Not => self.write_arithmetic("not"),
+        }
+    }
+
+    /// Write a `//` comment, one VM line per line of `text`.
+    ///
+    /// Comments are not commands: [`count_vm_lines`](crate::count_vm_lines)
+    /// skips them.
+    pub fn write_comment(&mut self, text: &str) {
+        if text.is_empty() {
+            self.output.push_str("//\n");
+        }
+        for line in text.lines() {
+            self.output.push_str("// ");
+            self.output.push_str(line);
+            self.output.push('\n');
+        }
+    }
+
     /// Write a label command.
     #[inline]
     pub fn write_label(&mut self, label: &str) {
@@ -121,7 +314,8 @@ impl VMWriter {
         self.output.push('\n');
     }
 
-    /// Get mutable access to the output buffer (for direct writes).
+    /// Get mutable access to the output buffer, for writes the methods do not
+    /// cover. Text written here bypasses the operand checks.
     #[inline]
     pub fn output_mut(&mut self) -> &mut String {
         &mut self.output
@@ -134,6 +328,8 @@ impl VMWriter {
     }
 
     /// Consume the writer and return the generated VM code.
+    ///
+    /// Recorded [`errors`](Self::errors) are dropped; check them first.
     pub fn into_output(self) -> String {
         self.output
     }
@@ -153,6 +349,17 @@ impl VMWriter {
         self.output.is_empty()
     }
 
+    /// Write the buffered output to `out` and clear the buffer.
+    ///
+    /// Calling this at points where the buffered code is complete (e.g.
+    /// after each function) streams the output instead of keeping it all
+    /// in memory. Recorded [`errors`](Self::errors) are kept.
+    pub fn flush_to<W: fmt::Write + ?Sized>(&mut self, out: &mut W) -> fmt::Result {
+        out.write_str(&self.output)?;
+        self.output.clear();
+        Ok(())
+    }
+
     /// Clear the output buffer (retains capacity).
     pub fn clear(&mut self) {
         self.output.clear();
@@ -179,21 +386,21 @@ mod tests {
     #[test]
     fn test_write_push() {
         let mut writer = VMWriter::new();
-        writer.write_push("constant", 7);
+        writer.write_push(Segment::Constant, 7);
         assert_eq!(writer.as_str(), "push constant 7\n");
     }
 
     #[test]
     fn test_write_push_various_segments() {
         let mut writer = VMWriter::new();
-        writer.write_push("constant", 0);
-        writer.write_push("local", 1);
-        writer.write_push("argument", 2);
-        writer.write_push("this", 3);
-        writer.write_push("that", 4);
-        writer.write_push("static", 5);
-        writer.write_push("temp", 6);
-        writer.write_push("pointer", 0);
+        writer.write_push(Segment::Constant, 0);
+        writer.write_push(Segment::Local, 1);
+        writer.write_push(Segment::Argument, 2);
+        writer.write_push(Segment::This, 3);
+        writer.write_push(Segment::That, 4);
+        writer.write_push(Segment::Static, 5);
+        writer.write_push(Segment::Temp, 6);
+        writer.write_push(Segment::Pointer, 0);
 
         let expected = "\
 push constant 0
@@ -211,20 +418,20 @@ push pointer 0
     #[test]
     fn test_write_pop() {
         let mut writer = VMWriter::new();
-        writer.write_pop("local", 0);
+        writer.write_pop(Segment::Local, 0);
         assert_eq!(writer.as_str(), "pop local 0\n");
     }
 
     #[test]
     fn test_write_pop_various_segments() {
         let mut writer = VMWriter::new();
-        writer.write_pop("local", 0);
-        writer.write_pop("argument", 1);
-        writer.write_pop("this", 2);
-        writer.write_pop("that", 3);
-        writer.write_pop("static", 4);
-        writer.write_pop("temp", 5);
-        writer.write_pop("pointer", 1);
+        writer.write_pop(Segment::Local, 0);
+        writer.write_pop(Segment::Argument, 1);
+        writer.write_pop(Segment::This, 2);
+        writer.write_pop(Segment::That, 3);
+        writer.write_pop(Segment::Static, 4);
+        writer.write_pop(Segment::Temp, 5);
+        writer.write_pop(Segment::Pointer, 1);
 
         let expected = "\
 pop local 0
@@ -276,6 +483,24 @@ pop pointer 1
         assert_eq!(writer.as_str(), "if-goto IF_FALSE\n");
     }
 
+    #[test]
+    fn test_write_operators() {
+        let mut writer = VMWriter::new();
+        writer.write_arithmetic_op(BinaryOp::Lt);
+        writer.write_arithmetic_op(BinaryOp::Div);
+        writer.write_unary_op(UnaryOp::Not);
+        assert_eq!(writer.as_str(), "lt\ncall Math.divide 2\nnot\n");
+    }
+
+    #[test]
+    fn test_write_comment() {
+        let mut writer = VMWriter::new();
+        writer.write_comment("two\nlines");
+        writer.write_comment("");
+        assert_eq!(writer.as_str(), "// two\n// lines\n//\n");
+        assert_eq!(crate::count_vm_lines(writer.as_str()), 0);
+    }
+
     #[test]
     fn test_write_function() {
         let mut writer = VMWriter::new();
@@ -311,17 +536,17 @@ pop pointer 1
         // function Main.main 1
         writer.write_function("Main.main", 1);
         // push constant 7
-        writer.write_push("constant", 7);
+        writer.write_push(Segment::Constant, 7);
         // pop local 0
-        writer.write_pop("local", 0);
+        writer.write_pop(Segment::Local, 0);
         // push local 0
-        writer.write_push("local", 0);
+        writer.write_push(Segment::Local, 0);
         // call Output.printInt 1
         writer.write_call("Output.printInt", 1);
         // pop temp 0
-        writer.write_pop("temp", 0);
+        writer.write_pop(Segment::Temp, 0);
         // push constant 0
-        writer.write_push("constant", 0);
+        writer.write_push(Segment::Constant, 0);
         // return
         writer.write_return();
 
@@ -343,16 +568,16 @@ return
         let mut writer = VMWriter::new();
 
         writer.write_label("WHILE_EXP0");
-        writer.write_push("local", 0);
-        writer.write_push("constant", 10);
+        writer.write_push(Segment::Local, 0);
+        writer.write_push(Segment::Constant, 10);
         writer.write_arithmetic("lt");
         writer.write_arithmetic("not");
         writer.write_if_goto("WHILE_END0");
         // loop body
-        writer.write_push("local", 0);
-        writer.write_push("constant", 1);
+        writer.write_push(Segment::Local, 0);
+        writer.write_push(Segment::Constant, 1);
         writer.write_arithmetic("add");
-        writer.write_pop("local", 0);
+        writer.write_pop(Segment::Local, 0);
         writer.write_goto("WHILE_EXP0");
         writer.write_label("WHILE_END0");
 
@@ -377,17 +602,17 @@ label WHILE_END0
     fn test_if_else() {
         let mut writer = VMWriter::new();
 
-        writer.write_push("local", 0);
+        writer.write_push(Segment::Local, 0);
         writer.write_arithmetic("not");
         writer.write_if_goto("IF_FALSE0");
         // then branch
-        writer.write_push("constant", 1);
-        writer.write_pop("local", 1);
+        writer.write_push(Segment::Constant, 1);
+        writer.write_pop(Segment::Local, 1);
         writer.write_goto("IF_END0");
         writer.write_label("IF_FALSE0");
         // else branch
-        writer.write_push("constant", 2);
-        writer.write_pop("local", 1);
+        writer.write_push(Segment::Constant, 2);
+        writer.write_pop(Segment::Local, 1);
         writer.write_label("IF_END0");
 
         let expected = "\
@@ -408,7 +633,7 @@ label IF_END0
     #[test]
     fn test_into_output() {
         let mut writer = VMWriter::new();
-        writer.write_push("constant", 42);
+        writer.write_push(Segment::Constant, 42);
         let output = writer.into_output();
         assert_eq!(output, "push constant 42\n");
     }
@@ -416,7 +641,7 @@ label IF_END0
     #[test]
     fn test_clear() {
         let mut writer = VMWriter::new();
-        writer.write_push("constant", 42);
+        writer.write_push(Segment::Constant, 42);
         assert!(!writer.is_empty());
         writer.clear();
         assert!(writer.is_empty());
@@ -425,7 +650,7 @@ label IF_END0
     #[test]
     fn test_large_index() {
         let mut writer = VMWriter::new();
-        writer.write_push("constant", 32767);
+        writer.write_push(Segment::Constant, 32767);
         assert_eq!(writer.as_str(), "push constant 32767\n");
     }
 
@@ -434,4 +659,72 @@ label IF_END0
         let writer = VMWriter::with_capacity(1024);
         assert!(writer.is_empty());
     }
+
+    #[test]
+    fn test_segment_names_round_trip() {
+        for segment in Segment::ALL {
+            assert_eq!(Segment::from_name(segment.as_str()), Some(segment));
+            assert_eq!(segment.to_string(), segment.as_str());
+        }
+        assert_eq!(Segment::from_name("consant"), None);
+    }
+
+    #[test]
+    fn test_invalid_operands_recorded_not_emitted() {
+        let mut writer = VMWriter::new();
+        writer.write_push(Segment::Pointer, 2);
+        writer.write_pop(Segment::Pointer, 2);
+        writer.write_push(Segment::Temp, 8);
+        writer.write_pop(Segment::Temp, 8);
+        writer.write_push(Segment::Constant, 32768);
+        writer.write_pop(Segment::Constant, 0);
+        assert!(writer.is_empty(), "emitted: {}", writer.as_str());
+
+        let messages: Vec<String> = writer.errors().iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                "Invalid VM operand 'push pointer 2'",
+                "Invalid VM operand 'pop pointer 2'",
+                "Invalid VM operand 'push temp 8'",
+                "Invalid VM operand 'pop temp 8'",
+                "Invalid VM operand 'push constant 32768'",
+                "Invalid VM operand 'pop constant 0'",
+            ]
+        );
+        assert_eq!(writer.take_errors().len(), 6);
+        assert!(writer.errors().is_empty());
+    }
+
+    #[test]
+    fn test_segment_bounds_inclusive() {
+        let mut writer = VMWriter::new();
+        writer.write_pop(Segment::Pointer, 1);
+        writer.write_pop(Segment::Temp, 7);
+        writer.write_push(Segment::Static, 300);
+        assert!(writer.errors().is_empty());
+        assert_eq!(
+            writer.as_str(),
+            "pop pointer 1\npop temp 7\npush static 300\n"
+        );
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_deprecated_str_wrappers() {
+        let mut writer = VMWriter::new();
+        writer.write_push_str("local", 2);
+        writer.write_pop_str("that", 0);
+        writer.write_pop_str("temp", 9);
+        assert_eq!(writer.as_str(), "push local 2\npop that 0\n");
+        assert_eq!(writer.errors().len(), 1);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "unknown VM segment 'consant'")]
+    #[allow(deprecated)]
+    fn test_deprecated_wrapper_panics_on_unknown_segment() {
+        VMWriter::new().write_push_str("consant", 5);
+    }
 }
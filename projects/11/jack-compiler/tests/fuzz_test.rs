@@ -3,6 +3,7 @@
 //! Uses proptest to generate random valid Jack programs and verify
 //! compiler invariants hold across all inputs.
 
+use jack_compiler::{OptLevel, Segment};
 use proptest::prelude::*;
 
 // =============================================================================
@@ -201,7 +202,9 @@ proptest! {
 
     /// Generated VM code should be syntactically valid.
     #[test]
-    fn test_vm_output_valid(source in arb_minimal_class()) {
+    fn test_vm_output_valid(
+        source in prop_oneof![arb_minimal_class(), arb_control_flow_class()]
+    ) {
         let result = jack_compiler::compile_source(&source, "Test");
         if result.is_ok() {
             for line in result.vm_code.lines() {
@@ -210,6 +213,36 @@ proptest! {
                     continue;
                 }
 
+                // Push/pop operands must name a segment and respect its bounds
+                if let Some(operands) = line
+                    .strip_prefix("push ")
+                    .or_else(|| line.strip_prefix("pop "))
+                {
+                    let mut parts = operands.split_whitespace();
+                    let segment = parts.next().and_then(Segment::from_name);
+                    let index = parts.next().and_then(|i| i.parse::<u16>().ok());
+                    prop_assert!(
+                        matches!((segment, index), (Some(s), Some(i)) if i <= s.max_index()),
+                        "Invalid VM operand: {}",
+                        line
+                    );
+                }
+
+                // Labels are `Class.sub$PREFIX_n`: identifier characters
+                // plus the `.` and `$` separators, not starting with a digit
+                if let Some(label) = line
+                    .strip_prefix("label ")
+                    .or_else(|| line.strip_prefix("goto "))
+                    .or_else(|| line.strip_prefix("if-goto "))
+                {
+                    let valid_label = label.chars().next().is_some_and(|c| !c.is_ascii_digit())
+                        && label
+                            .chars()
+                            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '$'))
+                        && label.matches('$').count() == 1;
+                    prop_assert!(valid_label, "Invalid VM label: {}", line);
+                }
+
                 // Verify each line is a valid VM command
                 let valid = line.starts_with("push ")
                     || line.starts_with("pop ")
@@ -240,12 +273,12 @@ proptest! {
         let optimized = jack_compiler::compile_source_with_options(
             &source,
             "Test",
-            jack_compiler::CompileOptions { optimize: true },
+            jack_compiler::CompileOptions { optimize: true, ..Default::default() },
         );
         let unoptimized = jack_compiler::compile_source_with_options(
             &source,
             "Test",
-            jack_compiler::CompileOptions { optimize: false },
+            jack_compiler::CompileOptions { optimize: false, ..Default::default() },
         );
 
         // Both should either succeed or fail
@@ -264,12 +297,12 @@ proptest! {
         let optimized = jack_compiler::compile_source_with_options(
             &source,
             "Test",
-            jack_compiler::CompileOptions { optimize: true },
+            jack_compiler::CompileOptions { optimize: true, ..Default::default() },
         );
         let unoptimized = jack_compiler::compile_source_with_options(
             &source,
             "Test",
-            jack_compiler::CompileOptions { optimize: false },
+            jack_compiler::CompileOptions { optimize: false, ..Default::default() },
         );
 
         if optimized.is_ok() && unoptimized.is_ok() {
@@ -284,6 +317,23 @@ proptest! {
         }
     }
 
+    /// Generated code only uses the local and argument slots it declares.
+    #[test]
+    fn test_slots_within_declared_counts(
+        source in prop_oneof![arb_minimal_class(), arb_control_flow_class()]
+    ) {
+        let tokens = jack_analyzer::tokenizer::JackTokenizer::new(&source).tokenize().unwrap();
+        let class = jack_analyzer::parser::Parser::new(&tokens).parse().unwrap();
+        for level in [OptLevel::O0, OptLevel::O1, OptLevel::O2] {
+            let options = jack_compiler::CompileOptions::with_opt_level(level);
+            let result = jack_compiler::compile_source_with_options(&source, "Test", options);
+            if result.is_ok() {
+                let errors = jack_compiler::verify_slots(&result.vm_code, &class);
+                prop_assert!(errors.is_empty(), "{:?}: {:?}", level, errors);
+            }
+        }
+    }
+
     /// VM code should always have a return statement for each function.
     #[test]
     fn test_functions_have_return(source in arb_minimal_class()) {
@@ -309,7 +359,8 @@ proptest! {
 
 mod optimizer_fuzz {
     use super::*;
-    use jack_compiler::PeepholeOptimizer;
+    use jack_compiler::{CompileOptions, PeepholeOptimizer, compile_source_with_options};
+    use std::path::Path;
 
     /// Generate a random VM instruction.
     fn arb_vm_instruction() -> impl Strategy<Value = String> {
@@ -341,11 +392,167 @@ mod optimizer_fuzz {
         ]
     }
 
+    /// Generate a `segment index` operand, including constants.
+    fn arb_location() -> impl Strategy<Value = String> {
+        (
+            prop_oneof![
+                Just("constant"),
+                Just("local"),
+                Just("argument"),
+                Just("this"),
+                Just("that"),
+                Just("static"),
+                Just("temp"),
+                Just("pointer"),
+            ],
+            0u16..2,
+        )
+            .prop_map(|(segment, index)| format!("{} {}", segment, index))
+    }
+
+    /// Unoptimized VM code for every class of the course programs.
+    fn course_programs_vm() -> Vec<String> {
+        let options = CompileOptions {
+            optimize: false,
+            ..Default::default()
+        };
+        let mut programs = Vec::new();
+        for dir in [
+            "Seven",
+            "ConvertToBin",
+            "Square",
+            "Average",
+            "Pong",
+            "ComplexArrays",
+        ] {
+            let mut paths: Vec<_> = std::fs::read_dir(Path::new("..").join(dir))
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .filter(|path| path.extension().is_some_and(|ext| ext == "jack"))
+                .collect();
+            paths.sort();
+            for path in paths {
+                let source = std::fs::read_to_string(&path).unwrap();
+                let name = path.file_stem().unwrap().to_str().unwrap();
+                let result = compile_source_with_options(&source, name, options);
+                assert!(result.is_ok(), "{} failed to compile", path.display());
+                programs.push(result.vm_code);
+            }
+        }
+        programs
+    }
+
     /// Generate a random VM program (sequence of instructions).
     fn arb_vm_program() -> impl Strategy<Value = String> {
         prop::collection::vec(arb_vm_instruction(), 1..50).prop_map(|lines| lines.join("\n") + "\n")
     }
 
+    /// Generate an instruction for a branchy program over the stack and temp.
+    fn arb_flow_instruction() -> impl Strategy<Value = String> {
+        prop_oneof![
+            (0u16..4).prop_map(|n| format!("push constant {}", n)),
+            (0u16..2).prop_map(|n| format!("push temp {}", n)),
+            (0u16..2).prop_map(|n| format!("pop temp {}", n)),
+            Just("add".to_string()),
+            Just("sub".to_string()),
+            Just("neg".to_string()),
+            Just("not".to_string()),
+            Just("eq".to_string()),
+            Just("lt".to_string()),
+            (0u8..3).prop_map(|n| format!("label L{}", n)),
+            (0u8..3).prop_map(|n| format!("goto L{}", n)),
+            (0u8..3).prop_map(|n| format!("if-goto L{}", n)),
+        ]
+    }
+
+    /// Generate a branchy program, biased towards `goto L` / `label L` pairs.
+    fn arb_flow_program() -> impl Strategy<Value = String> {
+        let pair = (0u8..3).prop_map(|n| format!("goto L{0}\nlabel L{0}", n));
+        let line = prop_oneof![3 => arb_flow_instruction(), 1 => pair];
+        prop::collection::vec(line, 1..30).prop_map(|lines| lines.join("\n") + "\n")
+    }
+
+    /// How a program run by [`run`] ended.
+    #[derive(Debug, PartialEq)]
+    enum Outcome {
+        /// Ran off the end, with this stack and temp segment.
+        Halted(Vec<i16>, [i16; 2]),
+        /// Popped an empty stack.
+        Underflow,
+    }
+
+    /// Run a program of `arb_flow_instruction`s.
+    ///
+    /// Returns how it ended, or `None` if the program defines a label twice
+    /// or does not stop within `max_steps`.
+    fn run(vm_code: &str, max_steps: usize) -> Option<Outcome> {
+        let lines: Vec<&str> = vm_code.lines().collect();
+        let mut labels = std::collections::HashSet::new();
+        if !lines
+            .iter()
+            .filter_map(|l| l.strip_prefix("label "))
+            .all(|label| labels.insert(label))
+        {
+            return None;
+        }
+        let target = |label: &str| {
+            lines
+                .iter()
+                .position(|l| l.strip_prefix("label ") == Some(label))
+                .unwrap_or(lines.len())
+        };
+        let mut stack: Vec<i16> = Vec::new();
+        let mut temp = [0i16; 2];
+        let mut pc = 0;
+        // Pop the stack, or end the run
+        macro_rules! pop {
+            () => {
+                match stack.pop() {
+                    Some(value) => value,
+                    None => return Some(Outcome::Underflow),
+                }
+            };
+        }
+        for _ in 0..max_steps {
+            let Some(line) = lines.get(pc) else {
+                return Some(Outcome::Halted(stack, temp));
+            };
+            pc += 1;
+            let mut parts = line.split_whitespace();
+            match (parts.next(), parts.next(), parts.next()) {
+                (Some("push"), Some("constant"), Some(n)) => stack.push(n.parse().unwrap()),
+                (Some("push"), Some("temp"), Some(i)) => {
+                    stack.push(temp[i.parse::<usize>().unwrap()])
+                }
+                (Some("pop"), Some("temp"), Some(i)) => temp[i.parse::<usize>().unwrap()] = pop!(),
+                (Some("label"), _, _) => {}
+                (Some("goto"), Some(label), _) => pc = target(label),
+                (Some("if-goto"), Some(label), _) => {
+                    if pop!() != 0 {
+                        pc = target(label);
+                    }
+                }
+                (Some(op @ ("neg" | "not")), None, _) => {
+                    let x = pop!();
+                    stack.push(if op == "neg" { x.wrapping_neg() } else { !x });
+                }
+                (Some(op), None, _) => {
+                    let y = pop!();
+                    let x = pop!();
+                    stack.push(match op {
+                        "add" => x.wrapping_add(y),
+                        "sub" => x.wrapping_sub(y),
+                        "eq" => -i16::from(x == y),
+                        "lt" => -i16::from(x < y),
+                        _ => panic!("unexpected op {op}"),
+                    });
+                }
+                _ => panic!("unexpected line {line}"),
+            }
+        }
+        None
+    }
+
     proptest! {
         #![proptest_config(ProptestConfig::with_cases(200))]
 
@@ -402,6 +609,56 @@ mod optimizer_fuzz {
             );
         }
 
+        /// A push/pop pair is removed exactly when it names the same
+        /// non-constant location.
+        #[test]
+        fn test_push_pop_pair_removed_iff_same_location(
+            push in arb_location(),
+            pop in arb_location(),
+        ) {
+            let vm_code = format!("push {}\npop {}\nadd\n", push, pop);
+            let optimized = PeepholeOptimizer::optimize(&vm_code);
+            prop_assert!(optimized.lines().count() <= vm_code.lines().count());
+            if push == pop && !push.starts_with("constant") {
+                prop_assert_eq!(optimized, "add\n");
+            } else {
+                prop_assert_eq!(optimized, vm_code);
+            }
+        }
+
+        /// Inserting push X / pop X round trips into a course program
+        /// does not change the optimized output.
+        #[test]
+        fn test_push_pop_round_trip_on_course_programs(location in arb_location()) {
+            prop_assume!(!location.starts_with("constant"));
+            for vm_code in course_programs_vm() {
+                let mut injected = String::with_capacity(vm_code.len() * 2);
+                for line in vm_code.lines() {
+                    injected.push_str(line);
+                    injected.push('\n');
+                    if line.starts_with("function ") {
+                        injected.push_str(&format!("push {0}\npop {0}\n", location));
+                    }
+                }
+                prop_assert_eq!(
+                    PeepholeOptimizer::optimize(&injected),
+                    PeepholeOptimizer::optimize(&vm_code)
+                );
+            }
+        }
+
+        /// Removing jumps to the next label keeps the program's behaviour
+        /// and never grows it. A program that underflows may stop doing so:
+        /// the optimizer removes pairs such as `not` / `not`.
+        #[test]
+        fn test_optimizer_preserves_control_flow(vm_code in arb_flow_program()) {
+            let optimized = PeepholeOptimizer::optimize(&vm_code);
+            prop_assert!(optimized.lines().count() <= vm_code.lines().count());
+            if let Some(expected @ Outcome::Halted(..)) = run(&vm_code, 500) {
+                prop_assert_eq!(run(&optimized, 500), Some(expected), "{}", optimized);
+            }
+        }
+
         /// Optimizer reaches fixed point within 3 iterations.
         /// Note: Single-pass optimization may expose new opportunities,
         /// so we test convergence rather than strict idempotence.
@@ -4,7 +4,10 @@
 //! Follows the automated testing pattern from previous projects.
 
 use jack_compiler::{
-    CompileOptions, compile_directory, compile_source, compile_source_with_options,
+    CompileCache, CompileError, CompileOptions, CompileWarning, FileOrder, OptLevel,
+    compile_directory, compile_directory_cached, compile_directory_with_options,
+    compile_directory_with_progress, compile_file, compile_file_with_options, compile_source,
+    compile_source_with_options,
 };
 use std::fs;
 use std::path::Path;
@@ -141,7 +144,7 @@ fn test_convert_to_bin() {
         "ConvertToBin",
         &[
             "function Main.main",
-            "label WHILE",
+            "label Main.convert$WHILE_EXP_",
             "if-goto",
             "goto",
             "call Main.convert",
@@ -160,9 +163,15 @@ fn test_convert_to_bin_while_loop_structure() {
     let vm = &main_result.vm_code;
 
     // Verify while loop pattern: label, condition, not, if-goto, body, goto
-    let label_pos = vm.find("label WHILE").expect("Should have WHILE label");
-    let if_goto_pos = vm.find("if-goto WHILE").expect("Should have if-goto WHILE");
-    let goto_pos = vm.rfind("goto WHILE").expect("Should have goto WHILE");
+    let label_pos = vm
+        .find("label Main.convert$WHILE")
+        .expect("Should have WHILE label");
+    let if_goto_pos = vm
+        .find("if-goto Main.convert$WHILE")
+        .expect("Should have if-goto WHILE");
+    let goto_pos = vm
+        .rfind("goto Main.convert$WHILE")
+        .expect("Should have goto WHILE");
 
     assert!(label_pos < if_goto_pos, "Label should come before if-goto");
     assert!(
@@ -420,7 +429,10 @@ class Main {
 }
 "#;
 
-    let options = CompileOptions { optimize: false };
+    let options = CompileOptions {
+        optimize: false,
+        ..Default::default()
+    };
     let result = compile_source_with_options(source, "Main", options);
     assert!(result.is_ok());
 
@@ -457,6 +469,50 @@ class Main {
     );
 }
 
+#[test]
+fn test_errors_sorted_by_location() {
+    let source = r#"
+class Main {
+    field int f;
+    function void main() {
+        let y = f;
+        do Main.helper(1, 2);
+        return;
+    }
+    function void helper(int a) {
+        let x = 5;
+        return;
+    }
+}
+"#;
+
+    for level in [OptLevel::O0, OptLevel::O1, OptLevel::O2] {
+        let result =
+            compile_source_with_options(source, "Main", CompileOptions::with_opt_level(level));
+        let lines: Vec<_> = result
+            .errors
+            .iter()
+            .map(|e| e.span().expect("located error").line)
+            .collect();
+        assert!(lines.len() >= 2, "{level:?}: {:?}", result.errors);
+        assert!(lines.is_sorted(), "{level:?}: {lines:?}");
+    }
+}
+
+#[test]
+fn test_diagnostics_json_for_undefined_variable() {
+    let source =
+        "class Main {\n    function void main() {\n        let x = 5;\n        return;\n    }\n}\n";
+    let result = compile_source(source, "Main");
+    let json: serde_json::Value = serde_json::from_str(&result.errors[0].to_json("Main")).unwrap();
+    assert_eq!(json["file"], "Main");
+    assert_eq!(json["line"], 3);
+    assert_eq!(json["col"], 9);
+    assert_eq!(json["code"], "JC0001");
+    assert_eq!(json["severity"], "error");
+    assert!(json["message"].as_str().unwrap().contains("'x'"));
+}
+
 #[test]
 fn test_all_test_programs_compile_successfully() {
     let test_dirs = [
@@ -498,3 +554,257 @@ fn test_all_test_programs_compile_successfully() {
         }
     }
 }
+
+#[test]
+fn test_all_test_programs_verify_stack() {
+    let test_dirs = [
+        "Seven",
+        "ConvertToBin",
+        "Square",
+        "Average",
+        "Pong",
+        "ComplexArrays",
+    ];
+
+    for level in [OptLevel::O0, OptLevel::O1, OptLevel::O2] {
+        let options = CompileOptions {
+            verify_stack: true,
+            bounds_checks: level == OptLevel::O0,
+            ..CompileOptions::with_opt_level(level)
+        };
+        for dir in &test_dirs {
+            for result in compile_directory_with_options(&Path::new("..").join(dir), options) {
+                assert!(
+                    result.is_ok(),
+                    "{}/{}.jack at {:?}: {:?}",
+                    dir,
+                    result.filename,
+                    level,
+                    result.errors
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_class_name_must_match_file_name() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("Foo.jack");
+    fs::write(&path, "class Bar { function void run() { return; } }\n")
+        .expect("Failed to write temp file");
+
+    let result = compile_file(&path);
+    assert!(!result.is_ok());
+    assert!(matches!(
+        &result.errors[0],
+        CompileError::ClassNameMismatch { expected, found } if expected == "Foo" && found == "Bar"
+    ));
+
+    let lenient = CompileOptions {
+        lenient_class_names: true,
+        ..CompileOptions::default()
+    };
+    let result = compile_file_with_options(&path, lenient);
+    assert!(result.is_ok());
+    assert!(result.vm_code.contains("function Bar.run 0"));
+    assert!(matches!(
+        &result.warnings[0],
+        CompileWarning::ClassNameMismatch { .. }
+    ));
+}
+
+#[test]
+fn test_compile_directory_on_dedicated_pool() {
+    let dir_path = Path::new("../Pong");
+    let expected = compile_directory(dir_path);
+    let options = CompileOptions {
+        threads: Some(1),
+        ..Default::default()
+    };
+    let results = compile_directory_with_options(dir_path, options);
+    assert_eq!(results.len(), expected.len());
+    for (result, expected) in results.iter().zip(&expected) {
+        assert_eq!(result.filename, expected.filename);
+        assert_eq!(result.vm_code, expected.vm_code);
+    }
+}
+
+#[test]
+fn test_compile_directory_file_order() {
+    let filenames = |file_order| {
+        let options = CompileOptions {
+            file_order,
+            ..Default::default()
+        };
+        compile_directory_with_options(Path::new("../Pong"), options)
+            .into_iter()
+            .map(|r| r.filename)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        filenames(FileOrder::Alphabetical),
+        ["Ball", "Bat", "Main", "PongGame"]
+    );
+    assert_eq!(
+        filenames(FileOrder::SysMainFirst),
+        ["Main", "Ball", "Bat", "PongGame"]
+    );
+}
+
+#[test]
+fn test_compile_directory_cached() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let write = |name: &str, body: &str| {
+        let class = name.trim_end_matches(".jack");
+        let source = format!("class {class} {{ function int f() {{ {body} }} }}");
+        fs::write(dir.path().join(name), source).unwrap();
+    };
+    write("A.jack", "return 1;");
+    write("B.jack", "return 2;");
+    let options = CompileOptions::default();
+    let mut cache = CompileCache::new();
+
+    let filenames = |results: Vec<&jack_compiler::CompileResult>| -> Vec<String> {
+        results.iter().map(|r| r.filename.clone()).collect()
+    };
+    let results = compile_directory_cached(dir.path(), options, &mut cache).unwrap();
+    assert_eq!(filenames(results), ["A", "B"]);
+    assert_eq!(cache.recompiled(), ["A", "B"]);
+
+    // Unchanged files are not recompiled and give the same results
+    let results = compile_directory_cached(dir.path(), options, &mut cache).unwrap();
+    let expected = compile_directory(dir.path());
+    assert_eq!(results.len(), expected.len());
+    for (result, expected) in results.iter().zip(&expected) {
+        assert_eq!(result.vm_code, expected.vm_code);
+    }
+    assert!(cache.recompiled().is_empty());
+
+    // Only the edited file is recompiled
+    write("B.jack", "return 3;");
+    let results = compile_directory_cached(dir.path(), options, &mut cache).unwrap();
+    assert!(results[1].vm_code.contains("push constant 3"));
+    assert_eq!(cache.recompiled(), ["B"]);
+
+    // Removed files leave the cache, added ones are compiled
+    fs::remove_file(dir.path().join("A.jack")).unwrap();
+    write("C.jack", "return 4;");
+    let results = compile_directory_cached(dir.path(), options, &mut cache).unwrap();
+    assert_eq!(filenames(results), ["B", "C"]);
+    assert_eq!(cache.recompiled(), ["C"]);
+    assert_eq!(cache.len(), 2);
+
+    // Other options invalidate everything; the pool size does not
+    let threads = CompileOptions {
+        threads: Some(1),
+        ..options
+    };
+    compile_directory_cached(dir.path(), threads, &mut cache).unwrap();
+    assert!(cache.recompiled().is_empty());
+    let unoptimized = CompileOptions {
+        optimize: false,
+        ..options
+    };
+    compile_directory_cached(dir.path(), unoptimized, &mut cache).unwrap();
+    assert_eq!(cache.recompiled(), ["B", "C"]);
+
+    let missing = dir.path().join("missing");
+    assert!(matches!(
+        compile_directory_cached(&missing, options, &mut cache),
+        Err(CompileError::Io { .. })
+    ));
+}
+
+#[test]
+fn test_code_size_budgets() {
+    let dir_path = Path::new("../Square");
+    let tiny = CompileOptions {
+        max_vm_lines_per_file: Some(20),
+        ..Default::default()
+    };
+    let results = compile_directory_with_options(dir_path, tiny);
+    let over: Vec<_> = results.iter().filter(|r| !r.is_ok()).collect();
+    assert_eq!(over.len(), 2, "Square and SquareGame exceed 20 lines");
+    for result in over {
+        assert!(matches!(
+            result.errors[..],
+            [CompileError::CodeSizeBudgetExceeded { limit: 20, .. }]
+        ));
+    }
+
+    let tiny_total = CompileOptions {
+        max_vm_lines_total: Some(100),
+        ..Default::default()
+    };
+    let results = compile_directory_with_options(dir_path, tiny_total);
+    assert_eq!(results.len(), 4);
+    let total = results.last().unwrap();
+    assert_eq!(total.filename, jack_compiler::TOTAL_BUDGET_FILENAME);
+    assert!(matches!(
+        total.errors[..],
+        [CompileError::CodeSizeBudgetExceeded { limit: 100, .. }]
+    ));
+
+    let generous = CompileOptions {
+        max_vm_lines_per_file: Some(10_000),
+        max_vm_lines_total: Some(100_000),
+        ..Default::default()
+    };
+    let results = compile_directory_with_options(dir_path, generous);
+    assert_eq!(results.len(), 3);
+    assert!(results.iter().all(|r| r.is_ok()));
+}
+
+#[test]
+fn test_cli_writes_nothing_over_total_budget() {
+    let dir = tempfile::tempdir().unwrap();
+    for class in ["Main", "Square", "SquareGame"] {
+        let name = format!("{class}.jack");
+        fs::copy(Path::new("../Square").join(&name), dir.path().join(&name)).unwrap();
+    }
+    let run = |budget: &str| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_JackCompiler"))
+            .args(["--max-lines-total", budget])
+            .arg(dir.path())
+            .output()
+            .unwrap()
+    };
+    let vm_files = || {
+        fs::read_dir(dir.path())
+            .unwrap()
+            .filter(|e| e.as_ref().unwrap().path().extension() == Some("vm".as_ref()))
+            .count()
+    };
+
+    // Every file is within any per-file budget, but not the total
+    let output = run("100");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("<total>"), "{stderr}");
+    assert_eq!(vm_files(), 0);
+
+    assert!(run("100000").status.success());
+    assert_eq!(vm_files(), 3);
+}
+
+#[test]
+fn test_compile_directory_progress() {
+    let count = std::sync::atomic::AtomicUsize::new(0);
+    let seen = std::sync::Mutex::new(Vec::new());
+    let options = CompileOptions {
+        threads: Some(2),
+        ..Default::default()
+    };
+    let results = compile_directory_with_progress(Path::new("../Pong"), options, |filename| {
+        count.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        seen.lock().unwrap().push(filename.to_string());
+    });
+
+    assert_eq!(count.into_inner(), results.len());
+    let mut seen = seen.into_inner().unwrap();
+    seen.sort();
+    let mut expected: Vec<_> = results.iter().map(|r| r.filename.clone()).collect();
+    expected.sort();
+    assert_eq!(seen, expected);
+}
@@ -207,7 +207,10 @@ class Main {
     }
 }
 "#;
-    let options = CompileOptions { optimize: false };
+    let options = CompileOptions {
+        optimize: false,
+        ..Default::default()
+    };
     let result = compile_source_with_options(source, "Main", options);
     assert!(result.is_ok());
 
@@ -305,7 +308,10 @@ class Main {
     }
 }
 "#;
-    let options = CompileOptions { optimize: false };
+    let options = CompileOptions {
+        optimize: false,
+        ..Default::default()
+    };
     let result = compile_source_with_options(source, "Main", options);
     assert!(result.is_ok());
 
@@ -387,9 +393,22 @@ class Main {
 }
 "#;
 
-    let optimized = compile_source_with_options(source, "Main", CompileOptions { optimize: true });
-    let unoptimized =
-        compile_source_with_options(source, "Main", CompileOptions { optimize: false });
+    let optimized = compile_source_with_options(
+        source,
+        "Main",
+        CompileOptions {
+            optimize: true,
+            ..Default::default()
+        },
+    );
+    let unoptimized = compile_source_with_options(
+        source,
+        "Main",
+        CompileOptions {
+            optimize: false,
+            ..Default::default()
+        },
+    );
 
     assert!(optimized.is_ok());
     assert!(unoptimized.is_ok());
@@ -0,0 +1,63 @@
+//! A global allocator that counts allocations, for benchmarks and tests.
+//!
+//! The assembler, translator, analyzer and compiler benchmarks print how many
+//! heap allocations one pass over a large input makes, so allocation churn
+//! stays visible next to criterion's timings; the translator's
+//! `alloc-count` test asserts its hot path makes none. A binary opts in by
+//! installing the allocator, then wraps the code to measure in [`count`]:
+//!
+//! ```ignore
+//! use alloc_counter::CountingAllocator;
+//!
+//! #[global_allocator]
+//! static GLOBAL: CountingAllocator = CountingAllocator;
+//!
+//! let (output, allocations) = alloc_counter::count(|| assemble(&source));
+//! ```
+//!
+//! Counts are per thread, so tests running in parallel do not see each
+//! other's allocations. Without the allocator installed every count is 0.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+/// Forwards to [`System`], counting every allocation and reallocation made
+/// on the current thread.
+pub struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+fn record() {
+    // Ignored once the thread's locals are torn down
+    let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        record();
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        record();
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+/// Allocations and reallocations made on this thread so far.
+pub fn allocations() -> usize {
+    ALLOCATIONS.with(Cell::get)
+}
+
+/// Run `f`, returning its result and the allocations it made on this thread.
+pub fn count<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = allocations();
+    let result = f();
+    (result, allocations() - before)
+}